@@ -1,31 +1,101 @@
-//! The jodin asm version string
+//! The version of jodin asm, which is stored as the magic number of every object and image.
+//!
+//! A version has a major and a minor number. A new minor version only appends instructions, so
+//! the assembly of an older minor version can still be decoded. Assembly of an older version is
+//! rewritten into the current instruction set by the [upgrader](crate::upgrade) when it's loaded.
+//!
+//! The magic number of a version is [`MAGIC_TAG`] in its upper four bytes, followed by the major and
+//! minor numbers as big endian `u16`s. Objects of version 1.0 instead have a hash of the string
+//! `"jodin_asm_version_1.0"` as their magic number.
 
-/// The current version of the jodin asm
-#[derive(Debug)]
-pub struct Version;
-const VERSION_STRING: &str = "1.0";
+use std::fmt::{Display, Formatter};
+use std::ops::RangeInclusive;
+
+/// The tag in the upper four bytes of every magic number
+pub const MAGIC_TAG: u32 = u32::from_be_bytes(*b"JASM");
+
+/// The versions of assembly that can be loaded
+pub const SUPPORTED_VERSIONS: RangeInclusive<Version> =
+    RangeInclusive::new(Version::new(1, 0), Version::CURRENT);
+
+/// A version of the jodin asm
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Version {
+    /// Changes when instructions are removed or encoded differently
+    pub major: u16,
+    /// Changes when instructions are added
+    pub minor: u16,
+}
 
 impl Version {
-    /// Gets the jodin asm bytecode string
-    pub const fn version_string(&self) -> &str {
-        VERSION_STRING
+    /// The version of the assembly produced by this compiler
+    pub const CURRENT: Version = Version::new(1, 1);
+
+    /// Creates a version
+    pub const fn new(major: u16, minor: u16) -> Self {
+        Self { major, minor }
     }
 
     /// Gets the 8-byte magic number for this version number
     pub fn to_magic_number(&self) -> u64 {
-        let version_string_full = format!("jodin_asm_version_{}", VERSION_STRING);
-        let mut sum = 0u64;
-        for (index, byte) in version_string_full.bytes().enumerate() {
-            let mult = index as u64 + 1;
-            let pow = u32::wrapping_sub(31, index as u32);
-            let add = u64::wrapping_mul((byte as u64).wrapping_pow(pow), mult);
-            sum = u64::wrapping_add(sum, add);
+        if *self == Version::new(1, 0) {
+            return legacy_magic_number();
         }
-        sum
+        (MAGIC_TAG as u64) << 32 | (self.major as u64) << 16 | self.minor as u64
+    }
+
+    /// Gets the version of a magic number, if it is one
+    pub fn from_magic_number(number: u64) -> Option<Self> {
+        if number == legacy_magic_number() {
+            return Some(Version::new(1, 0));
+        }
+        if (number >> 32) as u32 != MAGIC_TAG {
+            return None;
+        }
+        Some(Version::new((number >> 16) as u16, number as u16))
+    }
+
+    /// Whether assembly of this version can be loaded
+    pub fn is_supported(&self) -> bool {
+        SUPPORTED_VERSIONS.contains(self)
+    }
+}
+
+impl Display for Version {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}.{}", self.major, self.minor)
+    }
+}
+
+/// The magic number of version 1.0, which was a hash of its version string
+fn legacy_magic_number() -> u64 {
+    let version_string_full = "jodin_asm_version_1.0";
+    let mut sum = 0u64;
+    for (index, byte) in version_string_full.bytes().enumerate() {
+        let mult = index as u64 + 1;
+        let pow = u32::wrapping_sub(31, index as u32);
+        let add = u64::wrapping_mul((byte as u64).wrapping_pow(pow), mult);
+        sum = u64::wrapping_add(sum, add);
     }
+    sum
+}
 
-    /// Check whether the given magic number if valid for this bytecode version
-    pub fn verify_magic_number(&self, number: u64) -> bool {
-        self.to_magic_number() == number
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn magic_numbers_round_trip() {
+        assert_eq!(legacy_magic_number(), 0xb0c0e6177d956828);
+        for version in [Version::new(1, 0), Version::CURRENT, Version::new(3, 12)] {
+            assert_eq!(
+                Version::from_magic_number(version.to_magic_number()),
+                Some(version)
+            );
+        }
+        assert_eq!(Version::from_magic_number(0x1234), None);
+        assert!(Version::new(1, 0) < Version::CURRENT);
+        assert!(!Version::new(2, 0).is_supported());
+        assert_eq!(Version::new(1, 1).to_string(), "1.1");
     }
 }
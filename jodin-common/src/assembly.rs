@@ -2,9 +2,12 @@
 
 pub mod asm_block;
 pub mod asm_macros;
+pub mod debug_info;
 pub mod error;
 pub mod instructions;
 pub mod location;
+pub mod native_handle;
+pub mod text;
 pub mod value;
 
 pub mod prelude {
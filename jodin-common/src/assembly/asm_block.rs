@@ -1,5 +1,6 @@
 //! Contains supporting code for inserting and creating assembly code for the compiler
 
+use crate::assembly::debug_info::LineEntry;
 use crate::assembly::instructions::{Asm, Assembly};
 use crate::assembly::location::AsmLocation;
 use crate::compilation::{Compilable, Context, PaddedWriter, Target};
@@ -48,14 +49,28 @@ impl AssemblyBlock {
         output
     }
 
+    /// Marks that the following instructions were compiled from a line in the source file
+    pub fn mark_source_line(&mut self, line: u32) {
+        self.assembly.push(AssemblyBlockComponent::SourceLine(line));
+    }
+
     /// Normalizes the block into standard assembly. Relatives `@<label>` and removes `#<labels>`.
     pub fn normalize(&self) -> Assembly {
+        self.normalize_with_lines().0
+    }
+
+    /// Normalizes the block into standard assembly, also creating the line table from the source
+    /// lines marked in the block.
+    pub fn normalize_with_lines(&self) -> (Assembly, Vec<LineEntry>) {
         let mut to_normalize = self.clone();
         let base_namespace = self.name.as_ref().map(Identifier::new).unwrap_or(Identifier::empty());
         to_normalize.resolve_relative_labels(&base_namespace);
         let all_labels = to_normalize.find_all_labels();
         to_normalize.resolve_nonlocal_labels(&all_labels, &base_namespace);
-        to_normalize._normalize(&base_namespace)
+        let mut output = Assembly::new();
+        let mut lines = vec![];
+        to_normalize._normalize(&base_namespace, &mut output, &mut lines);
+        (output, lines)
     }
 
     fn reformat_nonlocal(label: String, nonlocal_hash: u64) -> String {
@@ -174,11 +189,12 @@ impl AssemblyBlock {
     }
 
 
-    fn _normalize(self, current_namespace: &Identifier) -> Assembly {
-
-        let mut output = Assembly::new();
+    fn _normalize(self, current_namespace: &Identifier, output: &mut Assembly, lines: &mut Vec<LineEntry>) {
         for comp in self.assembly {
             match comp {
+                // temporary labels only mark where assembly is inserted while compiling
+                AssemblyBlockComponent::SingleInstruction(Asm::Label(lbl))
+                    if lbl.starts_with(REMOVE_LABEL_MARKER) => {}
                 AssemblyBlockComponent::SingleInstruction(s) => {
                     output.push(s);
                 }
@@ -187,11 +203,13 @@ impl AssemblyBlock {
                         current_namespace,
                         b.name.as_ref().unwrap_or(&String::new()),
                     );
-                    output.extend(b._normalize(&namespace));
+                    b._normalize(&namespace, output, lines);
+                }
+                AssemblyBlockComponent::SourceLine(line) => {
+                    lines.push(LineEntry { pc: output.len(), line });
                 }
             }
         }
-        output
     }
 
     fn normalize_label(current_namespace: &Identifier, lbl: &String) -> String {
@@ -319,6 +337,8 @@ pub trait InsertAsm<T>: InsertAsmHelper {
 pub enum AssemblyBlockComponent {
     SingleInstruction(Asm),
     Block(AssemblyBlock),
+    /// Marks the source line of the following instructions. Doesn't produce any instructions.
+    SourceLine(u32),
 }
 
 impl Debug for AssemblyBlockComponent {
@@ -335,6 +355,9 @@ impl Debug for AssemblyBlockComponent {
                     write!(f, "{:?}", block)
                 }
             },
+            AssemblyBlockComponent::SourceLine(line) => {
+                write!(f, "<line {}>", line)
+            }
         }
     }
 }
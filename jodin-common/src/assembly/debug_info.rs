@@ -0,0 +1,63 @@
+//! Debug information that maps instructions back to the source they were compiled from
+
+use std::path::PathBuf;
+
+/// Marks that the instructions starting at `pc` were compiled from a source line
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub struct LineEntry {
+    /// The index of the first instruction of the line
+    pub pc: usize,
+    /// The line within the source file, starting at 1
+    pub line: u32,
+}
+
+/// The debug information of some assembly
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DebugInfo {
+    /// The source file the assembly was compiled from
+    pub source: Option<PathBuf>,
+    /// The line table, sorted by instruction
+    pub lines: Vec<LineEntry>,
+}
+
+impl DebugInfo {
+    /// Creates new debug info
+    pub fn new(source: Option<PathBuf>, mut lines: Vec<LineEntry>) -> Self {
+        lines.sort();
+        Self { source, lines }
+    }
+
+    /// Appends the debug info of assembly that was placed `offset` instructions after the start of
+    /// this assembly
+    pub fn append(&mut self, other: DebugInfo, offset: usize) {
+        if self.source.is_none() {
+            self.source = other.source;
+        }
+        self.lines
+            .extend(other.lines.into_iter().map(|entry| LineEntry {
+                pc: entry.pc + offset,
+                line: entry.line,
+            }));
+        self.lines.sort();
+    }
+
+    /// Gets the source line of an instruction
+    pub fn line_at(&self, pc: usize) -> Option<u32> {
+        let index = self.lines.partition_point(|entry| entry.pc <= pc);
+        index.checked_sub(1).map(|index| self.lines[index].line)
+    }
+
+    /// The instructions compiled from each entry of the line table. The range of an entry ends at
+    /// the start of the next entry, or at `len` for the last entry.
+    pub fn ranges(&self, len: usize) -> impl Iterator<Item = (u32, std::ops::Range<usize>)> + '_ {
+        self.lines.iter().enumerate().map(move |(index, entry)| {
+            let end = self
+                .lines
+                .get(index + 1)
+                .map(|next| next.pc)
+                .unwrap_or(len)
+                .max(entry.pc);
+            (entry.line, entry.pc..end)
+        })
+    }
+}
@@ -2,6 +2,7 @@
 //!
 //! Does not store any information about how byte codes are actually implemented
 
+use crate::assembly::debug_info::DebugInfo;
 use crate::assembly::error::BytecodeError;
 use crate::assembly::location::AsmLocation;
 use crate::assembly::value::Value;
@@ -136,6 +137,14 @@ impl Asm {
         Self::Push(value.into())
     }
 
+    /// The label that this instruction defines, if it's a label
+    pub fn defined_label(&self) -> Option<&str> {
+        match self {
+            Asm::Label(label) | Asm::PublicLabel(label) => Some(label),
+            _ => None,
+        }
+    }
+
     pub fn goto(lbl: impl AsRef<str>) -> Self {
         Self::Goto(AsmLocation::Label(lbl.as_ref().to_string()))
     }
@@ -181,6 +190,11 @@ impl Decode for Bytecode {
 
 pub trait GetAsm {
     fn get_asm(&self) -> Assembly;
+
+    /// The debug information of the assembly, if there is any
+    fn debug_info(&self) -> Option<DebugInfo> {
+        None
+    }
 }
 
 impl GetAsm for Assembly {
@@ -0,0 +1,169 @@
+//! Opaque handles to resources owned by native code, such as plugins.
+//!
+//! The resource itself never leaves its owner. The virtual machine only holds an id, which the
+//! owner uses to find the resource, and a type name. Handles are reference counted, and the
+//! finalizer of a handle runs once the last copy of it is dropped.
+//!
+//! Live handles are registered by their owner and id, so a handle that crossed a plugin boundary
+//! can be matched back to the original handle.
+
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fmt::{Debug, Display, Formatter};
+use std::rc::{Rc, Weak};
+
+type Finalizer = Box<dyn FnOnce(&str, u64)>;
+
+thread_local! {
+    static HANDLES: RefCell<HashMap<(String, u64), Weak<HandleInner>>> = RefCell::new(HashMap::new());
+}
+
+struct HandleInner {
+    owner: String,
+    type_name: String,
+    id: u64,
+    finalizer: RefCell<Option<Finalizer>>,
+}
+
+impl Drop for HandleInner {
+    fn drop(&mut self) {
+        if self.finalizer.get_mut().is_some() {
+            let _ = HANDLES.try_with(|handles| {
+                let mut handles = handles.borrow_mut();
+                let key = (self.owner.clone(), self.id);
+                if handles
+                    .get(&key)
+                    .is_some_and(|weak| weak.strong_count() == 0)
+                {
+                    handles.remove(&key);
+                }
+            });
+        }
+        if let Some(finalizer) = self.finalizer.get_mut().take() {
+            finalizer(&self.type_name, self.id);
+        }
+    }
+}
+
+/// A reference counted handle to a resource owned by native code
+#[derive(Clone)]
+pub struct NativeHandle {
+    inner: Rc<HandleInner>,
+}
+
+impl NativeHandle {
+    /// Creates a handle, which runs the finalizer once it's dropped. If a live handle already
+    /// exists for the same owner and id, that handle is returned instead and the finalizer is
+    /// dropped without running.
+    pub fn new<F>(
+        owner: impl Into<String>,
+        type_name: impl Into<String>,
+        id: u64,
+        finalizer: F,
+    ) -> Self
+    where
+        F: FnOnce(&str, u64) + 'static,
+    {
+        let owner = owner.into();
+        if let Some(existing) = Self::find(&owner, id) {
+            return existing;
+        }
+        let inner = Rc::new(HandleInner {
+            owner: owner.clone(),
+            type_name: type_name.into(),
+            id,
+            finalizer: RefCell::new(Some(Box::new(finalizer))),
+        });
+        HANDLES.with(|handles| {
+            handles
+                .borrow_mut()
+                .insert((owner, id), Rc::downgrade(&inner))
+        });
+        Self { inner }
+    }
+
+    /// Creates a handle that isn't registered and has no finalizer. Used for copies of handles
+    /// whose original lives on the other side of a plugin boundary.
+    pub fn unmanaged(owner: impl Into<String>, type_name: impl Into<String>, id: u64) -> Self {
+        Self {
+            inner: Rc::new(HandleInner {
+                owner: owner.into(),
+                type_name: type_name.into(),
+                id,
+                finalizer: RefCell::new(None),
+            }),
+        }
+    }
+
+    /// Finds the live handle of an owner with an id
+    pub fn find(owner: &str, id: u64) -> Option<Self> {
+        HANDLES.with(|handles| {
+            handles
+                .borrow()
+                .get(&(owner.to_string(), id))
+                .and_then(Weak::upgrade)
+                .map(|inner| Self { inner })
+        })
+    }
+
+    /// The owner of the resource
+    pub fn owner(&self) -> &str {
+        &self.inner.owner
+    }
+
+    /// The name of the type of the resource
+    pub fn type_name(&self) -> &str {
+        &self.inner.type_name
+    }
+
+    /// The id of the resource within its owner
+    pub fn id(&self) -> u64 {
+        self.inner.id
+    }
+}
+
+/// Handles are equal when they refer to the same resource
+impl PartialEq for NativeHandle {
+    fn eq(&self, other: &Self) -> bool {
+        self.owner() == other.owner() && self.id() == other.id()
+    }
+}
+
+impl Debug for NativeHandle {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("NativeHandle")
+            .field("owner", &self.inner.owner)
+            .field("type_name", &self.inner.type_name)
+            .field("id", &self.inner.id)
+            .finish()
+    }
+}
+
+impl Display for NativeHandle {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "<{} #{}>", self.inner.type_name, self.inner.id)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::Cell;
+
+    #[test]
+    fn finalizer_runs_once_after_last_copy() {
+        let finalized = Rc::new(Cell::new(0));
+        let counter = finalized.clone();
+        let handle = NativeHandle::new("owner", "file", 3, move |type_name, id| {
+            assert_eq!((type_name, id), ("file", 3));
+            counter.set(counter.get() + 1);
+        });
+        let copy = handle.clone();
+        assert_eq!(NativeHandle::find("owner", 3), Some(handle.clone()));
+        drop(handle);
+        assert_eq!(finalized.get(), 0);
+        drop(copy);
+        assert_eq!(finalized.get(), 1);
+        assert!(NativeHandle::find("owner", 3).is_none());
+    }
+}
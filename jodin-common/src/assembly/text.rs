@@ -0,0 +1,839 @@
+//! The textual form of jodin assembly, which is stored in `.jasm` files.
+//!
+//! Each [instruction](Asm) is written as its mnemonic followed by its operands, and labels are
+//! written as `name:`, or `pub name:` for public labels. Whitespace separates tokens, and comments
+//! start with `;` and go until the end of the line.
+//!
+//! ```text
+//! ; returns the sum of 1 and 2
+//! pub main:
+//!     push 1u64
+//!     push 2u64
+//!     add
+//!     return
+//! ```
+//!
+//! # Operands
+//!
+//! - Names, such as labels and symbols, are words or strings. Words can contain `::`, but not a
+//!   single `:`.
+//! - Locations are either a byte index, such as `12`, an instruction offset, such as `+3` or `-2`, or
+//!   a label.
+//! - Variable numbers and counts are unsigned integers.
+//!
+//! # Values
+//!
+//! | value                     | text                                     |
+//! |---------------------------|------------------------------------------|
+//! | [`Empty`](Value::Empty)   | `void`                                   |
+//! | [`Byte`](Value::Byte)     | `7u8`                                    |
+//! | [`Float`](Value::Float)   | `1.5f64`, or `1.5`                       |
+//! | [`Integer`](Value::Integer) | `-3i64`, or `-3`                       |
+//! | [`UInteger`](Value::UInteger) | `3u64`                               |
+//! | [`Str`](Value::Str)       | `"text\n"`                               |
+//! | [`Dictionary`](Value::Dictionary) | `{ "key": 1u64, "other": void }` |
+//! | [`Array`](Value::Array)   | `[1u64, "two"]`                          |
+//! | [`Reference`](Value::Reference) | `ref 1u64`                         |
+//! | [`Bytecode`](Value::Bytecode) | `asm { push 1u64 return }`, or `bytecode [1, 2, 3]` |
+//! | [`Function`](Value::Function) | `fn main`                            |
+//! | [`Native`](Value::Native) | `native`                                 |
+//!
+//! [Native handles](Value::NativeHandle) belong to a running virtual machine, so they have no
+//! textual form.
+//!
+//! [`to_jasm`] writes assembly that [`parse_jasm`] reads back into the same instructions.
+
+use crate::assembly::instructions::{Asm, Assembly, Encode};
+use crate::assembly::location::AsmLocation;
+use crate::assembly::value::{JRef, Value};
+use crate::error::{JodinErrorType, JodinResult};
+use std::collections::HashMap;
+use std::fmt::Write;
+use std::iter::Peekable;
+use std::str::{CharIndices, FromStr};
+
+/// The extension of textual assembly files
+pub const JASM_EXTENSION: &str = "jasm";
+
+/// Parses textual assembly
+///
+/// # Error
+///
+/// Will return a [JasmSyntaxError](JodinErrorType::JasmSyntaxError) with the line and column of the
+/// first thing that couldn't be parsed.
+///
+/// # Example
+///
+/// ```
+/// use jodin_common::assembly::instructions::Asm;
+/// use jodin_common::assembly::text::parse_jasm;
+/// let asm = parse_jasm("pub main: push 1u64 return").unwrap();
+/// assert_eq!(asm, vec![Asm::pub_label("main"), Asm::push(1u64), Asm::Return]);
+/// ```
+pub fn parse_jasm(text: &str) -> JodinResult<Assembly> {
+    let mut parser = Parser::new(text)?;
+    let mut asm = vec![];
+    while parser.peek().is_some() {
+        asm.push(parser.instruction()?);
+    }
+    Ok(asm)
+}
+
+/// Writes assembly as text, with one instruction per line.
+///
+/// # Error
+///
+/// Will return an error if an instruction pushes a [native handle](Value::NativeHandle).
+pub fn to_jasm(asm: &[Asm]) -> JodinResult<String> {
+    let mut text = String::new();
+    write_instructions(&mut text, asm, 0)?;
+    Ok(text)
+}
+
+/// Writes a single instruction as text, without indentation. Labels are written with their colon.
+///
+/// # Error
+///
+/// Will return an error if the instruction pushes a [native handle](Value::NativeHandle).
+pub fn instruction_to_jasm(instruction: &Asm) -> JodinResult<String> {
+    let mut text = String::new();
+    match instruction {
+        Asm::Label(label) => write!(text, "{}:", name(label))?,
+        Asm::PublicLabel(label) => write!(text, "pub {}:", name(label))?,
+        instruction => write_instruction(&mut text, instruction, 0)?,
+    }
+    Ok(text)
+}
+
+fn write_instructions(text: &mut String, asm: &[Asm], indent: usize) -> JodinResult<()> {
+    for instruction in asm {
+        match instruction {
+            Asm::Label(label) => writeln!(text, "{:indent$}{}:", "", name(label))?,
+            Asm::PublicLabel(label) => writeln!(text, "{:indent$}pub {}:", "", name(label))?,
+            instruction => {
+                write!(text, "{:indent$}    ", "")?;
+                write_instruction(text, instruction, indent + 4)?;
+                writeln!(text)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+fn write_instruction(text: &mut String, instruction: &Asm, indent: usize) -> JodinResult<()> {
+    match instruction {
+        Asm::Label(_) | Asm::PublicLabel(_) => unreachable!("labels aren't instructions"),
+        Asm::Static => text.push_str("static"),
+        Asm::Nop => text.push_str("nop"),
+        Asm::Halt => text.push_str("halt"),
+        Asm::Goto(location) => write!(text, "goto {}", self::location(location))?,
+        Asm::CondGoto(location) => write!(text, "cond_goto {}", self::location(location))?,
+        Asm::Push(value) => {
+            text.push_str("push ");
+            write_value(text, value, indent)?;
+        }
+        Asm::Pop => text.push_str("pop"),
+        Asm::Clear => text.push_str("clear"),
+        Asm::SetVar(var) => write!(text, "set_var {}", var)?,
+        Asm::GetVar(var) => write!(text, "get_var {}", var)?,
+        Asm::ClearVar(var) => write!(text, "clear_var {}", var)?,
+        Asm::GetSymbol(symbol) => write!(text, "get_symbol {}", name(symbol))?,
+        Asm::SetSymbol(symbol) => write!(text, "set_symbol {}", name(symbol))?,
+        Asm::GetAttribute(attribute) => write!(text, "get_attribute {}", name(attribute))?,
+        Asm::Index(index) => write!(text, "index {}", index)?,
+        Asm::Pack(count) => write!(text, "pack {}", count)?,
+        Asm::Deref => text.push_str("deref"),
+        Asm::GetRef => text.push_str("get_ref"),
+        Asm::SetRef => text.push_str("set_ref"),
+        Asm::Return => text.push_str("return"),
+        Asm::Call(location) => write!(text, "call {}", self::location(location))?,
+        Asm::Add => text.push_str("add"),
+        Asm::Subtract => text.push_str("subtract"),
+        Asm::Multiply => text.push_str("multiply"),
+        Asm::Divide => text.push_str("divide"),
+        Asm::Remainder => text.push_str("remainder"),
+        Asm::GT0 => text.push_str("gt0"),
+        Asm::Gt => text.push_str("gt"),
+        Asm::And => text.push_str("and"),
+        Asm::Not => text.push_str("not"),
+        Asm::Or => text.push_str("or"),
+        Asm::Boolify => text.push_str("boolify"),
+        Asm::BooleanAnd => text.push_str("boolean_and"),
+        Asm::BooleanOr => text.push_str("boolean_or"),
+        Asm::BooleanNot => text.push_str("boolean_not"),
+        Asm::BooleanXor => text.push_str("boolean_xor"),
+        Asm::SendMessage => text.push_str("send_message"),
+        Asm::IntoReference => text.push_str("into_reference"),
+        Asm::NativeMethod(method, args) => write!(text, "native_method {} {}", name(method), args)?,
+    }
+    Ok(())
+}
+
+fn write_value(text: &mut String, value: &Value, indent: usize) -> JodinResult<()> {
+    match value {
+        Value::Empty => text.push_str("void"),
+        Value::Byte(b) => write!(text, "{}u8", b)?,
+        Value::Float(f) => write!(text, "{}f64", f)?,
+        Value::Integer(i) => write!(text, "{:+}i64", i)?,
+        Value::UInteger(u) => write!(text, "{}u64", u)?,
+        Value::Str(s) => write!(text, "{:?}", s)?,
+        Value::Dictionary(dictionary) => {
+            if dictionary.is_empty() {
+                text.push_str("{}");
+                return Ok(());
+            }
+            // entries are sorted so that the same dictionary is always written the same way
+            let mut entries = dictionary.iter().collect::<Vec<_>>();
+            entries.sort_by_key(|&(key, _)| key);
+            text.push_str("{ ");
+            for (index, (key, value)) in entries.into_iter().enumerate() {
+                if index > 0 {
+                    text.push_str(", ");
+                }
+                write!(text, "{:?}: ", key)?;
+                write_value(text, value, indent)?;
+            }
+            text.push_str(" }");
+        }
+        Value::Array(values) => {
+            text.push('[');
+            for (index, value) in values.iter().enumerate() {
+                if index > 0 {
+                    text.push_str(", ");
+                }
+                write_value(text, value, indent)?;
+            }
+            text.push(']');
+        }
+        Value::Reference(reference) => {
+            text.push_str("ref ");
+            write_value(text, &reference.borrow(), indent)?;
+        }
+        Value::Bytecode(bytecode) => match bincode::deserialize::<Assembly>(bytecode) {
+            // only written as assembly when it would be encoded to the same bytes again
+            Ok(asm) if asm.clone().encode() == *bytecode => {
+                text.push_str("asm {\n");
+                write_instructions(text, &asm, indent)?;
+                write!(text, "{:indent$}}}", "")?;
+            }
+            _ => {
+                let bytes = bytecode.iter().map(u8::to_string).collect::<Vec<_>>();
+                write!(text, "bytecode [{}]", bytes.join(", "))?;
+            }
+        },
+        Value::Function(location) => write!(text, "fn {}", self::location(location))?,
+        Value::Native => text.push_str("native"),
+        Value::NativeHandle(handle) => {
+            return Err(anyhow::anyhow!("native handle {} has no textual form", handle).into())
+        }
+    }
+    Ok(())
+}
+
+/// Writes a name as a word when it would be read back as the same word, and as a string otherwise
+fn name(name: &str) -> String {
+    let is_word = matches!(
+        Lexer::new(name).collect::<Result<Vec<_>, _>>().as_deref(),
+        Ok([(Token::Word(word), _)]) if word == name
+    );
+    if is_word {
+        name.to_string()
+    } else {
+        format!("{:?}", name)
+    }
+}
+
+fn location(location: &AsmLocation) -> String {
+    match location {
+        AsmLocation::ByteIndex(index) => index.to_string(),
+        AsmLocation::InstructionDiff(diff) => format!("{:+}", diff),
+        // labels that look like numbers would be read as indices or offsets
+        AsmLocation::Label(label) if label.starts_with(is_number_start) => format!("{:?}", label),
+        AsmLocation::Label(label) => name(label),
+    }
+}
+
+fn is_number_start(c: char) -> bool {
+    c.is_ascii_digit() || c == '+' || c == '-'
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Word(String),
+    Str(String),
+    Colon,
+    Comma,
+    OpenBracket,
+    CloseBracket,
+    OpenBrace,
+    CloseBrace,
+}
+
+impl Token {
+    fn describe(&self) -> String {
+        match self {
+            Token::Word(word) => format!("`{}`", word),
+            Token::Str(s) => format!("{:?}", s),
+            Token::Colon => "`:`".to_string(),
+            Token::Comma => "`,`".to_string(),
+            Token::OpenBracket => "`[`".to_string(),
+            Token::CloseBracket => "`]`".to_string(),
+            Token::OpenBrace => "`{`".to_string(),
+            Token::CloseBrace => "`}`".to_string(),
+        }
+    }
+}
+
+/// The line and column of a token, both starting at 1
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Position {
+    line: usize,
+    column: usize,
+}
+
+fn syntax_error(position: Position, message: impl Into<String>) -> JodinErrorType {
+    JodinErrorType::JasmSyntaxError {
+        line: position.line,
+        column: position.column,
+        message: message.into(),
+    }
+}
+
+fn is_word_char(c: char) -> bool {
+    !c.is_whitespace() && !matches!(c, '"' | ':' | ',' | ';' | '[' | ']' | '{' | '}')
+}
+
+struct Lexer<'a> {
+    chars: Peekable<CharIndices<'a>>,
+    position: Position,
+}
+
+impl<'a> Lexer<'a> {
+    fn new(text: &'a str) -> Self {
+        Self {
+            chars: text.char_indices().peekable(),
+            position: Position { line: 1, column: 1 },
+        }
+    }
+
+    fn next_char(&mut self) -> Option<char> {
+        let (_, c) = self.chars.next()?;
+        if c == '\n' {
+            self.position.line += 1;
+            self.position.column = 1;
+        } else {
+            self.position.column += 1;
+        }
+        Some(c)
+    }
+
+    fn peek_char(&mut self) -> Option<char> {
+        self.chars.peek().map(|&(_, c)| c)
+    }
+
+    fn string(&mut self, start: Position) -> Result<String, JodinErrorType> {
+        let mut string = String::new();
+        loop {
+            let c = self
+                .next_char()
+                .ok_or_else(|| syntax_error(start, "unterminated string"))?;
+            match c {
+                '"' => return Ok(string),
+                '\\' => {
+                    let escape = self.position;
+                    let c = match self.next_char() {
+                        Some('n') => '\n',
+                        Some('t') => '\t',
+                        Some('r') => '\r',
+                        Some('0') => '\0',
+                        Some('\\') => '\\',
+                        Some('"') => '"',
+                        Some('\'') => '\'',
+                        Some('u') => self.unicode_escape(escape)?,
+                        _ => return Err(syntax_error(escape, "invalid escape sequence")),
+                    };
+                    string.push(c);
+                }
+                c => string.push(c),
+            }
+        }
+    }
+
+    /// Reads the `{XXXX}` of a `\u{XXXX}` escape
+    fn unicode_escape(&mut self, escape: Position) -> Result<char, JodinErrorType> {
+        let invalid = || syntax_error(escape, "invalid unicode escape");
+        if self.next_char() != Some('{') {
+            return Err(invalid());
+        }
+        let mut hex = String::new();
+        loop {
+            match self.next_char() {
+                Some('}') => break,
+                Some(c) if c.is_ascii_hexdigit() => hex.push(c),
+                _ => return Err(invalid()),
+            }
+        }
+        u32::from_str_radix(&hex, 16)
+            .ok()
+            .and_then(char::from_u32)
+            .ok_or_else(invalid)
+    }
+}
+
+impl Iterator for Lexer<'_> {
+    type Item = Result<(Token, Position), JodinErrorType>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            match self.peek_char()? {
+                c if c.is_whitespace() => {
+                    self.next_char();
+                }
+                ';' => while !matches!(self.next_char(), Some('\n') | None) {},
+                _ => break,
+            }
+        }
+
+        let position = self.position;
+        let token = match self.next_char()? {
+            '"' => match self.string(position) {
+                Ok(string) => Token::Str(string),
+                Err(e) => return Some(Err(e)),
+            },
+            ':' if self.peek_char() != Some(':') => Token::Colon,
+            ',' => Token::Comma,
+            '[' => Token::OpenBracket,
+            ']' => Token::CloseBracket,
+            '{' => Token::OpenBrace,
+            '}' => Token::CloseBrace,
+            c => {
+                let mut word = c.to_string();
+                if c == ':' {
+                    word.push(self.next_char().unwrap());
+                }
+                loop {
+                    match self.peek_char() {
+                        Some(c) if is_word_char(c) => {
+                            word.push(c);
+                            self.next_char();
+                        }
+                        // `::` is part of a word, but a single `:` ends it
+                        Some(':') => {
+                            let mut after = self.chars.clone();
+                            after.next();
+                            if after.peek().map(|&(_, c)| c) != Some(':') {
+                                break;
+                            }
+                            word.push_str("::");
+                            self.next_char();
+                            self.next_char();
+                        }
+                        _ => break,
+                    }
+                }
+                Token::Word(word)
+            }
+        };
+        Some(Ok((token, position)))
+    }
+}
+
+struct Parser {
+    tokens: Vec<(Token, Position)>,
+    next: usize,
+    end: Position,
+}
+
+impl Parser {
+    fn new(text: &str) -> Result<Self, JodinErrorType> {
+        let mut lexer = Lexer::new(text);
+        let tokens = (&mut lexer).collect::<Result<Vec<_>, _>>()?;
+        Ok(Self {
+            tokens,
+            next: 0,
+            end: lexer.position,
+        })
+    }
+
+    fn peek(&self) -> Option<&Token> {
+        self.peek_nth(0)
+    }
+
+    fn peek_nth(&self, n: usize) -> Option<&Token> {
+        self.tokens.get(self.next + n).map(|(token, _)| token)
+    }
+
+    fn position(&self) -> Position {
+        self.tokens
+            .get(self.next)
+            .map_or(self.end, |&(_, position)| position)
+    }
+
+    fn next_token(&mut self, expected: &str) -> Result<(Token, Position), JodinErrorType> {
+        match self.tokens.get(self.next) {
+            Some(token) => {
+                self.next += 1;
+                Ok(token.clone())
+            }
+            None => Err(syntax_error(
+                self.end,
+                format!("expected {}, found the end of the file", expected),
+            )),
+        }
+    }
+
+    fn unexpected<T>(
+        token: &Token,
+        position: Position,
+        expected: &str,
+    ) -> Result<T, JodinErrorType> {
+        Err(syntax_error(
+            position,
+            format!("expected {}, found {}", expected, token.describe()),
+        ))
+    }
+
+    fn expect(&mut self, expected: Token) -> Result<(), JodinErrorType> {
+        let (token, position) = self.next_token(&expected.describe())?;
+        if token == expected {
+            Ok(())
+        } else {
+            Self::unexpected(&token, position, &expected.describe())
+        }
+    }
+
+    fn instruction(&mut self) -> Result<Asm, JodinErrorType> {
+        match (self.peek(), self.peek_nth(1), self.peek_nth(2)) {
+            (Some(Token::Word(_) | Token::Str(_)), Some(Token::Colon), _) => {
+                let label = self.name()?;
+                self.expect(Token::Colon)?;
+                return Ok(Asm::Label(label));
+            }
+            (Some(Token::Word(word)), Some(Token::Word(_) | Token::Str(_)), Some(Token::Colon))
+                if word == "pub" =>
+            {
+                self.next += 1;
+                let label = self.name()?;
+                self.expect(Token::Colon)?;
+                return Ok(Asm::PublicLabel(label));
+            }
+            _ => {}
+        }
+
+        let (token, position) = self.next_token("an instruction")?;
+        let mnemonic = match token {
+            Token::Word(word) => word,
+            token => return Self::unexpected(&token, position, "an instruction"),
+        };
+        let asm = match mnemonic.as_str() {
+            "static" => Asm::Static,
+            "nop" => Asm::Nop,
+            "halt" => Asm::Halt,
+            "goto" => Asm::Goto(self.location()?),
+            "cond_goto" => Asm::CondGoto(self.location()?),
+            "push" => Asm::Push(self.value()?),
+            "pop" => Asm::Pop,
+            "clear" => Asm::Clear,
+            "set_var" => Asm::SetVar(self.integer()?),
+            "get_var" => Asm::GetVar(self.integer()?),
+            "clear_var" => Asm::ClearVar(self.integer()?),
+            "get_symbol" => Asm::GetSymbol(self.name()?),
+            "set_symbol" => Asm::SetSymbol(self.name()?),
+            "get_attribute" => Asm::GetAttribute(self.name()?),
+            "index" => Asm::Index(self.integer()?),
+            "pack" => Asm::Pack(self.integer()?),
+            "deref" => Asm::Deref,
+            "get_ref" => Asm::GetRef,
+            "set_ref" => Asm::SetRef,
+            "return" => Asm::Return,
+            "call" => Asm::Call(self.location()?),
+            "add" => Asm::Add,
+            "subtract" => Asm::Subtract,
+            "multiply" => Asm::Multiply,
+            "divide" => Asm::Divide,
+            "remainder" => Asm::Remainder,
+            "gt0" => Asm::GT0,
+            "gt" => Asm::Gt,
+            "and" => Asm::And,
+            "not" => Asm::Not,
+            "or" => Asm::Or,
+            "boolify" => Asm::Boolify,
+            "boolean_and" => Asm::BooleanAnd,
+            "boolean_or" => Asm::BooleanOr,
+            "boolean_not" => Asm::BooleanNot,
+            "boolean_xor" => Asm::BooleanXor,
+            "send_message" => Asm::SendMessage,
+            "into_reference" => Asm::IntoReference,
+            "native_method" => Asm::NativeMethod(self.name()?, self.integer()?),
+            _ => {
+                return Err(syntax_error(
+                    position,
+                    format!("unknown instruction `{}`", mnemonic),
+                ))
+            }
+        };
+        Ok(asm)
+    }
+
+    fn name(&mut self) -> Result<String, JodinErrorType> {
+        match self.next_token("a name")? {
+            (Token::Word(word) | Token::Str(word), _) => Ok(word),
+            (token, position) => Self::unexpected(&token, position, "a name"),
+        }
+    }
+
+    fn integer<T: FromStr>(&mut self) -> Result<T, JodinErrorType> {
+        match self.next_token("an unsigned integer")? {
+            (Token::Word(word), position) => word.parse().map_err(|_| {
+                syntax_error(position, format!("`{}` is not an unsigned integer", word))
+            }),
+            (token, position) => Self::unexpected(&token, position, "an unsigned integer"),
+        }
+    }
+
+    fn location(&mut self) -> Result<AsmLocation, JodinErrorType> {
+        match self.next_token("a location")? {
+            (Token::Word(word), position) if word.starts_with(is_number_start) => {
+                let location = if word.starts_with(|c: char| c.is_ascii_digit()) {
+                    word.parse().map(AsmLocation::ByteIndex).ok()
+                } else {
+                    word.parse().map(AsmLocation::InstructionDiff).ok()
+                };
+                location.ok_or_else(|| {
+                    syntax_error(position, format!("`{}` is not a valid location", word))
+                })
+            }
+            (Token::Word(label) | Token::Str(label), _) => Ok(AsmLocation::Label(label)),
+            (token, position) => Self::unexpected(&token, position, "a location"),
+        }
+    }
+
+    fn value(&mut self) -> Result<Value, JodinErrorType> {
+        let (token, position) = self.next_token("a value")?;
+        let word = match token {
+            Token::Str(string) => return Ok(Value::Str(string)),
+            Token::OpenBracket => {
+                let values = self.list(Token::CloseBracket, Self::value)?;
+                return Ok(Value::Array(values));
+            }
+            Token::OpenBrace => {
+                let entries = self.list(Token::CloseBrace, |parser| {
+                    let key = match parser.next_token("a key")? {
+                        (Token::Str(key), _) => key,
+                        (token, position) => return Self::unexpected(&token, position, "a key"),
+                    };
+                    parser.expect(Token::Colon)?;
+                    Ok((key, parser.value()?))
+                })?;
+                return Ok(Value::Dictionary(
+                    entries.into_iter().collect::<HashMap<_, _>>(),
+                ));
+            }
+            Token::Word(word) => word,
+            token => return Self::unexpected(&token, position, "a value"),
+        };
+
+        let value = match word.as_str() {
+            "void" => Value::Empty,
+            "native" => Value::Native,
+            "ref" => Value::Reference(JRef::new(self.value()?)),
+            "fn" => Value::Function(self.location()?),
+            "asm" => {
+                self.expect(Token::OpenBrace)?;
+                let mut asm = vec![];
+                while self.peek() != Some(&Token::CloseBrace) {
+                    if self.peek().is_none() {
+                        return Err(syntax_error(self.position(), "expected `}`"));
+                    }
+                    asm.push(self.instruction()?);
+                }
+                self.next += 1;
+                Value::Bytecode(asm.encode())
+            }
+            "bytecode" => {
+                self.expect(Token::OpenBracket)?;
+                Value::Bytecode(self.list(Token::CloseBracket, Self::integer)?)
+            }
+            _ => number(&word)
+                .ok_or_else(|| syntax_error(position, format!("`{}` is not a value", word)))?,
+        };
+        Ok(value)
+    }
+
+    /// Parses comma separated items until the closing token. A trailing comma is allowed.
+    fn list<T>(
+        &mut self,
+        close: Token,
+        mut item: impl FnMut(&mut Self) -> Result<T, JodinErrorType>,
+    ) -> Result<Vec<T>, JodinErrorType> {
+        let mut items = vec![];
+        loop {
+            if self.peek() == Some(&close) {
+                self.next += 1;
+                return Ok(items);
+            }
+            items.push(item(self)?);
+            match self.next_token(&close.describe())? {
+                (Token::Comma, _) => {}
+                (token, _) if token == close => return Ok(items),
+                (token, position) => {
+                    return Self::unexpected(
+                        &token,
+                        position,
+                        &format!("`,` or {}", close.describe()),
+                    )
+                }
+            }
+        }
+    }
+}
+
+/// Parses a number with an optional type suffix. Numbers without a suffix are `i64`s, or `f64`s when
+/// they have a decimal point.
+fn number(word: &str) -> Option<Value> {
+    if let Some(byte) = word.strip_suffix("u8") {
+        byte.parse().ok().map(Value::Byte)
+    } else if let Some(unsigned) = word.strip_suffix("u64") {
+        unsigned.parse().ok().map(Value::UInteger)
+    } else if let Some(integer) = word.strip_suffix("i64") {
+        integer.parse().ok().map(Value::Integer)
+    } else if let Some(float) = word.strip_suffix("f64") {
+        float.parse().ok().map(Value::Float)
+    } else if word.contains('.') {
+        word.parse().ok().map(Value::Float)
+    } else {
+        word.parse().ok().map(Value::Integer)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn every_instruction() -> Assembly {
+        vec![
+            Asm::pub_label("main"),
+            Asm::label("@@loop"),
+            Asm::label("std::math::sqrt"),
+            Asm::label("needs quotes:"),
+            Asm::label("pub"),
+            Asm::Static,
+            Asm::Nop,
+            Asm::Halt,
+            Asm::Goto(AsmLocation::ByteIndex(12)),
+            Asm::CondGoto(AsmLocation::InstructionDiff(-3)),
+            Asm::Goto(AsmLocation::InstructionDiff(4)),
+            Asm::Goto(AsmLocation::Label("-looks like a number".to_string())),
+            Asm::cond_goto("@@loop"),
+            Asm::push(()),
+            Asm::push(7u8),
+            Asm::push(-1.25f64),
+            Asm::push(2.0f64),
+            Asm::push(-42i64),
+            Asm::push(42u64),
+            Asm::push("quote \" and\nnewline ; not a comment \u{1F600}"),
+            Asm::push(Value::new_dict()),
+            Asm::push([("b", Value::from(1u64)), ("a", Value::from(vec![1u8, 2u8]))]),
+            Asm::push(Value::Array(vec![])),
+            Asm::push(Value::from(3u64).into_reference()),
+            Asm::push(vec![Asm::push(1u64), Asm::label("inner"), Asm::Return]),
+            Asm::push(Value::Bytecode(vec![255, 0, 1])),
+            Asm::push(Value::location("main")),
+            Asm::push(Value::Function(AsmLocation::ByteIndex(3))),
+            Asm::push(Value::Native),
+            Asm::Pop,
+            Asm::Clear,
+            Asm::SetVar(0),
+            Asm::GetVar(1),
+            Asm::ClearVar(2),
+            Asm::GetSymbol("@print".to_string()),
+            Asm::SetSymbol("has space".to_string()),
+            Asm::get_attribute("field"),
+            Asm::Index(3),
+            Asm::Pack(2),
+            Asm::Deref,
+            Asm::GetRef,
+            Asm::SetRef,
+            Asm::Return,
+            Asm::Call(AsmLocation::Label("main".to_string())),
+            Asm::Add,
+            Asm::Subtract,
+            Asm::Multiply,
+            Asm::Divide,
+            Asm::Remainder,
+            Asm::GT0,
+            Asm::Gt,
+            Asm::And,
+            Asm::Not,
+            Asm::Or,
+            Asm::Boolify,
+            Asm::BooleanAnd,
+            Asm::BooleanOr,
+            Asm::BooleanNot,
+            Asm::BooleanXor,
+            Asm::SendMessage,
+            Asm::IntoReference,
+            Asm::native_method("print", 1),
+        ]
+    }
+
+    #[test]
+    fn round_trips() {
+        let asm = every_instruction();
+        let text = to_jasm(&asm).unwrap();
+        assert_eq!(parse_jasm(&text).unwrap(), asm, "{}", text);
+        assert_eq!(to_jasm(&parse_jasm(&text).unwrap()).unwrap(), text);
+    }
+
+    #[test]
+    fn hand_written() {
+        let text = r#"
+            ; the answer
+            pub main:   ; comments end at the end of the line
+                push { "values": [1, 2.5, "three",], "nested": {} }
+                push ref fn "std::print"
+                native_method print 1
+                push asm {
+                    return
+                }
+                cond_goto +2
+                goto main
+        "#;
+        let asm = parse_jasm(text).unwrap();
+        assert_eq!(
+            asm,
+            vec![
+                Asm::pub_label("main"),
+                Asm::push([
+                    (
+                        "values",
+                        Value::from(vec![
+                            Value::Integer(1),
+                            Value::Float(2.5),
+                            Value::from("three")
+                        ])
+                    ),
+                    ("nested", Value::new_dict()),
+                ]),
+                Asm::push(Value::location("std::print").into_reference()),
+                Asm::native_method("print", 1),
+                Asm::push(vec![Asm::Return]),
+                Asm::CondGoto(AsmLocation::InstructionDiff(2)),
+                Asm::goto("main"),
+            ]
+        );
+    }
+
+    #[test]
+    fn syntax_errors_have_positions() {
+        let error = |text: &str| match parse_jasm(text).unwrap_err().error_type {
+            JodinErrorType::JasmSyntaxError { line, column, .. } => (line, column),
+            other => panic!("expected a syntax error, got {}", other),
+        };
+        assert_eq!(error("pub main:\n    jump main"), (2, 5));
+        assert_eq!(error("push [1, 2"), (1, 11));
+        assert_eq!(error("  push \"unterminated"), (1, 8));
+        assert_eq!(error("set_var -1"), (1, 9));
+        assert_eq!(error("push 12x"), (1, 6));
+    }
+}
@@ -1,5 +1,6 @@
 use crate::assembly::instructions::{Asm, Assembly, Bytecode, Encode};
 use crate::assembly::location::AsmLocation;
+use crate::assembly::native_handle::NativeHandle;
 use crate::core::literal::Literal;
 
 use crate::error::{JodinError, JodinResult};
@@ -29,6 +30,10 @@ pub enum Value {
     /// is a reference to the actual virtual machine. When used as the value of an entry of an
     /// attribute that's being checked for, this means to pretend that there's no entry at all.
     Native,
+    /// A resource owned by a plugin. Messages sent to it are handled by the owning plugin. Can't
+    /// be serialized.
+    #[serde(skip)]
+    NativeHandle(NativeHandle),
 }
 
 #[derive(Debug, Clone, PartialEq)]
@@ -148,6 +153,9 @@ impl Display for Value {
             Value::Native => {
                 write!(f, "NATIVE")
             }
+            Value::NativeHandle(handle) => {
+                write!(f, "{}", handle)
+            }
         }
     }
 }
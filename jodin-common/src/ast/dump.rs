@@ -0,0 +1,590 @@
+//! Dumps of [JodinNode] trees as JSON and as Graphviz DOT, so that trees can be inspected and
+//! checked by other tools.
+//!
+//! # JSON schema
+//!
+//! A dump is a document of the form
+//!
+//! ```json
+//! { "schema": 1, "source": "main.jodin", "root": <node> }
+//! ```
+//!
+//! where `source` is `null` when the tree didn't come from a file. Every node has the form
+//!
+//! ```json
+//! {
+//!     "kind": "FunctionDefinition",
+//!     "attributes": { "return_type": "int" },
+//!     "children": { "name": <node>, "arguments": [<node>], "block": <node> },
+//!     "tags": { "resolved_id": "main", "visibility": "public", "other": [] }
+//! }
+//! ```
+//!
+//! - `kind` is the name of the [JodinNodeType] variant.
+//! - `value` is only present for `Type`, `Literal` and `Identifier` nodes.
+//! - `attributes` are the fields of the variant that aren't nodes, written as strings.
+//! - `children` are the fields of the variant that are nodes. Each one is a node, `null` for a
+//!   missing optional node, or a list of nodes. The `fields_and_values` of a `StructInitializer`
+//!   are a list of `[field, value]` pairs.
+//! - `tags` has a key for each of the well known tags on the node: `resolved_id`, `block_id`,
+//!   `type`, `visibility`, `source_line` and `labels`. The types of the rest of the tags are listed
+//!   in `other`, except for the bookkeeping tags that every node has.
+//!
+//! Keys are only ever added to this schema. A change that removes or changes the meaning of a key
+//! increases [SCHEMA_VERSION].
+
+use crate::ast::{CompoundType, JodinNode, JodinNodeType};
+use crate::core::privacy::VisibilityTag;
+use crate::core::tags::{
+    BlockIdentifierTag, LabeledStatementTag, ResolvedIdentityTag, SourceLineTag,
+};
+use crate::error::JodinResult;
+use crate::types::TypeTag;
+use serde_json::{json, Map, Value};
+use std::fmt::Write;
+use std::path::Path;
+
+/// The version of the JSON schema of dumps
+pub const SCHEMA_VERSION: u32 = 1;
+
+/// The types of the tags that have their own key in the `tags` of a node
+const WELL_KNOWN_TAGS: &[&str] = &[
+    "ResolvedId",
+    "BlockNum",
+    "Type",
+    "VisibilityTag",
+    "source_line",
+    "labeled_statement",
+];
+
+/// The types of the tags that every node has, which aren't dumped
+const BOOKKEEPING_TAGS: &[&str] = &["ExtraProperties", "[NodeRef]"];
+
+/// Creates the JSON document of a tree, which records the source file it came from
+pub fn to_json_document(tree: &JodinNode, source: Option<&Path>) -> Value {
+    json!({
+        "schema": SCHEMA_VERSION,
+        "source": source.map(|path| path.to_string_lossy().into_owned()),
+        "root": to_json(tree),
+    })
+}
+
+/// Creates the JSON representation of a single node and its children
+pub fn to_json(node: &JodinNode) -> Value {
+    let mut attributes = Map::new();
+    let mut children = Map::new();
+    let mut value = None;
+
+    let mut attribute = |name: &str, attr: String| {
+        attributes.insert(name.to_string(), Value::String(attr));
+    };
+    let mut child = |name: &str, node: Value| {
+        children.insert(name.to_string(), node);
+    };
+    let optional = |node: &Option<JodinNode>| node.as_ref().map_or(Value::Null, to_json);
+    let list = |nodes: &Vec<JodinNode>| Value::Array(nodes.iter().map(to_json).collect());
+
+    let kind = match node.inner() {
+        JodinNodeType::Type(ty) => {
+            value = Some(ty.to_string());
+            "Type"
+        }
+        JodinNodeType::Literal(literal) => {
+            value = Some(literal.to_string());
+            "Literal"
+        }
+        JodinNodeType::Identifier(id) => {
+            value = Some(id.to_string());
+            "Identifier"
+        }
+        JodinNodeType::VarDeclarations {
+            var_type,
+            names,
+            values,
+        } => {
+            attribute("var_type", var_type.to_string());
+            child("names", list(names));
+            child(
+                "values",
+                Value::Array(values.iter().map(optional).collect()),
+            );
+            "VarDeclarations"
+        }
+        JodinNodeType::StoreVariable {
+            storage_type,
+            name,
+            var_type,
+            maybe_initial_value,
+        } => {
+            attribute("storage_type", format!("{:?}", storage_type));
+            attribute("var_type", var_type.to_string());
+            child("name", to_json(name));
+            child("maybe_initial_value", optional(maybe_initial_value));
+            "StoreVariable"
+        }
+        JodinNodeType::FunctionDefinition {
+            name,
+            return_type,
+            arguments,
+            block,
+        } => {
+            attribute("return_type", return_type.to_string());
+            child("name", to_json(name));
+            child("arguments", list(arguments));
+            child("block", to_json(block));
+            "FunctionDefinition"
+        }
+        JodinNodeType::FunctionSignature {
+            name,
+            return_type,
+            arguments,
+        } => {
+            attribute("return_type", return_type.to_string());
+            child("name", to_json(name));
+            child("arguments", list(arguments));
+            "FunctionSignature"
+        }
+        JodinNodeType::ExternDeclaration { declaration } => {
+            child("declaration", to_json(declaration));
+            "ExternDeclaration"
+        }
+        JodinNodeType::Block { expressions } => {
+            child("expressions", list(expressions));
+            "Block"
+        }
+        JodinNodeType::CompoundTypeDefinition {
+            compound_type,
+            name,
+            inheritance,
+            members,
+        } => {
+            let compound_type = match compound_type {
+                CompoundType::Structure => "struct",
+                CompoundType::Trait => "trait",
+                CompoundType::Class => "class",
+            };
+            attribute("compound_type", compound_type.to_string());
+            child("name", to_json(name));
+            child("inheritance", optional(inheritance));
+            child("members", list(members));
+            "CompoundTypeDefinition"
+        }
+        JodinNodeType::NamedValue { name, var_type } => {
+            attribute("var_type", var_type.to_string());
+            child("name", to_json(name));
+            "NamedValue"
+        }
+        JodinNodeType::Uniop { op, inner } => {
+            attribute("op", op.to_string());
+            child("inner", to_json(inner));
+            "Uniop"
+        }
+        JodinNodeType::CastExpression { to_type, factor } => {
+            attribute("to_type", to_type.to_string());
+            child("factor", to_json(factor));
+            "CastExpression"
+        }
+        JodinNodeType::Postop { op, inner } => {
+            attribute("op", op.to_string());
+            child("inner", to_json(inner));
+            "Postop"
+        }
+        JodinNodeType::Binop { op, lhs, rhs } => {
+            attribute("op", op.to_string());
+            child("lhs", to_json(lhs));
+            child("rhs", to_json(rhs));
+            "Binop"
+        }
+        JodinNodeType::Ternary { cond, yes, no } => {
+            child("cond", to_json(cond));
+            child("yes", to_json(yes));
+            child("no", to_json(no));
+            "Ternary"
+        }
+        JodinNodeType::Index {
+            indexed,
+            expression,
+        } => {
+            child("indexed", to_json(indexed));
+            child("expression", to_json(expression));
+            "Index"
+        }
+        JodinNodeType::Call {
+            called,
+            generics_instance,
+            arguments,
+        } => {
+            child("called", to_json(called));
+            child("generics_instance", list(generics_instance));
+            child("arguments", list(arguments));
+            "Call"
+        }
+        JodinNodeType::GetMember { compound, id } => {
+            child("compound", to_json(compound));
+            child("id", to_json(id));
+            "GetMember"
+        }
+        JodinNodeType::TopLevelDeclarations { decs } => {
+            child("decs", list(decs));
+            "TopLevelDeclarations"
+        }
+        JodinNodeType::InNamespace { namespace, inner } => {
+            child("namespace", to_json(namespace));
+            child("inner", to_json(inner));
+            "InNamespace"
+        }
+        JodinNodeType::ImportIdentifiers {
+            import_data,
+            affected,
+        } => {
+            attribute("import_data", format!("{:?}", import_data));
+            child("affected", to_json(affected));
+            "ImportIdentifiers"
+        }
+        JodinNodeType::NodeVector { vec } => {
+            child("vec", list(vec));
+            "NodeVector"
+        }
+        JodinNodeType::ReturnValue { expression } => {
+            child("expression", optional(expression));
+            "ReturnValue"
+        }
+        JodinNodeType::Continue => "Continue",
+        JodinNodeType::Break { id } => {
+            if let Some(id) = id {
+                attribute("id", id.to_string());
+            }
+            "Break"
+        }
+        JodinNodeType::Empty => "Empty",
+        JodinNodeType::Super => "Super",
+        JodinNodeType::ConstructorCall {
+            name,
+            generic_parameters,
+            arguments,
+        } => {
+            attribute("name", name.to_string());
+            let generics = generic_parameters
+                .iter()
+                .map(ToString::to_string)
+                .collect::<Vec<_>>();
+            attribute("generic_parameters", generics.join(", "));
+            child("arguments", list(arguments));
+            "ConstructorCall"
+        }
+        JodinNodeType::Unimplemented {
+            jodin_rule,
+            affected_string,
+        } => {
+            attribute("jodin_rule", jodin_rule.clone());
+            attribute("affected_string", affected_string.clone());
+            "Unimplemented"
+        }
+        JodinNodeType::Dereference { node } => {
+            child("node", to_json(node));
+            "Dereference"
+        }
+        JodinNodeType::GetReference { node } => {
+            child("node", to_json(node));
+            "GetReference"
+        }
+        JodinNodeType::StructInitializer {
+            struct_id,
+            fields_and_values,
+        } => {
+            child("struct_id", to_json(struct_id));
+            let pairs = fields_and_values
+                .iter()
+                .map(|(field, value)| json!([to_json(field), to_json(value)]))
+                .collect();
+            child("fields_and_values", Value::Array(pairs));
+            "StructInitializer"
+        }
+        JodinNodeType::IfStatement {
+            cond,
+            statement,
+            else_statement,
+        } => {
+            child("cond", to_json(cond));
+            child("statement", to_json(statement));
+            child("else_statement", optional(else_statement));
+            "IfStatement"
+        }
+        JodinNodeType::WhileStatement { cond, statement } => {
+            child("cond", to_json(cond));
+            child("statement", to_json(statement));
+            "WhileStatement"
+        }
+        JodinNodeType::ForStatement {
+            init,
+            cond,
+            delta,
+            statement,
+        } => {
+            child("init", optional(init));
+            child("cond", optional(cond));
+            child("delta", optional(delta));
+            child("statement", to_json(statement));
+            "ForStatement"
+        }
+        JodinNodeType::SwitchStatement {
+            to_switch,
+            labeled_statements,
+        } => {
+            child("to_switch", to_json(to_switch));
+            child("labeled_statements", list(labeled_statements));
+            "SwitchStatement"
+        }
+        JodinNodeType::DoStatement { statement, cond } => {
+            child("statement", to_json(statement));
+            child("cond", to_json(cond));
+            "DoStatement"
+        }
+        JodinNodeType::AssignmentExpression {
+            maybe_assignment_operator,
+            lhs,
+            rhs,
+        } => {
+            if let Some(op) = maybe_assignment_operator {
+                attribute("maybe_assignment_operator", op.to_string());
+            }
+            child("lhs", to_json(lhs));
+            child("rhs", to_json(rhs));
+            "AssignmentExpression"
+        }
+        JodinNodeType::NewPointer { inner } => {
+            child("inner", to_json(inner));
+            "NewPointer"
+        }
+        JodinNodeType::Case { case, statement } => {
+            child("case", optional(case));
+            child("statement", to_json(statement));
+            "Case"
+        }
+        JodinNodeType::RepeatedArrayInitializer { to_repeat, repeats } => {
+            child("to_repeat", to_json(to_repeat));
+            child("repeats", to_json(repeats));
+            "RepeatedArrayInitializer"
+        }
+        JodinNodeType::ListInitializer { values } => {
+            child("values", list(values));
+            "ListInitializer"
+        }
+    };
+
+    let mut object = Map::new();
+    object.insert("kind".to_string(), Value::String(kind.to_string()));
+    if let Some(value) = value {
+        object.insert("value".to_string(), Value::String(value));
+    }
+    object.insert("attributes".to_string(), Value::Object(attributes));
+    object.insert("children".to_string(), Value::Object(children));
+    object.insert("tags".to_string(), tags_to_json(node));
+    Value::Object(object)
+}
+
+fn tags_to_json(node: &JodinNode) -> Value {
+    let mut tags = Map::new();
+    if let Ok(tag) = node.get_tag::<ResolvedIdentityTag>() {
+        tags.insert(
+            "resolved_id".to_string(),
+            json!(tag.absolute_id().to_string()),
+        );
+    }
+    if let Ok(tag) = node.get_tag::<BlockIdentifierTag>() {
+        tags.insert("block_id".to_string(), json!(tag.block_num()));
+    }
+    if let Ok(tag) = node.get_tag::<TypeTag>() {
+        tags.insert("type".to_string(), json!(tag.jodin_type().to_string()));
+    }
+    if let Ok(tag) = node.get_tag::<VisibilityTag>() {
+        tags.insert(
+            "visibility".to_string(),
+            json!(tag.visibility().to_string()),
+        );
+    }
+    if let Ok(tag) = node.get_tag::<SourceLineTag>() {
+        tags.insert("source_line".to_string(), json!(tag.line));
+    }
+    let labels = node
+        .get_tags::<LabeledStatementTag>()
+        .into_iter()
+        .map(|tag| tag.label.clone())
+        .collect::<Vec<_>>();
+    if !labels.is_empty() {
+        tags.insert("labels".to_string(), json!(labels));
+    }
+    let mut other = node
+        .tags()
+        .iter()
+        .map(|tag| tag.tag_type())
+        .filter(|tag_type| {
+            !WELL_KNOWN_TAGS.contains(&tag_type.as_str())
+                && !BOOKKEEPING_TAGS.contains(&tag_type.as_str())
+        })
+        .collect::<Vec<_>>();
+    other.sort();
+    tags.insert("other".to_string(), json!(other));
+    Value::Object(tags)
+}
+
+/// Creates a Graphviz DOT graph of a tree. Each node is labeled with its kind, value, attributes
+/// and well known tags, and each edge is labeled with the field of the parent it belongs to.
+pub fn to_dot(tree: &JodinNode) -> String {
+    let mut dot = String::from("digraph ast {\n    node [shape=box, fontname=\"monospace\"];\n");
+    let mut next_id = 0;
+    write_dot_node(&to_json(tree), &mut dot, &mut next_id);
+    dot.push_str("}\n");
+    dot
+}
+
+/// Writes a node in the JSON representation and its children, returning the id of the node
+fn write_dot_node(node: &Value, dot: &mut String, next_id: &mut usize) -> usize {
+    let id = *next_id;
+    *next_id += 1;
+
+    let mut lines = vec![node["kind"].as_str().unwrap_or_default().to_string()];
+    if let Some(value) = node["value"].as_str() {
+        lines.push(value.to_string());
+    }
+    for (name, value) in node["attributes"].as_object().into_iter().flatten() {
+        lines.push(format!("{}: {}", name, value.as_str().unwrap_or_default()));
+    }
+    for (name, value) in node["tags"].as_object().into_iter().flatten() {
+        match value {
+            Value::String(value) => lines.push(format!("@{}: {}", name, value)),
+            Value::Number(value) => lines.push(format!("@{}: {}", name, value)),
+            _ => {}
+        }
+    }
+    let label = lines
+        .iter()
+        .map(|line| escape_dot(line))
+        .collect::<Vec<_>>()
+        .join("\\n");
+    let _ = writeln!(dot, "    n{} [label=\"{}\"];", id, label);
+
+    for (field, child) in node["children"].as_object().into_iter().flatten() {
+        match child {
+            Value::Object(_) => write_dot_edge(id, field, child, dot, next_id),
+            Value::Array(nodes) => {
+                for (index, child) in nodes.iter().enumerate() {
+                    let field = format!("{}[{}]", field, index);
+                    match child {
+                        Value::Array(pair) => {
+                            for child in pair {
+                                write_dot_edge(id, &field, child, dot, next_id);
+                            }
+                        }
+                        Value::Object(_) => write_dot_edge(id, &field, child, dot, next_id),
+                        _ => {}
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+    id
+}
+
+fn write_dot_edge(
+    parent: usize,
+    field: &str,
+    child: &Value,
+    dot: &mut String,
+    next_id: &mut usize,
+) {
+    let child = write_dot_node(child, dot, next_id);
+    let _ = writeln!(
+        dot,
+        "    n{} -> n{} [label=\"{}\"];",
+        parent,
+        child,
+        escape_dot(field)
+    );
+}
+
+fn escape_dot(string: &str) -> String {
+    string.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+/// Writes the JSON and DOT dumps of a tree next to each other, at `<base>.json` and `<base>.dot`.
+/// The directory of the dumps is created if it doesn't exist.
+pub fn write_dumps<P: AsRef<Path>>(
+    tree: &JodinNode,
+    source: Option<&Path>,
+    base: P,
+) -> JodinResult<()> {
+    let base = base.as_ref();
+    if let Some(parent) = base.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    let json = serde_json::to_string_pretty(&to_json_document(tree, source))
+        .map_err(|e| anyhow::anyhow!(e))?;
+    let mut path = base.as_os_str().to_os_string();
+    path.push(".json");
+    std::fs::write(&path, json)?;
+    let mut path = base.as_os_str().to_os_string();
+    path.push(".dot");
+    std::fs::write(&path, to_dot(tree))?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::privacy::Visibility;
+    use crate::identifier::Identifier;
+    use crate::parsing::parse_program;
+
+    #[test]
+    fn json_schema() {
+        let mut tree = parse_program("fn main() -> int { return 1 + 2; }").unwrap();
+        tree.add_tag(VisibilityTag::new(Visibility::Public))
+            .unwrap();
+        tree.add_tag(ResolvedIdentityTag::new(Identifier::from("main")))
+            .unwrap();
+
+        let document = to_json_document(&tree, Some(Path::new("main.jodin")));
+        assert_eq!(document["schema"], json!(SCHEMA_VERSION));
+        assert_eq!(document["source"], json!("main.jodin"));
+
+        let root = &document["root"];
+        assert_eq!(root["tags"]["visibility"], json!("public"));
+        assert_eq!(root["tags"]["resolved_id"], json!("main"));
+        assert_eq!(root["tags"]["other"], json!([]));
+        let function = match root["kind"].as_str() {
+            Some("FunctionDefinition") => root,
+            _ => &root["children"]["decs"][0],
+        };
+        assert_eq!(function["kind"], json!("FunctionDefinition"));
+        assert_eq!(function["children"]["name"]["value"], json!("main"));
+        assert_eq!(function["attributes"]["return_type"], json!("int"));
+
+        let ret = &function["children"]["block"]["children"]["expressions"][0];
+        assert_eq!(ret["kind"], json!("ReturnValue"));
+        let sum = &ret["children"]["expression"];
+        assert_eq!(sum["kind"], json!("Binop"));
+        assert_eq!(sum["children"]["lhs"]["value"], json!("1"));
+    }
+
+    #[test]
+    fn dot_graph() {
+        let tree = parse_program("fn main() -> string { return \"a\"; }").unwrap();
+        let dot = to_dot(&tree);
+        assert!(dot.starts_with("digraph ast {"));
+        assert!(dot.trim_end().ends_with('}'));
+        assert!(dot.contains("FunctionDefinition"));
+        assert!(dot.contains("[label=\"block\"]"));
+        // every quote within a label is escaped
+        for line in dot.lines().filter(|line| line.contains("label=")) {
+            let label = line.split("label=\"").nth(1).unwrap();
+            let unescaped = label
+                .replace("\\\\", "")
+                .replace("\\\"", "")
+                .matches('"')
+                .count();
+            assert_eq!(unescaped, 1, "{}", line);
+        }
+    }
+}
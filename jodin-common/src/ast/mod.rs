@@ -14,6 +14,7 @@ pub use crate::ast::node_type::{CompoundType, JodinNodeType};
 use crate::error::JodinResult;
 use crate::identifier::Identifier;
 
+pub mod dump;
 mod jodin_node;
 mod node_type;
 
@@ -2,16 +2,15 @@
 //! experimental features
 
 use std::iter::FromIterator;
-use std::path::{Path, PathBuf};
+use std::path::{Component, Path, PathBuf};
 
 /// Compilation settings
 #[derive(Debug)]
 pub struct CompilationSettings {
-    /// Whether to output the parse tree as a ".pt" file
-    pub output_parse_tree: bool,
-    /// Whether to output the abstract syntax tree as a ".ast" file
+    /// Whether to output the abstract syntax tree of each file as ".ast.json" and ".ast.dot" files
     pub output_ast: bool,
-    /// Whether to output the final tagged syntax tree as a ".tast" file
+    /// Whether to output the final tagged syntax tree of each file as ".tast.json" and ".tast.dot"
+    /// files
     pub output_tast: bool,
     /// The directory to output files in
     pub target_directory: PathBuf,
@@ -29,7 +28,7 @@ impl CompilationSettings {
     /// # Example
     ///
     /// ```
-    /// use std::path::{Path, PathBuf};
+    /// use std::path::{Component, Path, PathBuf};
     /// use jodin_common::compilation_settings::CompilationSettings;
     /// use std::iter::FromIterator;
     /// let file = Path::new("file.txt");
@@ -49,6 +48,49 @@ impl CompilationSettings {
         ret
     }
 
+    /// The path that the [dumps](crate::ast::dump) of the tree of a source file are written to,
+    /// without the ".json" or ".dot" extension. The path of the source file, relative to the
+    /// working directory when it's within it, is mirrored under the target directory so that
+    /// sources with the same name in different directories don't overwrite each other's dumps.
+    /// Dumps are named after the file stem of the source file, followed by the kind of tree.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use std::path::PathBuf;
+    /// use jodin_common::compilation_settings::CompilationSettings;
+    /// let mut settings = CompilationSettings::default();
+    /// settings.target_directory = PathBuf::from("target");
+    /// assert_eq!(
+    ///     settings.tree_dump_path("src/main.jodin", "ast"),
+    ///     PathBuf::from("target").join("src").join("main.ast")
+    /// )
+    /// ```
+    pub fn tree_dump_path<P: AsRef<Path>>(&self, source: P, kind: &str) -> PathBuf {
+        let source = source.as_ref();
+        let relative = std::env::current_dir()
+            .ok()
+            .and_then(|working| source.strip_prefix(working).ok())
+            .unwrap_or(source);
+        // roots and parent directories are left out so that dumps stay in the target directory
+        let directory = relative
+            .parent()
+            .into_iter()
+            .flat_map(Path::components)
+            .filter_map(|component| match component {
+                Component::Normal(name) => Some(name),
+                _ => None,
+            })
+            .collect::<PathBuf>();
+        let stem = source
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        self.target_directory
+            .join(directory)
+            .join(format!("{}.{}", stem, kind))
+    }
+
     /// Creates a new settings instance.
     ///
     /// The default target directory is the working directory.
@@ -61,10 +103,36 @@ impl Default for CompilationSettings {
     /// The default target directory is the working directory
     fn default() -> Self {
         Self {
-            output_parse_tree: false,
             output_ast: false,
             output_tast: false,
             target_directory: std::env::current_dir().unwrap(),
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tree_dumps_mirror_sources() {
+        let settings = CompilationSettings {
+            target_directory: PathBuf::from("target"),
+            ..CompilationSettings::default()
+        };
+        let first = settings.tree_dump_path("a/util.jodin", "ast");
+        let second = settings.tree_dump_path("b/util.jodin", "ast");
+        assert_ne!(first, second);
+        assert_eq!(second, Path::new("target").join("b").join("util.ast"));
+
+        let working = std::env::current_dir().unwrap();
+        assert_eq!(
+            settings.tree_dump_path(working.join("src").join("main.jodin"), "tast"),
+            Path::new("target").join("src").join("main.tast")
+        );
+        assert_eq!(
+            settings.tree_dump_path("../main.jodin", "tast"),
+            Path::new("target").join("main.tast")
+        );
+    }
+}
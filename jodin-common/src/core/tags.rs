@@ -112,6 +112,39 @@ impl LabeledStatementTag {
     }
 }
 
+/// The line in the source file that a statement starts on
+#[derive(Debug)]
+pub struct SourceLineTag {
+    /// The line, starting at 1
+    pub line: u32,
+}
+
+impl SourceLineTag {
+    /// Create a new source line tag from the byte offset of a statement within its source
+    pub fn from_offset(source: &str, offset: usize) -> Self {
+        let line = source[..offset.min(source.len())].matches('\n').count() as u32 + 1;
+        SourceLineTag { line }
+    }
+}
+
+impl Tag for SourceLineTag {
+    fn tag_type(&self) -> String {
+        "source_line".to_string()
+    }
+
+    fn max_of_this_tag(&self) -> u32 {
+        1
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+}
+
 /// The extra properties is a tag that allows for adding arbitrary properties to a node.
 pub struct ExtraProperties {
     properties: HashMap<String, Box<dyn Any>>,
@@ -3,6 +3,7 @@
 //!
 //! [JodinError]: crate::core::error::JodinError
 
+use crate::asm_version::Version;
 use crate::identifier::{Identifier, Namespaced};
 
 use backtrace::Backtrace;
@@ -12,10 +13,12 @@ use std::char::ParseCharError;
 use std::error::Error;
 use std::fmt::{Display, Formatter};
 use std::num::{ParseFloatError, ParseIntError};
+use std::ops::RangeInclusive;
 use std::string::FromUtf8Error;
 
 use crate::ast::JodinNode;
 use crate::core::literal::Literal;
+use crate::linker::SymbolError;
 use thiserror::Error;
 
 /// The inner data type for the error that contains specific information required by the error.
@@ -139,9 +142,68 @@ pub enum JodinErrorType {
     /// The given tree type is invalid
     #[error("Invalid tree type given to compiler (expected: {0})")]
     InvalidTreeTypeGivenToCompiler(String),
+    /// An extern declaration doesn't match anything declared by an object or a plugin
+    #[error("extern {0} is not declared by any object or plugin")]
+    UnresolvedExtern(Identifier),
+    /// The type of an extern declaration is different from the type it was declared with
+    #[error("extern {id} has type {declared}, but it was declared as {expected}")]
+    ExternTypeMismatch {
+        /// The extern declaration
+        id: Identifier,
+        /// The type given to the extern declaration
+        declared: String,
+        /// The type the object or plugin declared
+        expected: String,
+    },
+    /// A function was called with the wrong amount of arguments
+    #[error("{function} takes {expected} arguments, but {found} were given")]
+    IncorrectArgumentCount {
+        /// The called function
+        function: Identifier,
+        /// The amount of parameters of the function
+        expected: usize,
+        /// The amount of arguments given
+        found: usize,
+    },
+    /// An argument given to a function has the wrong type
+    #[error("argument {index} of {function} must be {expected} (found: {found})")]
+    ArgumentTypeMismatch {
+        /// The called function
+        function: Identifier,
+        /// The index of the argument
+        index: usize,
+        /// The type of the parameter
+        expected: String,
+        /// The type of the argument
+        found: String,
+    },
     /// The given incremental unit is invalid in the current context
     #[error("Given invalid string for incremental compilation (string: {0:?})")]
     InvalidCompilationUnit(String),
+    /// A compiled object is corrupt, or isn't an object
+    #[error("Invalid object: {0}")]
+    InvalidObject(String),
+    /// Textual assembly couldn't be parsed
+    #[error("Invalid assembly at {line}:{column}: {message}")]
+    JasmSyntaxError {
+        /// The line of the error, starting at 1
+        line: usize,
+        /// The column of the error, starting at 1
+        column: usize,
+        /// What was wrong
+        message: String,
+    },
+    /// Compilation objects couldn't be linked
+    #[error("Linking failed: {}", .0.iter().map(ToString::to_string).collect::<Vec<_>>().join("; "))]
+    LinkFailed(Vec<SymbolError>),
+    /// An object or image is of an asm version that can't be upgraded
+    #[error("jodin asm {version} is not supported (supported: {} to {})", .supported.start(), .supported.end())]
+    UnsupportedAsmVersion {
+        /// The version of the object or image
+        version: Version,
+        /// The versions that can be loaded
+        supported: RangeInclusive<Version>,
+    },
     /// An anyhow produced error
     #[error(transparent)]
     AnyHowError(#[from] anyhow::Error),
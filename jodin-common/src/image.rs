@@ -0,0 +1,229 @@
+//! Executable images (`.jexe` files) are programs that were statically linked by the
+//! [linker](crate::linker) into a single piece of assembly.
+//!
+//! Unlike a set of compilation objects, the labels of an image are already known to be unique and
+//! are stored in a table, so an image can be loaded without searching its assembly for labels. The
+//! container of an image is described in the [object file](crate::object_file) module.
+
+use crate::asm_version::Version;
+use crate::assembly::debug_info::DebugInfo;
+use crate::assembly::instructions::Assembly;
+use crate::error::{JodinError, JodinResult};
+use crate::object_file::{ObjectFile, SectionKind, SectionReader, StringTable};
+use crate::upgrade::Upgrader;
+use anyhow::anyhow;
+use std::collections::BTreeMap;
+use std::ops::Range;
+use std::path::Path;
+
+/// The extension of executable images
+pub const IMAGE_EXTENSION: &str = "jexe";
+
+/// The line table of a module within an image
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ImageDebugInfo {
+    /// The instructions of the image that were compiled from the module
+    pub instructions: Range<usize>,
+    /// The line table of the module, relative to the start of its instructions
+    pub debug_info: DebugInfo,
+}
+
+/// A statically linked program
+#[derive(Debug, Clone, PartialEq)]
+pub struct ExecutableImage {
+    /// The label of the function that runs the program
+    pub entry_point: String,
+    /// The assembly of every module within the image
+    pub jasm: Assembly,
+    /// The instruction of every label within the assembly
+    pub labels: BTreeMap<String, usize>,
+    /// The instructions that static modules start at, which are run in order once the image is
+    /// loaded
+    pub initializers: Vec<usize>,
+    /// The line tables of the modules within the image
+    pub debug_info: Vec<ImageDebugInfo>,
+}
+
+impl ExecutableImage {
+    /// Creates an image, finding the labels of its assembly
+    pub fn new<S: Into<String>>(entry_point: S, jasm: Assembly) -> Self {
+        let labels = jasm
+            .iter()
+            .enumerate()
+            .filter_map(|(index, asm)| asm.defined_label().map(|label| (label.to_string(), index)))
+            .collect();
+        Self {
+            entry_point: entry_point.into(),
+            jasm,
+            labels,
+            initializers: vec![],
+            debug_info: vec![],
+        }
+    }
+
+    /// Writes the image in the [object file format](crate::object_file)
+    pub fn to_bytes(&self) -> JodinResult<Vec<u8>> {
+        let mut strings = StringTable::new();
+        strings.push(self.entry_point.clone());
+        let mut symbols = (self.labels.len() as u32).to_be_bytes().to_vec();
+        for (label, &index) in &self.labels {
+            symbols.extend_from_slice(&strings.push(label.clone()).to_be_bytes());
+            symbols.extend_from_slice(&(index as u64).to_be_bytes());
+        }
+        let mut initializers = (self.initializers.len() as u32).to_be_bytes().to_vec();
+        for &initializer in &self.initializers {
+            initializers.extend_from_slice(&(initializer as u64).to_be_bytes());
+        }
+
+        let mut image = ObjectFile::new(Version::CURRENT.to_magic_number());
+        image.add_section(SectionKind::Strings, strings.to_bytes());
+        image.add_section(SectionKind::Symbols, symbols);
+        image.add_section(SectionKind::Initializers, initializers);
+        let encoded = bincode::serialize(&self.jasm).map_err(|e| anyhow!(e))?;
+        image.add_section(SectionKind::Code, encoded);
+        if !self.debug_info.is_empty() {
+            let encoded = bincode::serialize(&self.debug_info).map_err(|e| anyhow!(e))?;
+            image.add_section(SectionKind::DebugInfo, encoded);
+        }
+        Ok(image.to_bytes())
+    }
+
+    /// Reads the image at a path
+    pub fn open<P: AsRef<Path>>(path: P) -> JodinResult<Self> {
+        let buffer = std::fs::read(path)?;
+        Self::try_from(&*buffer)
+    }
+
+    /// Writes the image to a path
+    pub fn write<P: AsRef<Path>>(&self, path: P) -> JodinResult<()> {
+        if let Some(parent) = path.as_ref().parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        std::fs::write(path, self.to_bytes()?)?;
+        Ok(())
+    }
+}
+
+impl TryFrom<&[u8]> for ExecutableImage {
+    type Error = JodinError;
+
+    fn try_from(value: &[u8]) -> Result<Self, Self::Error> {
+        let image = ObjectFile::from_bytes(value)?;
+        let upgrader = Upgrader::default();
+        let version = upgrader.version_of(image.magic_number)?;
+
+        let strings = StringTable::from_bytes(image.required_section(SectionKind::Strings)?)?;
+        let entry_point = strings.get(0)?.to_string();
+
+        let mut reader = SectionReader::new(image.required_section(SectionKind::Symbols)?);
+        let mut labels = BTreeMap::new();
+        for _ in 0..reader.read_u32()? {
+            let label = strings.get(reader.read_u32()?)?.to_string();
+            labels.insert(label, reader.read_u64()? as usize);
+        }
+        reader.finish()?;
+
+        let mut reader = SectionReader::new(image.required_section(SectionKind::Initializers)?);
+        let mut initializers = vec![];
+        for _ in 0..reader.read_u32()? {
+            initializers.push(reader.read_u64()? as usize);
+        }
+        reader.finish()?;
+
+        let jasm: Assembly = bincode::deserialize(image.required_section(SectionKind::Code)?)
+            .map_err(|e| anyhow!(e))?;
+        let jasm = upgrader.upgrade(version, jasm)?;
+        let debug_info = match image.section(SectionKind::DebugInfo) {
+            Some(debug_info) => bincode::deserialize(debug_info).map_err(|e| anyhow!(e))?,
+            None => vec![],
+        };
+        // the checksum doesn't cover the image being built wrong, so the indices into the
+        // assembly are checked before anything runs
+        let length = jasm.len();
+        if let Some((label, index)) = labels.iter().find(|(_, &index)| index >= length) {
+            return Err(anyhow!(
+                "label {label:?} is at instruction {index}, but the image has {length} instructions"
+            )
+            .into());
+        }
+        if let Some(initializer) = initializers.iter().find(|&&index| index >= length) {
+            return Err(anyhow!(
+                "initializer at instruction {initializer}, but the image has {length} instructions"
+            )
+            .into());
+        }
+        if let Some(module) = debug_info
+            .iter()
+            .find(|module: &&ImageDebugInfo| module.instructions.end > length)
+        {
+            return Err(anyhow!(
+                "line table of instructions {:?}, but the image has {length} instructions",
+                module.instructions
+            )
+            .into());
+        }
+        Ok(Self {
+            entry_point,
+            jasm,
+            labels,
+            initializers,
+            debug_info,
+        })
+    }
+}
+
+/// Whether a path is of an executable image
+pub fn is_image<P: AsRef<Path>>(path: P) -> bool {
+    path.as_ref()
+        .extension()
+        .is_some_and(|ext| ext == IMAGE_EXTENSION)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::assembly::instructions::Asm;
+    use std::path::PathBuf;
+
+    #[test]
+    fn image_round_trips() {
+        let mut image = ExecutableImage::new(
+            "main",
+            vec![
+                Asm::label("init"),
+                Asm::push(1u64),
+                Asm::Return,
+                Asm::pub_label("main"),
+                Asm::push(0u64),
+                Asm::Return,
+            ],
+        );
+        image.initializers.push(0);
+        image.debug_info.push(ImageDebugInfo {
+            instructions: 3..6,
+            debug_info: DebugInfo::new(Some(PathBuf::from("main.jodin")), vec![]),
+        });
+        assert_eq!(image.labels["main"], 3);
+
+        let read = ExecutableImage::try_from(&*image.to_bytes().unwrap()).unwrap();
+        assert_eq!(read, image);
+    }
+
+    #[test]
+    fn indices_outside_of_the_image_are_rejected() {
+        let image = ExecutableImage::new("main", vec![Asm::pub_label("main"), Asm::Return]);
+
+        let mut bad_label = image.clone();
+        bad_label.labels.insert("missing".to_string(), 2);
+        let mut bad_initializer = image.clone();
+        bad_initializer.initializers.push(5);
+        let mut bad_debug_info = image;
+        bad_debug_info.debug_info.push(ImageDebugInfo {
+            instructions: 0..3,
+            debug_info: DebugInfo::new(None, vec![]),
+        });
+        for image in [bad_label, bad_initializer, bad_debug_info] {
+            assert!(ExecutableImage::try_from(&*image.to_bytes().unwrap()).is_err());
+        }
+    }
+}
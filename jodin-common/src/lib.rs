@@ -51,9 +51,14 @@ pub mod compilation_settings;
 pub mod core;
 pub mod error;
 pub mod identifier;
+pub mod image;
+pub mod linker;
+pub mod object_file;
+pub mod package;
 pub mod parsing;
 pub mod types;
 pub mod unit;
+pub mod upgrade;
 pub mod utility;
 
 #[macro_use]
@@ -69,8 +74,18 @@ pub use simplelog::*;
 use std::fs::File;
 use std::sync::atomic::{AtomicBool, Ordering};
 
-/// Initializes logging for the package
+/// Initializes logging for the package, to the terminal and to a `compiler.log` file in the
+/// working directory
 pub fn init_logging(level: LevelFilter) {
+    init_loggers(level, true)
+}
+
+/// Initializes logging for the package to the terminal only, without writing a log file
+pub fn init_terminal_logging(level: LevelFilter) {
+    init_loggers(level, false)
+}
+
+fn init_loggers(level: LevelFilter, log_file: bool) {
     static LOGGING_INIT: AtomicBool = AtomicBool::new(false);
     if let Ok(false) =
         LOGGING_INIT.compare_exchange(false, true, Ordering::Acquire, Ordering::Relaxed)
@@ -81,28 +96,27 @@ pub fn init_logging(level: LevelFilter) {
             .set_thread_level(LevelFilter::Off)
             .build();
 
-        let log_file_config = ConfigBuilder::new()
-            .set_thread_mode(ThreadLogMode::Names)
-            .set_thread_level(LevelFilter::Error)
-            .set_location_level(LevelFilter::Error)
-            .set_target_level(LevelFilter::Off)
-            .set_level_padding(LevelPadding::Right)
-            .build();
-
-        CombinedLogger::init(vec![
-            TermLogger::new(
-                level,
-                log_term_config,
-                TerminalMode::Mixed,
-                ColorChoice::Auto,
-            ),
-            WriteLogger::new(
+        let mut loggers: Vec<Box<dyn SharedLogger>> = vec![TermLogger::new(
+            level,
+            log_term_config,
+            TerminalMode::Mixed,
+            ColorChoice::Auto,
+        )];
+        if log_file {
+            let log_file_config = ConfigBuilder::new()
+                .set_thread_mode(ThreadLogMode::Names)
+                .set_thread_level(LevelFilter::Error)
+                .set_location_level(LevelFilter::Error)
+                .set_target_level(LevelFilter::Off)
+                .set_level_padding(LevelPadding::Right)
+                .build();
+            loggers.push(WriteLogger::new(
                 level,
                 log_file_config,
                 File::create("compiler.log").expect("Could not open log file"),
-            ),
-        ])
-        .expect("Could not create logger");
+            ));
+        }
+        CombinedLogger::init(loggers).expect("Could not create logger");
     }
 }
 
@@ -0,0 +1,667 @@
+//! The linker merges compilation objects into a single [executable image](crate::image).
+//!
+//! Every label of the virtual machine is global, so the labels defined by the linked objects must
+//! be unique, and every label or symbol that they use must be defined by one of them, unless it's
+//! provided by a plugin once the program runs. All problems are reported at once, as a list of
+//! [symbol errors](SymbolError).
+//!
+//! Functions that aren't public, and that can't be reached from the entry point, public functions,
+//! or code outside of functions, are left out of the image. A function extends from its label to
+//! the next public label of its object.
+
+use crate::assembly::debug_info::{DebugInfo, LineEntry};
+use crate::assembly::instructions::Asm;
+use crate::assembly::location::AsmLocation;
+use crate::assembly::value::Value;
+use crate::core::privacy::Visibility;
+use crate::error::{JodinErrorType, JodinResult};
+use crate::image::{ExecutableImage, ImageDebugInfo};
+use crate::package::{collect_objects, is_package, Package};
+use crate::unit::CompilationObject;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use thiserror::Error;
+
+/// The function that runs a program, unless another is given to the linker
+pub const DEFAULT_ENTRY_POINT: &str = "main";
+
+/// A problem with the symbols of the linked objects
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum SymbolError {
+    /// A symbol is used, but isn't defined by any object or provided by a plugin
+    #[error("{symbol} is not defined (used by {})", .used_by.join(", "))]
+    Undefined {
+        /// The symbol
+        symbol: String,
+        /// The modules that use the symbol
+        used_by: Vec<String>,
+    },
+    /// A label is defined more than once
+    #[error("{symbol} is defined more than once (defined by {})", .defined_by.join(", "))]
+    Duplicate {
+        /// The label
+        symbol: String,
+        /// The modules that define the label
+        defined_by: Vec<String>,
+    },
+    /// The entry point isn't defined by any object
+    #[error("no {0} function was linked")]
+    MissingEntryPoint(String),
+    /// The entry point could be one of many functions within different namespaces
+    #[error("{entry} is ambiguous, found {}", .candidates.join(", "))]
+    AmbiguousEntryPoint {
+        /// The entry point given to the linker
+        entry: String,
+        /// The labels that the entry point could be
+        candidates: Vec<String>,
+    },
+}
+
+/// An object given to the linker
+#[derive(Debug)]
+struct LinkedModule {
+    name: PathBuf,
+    object: CompilationObject,
+}
+
+impl LinkedModule {
+    /// How the module is referred to in errors
+    fn describe(&self) -> String {
+        match self.object.module.is_empty() {
+            true => self.name.display().to_string(),
+            false => format!("{} ({})", self.object.module, self.name.display()),
+        }
+    }
+
+    /// Static modules are run entirely when they're loaded
+    fn is_static(&self) -> bool {
+        self.name
+            .file_name()
+            .and_then(|name| name.to_str())
+            .is_some_and(|name| name.starts_with("static"))
+    }
+
+    /// The functions of the module that can be left out of the image. The code of static modules
+    /// is run from start to end, so none of their functions are.
+    fn removable_functions(&self) -> Vec<Range<usize>> {
+        if self.is_static() {
+            return vec![];
+        }
+        let jasm = &self.object.jasm;
+        let mut functions = vec![];
+        for unit in &self.object.units {
+            if unit.vis == Visibility::Public {
+                continue;
+            }
+            let label = unit.name.to_string();
+            let start = match jasm
+                .iter()
+                .position(|asm| matches!(asm, Asm::PublicLabel(public) if public == &label))
+            {
+                Some(start) => start,
+                None => continue,
+            };
+            let end = jasm[start + 1..]
+                .iter()
+                .position(|asm| matches!(asm, Asm::PublicLabel(_)))
+                .map_or(jasm.len(), |offset| start + 1 + offset);
+            // only code produced by the compiler for a function is known to end where it does
+            let is_function = jasm[start..end]
+                .iter()
+                .any(|asm| matches!(asm, Asm::Label(label) if label.ends_with("__func_end__")));
+            if is_function {
+                functions.push(start..end);
+            }
+        }
+        functions
+    }
+}
+
+/// Links compilation objects into an [executable image](ExecutableImage)
+#[derive(Debug)]
+pub struct Linker {
+    modules: Vec<LinkedModule>,
+    entry_point: String,
+    external_symbols: HashSet<String>,
+}
+
+impl Default for Linker {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Linker {
+    /// Creates a linker without any objects, which links programs that start at `main`
+    pub fn new() -> Self {
+        Self {
+            modules: vec![],
+            entry_point: DEFAULT_ENTRY_POINT.to_string(),
+            external_symbols: HashSet::new(),
+        }
+    }
+
+    /// Sets the function that runs the program. The function can be within a namespace, as long
+    /// as only one namespace has a function of that name.
+    pub fn with_entry_point<S: AsRef<str>>(mut self, entry_point: S) -> Self {
+        self.entry_point = entry_point.as_ref().to_string();
+        self
+    }
+
+    /// Adds symbols that are provided once the program runs, such as the labels of plugins
+    pub fn with_external_symbols<S: AsRef<str>, I: IntoIterator<Item = S>>(
+        mut self,
+        symbols: I,
+    ) -> Self {
+        self.external_symbols.extend(
+            symbols
+                .into_iter()
+                .map(|symbol| symbol.as_ref().to_string()),
+        );
+        self
+    }
+
+    /// Adds an object to the end of the image
+    pub fn add_object(&mut self, object: CompilationObject) {
+        self.modules.push(LinkedModule {
+            name: object.file_location.clone(),
+            object,
+        });
+    }
+
+    /// Adds every object within a package to the end of the image
+    pub fn add_package(&mut self, package: Package) {
+        for entry in package.into_entries() {
+            self.modules.push(LinkedModule {
+                name: entry.name,
+                object: entry.object,
+            });
+        }
+    }
+
+    /// Adds the objects at a path, which is either an object, a package, or a directory that's
+    /// searched for objects
+    pub fn add_path<P: AsRef<Path>>(&mut self, path: P) -> JodinResult<()> {
+        let path = path.as_ref();
+        if path.is_dir() {
+            let mut objects = vec![];
+            collect_objects(path, &mut objects)?;
+            objects.sort();
+            for object in objects {
+                self.add_object(CompilationObject::try_from(object.as_path())?);
+            }
+        } else if is_package(path) {
+            self.add_package(Package::open(path)?);
+        } else {
+            self.add_object(CompilationObject::try_from(path)?);
+        }
+        Ok(())
+    }
+
+    /// Links the objects, in the order they were added, into an image. The static objects
+    /// (`static*.jobj`) of the image are run in the same order once it's loaded.
+    ///
+    /// # Error
+    ///
+    /// Will return a [`LinkFailed`](JodinErrorType::LinkFailed) error listing every label that's
+    /// defined more than once and every symbol that isn't defined, or if the entry point can't be
+    /// found.
+    pub fn link(self) -> JodinResult<ExecutableImage> {
+        let references = self
+            .modules
+            .iter()
+            .map(|module| CrossReferences::new(&module.object.jasm))
+            .collect::<Vec<_>>();
+        let entry_point = self
+            .check_symbols(&references)
+            .map_err(JodinErrorType::LinkFailed)?;
+        let kept = self.kept_instructions(&references, &entry_point);
+
+        let mut jasm = vec![];
+        let mut initializers = vec![];
+        let mut debug_info = vec![];
+        for (module, kept) in self.modules.into_iter().zip(kept) {
+            let start = jasm.len();
+            let is_static = module.is_static();
+            let mut new_indices = vec![None; kept.len()];
+            for ((index, asm), kept) in module.object.jasm.into_iter().enumerate().zip(kept) {
+                if kept {
+                    new_indices[index] = Some(jasm.len() - start);
+                    jasm.push(asm);
+                }
+            }
+            if is_static && jasm.len() > start {
+                initializers.push(start);
+            }
+            if let Some(module_debug_info) = module.object.debug_info {
+                let lines = module_debug_info
+                    .lines
+                    .iter()
+                    .filter_map(|entry| {
+                        Some(LineEntry {
+                            pc: new_indices.get(entry.pc).copied().flatten()?,
+                            line: entry.line,
+                        })
+                    })
+                    .collect();
+                debug_info.push(ImageDebugInfo {
+                    instructions: start..jasm.len(),
+                    debug_info: DebugInfo::new(module_debug_info.source, lines),
+                });
+            }
+        }
+
+        let mut image = ExecutableImage::new(entry_point, jasm);
+        image.initializers = initializers;
+        image.debug_info = debug_info;
+        Ok(image)
+    }
+
+    /// Checks that every label is defined once and every used symbol is defined, getting the label
+    /// of the entry point
+    fn check_symbols(&self, references: &[CrossReferences]) -> Result<String, Vec<SymbolError>> {
+        let mut definitions = BTreeMap::<&str, Vec<usize>>::new();
+        for (index, module) in self.modules.iter().enumerate() {
+            for label in module.object.jasm.iter().filter_map(Asm::defined_label) {
+                definitions.entry(label).or_default().push(index);
+            }
+        }
+
+        let mut errors = vec![];
+        for (&label, modules) in &definitions {
+            // labels starting with `@@` are placeholders, which can be replaced
+            if modules.len() > 1 && !label.starts_with("@@") {
+                errors.push(SymbolError::Duplicate {
+                    symbol: label.to_string(),
+                    defined_by: modules
+                        .iter()
+                        .map(|&index| self.modules[index].describe())
+                        .collect(),
+                });
+            }
+        }
+
+        let mut undefined = BTreeMap::<&str, Vec<String>>::new();
+        for (module, references) in self.modules.iter().zip(references) {
+            for (symbol, _) in references.external() {
+                if !definitions.contains_key(symbol) && !self.external_symbols.contains(symbol) {
+                    undefined.entry(symbol).or_default().push(module.describe());
+                }
+            }
+        }
+        errors.extend(
+            undefined
+                .into_iter()
+                .map(|(symbol, used_by)| SymbolError::Undefined {
+                    symbol: symbol.to_string(),
+                    used_by,
+                }),
+        );
+
+        let entry_point = self.find_entry_point(definitions.keys().copied());
+        match entry_point {
+            Ok(entry_point) if errors.is_empty() => Ok(entry_point),
+            Ok(_) => Err(errors),
+            Err(error) => {
+                errors.push(error);
+                Err(errors)
+            }
+        }
+    }
+
+    /// Finds the label of the entry point, which can be within a namespace
+    fn find_entry_point<'a, I: IntoIterator<Item = &'a str>>(
+        &self,
+        labels: I,
+    ) -> Result<String, SymbolError> {
+        let entry = &self.entry_point;
+        let namespaced = format!("::{entry}");
+        let mut candidates = vec![];
+        for label in labels {
+            if label == entry {
+                return Ok(label.to_string());
+            }
+            if label.ends_with(&namespaced) {
+                candidates.push(label.to_string());
+            }
+        }
+        match candidates.len() {
+            0 => Err(SymbolError::MissingEntryPoint(entry.clone())),
+            1 => Ok(candidates.remove(0)),
+            _ => Err(SymbolError::AmbiguousEntryPoint {
+                entry: entry.clone(),
+                candidates,
+            }),
+        }
+    }
+
+    /// Whether each instruction of each module is kept in the image. Removable functions are only
+    /// kept when they can be reached from the entry point or from code that's always kept.
+    fn kept_instructions(
+        &self,
+        references: &[CrossReferences],
+        entry_point: &str,
+    ) -> Vec<Vec<bool>> {
+        let mut functions = vec![];
+        let mut owners = vec![];
+        let mut function_of_label = HashMap::new();
+        for (module_index, module) in self.modules.iter().enumerate() {
+            let mut owner = vec![None; module.object.jasm.len()];
+            for instructions in module.removable_functions() {
+                for index in instructions.clone() {
+                    owner[index] = Some(functions.len());
+                    if let Some(label) = module.object.jasm[index].defined_label() {
+                        function_of_label.insert(label, functions.len());
+                    }
+                }
+                functions.push((module_index, instructions));
+            }
+            owners.push(owner);
+        }
+
+        let mut function_uses = vec![vec![]; functions.len()];
+        let mut pending = vec![entry_point];
+        for (owner, references) in owners.iter().zip(references) {
+            for (label, symbol) in references.iter() {
+                for &index in &symbol.uses {
+                    match owner[index] {
+                        Some(function) => function_uses[function].push(label),
+                        None => pending.push(label),
+                    }
+                }
+            }
+        }
+
+        let mut reached = vec![false; functions.len()];
+        while let Some(label) = pending.pop() {
+            if let Some(&function) = function_of_label.get(label) {
+                if !reached[function] {
+                    reached[function] = true;
+                    pending.extend(function_uses[function].iter().copied());
+                }
+            }
+        }
+
+        owners
+            .into_iter()
+            .map(|owner| {
+                owner
+                    .into_iter()
+                    .map(|function| function.is_none_or(|function| reached[function]))
+                    .collect()
+            })
+            .collect()
+    }
+}
+
+/// Where a label is defined, and the instructions that refer to it
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct SymbolReferences {
+    /// The index of the instruction that defines the label, if it's defined by this assembly
+    pub definition: Option<usize>,
+    /// Whether the label is public
+    pub public: bool,
+    /// The indices of the instructions that refer to the label
+    pub uses: Vec<usize>,
+}
+
+impl SymbolReferences {
+    /// Whether the label is defined by the same assembly that uses it
+    pub fn is_local(&self) -> bool {
+        self.definition.is_some()
+    }
+}
+
+/// The labels that some assembly defines, and the labels and symbols that it refers to through
+/// jumps, calls, function values and `get_symbol`.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct CrossReferences {
+    symbols: BTreeMap<String, SymbolReferences>,
+}
+
+impl CrossReferences {
+    /// Finds the cross-references of some assembly
+    pub fn new(jasm: &[Asm]) -> Self {
+        let mut references = Self::default();
+        for (index, asm) in jasm.iter().enumerate() {
+            match asm {
+                Asm::Label(label) => references.define(label, index, false),
+                Asm::PublicLabel(label) => references.define(label, index, true),
+                Asm::Goto(location) | Asm::CondGoto(location) | Asm::Call(location) => {
+                    references.refer_to_location(location, index)
+                }
+                Asm::GetSymbol(symbol) => references.refer(symbol, index),
+                Asm::Push(value) => references.refer_to_value(value, index),
+                _ => {}
+            }
+        }
+        references
+    }
+
+    fn define(&mut self, label: &str, index: usize, public: bool) {
+        let symbol = self.symbols.entry(label.to_string()).or_default();
+        if symbol.definition.is_none() {
+            symbol.definition = Some(index);
+            symbol.public = public;
+        }
+    }
+
+    fn refer(&mut self, label: &str, index: usize) {
+        let symbol = self.symbols.entry(label.to_string()).or_default();
+        if symbol.uses.last() != Some(&index) {
+            symbol.uses.push(index);
+        }
+    }
+
+    fn refer_to_location(&mut self, location: &AsmLocation, index: usize) {
+        if let AsmLocation::Label(label) = location {
+            self.refer(label, index);
+        }
+    }
+
+    fn refer_to_value(&mut self, value: &Value, index: usize) {
+        match value {
+            Value::Function(location) => self.refer_to_location(location, index),
+            Value::Array(values) => {
+                for value in values {
+                    self.refer_to_value(value, index);
+                }
+            }
+            Value::Dictionary(dictionary) => {
+                for value in dictionary.values() {
+                    self.refer_to_value(value, index);
+                }
+            }
+            Value::Reference(reference) => self.refer_to_value(&reference.borrow(), index),
+            _ => {}
+        }
+    }
+
+    /// Every label that's defined or used, sorted by name
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &SymbolReferences)> {
+        self.symbols
+            .iter()
+            .map(|(label, symbol)| (label.as_str(), symbol))
+    }
+
+    /// The references of a label
+    pub fn get(&self, label: &str) -> Option<&SymbolReferences> {
+        self.symbols.get(label)
+    }
+
+    /// The labels defined by the assembly, sorted by name
+    pub fn local(&self) -> impl Iterator<Item = (&str, &SymbolReferences)> {
+        self.symbols
+            .iter()
+            .filter(|(_, symbol)| symbol.is_local())
+            .map(|(label, symbol)| (label.as_str(), symbol))
+    }
+
+    /// The labels and symbols that are used, but not defined, by the assembly, sorted by name
+    pub fn external(&self) -> impl Iterator<Item = (&str, &SymbolReferences)> {
+        self.symbols
+            .iter()
+            .filter(|(_, symbol)| !symbol.is_local())
+            .map(|(label, symbol)| (label.as_str(), symbol))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::identifier::Identifier;
+    use crate::types::primitives::Primitive;
+    use crate::types::Type;
+    use crate::unit::TranslationUnit;
+
+    #[test]
+    fn local_and_external_references() {
+        let jasm = vec![
+            Asm::pub_label("main"),
+            Asm::label("loop"),
+            Asm::Call(AsmLocation::Label("helper".to_string())),
+            Asm::GetSymbol("print".to_string()),
+            Asm::push(Value::location("main")),
+            Asm::goto("loop"),
+            Asm::label("unused"),
+        ];
+        let references = CrossReferences::new(&jasm);
+
+        let local = references
+            .local()
+            .map(|(label, symbol)| (label, symbol.definition.unwrap(), symbol.public))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            local,
+            vec![("loop", 1, false), ("main", 0, true), ("unused", 6, false)]
+        );
+        assert_eq!(references.get("main").unwrap().uses, vec![4]);
+        assert_eq!(references.get("loop").unwrap().uses, vec![5]);
+
+        let external = references
+            .external()
+            .map(|(label, symbol)| (label, symbol.uses.clone()))
+            .collect::<Vec<_>>();
+        assert_eq!(external, vec![("helper", vec![2]), ("print", vec![3])]);
+    }
+
+    fn function(name: &str, body: Vec<Asm>) -> Vec<Asm> {
+        let mut jasm = vec![Asm::pub_label(name)];
+        jasm.extend(body);
+        jasm.push(Asm::label(format!("{name}___func_end__")));
+        jasm.push(Asm::Return);
+        jasm
+    }
+
+    fn object(
+        name: &str,
+        functions: &[(&str, Visibility)],
+        jasm: Vec<Vec<Asm>>,
+    ) -> CompilationObject {
+        let units = functions
+            .iter()
+            .map(|(function, visibility)| {
+                TranslationUnit::new(
+                    visibility.clone(),
+                    Primitive::Int.as_intermediate(),
+                    Identifier::from(*function),
+                )
+            })
+            .collect();
+        CompilationObject::new(
+            PathBuf::from(name),
+            Identifier::empty(),
+            units,
+            jasm.concat(),
+        )
+    }
+
+    #[test]
+    fn unused_private_functions_are_removed() {
+        let mut linker = Linker::new();
+        linker.add_object(object(
+            "program.jobj",
+            &[
+                ("main", Visibility::Protected),
+                ("helper", Visibility::Private),
+                ("unused", Visibility::Private),
+                ("exported", Visibility::Public),
+            ],
+            vec![
+                function("main", vec![Asm::GetSymbol("answer".to_string())]),
+                function("helper", vec![Asm::push(1u64)]),
+                function("unused", vec![Asm::push(2u64)]),
+                function("exported", vec![Asm::push(3u64)]),
+            ],
+        ));
+        linker.add_object(object(
+            "library.jobj",
+            &[("answer", Visibility::Protected)],
+            vec![function(
+                "answer",
+                vec![Asm::push(Value::location("helper"))],
+            )],
+        ));
+        let image = linker.link().unwrap();
+
+        assert_eq!(image.entry_point, "main");
+        assert!(image.labels.contains_key("helper"));
+        assert!(image.labels.contains_key("exported"));
+        assert!(!image.labels.contains_key("unused"));
+        assert!(!image.jasm.contains(&Asm::push(2u64)));
+        assert_eq!(image.jasm[image.labels["answer"]], Asm::pub_label("answer"));
+    }
+
+    #[test]
+    fn symbol_errors_are_reported_together() {
+        let mut linker = Linker::new().with_external_symbols(["print"]);
+        linker.add_object(object(
+            "a.jobj",
+            &[],
+            vec![
+                function("main", vec![Asm::GetSymbol("missing".to_string())]),
+                function("shared", vec![Asm::GetSymbol("print".to_string())]),
+            ],
+        ));
+        linker.add_object(object("b.jobj", &[], vec![function("shared", vec![])]));
+        let error = linker.link().unwrap_err();
+
+        let errors = match error.into_err_and_bt().0 {
+            JodinErrorType::LinkFailed(errors) => errors,
+            other => panic!("expected a link error, found {:?}", other),
+        };
+        assert_eq!(
+            errors,
+            vec![
+                SymbolError::Duplicate {
+                    symbol: "shared".to_string(),
+                    defined_by: vec!["a.jobj".to_string(), "b.jobj".to_string()],
+                },
+                SymbolError::Duplicate {
+                    symbol: "shared___func_end__".to_string(),
+                    defined_by: vec!["a.jobj".to_string(), "b.jobj".to_string()],
+                },
+                SymbolError::Undefined {
+                    symbol: "missing".to_string(),
+                    used_by: vec!["a.jobj".to_string()],
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn entry_point_can_be_namespaced() {
+        let mut linker = Linker::new().with_entry_point("start");
+        linker.add_object(object(
+            "app.jobj",
+            &[],
+            vec![function("app::start", vec![])],
+        ));
+        assert_eq!(linker.link().unwrap().entry_point, "app::start");
+
+        let mut linker = Linker::new();
+        linker.add_object(object("app.jobj", &[], vec![function("start", vec![])]));
+        assert!(linker.link().is_err());
+    }
+}
@@ -0,0 +1,377 @@
+//! The container format of compiled objects (`.jobj` files).
+//!
+//! An object file is a header, followed by a table of sections, followed by the data of each
+//! section. All integers are big endian.
+//!
+//! | Offset | Size | Field                                                             |
+//! |--------|------|-------------------------------------------------------------------|
+//! | 0      | 8    | The magic number of the [asm version](crate::asm_version::Version) |
+//! | 8      | 4    | The signature, `JOBJ`                                             |
+//! | 12     | 2    | The [format version](FORMAT_VERSION)                              |
+//! | 14     | 2    | The amount of sections                                            |
+//! | 16     | 4    | The CRC-32 checksum of everything after the header                |
+//!
+//! Each entry of the section table is the [kind](SectionKind) of the section as a `u32`, followed
+//! by the offset of its data from the start of the file and the length of its data, both `u64`s.
+//! The data of the sections follow the table in the same order.
+//!
+//! Compilation objects use these sections:
+//! - [`Strings`](SectionKind::Strings): a [string table](StringTable). The first string is the
+//!   location of the object, and the second is its module.
+//! - [`Units`](SectionKind::Units): the amount of translation units as a `u32`, then the index of
+//!   each unit within the string table as a `u32`.
+//! - [`Code`](SectionKind::Code): the bincode encoded assembly.
+//! - [`DebugInfo`](SectionKind::DebugInfo): the bincode encoded line table, which is optional.
+//!
+//! [Executable images](crate::image) use the same container, with these sections:
+//! - [`Strings`](SectionKind::Strings): a string table. The first string is the entry point of the
+//!   image, and the rest are the names of its labels.
+//! - [`Symbols`](SectionKind::Symbols): the amount of labels as a `u32`, then for each label the
+//!   index of its name within the string table as a `u32` and the index of its instruction as a
+//!   `u64`.
+//! - [`Initializers`](SectionKind::Initializers): the amount of initializers as a `u32`, then the
+//!   index of the first instruction of each initializer as a `u64`.
+//! - [`Code`](SectionKind::Code): the bincode encoded assembly.
+//! - [`DebugInfo`](SectionKind::DebugInfo): the bincode encoded line tables of the modules within
+//!   the image, which is optional.
+//!
+//! Sections of unknown kinds are kept, but aren't used.
+
+use crate::error::{JodinErrorType, JodinResult};
+use std::convert::TryInto;
+
+/// The signature of every object file
+pub const SIGNATURE: [u8; 4] = *b"JOBJ";
+/// The version of the container format
+pub const FORMAT_VERSION: u16 = 1;
+/// The size of the header, in bytes
+pub const HEADER_SIZE: usize = 20;
+/// The size of an entry of the section table, in bytes
+pub const SECTION_ENTRY_SIZE: usize = 20;
+
+/// The kinds of sections within an object file
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum SectionKind {
+    /// The string table
+    Strings,
+    /// The translation units
+    Units,
+    /// The assembly
+    Code,
+    /// The line table of the assembly
+    DebugInfo,
+    /// The labels of an executable image
+    Symbols,
+    /// The static code of an executable image
+    Initializers,
+    /// A section that isn't known by this version
+    Unknown(u32),
+}
+
+impl SectionKind {
+    /// The identifier of the kind within the section table
+    pub fn id(&self) -> u32 {
+        match self {
+            SectionKind::Strings => 1,
+            SectionKind::Units => 2,
+            SectionKind::Code => 3,
+            SectionKind::DebugInfo => 4,
+            SectionKind::Symbols => 5,
+            SectionKind::Initializers => 6,
+            SectionKind::Unknown(id) => *id,
+        }
+    }
+
+    /// Gets the kind of a section from its identifier
+    pub fn from_id(id: u32) -> Self {
+        match id {
+            1 => SectionKind::Strings,
+            2 => SectionKind::Units,
+            3 => SectionKind::Code,
+            4 => SectionKind::DebugInfo,
+            5 => SectionKind::Symbols,
+            6 => SectionKind::Initializers,
+            id => SectionKind::Unknown(id),
+        }
+    }
+}
+
+/// A section of an object file
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Section {
+    /// The kind of the section
+    pub kind: SectionKind,
+    /// The data of the section
+    pub data: Vec<u8>,
+}
+
+/// An object file, which is a list of sections
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ObjectFile {
+    /// The magic number of the asm version the object was compiled with
+    pub magic_number: u64,
+    sections: Vec<Section>,
+}
+
+impl ObjectFile {
+    /// Creates an object file without any sections
+    pub fn new(magic_number: u64) -> Self {
+        Self {
+            magic_number,
+            sections: vec![],
+        }
+    }
+
+    /// Adds a section to the end of the object file
+    pub fn add_section(&mut self, kind: SectionKind, data: Vec<u8>) {
+        self.sections.push(Section { kind, data });
+    }
+
+    /// The sections of the object file
+    pub fn sections(&self) -> &[Section] {
+        &self.sections
+    }
+
+    /// Gets the data of the first section of a kind
+    pub fn section(&self, kind: SectionKind) -> Option<&[u8]> {
+        self.sections
+            .iter()
+            .find(|section| section.kind == kind)
+            .map(|section| &*section.data)
+    }
+
+    /// Gets the data of a section that must be present
+    pub fn required_section(&self, kind: SectionKind) -> JodinResult<&[u8]> {
+        self.section(kind)
+            .ok_or_else(|| invalid(format!("missing the {:?} section", kind)))
+    }
+
+    /// Writes the object file
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut body = vec![];
+        let mut offset = (HEADER_SIZE + SECTION_ENTRY_SIZE * self.sections.len()) as u64;
+        for section in &self.sections {
+            body.extend_from_slice(&section.kind.id().to_be_bytes());
+            body.extend_from_slice(&offset.to_be_bytes());
+            body.extend_from_slice(&(section.data.len() as u64).to_be_bytes());
+            offset += section.data.len() as u64;
+        }
+        for section in &self.sections {
+            body.extend_from_slice(&section.data);
+        }
+
+        let mut bytes = Vec::with_capacity(HEADER_SIZE + body.len());
+        bytes.extend_from_slice(&self.magic_number.to_be_bytes());
+        bytes.extend_from_slice(&SIGNATURE);
+        bytes.extend_from_slice(&FORMAT_VERSION.to_be_bytes());
+        bytes.extend_from_slice(&(self.sections.len() as u16).to_be_bytes());
+        bytes.extend_from_slice(&checksum(&body).to_be_bytes());
+        bytes.extend(body);
+        bytes
+    }
+
+    /// Reads an object file, checking that it isn't corrupt
+    pub fn from_bytes(bytes: &[u8]) -> JodinResult<Self> {
+        if bytes.len() < HEADER_SIZE {
+            return Err(invalid("too short to contain a header"));
+        }
+        if bytes[8..12] != SIGNATURE {
+            return Err(invalid("not an object file"));
+        }
+        let magic_number = u64::from_be_bytes(bytes[0..8].try_into().unwrap());
+        let format_version = u16::from_be_bytes(bytes[12..14].try_into().unwrap());
+        if format_version != FORMAT_VERSION {
+            return Err(invalid(format!(
+                "unsupported format version {} (expected: {})",
+                format_version, FORMAT_VERSION
+            )));
+        }
+        let section_count = u16::from_be_bytes(bytes[14..16].try_into().unwrap()) as usize;
+        let expected_checksum = u32::from_be_bytes(bytes[16..20].try_into().unwrap());
+        if checksum(&bytes[HEADER_SIZE..]) != expected_checksum {
+            return Err(invalid("checksum mismatch"));
+        }
+
+        let table_end = HEADER_SIZE + SECTION_ENTRY_SIZE * section_count;
+        if bytes.len() < table_end {
+            return Err(invalid("section table is truncated"));
+        }
+        let mut object = Self::new(magic_number);
+        for entry in bytes[HEADER_SIZE..table_end].chunks_exact(SECTION_ENTRY_SIZE) {
+            let kind = SectionKind::from_id(u32::from_be_bytes(entry[0..4].try_into().unwrap()));
+            let offset = u64::from_be_bytes(entry[4..12].try_into().unwrap());
+            let length = u64::from_be_bytes(entry[12..20].try_into().unwrap());
+            let data = usize::try_from(offset)
+                .ok()
+                .zip(usize::try_from(length).ok())
+                .and_then(|(offset, length)| Some(offset..offset.checked_add(length)?))
+                .filter(|range| range.start >= table_end && range.end <= bytes.len())
+                .map(|range| &bytes[range])
+                .ok_or_else(|| invalid(format!("{:?} section is out of bounds", kind)))?;
+            object.add_section(kind, data.to_vec());
+        }
+        Ok(object)
+    }
+}
+
+/// A table of strings, which are referred to by their index
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct StringTable {
+    strings: Vec<String>,
+}
+
+impl StringTable {
+    /// Creates an empty string table
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a string to the end of the table, returning its index
+    pub fn push<S: Into<String>>(&mut self, string: S) -> u32 {
+        self.strings.push(string.into());
+        (self.strings.len() - 1) as u32
+    }
+
+    /// Gets a string by its index
+    pub fn get(&self, index: u32) -> JodinResult<&str> {
+        self.strings
+            .get(index as usize)
+            .map(String::as_str)
+            .ok_or_else(|| invalid(format!("no string at index {}", index)))
+    }
+
+    /// Writes the table as the amount of strings, then the length and bytes of each string
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = (self.strings.len() as u32).to_be_bytes().to_vec();
+        for string in &self.strings {
+            bytes.extend_from_slice(&(string.len() as u32).to_be_bytes());
+            bytes.extend_from_slice(string.as_bytes());
+        }
+        bytes
+    }
+
+    /// Reads a table
+    pub fn from_bytes(bytes: &[u8]) -> JodinResult<Self> {
+        let mut reader = SectionReader::new(bytes);
+        let count = reader.read_u32()?;
+        let mut table = Self::new();
+        for _ in 0..count {
+            let length = reader.read_u32()? as usize;
+            let string = String::from_utf8(reader.read_bytes(length)?.to_vec())?;
+            table.push(string);
+        }
+        reader.finish()?;
+        Ok(table)
+    }
+}
+
+/// Reads values from the data of a section, erroring if the data ends too early
+#[derive(Debug)]
+pub struct SectionReader<'b> {
+    bytes: &'b [u8],
+    position: usize,
+}
+
+impl<'b> SectionReader<'b> {
+    /// Creates a reader at the start of some data
+    pub fn new(bytes: &'b [u8]) -> Self {
+        Self { bytes, position: 0 }
+    }
+
+    /// Reads some amount of bytes
+    pub fn read_bytes(&mut self, length: usize) -> JodinResult<&'b [u8]> {
+        let end = self
+            .position
+            .checked_add(length)
+            .filter(|&end| end <= self.bytes.len())
+            .ok_or_else(|| invalid("section is truncated"))?;
+        let bytes = &self.bytes[self.position..end];
+        self.position = end;
+        Ok(bytes)
+    }
+
+    /// Reads a `u32`
+    pub fn read_u32(&mut self) -> JodinResult<u32> {
+        Ok(u32::from_be_bytes(self.read_bytes(4)?.try_into().unwrap()))
+    }
+
+    /// Reads a `u64`
+    pub fn read_u64(&mut self) -> JodinResult<u64> {
+        Ok(u64::from_be_bytes(self.read_bytes(8)?.try_into().unwrap()))
+    }
+
+    /// Checks that all of the data was read
+    pub fn finish(self) -> JodinResult<()> {
+        if self.position == self.bytes.len() {
+            Ok(())
+        } else {
+            Err(invalid("section has trailing data"))
+        }
+    }
+}
+
+/// The CRC-32 checksum of some bytes
+pub fn checksum(bytes: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for &byte in bytes {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            let mask = (crc & 1).wrapping_neg();
+            crc = (crc >> 1) ^ (0xEDB8_8320 & mask);
+        }
+    }
+    !crc
+}
+
+fn invalid<S: Into<String>>(reason: S) -> crate::error::JodinError {
+    JodinErrorType::InvalidObject(reason.into()).into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn object() -> ObjectFile {
+        let mut strings = StringTable::new();
+        strings.push("main.jobj");
+        strings.push("std");
+        let mut object = ObjectFile::new(42);
+        object.add_section(SectionKind::Strings, strings.to_bytes());
+        object.add_section(SectionKind::Code, vec![b'{', b'}', 0, 1, 2]);
+        object.add_section(SectionKind::Unknown(99), vec![]);
+        object
+    }
+
+    #[test]
+    fn round_trip() {
+        let object = object();
+        let bytes = object.to_bytes();
+        let read = ObjectFile::from_bytes(&bytes).unwrap();
+        assert_eq!(read, object);
+        assert_eq!(read.to_bytes(), bytes);
+        let strings = StringTable::from_bytes(read.section(SectionKind::Strings).unwrap()).unwrap();
+        assert_eq!(strings.get(1).unwrap(), "std");
+        assert!(strings.get(2).is_err());
+    }
+
+    #[test]
+    fn checksum_of_known_value() {
+        assert_eq!(checksum(b"123456789"), 0xCBF4_3926);
+    }
+
+    #[test]
+    fn corrupt_files_are_rejected() {
+        let bytes = object().to_bytes();
+        for length in 0..bytes.len() {
+            assert!(ObjectFile::from_bytes(&bytes[..length]).is_err());
+        }
+        for index in 0..bytes.len() {
+            let mut corrupt = bytes.clone();
+            corrupt[index] ^= 0x10;
+            // the magic number is checked by the reader of the object
+            if index >= 8 {
+                assert!(ObjectFile::from_bytes(&corrupt).is_err(), "{}", index);
+            }
+        }
+    }
+}
@@ -0,0 +1,227 @@
+//! Packages (`.jdp` files) bundle many compilation objects into a single file, so that libraries
+//! can be distributed without their sources.
+//!
+//! A package is a zip archive compressed with `DEFLATE`. Each entry is a `.jobj` file, laid out by
+//! namespace in the same way as the output directory of the compiler.
+
+use crate::error::JodinResult;
+use crate::unit::{CompilationObject, Incremental, TranslationUnit};
+use anyhow::anyhow;
+use std::fs::File;
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+use zip::write::FileOptions;
+use zip::{CompressionMethod, ZipArchive, ZipWriter};
+
+/// The extension of package files
+pub const PACKAGE_EXTENSION: &str = "jdp";
+/// The extension of the objects within a package
+const OBJECT_EXTENSION: &str = "jobj";
+
+/// A compilation object within a package
+#[derive(Debug)]
+pub struct PackageEntry {
+    /// The path of the object within the package
+    pub name: PathBuf,
+    /// The object
+    pub object: CompilationObject,
+}
+
+/// A package of compilation objects
+#[derive(Debug)]
+pub struct Package {
+    path: PathBuf,
+    entries: Vec<PackageEntry>,
+}
+
+impl Package {
+    /// Creates an empty package, which is written to a path
+    pub fn new<P: AsRef<Path>>(path: P) -> Self {
+        Self {
+            path: path.as_ref().to_path_buf(),
+            entries: vec![],
+        }
+    }
+
+    /// Reads the package at a path
+    pub fn open<P: AsRef<Path>>(path: P) -> JodinResult<Self> {
+        let file = File::open(&path)?;
+        let mut archive = ZipArchive::new(file).map_err(|e| anyhow!(e))?;
+        let mut package = Self::new(path);
+        for index in 0..archive.len() {
+            let mut entry = archive.by_index(index).map_err(|e| anyhow!(e))?;
+            let name = match entry.enclosed_name() {
+                Some(name) if entry.is_file() => name.to_path_buf(),
+                _ => continue,
+            };
+            if name.extension().is_none_or(|ext| ext != OBJECT_EXTENSION) {
+                continue;
+            }
+            let mut buffer = vec![];
+            entry.read_to_end(&mut buffer)?;
+            let object = CompilationObject::try_from(&*buffer)
+                .map_err(|e| anyhow!("{:?} within {:?}: {}", name, package.path, e))?;
+            package.add_object(name, object);
+        }
+        Ok(package)
+    }
+
+    /// Creates a package of every object within a directory, keeping their paths relative to the
+    /// directory
+    pub fn from_directory<P1: AsRef<Path>, P2: AsRef<Path>>(
+        directory: P1,
+        path: P2,
+    ) -> JodinResult<Self> {
+        let directory = directory.as_ref();
+        let mut objects = vec![];
+        collect_objects(directory, &mut objects)?;
+        objects.sort();
+
+        let mut package = Self::new(path);
+        for object_path in objects {
+            let object = CompilationObject::try_from(object_path.as_path())?;
+            let name = object_path.strip_prefix(directory).unwrap().to_path_buf();
+            package.add_object(name, object);
+        }
+        Ok(package)
+    }
+
+    /// Adds an object to the package
+    pub fn add_object<P: AsRef<Path>>(&mut self, name: P, object: CompilationObject) {
+        self.entries.push(PackageEntry {
+            name: name.as_ref().to_path_buf(),
+            object,
+        });
+    }
+
+    /// The path of the package
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// The objects within the package, in the order they were added
+    pub fn entries(&self) -> &[PackageEntry] {
+        &self.entries
+    }
+
+    /// Consumes the package, getting its objects
+    pub fn into_entries(self) -> Vec<PackageEntry> {
+        self.entries
+    }
+
+    /// Writes the package to its path
+    pub fn write(&self) -> JodinResult<()> {
+        if let Some(parent) = self.path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        let file = File::create(&self.path)?;
+        let mut writer = ZipWriter::new(file);
+        let options = FileOptions::default().compression_method(CompressionMethod::Deflated);
+        for entry in &self.entries {
+            // zip archives always separate directories with `/`
+            let name = entry
+                .name
+                .iter()
+                .map(|part| part.to_string_lossy())
+                .collect::<Vec<_>>()
+                .join("/");
+            writer.start_file(name, options).map_err(|e| anyhow!(e))?;
+            writer.write_all(&entry.object.to_bytes()?)?;
+        }
+        writer.finish().map_err(|e| anyhow!(e))?;
+        Ok(())
+    }
+}
+
+impl Incremental for Package {
+    fn translation_units(&self) -> Vec<TranslationUnit> {
+        self.entries
+            .iter()
+            .flat_map(|entry| entry.object.units.clone())
+            .collect()
+    }
+
+    fn representative_path(&self) -> PathBuf {
+        self.path.clone()
+    }
+}
+
+/// Whether a path is of a package
+pub fn is_package<P: AsRef<Path>>(path: P) -> bool {
+    path.as_ref()
+        .extension()
+        .is_some_and(|ext| ext == PACKAGE_EXTENSION)
+}
+
+/// Finds every object within a directory and its subdirectories
+pub(crate) fn collect_objects(directory: &Path, objects: &mut Vec<PathBuf>) -> JodinResult<()> {
+    for entry in std::fs::read_dir(directory)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            collect_objects(&path, objects)?;
+        } else if path.extension().is_some_and(|ext| ext == OBJECT_EXTENSION) {
+            objects.push(path);
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::assembly::instructions::Asm;
+    use crate::core::privacy::Visibility;
+    use crate::identifier::Identifier;
+    use crate::types::primitives::Primitive;
+    use crate::types::Type;
+
+    #[test]
+    fn package_round_trips() {
+        let directory = std::env::temp_dir().join(format!("jodin-package-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&directory);
+        let package_path = directory.join("std.jdp");
+
+        let mut package = Package::new(&package_path);
+        package.add_object(
+            Path::new("std").join("math.jobj"),
+            CompilationObject::new(
+                PathBuf::from("std/math.jobj"),
+                Identifier::from("std"),
+                vec![TranslationUnit::new(
+                    Visibility::Public,
+                    Primitive::Int.as_intermediate(),
+                    Identifier::from_iter(["std", "zero"]),
+                )],
+                vec![Asm::pub_label("std::zero"), Asm::push(0u64), Asm::Return],
+            ),
+        );
+        package.add_object(
+            "static.jobj",
+            CompilationObject::new(
+                PathBuf::from("static.jobj"),
+                Identifier::empty(),
+                vec![],
+                vec![],
+            ),
+        );
+        package.write().unwrap();
+
+        let read = Package::open(&package_path).unwrap();
+        assert_eq!(read.path(), package_path);
+        let names = read
+            .entries()
+            .iter()
+            .map(|entry| entry.name.clone())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            names,
+            vec![
+                Path::new("std").join("math.jobj"),
+                PathBuf::from("static.jobj")
+            ]
+        );
+        assert_eq!(read.translation_units(), package.translation_units());
+        assert_eq!(read.entries()[0].object.jasm.len(), 3);
+        std::fs::remove_dir_all(directory).unwrap();
+    }
+}
@@ -615,4 +615,29 @@ mod tests {
         )
         .unwrap();
     }
+
+    #[test]
+    fn statements_know_their_source_line() {
+        use crate::core::tags::SourceLineTag;
+
+        let program = parse_program(
+            r"fn main() {
+    let x: int = 0;
+    if (x < 2) {
+        x = 1;
+    }
+}",
+        )
+        .unwrap();
+        let mut lines = vec![];
+        let mut stack = vec![&program];
+        while let Some(node) = stack.pop() {
+            if let Ok(tag) = node.get_tag::<SourceLineTag>() {
+                lines.push(tag.line);
+            }
+            stack.extend(node);
+        }
+        lines.sort();
+        assert_eq!(lines, vec![2, 3, 4]);
+    }
 }
@@ -1,25 +1,18 @@
 use crate::core::privacy::Visibility;
-use crate::error::{JodinError, JodinErrorType, JodinResult};
+use crate::error::JodinResult;
 use crate::identifier::Identifier;
 use crate::types::intermediate_type::IntermediateType;
 use crate::types::traits::JTrait;
 use crate::types::Field;
-use std::sync::atomic::{AtomicBool, Ordering};
 
-static BASE_TYPE_GENERATED: AtomicBool = AtomicBool::new(false);
-
-/// Generate the base type. Ensures that only one is ever created to prevent potential future errors
+/// Get the base type. It's only ever generated once, and every type environment gets a copy of
+/// the same base type.
 pub fn base_type() -> JodinResult<JTrait> {
-    if Ok(false)
-        == BASE_TYPE_GENERATED.compare_exchange(false, true, Ordering::Acquire, Ordering::Relaxed)
-    {
-        _base_type()
-    } else {
-        Err(JodinError::new(JodinErrorType::BaseTypeAlreadyGenerated))
-    }
+    Ok(BASE_TYPE.clone())
 }
 
 lazy_static::lazy_static! {
+    static ref BASE_TYPE: JTrait = _base_type().expect("Creating the base type shouldn't fail");
     pub static ref BASE_TYPE_ID: Identifier = Identifier::from("Object");
     pub static ref TO_STRING_ID: Identifier = &*BASE_TYPE_ID << &Identifier::from("to_string");
     pub static ref GET_TYPE_ID: Identifier = &*BASE_TYPE_ID << &Identifier::from("get_type");
@@ -143,8 +143,8 @@ impl IntermediateType {
     pub fn into_unsigned(mut self) -> Self {
         let new_type = match self.type_specifier {
             TypeSpecifier::Primitive(Primitive::Char) => Primitive::UnsignedByte,
-            TypeSpecifier::Primitive(Primitive::Int) => Primitive::UnsignedShort,
-            TypeSpecifier::Primitive(Primitive::Short) => Primitive::UnsignedInt,
+            TypeSpecifier::Primitive(Primitive::Int) => Primitive::UnsignedInt,
+            TypeSpecifier::Primitive(Primitive::Short) => Primitive::UnsignedShort,
             TypeSpecifier::Primitive(Primitive::Long) => Primitive::UnsignedLong,
             r#else => panic!("{:?} can not be made unsigned", r#else),
         };
@@ -2,19 +2,21 @@
 //! declarations
 
 use crate::asm_version::Version;
-use crate::assembly::instructions::{Assembly, Bytecode, Decode, Encode, GetAsm};
+use crate::assembly::debug_info::DebugInfo;
+use crate::assembly::instructions::{Assembly, GetAsm};
 use crate::compilation::{Compilable, Context, PaddedWriter, Target};
 use crate::core::privacy::Visibility;
 use crate::error::{JodinError, JodinErrorType, JodinResult};
 use crate::identifier::Identifier;
+use crate::object_file::{ObjectFile, SectionKind, SectionReader, StringTable};
 use crate::types::intermediate_type::IntermediateType;
 use crate::types::Field;
+use crate::upgrade::Upgrader;
 use anyhow::anyhow;
 
 use std::fmt::{Debug, Display, Formatter};
 use std::fs::File;
 use std::io::Write;
-use std::mem;
 use std::ops::{Add, AddAssign, Deref};
 use std::path::{Path, PathBuf};
 use std::str::FromStr;
@@ -49,13 +51,12 @@ impl FromStr for TranslationUnit {
     fn from_str(s: &str) -> Result<Self, Self::Err> {
         let as_split: Vec<&str> = s.split(FIELD_SEPARATOR).collect();
         let as_split_slice = as_split.as_slice();
-        if let &[_name, _jtype, _visibility] = as_split_slice {
-            // Ok(TranslationUnit::new(
-            //     Visibility::from_str(visibility)?,
-            //     parse_type(jtype)?,
-            //     name,
-            // ))
-            unimplemented!()
+        if let &[name, jtype, visibility] = as_split_slice {
+            Ok(TranslationUnit::new(
+                Visibility::from_str(visibility)?,
+                IntermediateType::from_str(jtype)?,
+                Identifier::from_str(name).unwrap(),
+            ))
         } else {
             Err(JodinErrorType::InvalidCompilationUnit(s.to_string()).into())
         }
@@ -136,7 +137,6 @@ pub fn join_translation_units<S: AsRef<str>, I: IntoIterator<Item = S>>(iterator
 /// incremental compiler to get the the translation units
 #[derive(Debug)]
 pub struct CompilationObject {
-    magic_number: u64,
     pub file_location: PathBuf,
     /// The module that the translation units are part of
     pub module: Identifier,
@@ -144,6 +144,8 @@ pub struct CompilationObject {
     pub units: Vec<TranslationUnit>,
     /// The assembly in the compilation object
     pub jasm: Assembly,
+    /// The line table of the assembly. Written after the assembly when present.
+    pub debug_info: Option<DebugInfo>,
 }
 
 impl CompilationObject {
@@ -154,11 +156,30 @@ impl CompilationObject {
         jasm: Assembly,
     ) -> Self {
         CompilationObject {
-            magic_number: Version.to_magic_number(),
             file_location,
             module,
             units,
             jasm,
+            debug_info: None,
+        }
+    }
+
+    /// Adds debug information to the compilation object
+    pub fn with_debug_info(mut self, debug_info: DebugInfo) -> Self {
+        self.debug_info = Some(debug_info);
+        self
+    }
+
+    /// Appends the debug info of assembly that was appended to this object
+    fn append_debug_info(&mut self, other: Option<DebugInfo>, offset: usize) {
+        match (&mut self.debug_info, other) {
+            (_, None) => {}
+            (Some(debug_info), Some(other)) => debug_info.append(other, offset),
+            (None, Some(other)) => {
+                let mut debug_info = DebugInfo::default();
+                debug_info.append(other, offset);
+                self.debug_info = Some(debug_info);
+            }
         }
     }
 
@@ -167,13 +188,9 @@ impl CompilationObject {
             return Err(anyhow!("Compilation objects must have same location (left= {:?}, right= {:?})", self.file_location, other.file_location).into());
         }
 
-        let mut units = self.units;
-        units.extend(other.units);
-
-        let mut jasm = self.jasm;
-        jasm.extend(other.jasm);
-
-        Ok(Self::new(self.file_location, self.module, units, jasm))
+        let mut output = self;
+        output.merge_from(other)?;
+        Ok(output)
     }
 
     pub fn merge_from(&mut self, other: Self) -> Result<(), JodinError> {
@@ -181,7 +198,9 @@ impl CompilationObject {
             return Err(anyhow!("Compilation objects must have same location (left= {:?}, right= {:?})", self.file_location, other.file_location).into());
         }
         self.units.extend(other.units);
+        let offset = self.jasm.len();
         self.jasm.extend(other.jasm);
+        self.append_debug_info(other.debug_info, offset);
         Ok(())
     }
 
@@ -226,88 +245,121 @@ impl Display for CompilationObject {
 
 impl<T: Target> Compilable<T> for CompilationObject {
     fn compile<W: Write>(self, _context: &Context, w: &mut PaddedWriter<W>) -> JodinResult<()> {
-        let magic_num_as_bytes = self.magic_number.to_be_bytes();
-        trace!("Wrote magic num {:?} to file", magic_num_as_bytes);
-        w.write_all(&magic_num_as_bytes)?;
-        writeln!(w)?;
-        writeln!(w, "{:?}", &self.file_location)?;
-        writeln!(w, "{}", &self.module)?;
-        write!(w, "{{")?;
-        for unit in &self.units {
-            write!(w, "{};", unit)?;
-        }
-        write!(w, "}}")?;
-        let encoded = self.jasm.encode();
-        w.write_all(&*encoded)?;
+        w.write_all(&self.to_bytes()?)?;
         w.flush()?;
         Ok(())
     }
 }
 
+impl CompilationObject {
+    /// Writes the object in the [object file format](crate::object_file)
+    pub fn to_bytes(&self) -> JodinResult<Vec<u8>> {
+        let mut strings = StringTable::new();
+        strings.push(self.file_location.to_string_lossy());
+        strings.push(self.module.to_string());
+        let mut units = (self.units.len() as u32).to_be_bytes().to_vec();
+        for unit in &self.units {
+            units.extend_from_slice(&strings.push(unit.to_string()).to_be_bytes());
+        }
+
+        let mut object = ObjectFile::new(Version::CURRENT.to_magic_number());
+        object.add_section(SectionKind::Strings, strings.to_bytes());
+        object.add_section(SectionKind::Units, units);
+        let encoded = bincode::serialize(&self.jasm).map_err(|e| anyhow!(e))?;
+        object.add_section(SectionKind::Code, encoded);
+        if let Some(debug_info) = &self.debug_info {
+            let encoded = bincode::serialize(debug_info).map_err(|e| anyhow!(e))?;
+            object.add_section(SectionKind::DebugInfo, encoded);
+        }
+        Ok(object.to_bytes())
+    }
+}
+
 impl TryFrom<&[u8]> for CompilationObject {
     type Error = JodinError;
 
     fn try_from(value: &[u8]) -> Result<Self, Self::Error> {
-        let mut bytes = [0u8; mem::size_of::<u64>()];
-        bytes.copy_from_slice(&value[0..8]);
-        let magic_num = u64::from_be_bytes(bytes);
-        if !Version.verify_magic_number(magic_num) {
-            return Err(anyhow!(
-                "Incorrect magic number for for {} (found: {}, expected: {})",
-                Version.version_string(),
-                magic_num,
-                Version.to_magic_number()
-            )
-            .into());
+        let legacy_magic_number = Version::new(1, 0).to_magic_number().to_be_bytes();
+        if value.starts_with(&legacy_magic_number) {
+            return Self::from_legacy_bytes(value);
+        }
+        let object = ObjectFile::from_bytes(value)?;
+        let upgrader = Upgrader::default();
+        let version = upgrader.version_of(object.magic_number)?;
+
+        let strings = StringTable::from_bytes(object.required_section(SectionKind::Strings)?)?;
+        let file_location = PathBuf::from(strings.get(0)?);
+        let module = Identifier::from_str(strings.get(1)?).unwrap();
+
+        let mut reader = SectionReader::new(object.required_section(SectionKind::Units)?);
+        let mut translation_units = vec![];
+        for _ in 0..reader.read_u32()? {
+            let unit = strings.get(reader.read_u32()?)?;
+            debug!("unit: {:?}", unit);
+            translation_units.push(TranslationUnit::from_str(unit)?);
+        }
+        reader.finish()?;
+
+        let assembly: Assembly =
+            bincode::deserialize(object.required_section(SectionKind::Code)?)
+                .map_err(|e| anyhow!(e))?;
+        let assembly = upgrader.upgrade(version, assembly)?;
+        let mut output = CompilationObject::new(file_location, module, translation_units, assembly);
+        if let Some(debug_info) = object.section(SectionKind::DebugInfo) {
+            let debug_info: DebugInfo = bincode::deserialize(debug_info).map_err(|e| anyhow!(e))?;
+            output = output.with_debug_info(debug_info);
         }
+        info!("Generated {}", output);
+        Ok(output)
+    }
+}
 
-        let translation_unit_start_index = value
+impl CompilationObject {
+    /// Reads an object of version 1.0, which was written before the object file format. Its magic
+    /// number is followed by a text header with the location and module of the object, then the
+    /// translation units within braces, each ending with `;`, then the bincode encoded assembly.
+    fn from_legacy_bytes(value: &[u8]) -> JodinResult<Self> {
+        let invalid = |reason: &str| -> JodinError {
+            JodinErrorType::InvalidObject(format!("version 1.0 object {}", reason)).into()
+        };
+        let units_start = value
             .iter()
-            .position(|&p| p == b'{')
-            .ok_or(anyhow!("No position found for {{"))?;
-        let translation_unit_end_offset = value
+            .skip(8)
+            .position(|&byte| byte == b'{')
+            .map(|offset| 8 + offset)
+            .ok_or_else(|| invalid("has no translation units"))?;
+        let units_end = value[units_start..]
             .iter()
-            .skip(translation_unit_start_index)
-            .position(|&p| p == b'}')
-            .ok_or(anyhow!("No position found for }}"))?;
-
-        let translation_units_end = translation_unit_start_index + translation_unit_end_offset;
-        let translation_unit_string =
-            &value[translation_unit_start_index + 1..(translation_units_end)];
-        let translation_unit_string = String::from_utf8(translation_unit_string.to_vec())?;
-        let tu_strings: Vec<&str> = translation_unit_string
-            .trim()
-            .split(UNIT_SEPARATOR)
-            .filter(|&s| !s.is_empty())
-            .collect();
-        debug!("Translation Unit Strings: {:#?}", tu_strings);
-        let mut translation_units: Vec<TranslationUnit> = vec![];
-        for translation_unit_string in tu_strings {
-            info!("unit: {:?}", translation_unit_string);
-            let field_split_vec = translation_unit_string
-                .split(FIELD_SEPARATOR)
-                .collect::<Vec<_>>();
-            if let &[name, jtype, visibility] = &*field_split_vec {
-                let id: Identifier = name.parse().unwrap();
-                let jtype: IntermediateType = jtype.parse()?;
-                let visibility: Visibility = visibility.parse()?;
-
-                translation_units.push(TranslationUnit::new(visibility, jtype, id));
-            }
-        }
-
-        let header_bytes = value[8..translation_unit_start_index].to_vec();
-        // Header should be in utf-8
-        let header = String::from_utf8(header_bytes)?;
-        let mut split = header.lines().skip(1);
-        let file_location: PathBuf = PathBuf::from(split.next().unwrap().replace('"', ""));
-        let module: Identifier = Identifier::from(split.next().unwrap());
-
-        let bytecode_raw = &value[translation_units_end + 1..];
-        let bytecode: Bytecode = Bytecode::from(bytecode_raw);
-        let assembly: Assembly = bytecode.decode();
-        let output = CompilationObject::new(file_location, module, translation_units, assembly);
-        info!("Generated {}", output);
+            .position(|&byte| byte == b'}')
+            .map(|offset| units_start + offset)
+            .ok_or_else(|| invalid("has unterminated translation units"))?;
+
+        let header = std::str::from_utf8(&value[8..units_start]).map_err(|e| anyhow!(e))?;
+        let mut lines = header.lines().skip(1);
+        let file_location = lines
+            .next()
+            .ok_or_else(|| invalid("has no location"))?
+            .replace('"', "");
+        let module = lines.next().ok_or_else(|| invalid("has no module"))?;
+
+        let units =
+            std::str::from_utf8(&value[units_start + 1..units_end]).map_err(|e| anyhow!(e))?;
+        let translation_units = units
+            .split(';')
+            .filter(|unit| !unit.is_empty())
+            .map(TranslationUnit::from_str)
+            .collect::<JodinResult<Vec<_>>>()?;
+
+        let assembly: Assembly =
+            bincode::deserialize(&value[units_end + 1..]).map_err(|e| anyhow!(e))?;
+        let assembly = Upgrader::default().upgrade(Version::new(1, 0), assembly)?;
+        let output = CompilationObject::new(
+            PathBuf::from(file_location),
+            Identifier::from_str(module).unwrap(),
+            translation_units,
+            assembly,
+        );
+        info!("Generated {} from a version 1.0 object", output);
         Ok(output)
     }
 }
@@ -341,6 +393,10 @@ impl GetAsm for CompilationObject {
     fn get_asm(&self) -> Assembly {
         self.jasm.clone()
     }
+
+    fn debug_info(&self) -> Option<DebugInfo> {
+        self.debug_info.clone()
+    }
 }
 
 pub trait Incremental {
@@ -348,6 +404,16 @@ pub trait Incremental {
     fn representative_path(&self) -> PathBuf;
 }
 
+impl Incremental for CompilationObject {
+    fn translation_units(&self) -> Vec<TranslationUnit> {
+        self.units.clone()
+    }
+
+    fn representative_path(&self) -> PathBuf {
+        self.file_location.clone()
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -396,4 +462,127 @@ mod tests {
         );
         assert!(unit_iterator.next().is_none())
     }
+
+    #[test]
+    fn line_table_is_kept() {
+        use crate::assembly::debug_info::{DebugInfo, LineEntry};
+        use crate::assembly::instructions::Asm;
+        use crate::compilation::{Compilable, Context, PaddedWriter, Target};
+
+        struct TestTarget;
+        impl Target for TestTarget {}
+
+        let debug_info = DebugInfo::new(
+            Some(PathBuf::from("main.jdn")),
+            vec![LineEntry { pc: 0, line: 2 }, LineEntry { pc: 2, line: 3 }],
+        );
+        let object = CompilationObject::new(
+            PathBuf::from("main.jobj"),
+            Identifier::from("main"),
+            vec![],
+            vec![Asm::pub_label("main"), Asm::push(0u64), Asm::Return],
+        )
+        .with_debug_info(debug_info.clone());
+
+        let mut buffer = vec![];
+        let mut writer = PaddedWriter::new(&mut buffer);
+        Compilable::<TestTarget>::compile(object, &Context::new(), &mut writer).unwrap();
+        drop(writer);
+        let read = CompilationObject::try_from(&*buffer).unwrap();
+        assert_eq!(read.jasm.len(), 3);
+        assert_eq!(read.debug_info, Some(debug_info));
+        assert_eq!(read.debug_info.unwrap().line_at(1), Some(2));
+    }
+
+    #[test]
+    fn object_round_trips() {
+        use crate::assembly::instructions::Asm;
+        use crate::compilation::{Compilable, Context, PaddedWriter, Target};
+
+        struct TestTarget;
+        impl Target for TestTarget {}
+
+        let units = vec![
+            TranslationUnit::new(
+                Visibility::Public,
+                Primitive::Int
+                    .as_intermediate()
+                    .with_function_params([Primitive::Int.as_intermediate()]),
+                Identifier::from_iter(["std", "math", "abs"]),
+            ),
+            TranslationUnit::new(
+                Visibility::Protected,
+                Primitive::Float.as_intermediate().with_abstract_array(),
+                Identifier::from_iter(["std", "math", "constants"]),
+            ),
+        ];
+        // braces used to end the units of older objects
+        let jasm = vec![Asm::pub_label("std::math::abs"), Asm::push("{}"), Asm::Return];
+        let object = CompilationObject::new(
+            PathBuf::from("out/std/math.jobj"),
+            Identifier::from_str("std::math").unwrap(),
+            units.clone(),
+            jasm.clone(),
+        );
+
+        let mut buffer = vec![];
+        let mut writer = PaddedWriter::new(&mut buffer);
+        Compilable::<TestTarget>::compile(object, &Context::new(), &mut writer).unwrap();
+        drop(writer);
+        let read = CompilationObject::try_from(&*buffer).unwrap();
+        assert_eq!(read.file_location, PathBuf::from("out/std/math.jobj"));
+        assert_eq!(read.module, Identifier::from_iter(["std", "math"]));
+        assert_eq!(read.units, units);
+        assert_eq!(read.jasm, jasm);
+        assert_eq!(read.debug_info, None);
+
+        let mut corrupt = buffer.clone();
+        *corrupt.last_mut().unwrap() ^= 1;
+        assert!(CompilationObject::try_from(&*corrupt).is_err());
+        assert!(CompilationObject::try_from(&buffer[..buffer.len() - 1]).is_err());
+    }
+
+    #[test]
+    fn objects_of_other_versions() {
+        use crate::asm_version::Version;
+        use crate::assembly::instructions::Asm;
+
+        let jasm = vec![Asm::pub_label("main"), Asm::push(0u64), Asm::Return];
+        let object = CompilationObject::new(
+            PathBuf::from("main.jobj"),
+            Identifier::from("main"),
+            vec![],
+            jasm.clone(),
+        );
+        let mut buffer = object.to_bytes().unwrap();
+        let with_version = |buffer: &mut Vec<u8>, version: Version| {
+            buffer[..8].copy_from_slice(&version.to_magic_number().to_be_bytes());
+        };
+
+        // version 1.0 objects were a text header followed by the assembly
+        let unit = TranslationUnit::new(Visibility::Public, Primitive::Int.as_intermediate(), "x");
+        let mut legacy = Version::new(1, 0).to_magic_number().to_be_bytes().to_vec();
+        legacy.extend_from_slice(b"\n\"main.jobj\"\nmain\n{");
+        legacy.extend_from_slice(format!("{};", unit).as_bytes());
+        legacy.push(b'}');
+        legacy.extend(bincode::serialize(&jasm).unwrap());
+        let read = CompilationObject::try_from(&*legacy).unwrap();
+        assert_eq!(read.jasm, jasm);
+        assert_eq!(read.file_location, PathBuf::from("main.jobj"));
+        assert_eq!(read.module, Identifier::from("main"));
+        assert_eq!(read.units, vec![unit]);
+        assert_eq!(
+            ObjectFile::from_bytes(&read.to_bytes().unwrap())
+                .unwrap()
+                .magic_number,
+            Version::CURRENT.to_magic_number()
+        );
+
+        with_version(&mut buffer, Version::new(Version::CURRENT.major + 1, 0));
+        let error = CompilationObject::try_from(&*buffer).unwrap_err();
+        assert!(matches!(
+            error.into_err_and_bt().0,
+            JodinErrorType::UnsupportedAsmVersion { .. }
+        ));
+    }
 }
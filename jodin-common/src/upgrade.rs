@@ -0,0 +1,195 @@
+//! Upgrades the assembly of older [versions](Version) into the current instruction set, so that
+//! objects and images compiled for an older virtual machine can still be loaded.
+//!
+//! Every [upgrade](Upgrade) rewrites assembly of one version into the next, and an
+//! [upgrader](Upgrader) applies them in order until the assembly is of its target version. An
+//! upgrade replaces every instruction with exactly one instruction, so the labels, line tables and
+//! initializers that refer to instructions by their position stay correct.
+
+use crate::asm_version::Version;
+use crate::assembly::instructions::{Asm, Assembly};
+use crate::error::{JodinErrorType, JodinResult};
+use std::ops::RangeInclusive;
+
+/// Rewrites a single instruction of an older version
+pub type RewriteFn = fn(Asm) -> JodinResult<Asm>;
+
+/// Rewrites assembly of one version into the next version
+#[derive(Debug, Clone)]
+pub struct Upgrade {
+    /// The version the assembly is upgraded from
+    pub from: Version,
+    /// The version the assembly is upgraded to
+    pub to: Version,
+    rewrite: RewriteFn,
+}
+
+impl Upgrade {
+    /// Creates an upgrade, which rewrites every instruction with a function
+    pub fn new(from: Version, to: Version, rewrite: RewriteFn) -> Self {
+        Self { from, to, rewrite }
+    }
+
+    /// Rewrites assembly of the version this upgrade is from
+    pub fn apply(&self, jasm: Assembly) -> JodinResult<Assembly> {
+        jasm.into_iter().map(self.rewrite).collect()
+    }
+}
+
+/// The upgrades between every supported version
+fn upgrades() -> Vec<Upgrade> {
+    vec![
+        // 1.1 stores the version in the magic number, but has the same instructions
+        Upgrade::new(Version::new(1, 0), Version::new(1, 1), Ok),
+    ]
+}
+
+/// Upgrades assembly into a target version
+#[derive(Debug, Clone)]
+pub struct Upgrader {
+    target: Version,
+    upgrades: Vec<Upgrade>,
+}
+
+impl Default for Upgrader {
+    fn default() -> Self {
+        Self {
+            target: Version::CURRENT,
+            upgrades: upgrades(),
+        }
+    }
+}
+
+impl Upgrader {
+    /// Creates an upgrader into some version, without any upgrades
+    pub fn new(target: Version) -> Self {
+        Self {
+            target,
+            upgrades: vec![],
+        }
+    }
+
+    /// Adds an upgrade
+    pub fn with_upgrade(mut self, upgrade: Upgrade) -> Self {
+        self.upgrades.push(upgrade);
+        self
+    }
+
+    /// The version that assembly is upgraded into
+    pub fn target(&self) -> Version {
+        self.target
+    }
+
+    /// The versions that can be upgraded into the target version
+    pub fn supported(&self) -> RangeInclusive<Version> {
+        let mut oldest = self.target;
+        while let Some(upgrade) = self.upgrades.iter().find(|upgrade| upgrade.to == oldest) {
+            oldest = upgrade.from;
+        }
+        oldest..=self.target
+    }
+
+    /// Gets the version of a magic number, if the version can be upgraded
+    pub fn version_of(&self, magic_number: u64) -> JodinResult<Version> {
+        let version = Version::from_magic_number(magic_number).ok_or_else(|| {
+            JodinErrorType::InvalidObject(format!("unknown magic number 0x{:016x}", magic_number))
+        })?;
+        let supported = self.supported();
+        if !supported.contains(&version) {
+            return Err(JodinErrorType::UnsupportedAsmVersion { version, supported }.into());
+        }
+        Ok(version)
+    }
+
+    /// Rewrites assembly of some version into the target version
+    pub fn upgrade(&self, version: Version, mut jasm: Assembly) -> JodinResult<Assembly> {
+        let mut current = version;
+        while current != self.target {
+            let upgrade = self
+                .upgrades
+                .iter()
+                .find(|upgrade| upgrade.from == current)
+                .filter(|upgrade| upgrade.to <= self.target)
+                .ok_or_else(|| JodinErrorType::UnsupportedAsmVersion {
+                    version,
+                    supported: self.supported(),
+                })?;
+            jasm = upgrade.apply(jasm)?;
+            current = upgrade.to;
+        }
+        Ok(jasm)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::asm_version::SUPPORTED_VERSIONS;
+    use crate::assembly::value::Value;
+
+    #[test]
+    fn default_upgrader_supports_every_version() {
+        assert_eq!(Upgrader::default().supported(), SUPPORTED_VERSIONS);
+        let jasm = vec![Asm::pub_label("main"), Asm::push(1u64), Asm::Return];
+        let upgrader = Upgrader::default();
+        let legacy = upgrader
+            .version_of(Version::new(1, 0).to_magic_number())
+            .unwrap();
+        assert_eq!(upgrader.upgrade(legacy, jasm.clone()).unwrap(), jasm);
+    }
+
+    #[test]
+    fn upgrades_are_applied_in_order() {
+        let upgrader = Upgrader::new(Version::new(1, 2))
+            .with_upgrade(Upgrade::new(
+                Version::new(1, 1),
+                Version::new(1, 2),
+                |asm| {
+                    Ok(match asm {
+                        Asm::Push(Value::UInteger(value)) => Asm::Push(Value::UInteger(value * 10)),
+                        asm => asm,
+                    })
+                },
+            ))
+            .with_upgrade(Upgrade::new(
+                Version::new(1, 0),
+                Version::new(1, 1),
+                |asm| {
+                    Ok(match asm {
+                        Asm::Nop => Asm::push(1u64),
+                        asm => asm,
+                    })
+                },
+            ));
+        assert_eq!(
+            upgrader.supported(),
+            Version::new(1, 0)..=Version::new(1, 2)
+        );
+        let jasm = vec![Asm::Nop, Asm::push(2u64), Asm::Return];
+        assert_eq!(
+            upgrader.upgrade(Version::new(1, 0), jasm.clone()).unwrap(),
+            vec![Asm::push(10u64), Asm::push(20u64), Asm::Return]
+        );
+        assert_eq!(
+            upgrader.upgrade(Version::new(1, 1), jasm.clone()).unwrap(),
+            vec![Asm::Nop, Asm::push(20u64), Asm::Return]
+        );
+        assert_eq!(
+            upgrader.upgrade(Version::new(1, 2), jasm.clone()).unwrap(),
+            jasm
+        );
+    }
+
+    #[test]
+    fn unsupported_versions_are_rejected() {
+        let upgrader = Upgrader::default();
+        let newer = Version::new(Version::CURRENT.major + 1, 0);
+        let error = upgrader.version_of(newer.to_magic_number()).unwrap_err();
+        assert!(matches!(
+            error.into_err_and_bt().0,
+            JodinErrorType::UnsupportedAsmVersion { version, .. } if version == newer
+        ));
+        assert!(upgrader.version_of(0x1234).is_err());
+        assert!(upgrader.upgrade(newer, vec![]).is_err());
+    }
+}
@@ -0,0 +1,56 @@
+//! The command line arguments of the runner
+
+use clap::{App, ArgMatches};
+use std::ops::{Deref, DerefMut};
+
+/// Contains the clap app that takes in command line arguments
+pub struct JodinApp<'a, 'b: 'a>(App<'a, 'b>);
+
+impl<'a, 'b: 'a> Deref for JodinApp<'a, 'b> {
+    type Target = App<'a, 'b>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl<'a, 'b: 'a> DerefMut for JodinApp<'a, 'b> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.0
+    }
+}
+
+impl<'a, 'b: 'a> JodinApp<'a, 'b> {
+    /// Creates the clap application to be used for the cli
+    pub fn new() -> Self {
+        Self(clap_app!(jodin =>
+            (version: "0.1.0")
+            (author: "Joshua Radin <jradin16@gmail.com>")
+            (about: "Runs compiled jodin programs")
+            (@arg debug: -d --debug_level +takes_value {is_debug_level} "set the debug level, from 0 to 5 (defaults to 2, warnings)")
+            (@arg entry: -e --entry +takes_value "the function to run instead of main")
+            (@arg lazy_path: -L --lazy_path +takes_value ... "a directory of modules that are loaded once they're used")
+            (@arg plugin_path: -P --plugin_path +takes_value ... "a directory to load plugins from")
+            (@arg plugin_manifest: --plugin_manifest +takes_value ... "a manifest of plugins to load")
+            (@arg memory: -m --memory +takes_value possible_value[scoped minimum] "the memory implementation of the virtual machine")
+            (@arg max_instructions: --max_instructions +takes_value "stop the program after running this many instructions")
+            (@arg max_call_depth: --max_call_depth +takes_value "stop the program if calls are nested deeper than this")
+            (@arg coverage: --coverage +takes_value "write the line coverage of the program to this file, in the lcov format")
+            (@arg INPUT: +required +takes_value ... "the .jobj, .jdp, .jexe or .jasm files or directories of the program")
+            (@arg ARGS: +last +multiple "the arguments of the program, after --")
+        ))
+    }
+
+    /// Consumes the application, getting the command line arguments passed into the program
+    pub fn into_matches(self) -> ArgMatches<'a> {
+        self.0.get_matches()
+    }
+}
+
+/// Checks that a debug level is between 0 and 5
+fn is_debug_level(level: String) -> Result<(), String> {
+    match level.parse::<u8>() {
+        Ok(0..=5) => Ok(()),
+        _ => Err(format!("no debug level {level}, expected 0 to 5")),
+    }
+}
@@ -1,31 +1,135 @@
-use jasm_macros::{call, jasm, label, native, push, return_};
-use jodin_common::{block, init_logging};
-use jodin_rs_vm::core_traits::VirtualMachine;
-use jodin_rs_vm::mvp::MinimumALU;
+#[macro_use]
+extern crate clap;
+
+use crate::cli::JodinApp;
+use clap::ArgMatches;
+use jodin_common::assembly::value::Value;
+use jodin_common::image::ExecutableImage;
+use jodin_common::{init_logging, init_terminal_logging};
+use jodin_rs_vm::core_traits::{MemoryTrait, VirtualMachine};
+use jodin_rs_vm::error::VMError;
+use jodin_rs_vm::kernel::boot;
+use jodin_rs_vm::limits::ResourceLimits;
+use jodin_rs_vm::mvp::{MinimumALU, MinimumMemory};
 use jodin_rs_vm::scoped_memory::VMMemory;
+use jodin_rs_vm::standalone::embedded_image;
 use jodin_rs_vm::vm::VMBuilder;
 use jodin_vm_kernel::KernelPlugin;
+use jodin_vm_plugins::discovery::PluginSearchPath;
 use log::LevelFilter;
 use std::process::exit;
 
+mod cli;
+
 fn main() {
-    init_logging(LevelFilter::Info);
-    let mut vm_builder = VMBuilder::new()
+    // standalone executables run the image within them, and take the arguments of the program.
+    // They only log to the terminal, so running one doesn't leave a log file behind.
+    if let Ok(executable) = std::env::current_exe() {
+        match embedded_image(&executable) {
+            Ok(Some(image)) => {
+                init_terminal_logging(LevelFilter::Warn);
+                exit_with(run_standalone(image));
+            }
+            Err(e @ VMError::CorruptImage(_)) => {
+                eprintln!("{:?}: {}", executable, e);
+                exit(1);
+            }
+            // runners that can't read themselves still work as the normal command line
+            Ok(None) | Err(_) => {}
+        }
+    }
+
+    let matches = JodinApp::new().into_matches();
+
+    // only warnings are logged by default, so the log doesn't mix with the output of the program
+    let level = match matches.value_of("debug") {
+        Some("0") => LevelFilter::Off,
+        Some("1") => LevelFilter::Error,
+        Some("3") => LevelFilter::Info,
+        Some("4") => LevelFilter::Debug,
+        Some("5") => LevelFilter::Trace,
+        _ => LevelFilter::Warn,
+    };
+    init_logging(level);
+
+    let result = match matches.value_of("memory") {
+        Some("minimum") => run(MinimumMemory::default(), &matches),
+        _ => run(VMMemory::default(), &matches),
+    };
+    exit_with(result)
+}
+
+fn exit_with(result: Result<u32, VMError>) -> ! {
+    match result {
+        Ok(exit_code) => exit(exit_code as i32),
+        Err(e) => {
+            eprintln!("{}", e);
+            exit(1);
+        }
+    }
+}
+
+/// Boots the image of a standalone executable through the kernel, returning its exit code
+fn run_standalone(image: ExecutableImage) -> Result<u32, VMError> {
+    let mut vm = VMBuilder::new()
         .memory(VMMemory::default())
         .alu(MinimumALU)
-        .build()
-        .unwrap();
+        .plugin_path(PluginSearchPath::from_env())
+        .build()?;
+    vm.load_plugin::<KernelPlugin>()?;
+    let entry = Value::from(image.entry_point.as_str());
+    vm.load_image(image)?;
 
-    const KERNEL: &str = "target/debug/jodin_vm_kernel.dll";
+    let arguments = Value::Array(std::env::args().map(Value::from).collect());
+    boot(&mut vm, Value::Empty, arguments, entry)
+}
 
-    vm_builder.load_plugin::<KernelPlugin>();
+/// Boots the program given on the command line through the kernel, returning its exit code
+fn run<M: MemoryTrait>(memory: M, matches: &ArgMatches) -> Result<u32, VMError> {
+    let mut limits = ResourceLimits::new();
+    if matches.is_present("max_instructions") {
+        limits = limits.max_instructions(value_t_or_exit!(matches, "max_instructions", u64));
+    }
+    if matches.is_present("max_call_depth") {
+        limits = limits.max_call_depth(value_t_or_exit!(matches, "max_call_depth", usize));
+    }
+
+    let mut plugin_path = PluginSearchPath::from_env();
+    for directory in matches.values_of("plugin_path").into_iter().flatten() {
+        plugin_path = plugin_path.with_directory(directory);
+    }
+    let mut vm_builder = VMBuilder::new()
+        .memory(memory)
+        .alu(MinimumALU)
+        .plugin_path(plugin_path)
+        .limits(limits);
+    for manifest in matches.values_of("plugin_manifest").into_iter().flatten() {
+        vm_builder = vm_builder.plugin_manifest(manifest);
+    }
+    for directory in matches.values_of("lazy_path").into_iter().flatten() {
+        vm_builder = vm_builder.lazy_object_path(directory);
+    }
+    let mut vm = vm_builder.build()?;
+    vm.load_plugin::<KernelPlugin>()?;
 
-    vm_builder.load(jasm![
-        label!(pub start);
-        call!(~ __start);
-        return_!();
-    ]);
+    let inputs = matches.values_of("INPUT").unwrap().collect::<Vec<_>>();
+    let object_path = Value::Array(inputs.iter().map(|&input| Value::from(input)).collect());
+    // the first input is also the first argument of the program
+    let mut arguments = vec![inputs[0]];
+    arguments.extend(matches.values_of("ARGS").into_iter().flatten());
+    let arguments = Value::Array(arguments.into_iter().map(Value::from).collect());
+    let entry = match matches.value_of("entry") {
+        Some(entry) => Value::from(entry),
+        None => Value::Empty,
+    };
 
-    let exit_code = vm_builder.run("start").unwrap();
-    exit(exit_code as i32);
+    let coverage_output = matches.value_of("coverage");
+    if coverage_output.is_some() {
+        vm.enable_coverage();
+    }
+    let exit_code = boot(&mut vm, object_path, arguments, entry)?;
+    if let (Some(output), Some(coverage)) = (coverage_output, vm.take_coverage()) {
+        coverage.save_lcov(output)?;
+    }
+    Ok(exit_code)
 }
@@ -6,6 +6,7 @@ use crate::fault::Fault;
 use jodin_common::assembly::error::BytecodeError;
 use jodin_common::assembly::instructions::{Asm, Assembly, GetAsm};
 use jodin_common::assembly::value::Value;
+use jodin_common::image::ExecutableImage;
 
 use std::cell::RefCell;
 use std::collections::HashMap;
@@ -26,19 +27,48 @@ pub trait VirtualMachine {
     fn enclosed(&mut self, asm: &Assembly) -> Value;
 
     /// Loads some asm into a the virtual machine for future use. Automatically runs code within "static" blocks
-    fn load<A: GetAsm>(&mut self, asm: A);
+    ///
+    /// # Panic
+    /// Panics if the asm fails [verification](crate::verifier)
+    fn load<A: GetAsm>(&mut self, asm: A) {
+        if let Err(e) = self.try_load(asm) {
+            panic!("{}", e)
+        }
+    }
+
+    /// Loads some asm into the virtual machine once it's [verified](crate::verifier). Automatically
+    /// runs code within "static" blocks.
+    ///
+    /// Errors if one of its labels is already registered, or if its static code doesn't exit
+    /// with 0.
+    fn try_load<A: GetAsm>(&mut self, asm: A) -> Result<(), VMError>;
 
     /// Loads some asm into the virtual machine, then RUNS said ASM
-    fn load_static<A: GetAsm>(&mut self, asm: A);
+    ///
+    /// # Panic
+    /// Panics if the asm fails [verification](crate::verifier) or doesn't exit with 0
+    fn load_static<A: GetAsm>(&mut self, asm: A) {
+        if let Err(e) = self.try_load_static(asm) {
+            panic!("{}", e)
+        }
+    }
 
-    /// Runs the VM starting at a label
+    /// Loads some asm into the virtual machine once it's [verified](crate::verifier), then runs it.
+    /// Static asm is verified as if it's loaded in kernel mode.
+    fn try_load_static<A: GetAsm>(&mut self, asm: A) -> Result<(), VMError>;
+
+    /// Loads a statically linked image. The labels of an image are already known, so its assembly
+    /// isn't searched for them. The static modules of the image are run once all of it is loaded.
+    fn load_image(&mut self, image: ExecutableImage) -> Result<(), VMError>;
+
+    /// Runs the VM starting at a label. Errors if the label isn't loaded.
     fn run(&mut self, start_label: &str) -> Result<u32, VMError>;
 
     /// Runs the VM starting at a label
     fn run_from_index(&mut self, index: usize) -> Result<u32, VMError>;
 
-    /// Forces the VM to encounter a fault
-    fn fault(&mut self, fault: Fault);
+    /// Forces the VM to encounter a fault. Errors if the fault couldn't be handled.
+    fn fault(&mut self, fault: Fault) -> Result<(), VMError>;
 
     /// Checks whether the virtual machine is in kernel mode.
     ///
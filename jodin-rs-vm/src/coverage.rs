@@ -0,0 +1,103 @@
+//! Line coverage of the programs run by the virtual machine.
+//!
+//! While coverage is enabled, the virtual machine counts how many times every instruction was
+//! executed. The counts are mapped back to source lines using the line tables of the loaded
+//! objects. A line was executed as many times as the first instruction compiled from it.
+
+use jodin_common::assembly::debug_info::DebugInfo;
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::Write;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+
+/// The name used for lines whose source file isn't known
+pub const UNKNOWN_SOURCE: &str = "<unknown>";
+
+/// The line table of an object loaded into the virtual machine
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LoadedDebugInfo {
+    /// The instructions of the object within the virtual machine
+    pub instructions: Range<usize>,
+    pub debug_info: DebugInfo,
+}
+
+/// The lines of every source file that were found in line tables, and how many times each line was
+/// executed
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct CoverageReport {
+    files: BTreeMap<PathBuf, BTreeMap<u32, u64>>,
+}
+
+impl CoverageReport {
+    /// Creates the report from the execution counts of every instruction
+    pub(crate) fn new(counts: &[u64], loaded: &[LoadedDebugInfo]) -> Self {
+        let mut files: BTreeMap<PathBuf, BTreeMap<u32, u64>> = BTreeMap::new();
+        for object in loaded {
+            let source = object
+                .debug_info
+                .source
+                .clone()
+                .unwrap_or_else(|| PathBuf::from(UNKNOWN_SOURCE));
+            let lines = files.entry(source).or_default();
+            for (line, range) in object.debug_info.ranges(object.instructions.len()) {
+                if range.is_empty() {
+                    // nothing was compiled from this entry, so it can't be executed
+                    continue;
+                }
+                let pc = object.instructions.start + range.start;
+                let count = counts.get(pc).copied().unwrap_or(0);
+                let entry = lines.entry(line).or_default();
+                *entry = (*entry).max(count);
+            }
+        }
+        Self { files }
+    }
+
+    /// The source files within the report
+    pub fn files(&self) -> impl Iterator<Item = &Path> {
+        self.files.keys().map(PathBuf::as_path)
+    }
+
+    /// The execution counts of every line of a file
+    pub fn lines(&self, file: impl AsRef<Path>) -> Option<&BTreeMap<u32, u64>> {
+        self.files.get(file.as_ref())
+    }
+
+    /// The lines of a file that were never executed
+    pub fn uncovered_lines(&self, file: impl AsRef<Path>) -> Vec<u32> {
+        self.lines(file)
+            .map(|lines| {
+                lines
+                    .iter()
+                    .filter(|(_, &count)| count == 0)
+                    .map(|(&line, _)| line)
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    /// Writes the report in the lcov tracefile format
+    pub fn write_lcov<W: Write>(&self, mut writer: W) -> std::io::Result<()> {
+        for (file, lines) in &self.files {
+            writeln!(writer, "TN:")?;
+            writeln!(writer, "SF:{}", file.display())?;
+            for (line, count) in lines {
+                writeln!(writer, "DA:{},{}", line, count)?;
+            }
+            writeln!(writer, "LF:{}", lines.len())?;
+            writeln!(
+                writer,
+                "LH:{}",
+                lines.values().filter(|&&count| count > 0).count()
+            )?;
+            writeln!(writer, "end_of_record")?;
+        }
+        Ok(())
+    }
+
+    /// Saves the report to a file in the lcov tracefile format
+    pub fn save_lcov<P: AsRef<Path>>(&self, path: P) -> std::io::Result<()> {
+        self.write_lcov(File::create(path)?)
+    }
+}
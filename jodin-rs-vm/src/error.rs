@@ -1,3 +1,4 @@
+use crate::verifier::VerifyError;
 use jodin_common::assembly::value::Value;
 use jodin_common::error::JodinError;
 use std::error::Error as StdError;
@@ -12,6 +13,35 @@ pub enum VMError {
     ExitCodeInvalidType(Value),
     #[error("Invalid type found (expected= {expected}, found= {value:?})")]
     InvalidType { value: Value, expected: String },
+    #[error("The virtual machine was paused")]
+    Paused,
+    #[error("Invalid snapshot: {0}")]
+    InvalidSnapshot(String),
+    #[error("Replay diverged at 0x{pc:016X} ({instruction}): expected {expected}, found {found}")]
+    ReplayDivergence {
+        pc: usize,
+        instruction: String,
+        expected: String,
+        found: String,
+    },
+    #[error("Can't record {0}")]
+    Unrecordable(String),
+    #[error("The instruction limit of {0} was exceeded")]
+    InstructionLimitExceeded(u64),
+    #[error("The call depth limit of {0} was exceeded")]
+    CallDepthExceeded(usize),
+    #[error("Symbol {0} could not be found")]
+    MissingSymbol(String),
+    #[error("Guest code called by a plugin did not return")]
+    GuestCallIncomplete,
+    #[error("Label {0:?} is already registered")]
+    LabelAlreadyRegistered(String),
+    #[error("Verification failed: {}", .0.iter().map(ToString::to_string).collect::<Vec<_>>().join("; "))]
+    VerificationFailed(Vec<VerifyError>),
+    #[error("Static code exited with {0}")]
+    StaticCodeFailed(u32),
+    #[error("The embedded image is corrupt: {0}")]
+    CorruptImage(String),
     #[error("Given file is incorrect type")]
     WrongFileType,
     #[error("IO Error: {0}")]
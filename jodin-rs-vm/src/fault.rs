@@ -4,7 +4,7 @@ use jodin_common::assembly::value::Value;
 
 /// A fault is a VM-level exception. The fault should return to the original point of execution once
 /// it completes.
-#[derive(Debug)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum Fault {
     /// The following symbol is missing
     MissingSymbol(String),
@@ -13,6 +13,15 @@ pub enum Fault {
 }
 
 impl Fault {
+    /// Gets a fault by its name, such as `MissingSymbol`. The detail is used by faults that carry
+    /// a value. Double faults can't be raised this way.
+    pub fn from_name(name: &str, detail: &str) -> Option<Self> {
+        match name {
+            "MissingSymbol" => Some(Fault::MissingSymbol(detail.to_string())),
+            _ => None,
+        }
+    }
+
     pub fn handle_fault<'vm, 'l, M: MemoryTrait, A: ArithmeticsTrait>(
         &self,
         _handle: &FaultHandle,
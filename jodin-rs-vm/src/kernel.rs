@@ -1 +1,33 @@
+//! Booting programs through the [kernel plugin](jodin_vm_kernel::KernelPlugin), which loads the
+//! modules of a program and calls its entry point with the arguments of the program.
 
+use crate::core_traits::VirtualMachine;
+use crate::error::VMError;
+use jasm_macros::{call, jasm, return_};
+use jodin_common::assembly::instructions::Asm;
+use jodin_common::assembly::value::Value;
+use jodin_common::block;
+
+/// The label of the code that calls the kernel. Labels starting with `@@` can be registered again,
+/// so it can't clash with a label of the program, and programs can be booted more than once.
+pub const BOOT_LABEL: &str = "@@boot";
+
+/// Calls the kernel with the object path, the arguments and the entry point of a program,
+/// returning the exit code of the program. The kernel plugin must already be loaded.
+///
+/// Empty values are ignored by the kernel, so an empty entry point runs `main`, or the entry
+/// point of a loaded image.
+pub fn boot<VM: VirtualMachine>(
+    vm: &mut VM,
+    object_path: Value,
+    arguments: Value,
+    entry: Value,
+) -> Result<u32, VMError> {
+    vm.try_load(jasm![
+        Asm::pub_label(BOOT_LABEL);
+        call!(~ __start, Asm::push(object_path), Asm::push(arguments), Asm::push(entry));
+        return_!();
+    ])?;
+
+    vm.run(BOOT_LABEL)
+}
@@ -34,15 +34,25 @@ extern crate log;
 #[macro_use]
 extern crate anyhow;
 
+#[macro_use]
+extern crate serde_derive;
+
 use crate::core_traits::{ArithmeticsTrait, MemoryTrait, VirtualMachine};
 use jodin_common::core::function_names::{CALL, RECEIVE_MESSAGE};
 
 pub mod core_traits;
+pub mod coverage;
 pub use core_traits::*;
 pub mod error;
 pub mod fault;
 pub mod kernel;
+pub mod limits;
 pub mod loadables;
 pub mod mvp;
+pub mod profiler;
+pub mod replay;
 pub mod scoped_memory;
+pub mod snapshot;
+pub mod standalone;
+pub mod verifier;
 pub mod vm;
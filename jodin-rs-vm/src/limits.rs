@@ -0,0 +1,32 @@
+//! Limits on the resources a program can use while it runs in the virtual machine.
+//!
+//! Exceeding a limit stops the virtual machine with an error, unlike
+//! [pausing](crate::vm::VM::pause_after), which can be resumed.
+
+/// The resources a program can use. Resources without a limit are unbounded.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct ResourceLimits {
+    /// The maximum amount of instructions that can be run
+    pub max_instructions: Option<u64>,
+    /// The maximum amount of nested calls
+    pub max_call_depth: Option<usize>,
+}
+
+impl ResourceLimits {
+    /// No limits on any resource
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Limits the amount of instructions that can be run
+    pub fn max_instructions(mut self, instructions: u64) -> Self {
+        self.max_instructions = Some(instructions);
+        self
+    }
+
+    /// Limits the amount of nested calls
+    pub fn max_call_depth(mut self, depth: usize) -> Self {
+        self.max_call_depth = Some(depth);
+        self
+    }
+}
@@ -2,8 +2,14 @@
 
 use crate::error::VMError;
 use crate::{VMTryLoadable, VirtualMachine};
+use jodin_common::assembly::instructions::Asm;
+use jodin_common::assembly::text::{parse_jasm, JASM_EXTENSION};
+use jodin_common::identifier::Identifier;
+use jodin_common::image::{ExecutableImage, IMAGE_EXTENSION};
+use jodin_common::package::{Package, PACKAGE_EXTENSION};
 use jodin_common::unit::CompilationObject;
 
+use std::collections::HashSet;
 use std::path::{Path, PathBuf};
 
 /// A regular file
@@ -30,19 +36,16 @@ impl VMTryLoadable for RegularFile {
         if let Some(ext) = self.0.extension() {
             if ext == "jobj" {
                 let compilable = CompilationObject::try_from(self.0.as_path())?;
-                if self
-                    .0
-                    .file_name()
-                    .ok_or(VMError::WrongFileType)?
-                    .to_str()
-                    .unwrap()
-                    .starts_with("static")
-                {
-                    vm.load_static(compilable);
-                } else {
-                    vm.load(compilable);
-                }
-                return Ok(());
+                return load_object(vm, &self.0, compilable);
+            } else if ext == PACKAGE_EXTENSION {
+                return Package::open(&self.0)?.try_load_into_vm(vm);
+            } else if ext == IMAGE_EXTENSION {
+                return ExecutableImage::open(&self.0)?.try_load_into_vm(vm);
+            } else if ext == JASM_EXTENSION {
+                let jasm = parse_jasm(&std::fs::read_to_string(&self.0)?)?;
+                let object =
+                    CompilationObject::new(self.0.clone(), Identifier::empty(), vec![], jasm);
+                return load_object(vm, &self.0, object);
             }
         }
 
@@ -50,6 +53,53 @@ impl VMTryLoadable for RegularFile {
     }
 }
 
+impl VMTryLoadable for Package {
+    fn try_load_into_vm<VM>(self, vm: &mut VM) -> Result<(), VMError>
+    where
+        VM: VirtualMachine,
+    {
+        for entry in self.into_entries() {
+            load_object(vm, &entry.name, entry.object)?;
+        }
+        Ok(())
+    }
+}
+
+impl VMTryLoadable for ExecutableImage {
+    fn try_load_into_vm<VM>(self, vm: &mut VM) -> Result<(), VMError>
+    where
+        VM: VirtualMachine,
+    {
+        vm.load_image(self)
+    }
+}
+
+/// Loads an object, running it when it's a static object
+fn load_object<VM: VirtualMachine>(
+    vm: &mut VM,
+    path: &Path,
+    object: CompilationObject,
+) -> Result<(), VMError> {
+    if path
+        .file_name()
+        .ok_or(VMError::WrongFileType)?
+        .to_str()
+        .unwrap()
+        .starts_with("static")
+    {
+        vm.try_load_static(object)
+    } else {
+        vm.try_load(object)
+    }
+}
+
+/// Whether a file is an object, a package, an image or textual assembly
+fn is_loadable_file(path: &Path) -> bool {
+    path.extension().is_some_and(|ext| {
+        ext == "jobj" || ext == PACKAGE_EXTENSION || ext == IMAGE_EXTENSION || ext == JASM_EXTENSION
+    })
+}
+
 /// Represents a directory that can be loaded
 #[derive(Debug)]
 pub struct Directory(PathBuf);
@@ -75,6 +125,10 @@ impl VMTryLoadable for Directory {
         for entry in dir {
             let entry = entry?;
             let path = entry.path();
+            // other files, such as the build cache of the compiler, can be kept alongside objects
+            if path.is_file() && !is_loadable_file(&path) {
+                continue;
+            }
             let node = FileSystemNode::try_from(path)?;
             node.try_load_into_vm(vm)?;
         }
@@ -131,3 +185,99 @@ impl VMTryLoadable for PathBuf {
         FileSystemNode::try_from(self)?.try_load_into_vm(vm)
     }
 }
+
+/// Directories of modules that are only loaded once one of their symbols is used. Modules are
+/// found by their namespace, so the module `std::math` is searched for in `std/math` within each
+/// directory.
+#[derive(Debug, Default, Clone)]
+pub struct LazyObjectPath {
+    directories: Vec<PathBuf>,
+    loaded: HashSet<PathBuf>,
+}
+
+impl LazyObjectPath {
+    /// An object path without any directories
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a directory to the end of the object path
+    pub fn with_directory<P: AsRef<Path>>(mut self, directory: P) -> Self {
+        self.directories.push(directory.as_ref().to_path_buf());
+        self
+    }
+
+    /// The directories of the object path
+    pub fn directories(&self) -> &[PathBuf] {
+        &self.directories
+    }
+
+    /// Finds the module that owns a symbol, searching the namespace of the symbol and then its
+    /// parents. A module is only found once, so it isn't loaded again.
+    pub fn find_module(&mut self, symbol: &str) -> Result<Option<PathBuf>, VMError> {
+        let module = self.search(symbol)?;
+        if let Some(path) = &module {
+            self.loaded.insert(path.clone());
+        }
+        Ok(module)
+    }
+
+    /// Whether a module that isn't loaded yet owns a symbol
+    pub fn provides(&self, symbol: &str) -> bool {
+        matches!(self.search(symbol), Ok(Some(_)))
+    }
+
+    /// Searches for the module that owns a symbol, skipping the modules that were already found
+    fn search(&self, symbol: &str) -> Result<Option<PathBuf>, VMError> {
+        let symbol_id = symbol.parse::<Identifier>().unwrap();
+        let mut namespace = symbol_id.into_parent();
+        loop {
+            let relative = namespace.clone().map(PathBuf::from).unwrap_or_default();
+            for directory in &self.directories {
+                for path in objects_in(&directory.join(&relative))? {
+                    if self.loaded.contains(&path) {
+                        continue;
+                    }
+                    let object = CompilationObject::try_from(path.as_path())?;
+                    if in_namespace(&object, namespace.as_ref()) && defines(&object, symbol) {
+                        return Ok(Some(path));
+                    }
+                }
+            }
+            namespace = match namespace {
+                Some(namespace) => namespace.into_parent(),
+                None => return Ok(None),
+            };
+        }
+    }
+}
+
+/// The objects directly within a directory, sorted by their path
+fn objects_in(directory: &Path) -> Result<Vec<PathBuf>, VMError> {
+    if !directory.is_dir() {
+        return Ok(vec![]);
+    }
+    let mut objects = vec![];
+    for entry in std::fs::read_dir(directory)? {
+        let path = entry?.path();
+        if path.is_file() && path.extension().is_some_and(|ext| ext == "jobj") {
+            objects.push(path);
+        }
+    }
+    objects.sort();
+    Ok(objects)
+}
+
+fn in_namespace(object: &CompilationObject, namespace: Option<&Identifier>) -> bool {
+    match namespace {
+        Some(namespace) => &object.module == namespace,
+        None => object.module.is_empty(),
+    }
+}
+
+fn defines(object: &CompilationObject, symbol: &str) -> bool {
+    object
+        .jasm
+        .iter()
+        .any(|asm| matches!(asm, Asm::PublicLabel(label) if label == symbol))
+}
@@ -1,3 +1,5 @@
+use crate::error::VMError;
+use crate::snapshot::{HeapReader, HeapWriter, SnapshotMemory, SnapshotValue};
 use crate::{ArithmeticsTrait, MemoryTrait};
 use jodin_common::assembly::error::BytecodeError;
 use jodin_common::assembly::value::Value;
@@ -76,6 +78,41 @@ impl MemoryTrait for MinimumMemory {
     }
 }
 
+/// The snapshot image of a [MinimumMemory]
+#[derive(Debug, Serialize, Deserialize)]
+pub struct MinimumMemoryImage {
+    stack: Vec<SnapshotValue>,
+    vars: Vec<(usize, usize)>,
+}
+
+impl SnapshotMemory for MinimumMemory {
+    type Image = MinimumMemoryImage;
+
+    fn to_image(&self, heap: &mut HeapWriter) -> Self::Image {
+        let mut vars = self
+            .vars
+            .iter()
+            .map(|(&var, cell)| (var, heap.cell(cell)))
+            .collect::<Vec<_>>();
+        vars.sort();
+        MinimumMemoryImage {
+            stack: heap.values(&self.stack),
+            vars,
+        }
+    }
+
+    fn from_image(image: Self::Image, heap: &HeapReader) -> Result<Self, VMError> {
+        Ok(Self {
+            stack: heap.values(&image.stack)?,
+            vars: image
+                .vars
+                .into_iter()
+                .map(|(var, id)| Ok((var, heap.cell(id)?)))
+                .collect::<Result<_, VMError>>()?,
+        })
+    }
+}
+
 #[derive(Default)]
 pub struct MinimumALU;
 
@@ -0,0 +1,283 @@
+//! An instruction level profiler for the virtual machine.
+//!
+//! Every executed instruction is attributed to the function it belongs to, which is the most recent
+//! public label before the instruction. Time spent within native methods and plugin labels is
+//! attributed to pseudo-frames named `[native] <method>` and `[plugin] <label>`. Guest code that a
+//! plugin calls back into is nested within the frame of the plugin label, but isn't counted as the
+//! plugin's own time.
+//!
+//! The results can be written either as a text report, or as folded stacks that flamegraph tools
+//! accept. Folded stacks are weighted by nanoseconds of self time.
+
+use std::collections::{BTreeMap, HashMap};
+use std::fs::File;
+use std::io::Write;
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+/// The name given to instructions that aren't after any public label
+pub const UNKNOWN_FUNCTION: &str = "<none>";
+
+/// What was measured for a single function
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct FunctionProfile {
+    /// The number of times the function was entered
+    pub calls: u64,
+    /// The number of instructions executed within the function itself
+    pub instructions: u64,
+    /// The time spent within the function itself, excluding the functions it called
+    pub self_time: Duration,
+}
+
+/// The results of profiling a virtual machine
+#[derive(Debug, Default, Clone)]
+pub struct Profile {
+    functions: BTreeMap<String, FunctionProfile>,
+    folded: BTreeMap<String, Duration>,
+    total_instructions: u64,
+    total_time: Duration,
+}
+
+impl Profile {
+    /// The measurements of every function, by name
+    pub fn functions(&self) -> &BTreeMap<String, FunctionProfile> {
+        &self.functions
+    }
+
+    /// The measurements of a single function
+    pub fn function(&self, name: &str) -> Option<&FunctionProfile> {
+        self.functions.get(name)
+    }
+
+    /// The total number of instructions that were executed
+    pub fn total_instructions(&self) -> u64 {
+        self.total_instructions
+    }
+
+    /// The total time spent while profiling
+    pub fn total_time(&self) -> Duration {
+        self.total_time
+    }
+
+    /// Writes a text report of every function, sorted by self time
+    pub fn write_report<W: Write>(&self, mut writer: W) -> std::io::Result<()> {
+        writeln!(
+            writer,
+            "{} instructions in {:.6} seconds",
+            self.total_instructions,
+            self.total_time.as_secs_f64()
+        )?;
+        writeln!(
+            writer,
+            "{:<32} {:>10} {:>14} {:>14} {:>8}",
+            "function", "calls", "instructions", "self time (s)", "%"
+        )?;
+        let mut functions = self.functions.iter().collect::<Vec<_>>();
+        functions.sort_by_key(|(_, profile)| std::cmp::Reverse(profile.self_time));
+        for (name, profile) in functions {
+            let percent = if self.total_time.is_zero() {
+                0.0
+            } else {
+                profile.self_time.as_secs_f64() / self.total_time.as_secs_f64() * 100.0
+            };
+            writeln!(
+                writer,
+                "{:<32} {:>10} {:>14} {:>14.6} {:>7.2}%",
+                name,
+                profile.calls,
+                profile.instructions,
+                profile.self_time.as_secs_f64(),
+                percent
+            )?;
+        }
+        Ok(())
+    }
+
+    /// Writes the folded stacks, one stack per line
+    pub fn write_folded<W: Write>(&self, mut writer: W) -> std::io::Result<()> {
+        for (stack, time) in &self.folded {
+            writeln!(writer, "{} {}", stack, time.as_nanos())?;
+        }
+        Ok(())
+    }
+
+    /// Saves the text report to a file
+    pub fn save_report<P: AsRef<Path>>(&self, path: P) -> std::io::Result<()> {
+        self.write_report(File::create(path)?)
+    }
+
+    /// Saves the folded stacks to a file
+    pub fn save_folded<P: AsRef<Path>>(&self, path: P) -> std::io::Result<()> {
+        self.write_folded(File::create(path)?)
+    }
+}
+
+/// A native method or plugin label that is currently running
+#[derive(Debug)]
+struct ExternalFrame {
+    /// The index of the frame of the native method or plugin label
+    index: usize,
+    /// The number of program counters when the frame was entered. Guest code that a plugin calls
+    /// back into has the counters after these.
+    counters: usize,
+    start: Instant,
+    children: Duration,
+}
+
+/// Collects a [Profile] while the virtual machine runs
+#[derive(Debug)]
+pub(crate) struct Profiler {
+    /// The function names of instructions that have already been looked up
+    names: HashMap<usize, String>,
+    frames: Vec<String>,
+    external: Vec<ExternalFrame>,
+    /// The time spent in natives and plugins during the current instruction
+    external_time: Duration,
+    started: Instant,
+    profile: Profile,
+}
+
+impl Profiler {
+    pub(crate) fn new() -> Self {
+        Self {
+            names: HashMap::new(),
+            frames: vec![],
+            external: vec![],
+            external_time: Duration::ZERO,
+            started: Instant::now(),
+            profile: Profile::default(),
+        }
+    }
+
+    /// Gets the function an instruction belongs to
+    pub(crate) fn function_name<F>(&mut self, pc: usize, lookup: F) -> String
+    where
+        F: FnOnce(usize) -> Option<String>,
+    {
+        self.names
+            .entry(pc)
+            .or_insert_with(|| lookup(pc).unwrap_or_else(|| UNKNOWN_FUNCTION.to_string()))
+            .clone()
+    }
+
+    /// Makes the frames match the program counters of the virtual machine, one frame per counter.
+    /// Only the frames above the most recent native method or plugin label are changed.
+    pub(crate) fn sync<F>(&mut self, counters: &[usize], mut lookup: F)
+    where
+        F: FnMut(usize) -> Option<String>,
+    {
+        let (base, base_counters) = self.base();
+        let mut counters = &counters[base_counters.min(counters.len())..];
+        if !self.external.is_empty() {
+            // guest code called by a plugin returns to a counter of 0, which isn't a function
+            if let [0, rest @ ..] = counters {
+                counters = rest;
+            }
+        }
+        self.frames.truncate(base + counters.len());
+        while self.frames.len() < base + counters.len() {
+            let pc = counters[self.frames.len() - base];
+            let name = self.function_name(pc, &mut lookup);
+            self.enter(name);
+        }
+        if let Some(&pc) = counters.last() {
+            let name = self.function_name(pc, &mut lookup);
+            if self.frames.last() != Some(&name) {
+                self.frames.pop();
+                self.enter(name);
+            }
+        }
+    }
+
+    /// The first frame that belongs to guest code, and the number of program counters below it
+    fn base(&self) -> (usize, usize) {
+        self.external
+            .last()
+            .map(|frame| (frame.index + 1, frame.counters))
+            .unwrap_or((0, 0))
+    }
+
+    fn enter(&mut self, name: String) {
+        self.profile
+            .functions
+            .entry(name.clone())
+            .or_default()
+            .calls += 1;
+        self.frames.push(name);
+    }
+
+    /// Attributes an executed instruction to the current frame
+    pub(crate) fn instruction(&mut self, elapsed: Duration) {
+        let self_time = elapsed.saturating_sub(std::mem::take(&mut self.external_time));
+        let name = self
+            .frames
+            .last()
+            .cloned()
+            .unwrap_or_else(|| UNKNOWN_FUNCTION.to_string());
+        let function = self.profile.functions.entry(name).or_default();
+        function.instructions += 1;
+        function.self_time += self_time;
+        self.profile.total_instructions += 1;
+        self.add_folded(self_time);
+        // guest code called back into by a plugin isn't part of the plugin's own time
+        if let Some(external) = self.external.last_mut() {
+            external.children += elapsed;
+        }
+    }
+
+    /// Starts timing a native method or plugin label
+    pub(crate) fn enter_external(&mut self, name: String) {
+        let (base, base_counters) = self.base();
+        let counters = base_counters + self.frames.len().saturating_sub(base);
+        let index = self.frames.len();
+        self.enter(name);
+        self.external.push(ExternalFrame {
+            index,
+            counters,
+            start: Instant::now(),
+            children: Duration::ZERO,
+        });
+    }
+
+    /// Stops timing the most recent native method or plugin label
+    pub(crate) fn exit_external(&mut self) {
+        let frame = match self.external.pop() {
+            None => return,
+            Some(frame) => frame,
+        };
+        let total = frame.start.elapsed();
+        let self_time = total.saturating_sub(frame.children);
+        // guest code called by a plugin may have left frames that haven't been synced yet
+        self.frames.truncate(frame.index + 1);
+        if let Some(name) = self.frames.last() {
+            self.profile
+                .functions
+                .entry(name.clone())
+                .or_default()
+                .self_time += self_time;
+        }
+        self.add_folded(self_time);
+        self.frames.pop();
+        match self.external.last_mut() {
+            Some(parent) if parent.index + 1 == frame.index => parent.children += total,
+            _ => self.external_time += total,
+        }
+    }
+
+    fn add_folded(&mut self, time: Duration) {
+        if self.frames.is_empty() {
+            return;
+        }
+        *self
+            .profile
+            .folded
+            .entry(self.frames.join(";"))
+            .or_default() += time;
+    }
+
+    /// Finishes profiling
+    pub(crate) fn finish(mut self) -> Profile {
+        self.profile.total_time = self.started.elapsed();
+        self.profile
+    }
+}
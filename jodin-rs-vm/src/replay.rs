@@ -0,0 +1,242 @@
+//! Recording and replaying of the interactions a program has with the world outside of the
+//! virtual machine.
+//!
+//! While recording, every native method, every value produced by a plugin, and every read from the
+//! standard input is logged into a [Trace]. While replaying, plugins and the standard input are
+//! never touched. Instead, the recorded results are fed back to the program, so a failure can be
+//! reproduced exactly. Output natives such as `print` still write while replaying, so the output of
+//! the replay can be compared against the original run.
+//!
+//! Native handles only mean something to the plugin that created them, which isn't called during a
+//! replay, so a recording fails once a plugin produces one.
+
+use crate::error::VMError;
+use jodin_common::assembly::value::Value;
+use std::fmt::{Display, Formatter};
+use std::fs::File;
+use std::io::{Read, Write};
+use std::path::Path;
+
+/// The magic bytes at the beginning of every trace file
+pub const TRACE_MAGIC: &[u8; 8] = b"JODINTRC";
+
+/// A single interaction between a program and the world outside of the virtual machine
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub enum TraceEvent {
+    /// A native method was invoked
+    Native { pc: usize, method: String },
+    /// A plugin produced a value for a label. Because the plugin isn't called during a replay,
+    /// its effect on the stack and the number of events it caused are recorded as well.
+    Plugin {
+        pc: usize,
+        label: String,
+        popped: usize,
+        pushed: Vec<Value>,
+        result: Value,
+        nested: usize,
+    },
+    /// A plugin failed to produce a value for a label. The error is reproduced during a replay.
+    PluginFailed {
+        pc: usize,
+        label: String,
+        error: String,
+        nested: usize,
+    },
+    /// A line was read from the standard input. `None` if the input was exhausted.
+    Input { pc: usize, line: Option<String> },
+}
+
+impl TraceEvent {
+    /// The instruction that caused this event
+    pub fn pc(&self) -> usize {
+        match self {
+            TraceEvent::Native { pc, .. } => *pc,
+            TraceEvent::Plugin { pc, .. } => *pc,
+            TraceEvent::PluginFailed { pc, .. } => *pc,
+            TraceEvent::Input { pc, .. } => *pc,
+        }
+    }
+
+    /// Checks whether two events were caused by the same interaction, ignoring their results
+    pub fn same_interaction(&self, other: &Self) -> bool {
+        match (self, other) {
+            (
+                TraceEvent::Native { pc, method },
+                TraceEvent::Native {
+                    pc: o_pc,
+                    method: o_method,
+                },
+            ) => pc == o_pc && method == o_method,
+            (
+                TraceEvent::Plugin { pc, label, .. } | TraceEvent::PluginFailed { pc, label, .. },
+                TraceEvent::Plugin {
+                    pc: o_pc,
+                    label: o_label,
+                    ..
+                }
+                | TraceEvent::PluginFailed {
+                    pc: o_pc,
+                    label: o_label,
+                    ..
+                },
+            ) => pc == o_pc && label == o_label,
+            (TraceEvent::Input { pc, .. }, TraceEvent::Input { pc: o_pc, .. }) => pc == o_pc,
+            _ => false,
+        }
+    }
+}
+
+impl Display for TraceEvent {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TraceEvent::Native { pc, method } => {
+                write!(f, "native {method:?} at 0x{pc:016X}")
+            }
+            TraceEvent::Plugin { pc, label, .. } | TraceEvent::PluginFailed { pc, label, .. } => {
+                write!(f, "plugin label {label:?} at 0x{pc:016X}")
+            }
+            TraceEvent::Input { pc, .. } => write!(f, "input read at 0x{pc:016X}"),
+        }
+    }
+}
+
+/// Whether a value is or contains a native handle, which can't be recorded
+pub(crate) fn contains_native_handle(value: &Value) -> bool {
+    match value {
+        Value::NativeHandle(_) => true,
+        Value::Array(values) => values.iter().any(contains_native_handle),
+        Value::Dictionary(values) => values.values().any(contains_native_handle),
+        Value::Reference(reference) => contains_native_handle(&reference.borrow()),
+        _ => false,
+    }
+}
+
+/// A log of the interactions of a program, in the order they occurred
+#[derive(Debug, Default, Clone, Serialize, Deserialize, PartialEq)]
+pub struct Trace {
+    events: Vec<TraceEvent>,
+}
+
+impl Trace {
+    /// The recorded events
+    pub fn events(&self) -> &[TraceEvent] {
+        &self.events
+    }
+
+    /// Writes the trace, prefixed by [TRACE_MAGIC]
+    pub fn write_to<W: Write>(&self, mut writer: W) -> Result<(), VMError> {
+        writer.write_all(TRACE_MAGIC)?;
+        bincode::serialize_into(&mut writer, self).map_err(|e| anyhow!("{e}"))?;
+        writer.flush()?;
+        Ok(())
+    }
+
+    /// Reads a trace that was written using [write_to](Self::write_to)
+    pub fn read_from<R: Read>(mut reader: R) -> Result<Self, VMError> {
+        let mut magic = [0u8; 8];
+        reader.read_exact(&mut magic)?;
+        if &magic != TRACE_MAGIC {
+            return Err(anyhow!("Not a jodin trace file").into());
+        }
+        Ok(bincode::deserialize_from(reader).map_err(|e| anyhow!("{e}"))?)
+    }
+
+    /// Saves the trace to a file
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), VMError> {
+        self.write_to(File::create(path)?)
+    }
+
+    /// Loads a trace from a file
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, VMError> {
+        Self::read_from(File::open(path)?)
+    }
+}
+
+/// Whether the virtual machine is recording or replaying a trace
+#[derive(Debug)]
+pub(crate) enum TraceMode {
+    Recording(Trace),
+    Replaying { trace: Trace, next: usize },
+}
+
+impl TraceMode {
+    /// While replaying, takes the next recorded event, making sure that it was caused by the same
+    /// interaction as `expected`. Returns `None` if not replaying.
+    pub(crate) fn replayed(
+        &mut self,
+        expected: &TraceEvent,
+    ) -> Result<Option<TraceEvent>, TraceEventMismatch> {
+        match self {
+            TraceMode::Recording(_) => Ok(None),
+            TraceMode::Replaying { trace, next } => match trace.events.get(*next) {
+                Some(recorded) if recorded.same_interaction(expected) => {
+                    *next += 1;
+                    Ok(Some(recorded.clone()))
+                }
+                Some(recorded) => Err(TraceEventMismatch {
+                    expected: recorded.to_string(),
+                    found: expected.to_string(),
+                }),
+                None => Err(TraceEventMismatch {
+                    expected: "the end of the trace".to_string(),
+                    found: expected.to_string(),
+                }),
+            },
+        }
+    }
+
+    /// While recording, adds an event to the trace
+    pub(crate) fn record(&mut self, event: TraceEvent) {
+        if let TraceMode::Recording(trace) = self {
+            trace.events.push(event);
+        }
+    }
+
+    /// While recording, reserves a spot in the trace for an event whose result isn't known yet
+    pub(crate) fn reserve(&mut self) -> Option<usize> {
+        match self {
+            TraceMode::Recording(trace) => {
+                trace.events.push(TraceEvent::Native {
+                    pc: 0,
+                    method: String::new(),
+                });
+                Some(trace.events.len() - 1)
+            }
+            TraceMode::Replaying { .. } => None,
+        }
+    }
+
+    /// Fills in a reserved event. If the event is a plugin event, the number of events recorded
+    /// since the reservation is stored within it.
+    pub(crate) fn complete(&mut self, index: usize, mut event: TraceEvent) {
+        if let TraceMode::Recording(trace) = self {
+            if let TraceEvent::Plugin { nested, .. } | TraceEvent::PluginFailed { nested, .. } =
+                &mut event
+            {
+                *nested = trace.events.len() - index - 1;
+            }
+            trace.events[index] = event;
+        }
+    }
+
+    /// While replaying, skips over events
+    pub(crate) fn skip(&mut self, count: usize) {
+        if let TraceMode::Replaying { next, .. } = self {
+            *next += count;
+        }
+    }
+
+    pub(crate) fn into_trace(self) -> Trace {
+        match self {
+            TraceMode::Recording(trace) => trace,
+            TraceMode::Replaying { trace, .. } => trace,
+        }
+    }
+}
+
+/// The difference between the recorded event and the event that occurred during a replay
+#[derive(Debug)]
+pub(crate) struct TraceEventMismatch {
+    pub expected: String,
+    pub found: String,
+}
@@ -1,5 +1,7 @@
 //! The scoped memory module is the improved memory abstraction for the VM
 
+use crate::error::VMError;
+use crate::snapshot::{HeapReader, HeapWriter, SnapshotMemory, SnapshotValue};
 use crate::MemoryTrait;
 use jodin_common::assembly::error::BytecodeError;
 use jodin_common::assembly::value::Value;
@@ -35,6 +37,10 @@ mod helper_structs {
             }
         }
 
+        pub fn with_values(id: usize, num_to_value: HashMap<usize, Rc<RefCell<Value>>>) -> Self {
+            MemNode { id, num_to_value }
+        }
+
         pub fn id(&self) -> usize {
             self.id
         }
@@ -75,6 +81,18 @@ mod helper_structs {
     }
 
     impl VarIdPool {
+        pub fn with_state(next_id: Option<usize>, reclaimed: Vec<usize>) -> Self {
+            Self {
+                next_id,
+                reclaimed: VecDeque::from(reclaimed),
+            }
+        }
+
+        /// The next fresh id and the reclaimed ids of the pool
+        pub fn state(&self) -> (Option<usize>, Vec<usize>) {
+            (self.next_id, self.reclaimed.iter().copied().collect())
+        }
+
         pub fn next_id(&mut self) -> Option<usize> {
             let output = match self.reclaimed.pop_front() {
                 None => {
@@ -223,6 +241,90 @@ impl Default for VMMemory {
     }
 }
 
+/// The snapshot image of a [VMMemory]
+#[derive(Debug, Serialize, Deserialize)]
+pub struct VMMemoryImage {
+    /// Each node id with its variables, which are stored as heap cell ids
+    mem_nodes: Vec<(usize, Vec<(usize, usize)>)>,
+    global_scope_id: usize,
+    hash_to_id: Vec<(u64, usize)>,
+    id_to_prev_id: Vec<(usize, usize)>,
+    mem_node_stack: Vec<Vec<usize>>,
+    next_var_id: Option<usize>,
+    reclaimed_var_ids: Vec<usize>,
+    stack: Vec<SnapshotValue>,
+}
+
+impl SnapshotMemory for VMMemory {
+    type Image = VMMemoryImage;
+
+    fn to_image(&self, heap: &mut HeapWriter) -> Self::Image {
+        // nodes and variables are sorted before their cells are stored, so the heap table of the
+        // same memory is always in the same order
+        let mut nodes = self.mem_nodes.iter().collect::<Vec<_>>();
+        nodes.sort_by_key(|(&id, _)| id);
+        let mem_nodes = nodes
+            .into_iter()
+            .map(|(&id, node)| {
+                let mut vars = node.num_to_value().iter().collect::<Vec<_>>();
+                vars.sort_by_key(|(&var, _)| var);
+                let vars = vars
+                    .into_iter()
+                    .map(|(&var, cell)| (var, heap.cell(cell)))
+                    .collect::<Vec<_>>();
+                (id, vars)
+            })
+            .collect::<Vec<_>>();
+        let mut hash_to_id = self
+            .hash_to_id
+            .iter()
+            .map(|(&hash, &id)| (hash, id))
+            .collect::<Vec<_>>();
+        hash_to_id.sort();
+        let mut id_to_prev_id = self
+            .id_to_prev_id
+            .iter()
+            .map(|(&id, &prev)| (id, prev))
+            .collect::<Vec<_>>();
+        id_to_prev_id.sort();
+        let (next_var_id, reclaimed_var_ids) = self.id_pool.borrow().state();
+
+        VMMemoryImage {
+            mem_nodes,
+            global_scope_id: self.global_scope_id,
+            hash_to_id,
+            id_to_prev_id,
+            mem_node_stack: self.mem_node_stack.clone(),
+            next_var_id,
+            reclaimed_var_ids,
+            stack: heap.values(&self.stack),
+        }
+    }
+
+    fn from_image(image: Self::Image, heap: &HeapReader) -> Result<Self, VMError> {
+        let mut mem_nodes = HashMap::new();
+        for (id, vars) in image.mem_nodes {
+            let vars = vars
+                .into_iter()
+                .map(|(var, cell)| Ok((var, heap.cell(cell)?)))
+                .collect::<Result<_, VMError>>()?;
+            mem_nodes.insert(id, MemNode::with_values(id, vars));
+        }
+        Ok(Self {
+            mem_nodes,
+            global_scope_id: image.global_scope_id,
+            hash_to_id: image.hash_to_id.into_iter().collect(),
+            id_to_prev_id: image.id_to_prev_id.into_iter().collect(),
+            mem_node_stack: image.mem_node_stack,
+            id_pool: RefCell::new(VarIdPool::with_state(
+                image.next_var_id,
+                image.reclaimed_var_ids,
+            )),
+            stack: heap.values(&image.stack)?,
+        })
+    }
+}
+
 impl MemoryTrait for VMMemory {
     fn global_scope(&mut self) {
         trace!("Loading global scope");
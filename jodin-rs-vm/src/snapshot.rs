@@ -0,0 +1,299 @@
+//! Snapshots capture the full state of a paused virtual machine as bytes, so that it can be
+//! restored later, possibly in another process.
+//!
+//! Values that live behind a reference are stored once in a heap table and referred to by index,
+//! so references that alias the same object (including cycles) still alias after a restore.
+
+use crate::coverage::LoadedDebugInfo;
+use crate::error::VMError;
+use crate::fault::Fault;
+use crate::MemoryTrait;
+use jodin_common::assembly::instructions::{Assembly, Bytecode};
+use jodin_common::assembly::location::AsmLocation;
+use jodin_common::assembly::native_handle::NativeHandle;
+use jodin_common::assembly::value::{JRef, Value};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::rc::Rc;
+
+/// The magic bytes at the beginning of every snapshot
+pub const SNAPSHOT_MAGIC: &[u8; 8] = b"JODINSNP";
+/// The version of the snapshot format
+pub const SNAPSHOT_VERSION: u32 = 2;
+
+/// A value as it's stored within a snapshot. References are replaced by indices into the heap table.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub enum SnapshotValue {
+    Empty,
+    Byte(u8),
+    Float(f64),
+    Integer(i64),
+    UInteger(u64),
+    Str(String),
+    Dictionary(Vec<(String, SnapshotValue)>),
+    Array(Vec<SnapshotValue>),
+    /// An index into the heap table
+    Reference(usize),
+    Bytecode(Bytecode),
+    Function(AsmLocation),
+    Native,
+    /// A native handle, which can only be restored while the handle is still alive
+    NativeHandle {
+        owner: String,
+        type_name: String,
+        id: u64,
+    },
+}
+
+/// Assigns ids to heap cells while a snapshot is being taken, so that every shared cell is only
+/// stored once.
+#[derive(Debug, Default)]
+pub struct HeapWriter {
+    ids: HashMap<*const RefCell<Value>, usize>,
+    cells: Vec<SnapshotValue>,
+}
+
+impl HeapWriter {
+    /// Gets the id of a heap cell, storing it in the heap table if it hasn't been seen yet
+    pub fn cell(&mut self, cell: &Rc<RefCell<Value>>) -> usize {
+        self.cell_at(Rc::as_ptr(cell), cell)
+    }
+
+    fn cell_at(&mut self, ptr: *const RefCell<Value>, cell: &RefCell<Value>) -> usize {
+        if let Some(&id) = self.ids.get(&ptr) {
+            return id;
+        }
+        let id = self.cells.len();
+        // the id is reserved before the inner value is visited so cycles terminate
+        self.cells.push(SnapshotValue::Empty);
+        self.ids.insert(ptr, id);
+        let inner = self.value(&*cell.borrow());
+        self.cells[id] = inner;
+        id
+    }
+
+    /// Converts a value into its snapshot representation
+    pub fn value(&mut self, value: &Value) -> SnapshotValue {
+        match value {
+            Value::Empty => SnapshotValue::Empty,
+            &Value::Byte(b) => SnapshotValue::Byte(b),
+            &Value::Float(f) => SnapshotValue::Float(f),
+            &Value::Integer(i) => SnapshotValue::Integer(i),
+            &Value::UInteger(u) => SnapshotValue::UInteger(u),
+            Value::Str(s) => SnapshotValue::Str(s.clone()),
+            Value::Dictionary(dict) => {
+                let mut entries = dict
+                    .iter()
+                    .map(|(key, value)| (key.clone(), self.value(value)))
+                    .collect::<Vec<_>>();
+                entries.sort_by(|(left, _), (right, _)| left.cmp(right));
+                SnapshotValue::Dictionary(entries)
+            }
+            Value::Array(array) => {
+                SnapshotValue::Array(array.iter().map(|v| self.value(v)).collect())
+            }
+            Value::Reference(reference) => {
+                let cell: &RefCell<Value> = &**reference;
+                SnapshotValue::Reference(self.cell_at(cell as *const _, cell))
+            }
+            Value::Bytecode(bytecode) => SnapshotValue::Bytecode(bytecode.clone()),
+            Value::Function(location) => SnapshotValue::Function(location.clone()),
+            Value::Native => SnapshotValue::Native,
+            Value::NativeHandle(handle) => SnapshotValue::NativeHandle {
+                owner: handle.owner().to_string(),
+                type_name: handle.type_name().to_string(),
+                id: handle.id(),
+            },
+        }
+    }
+
+    /// Converts a slice of values into their snapshot representations
+    pub fn values(&mut self, values: &[Value]) -> Vec<SnapshotValue> {
+        values.iter().map(|v| self.value(v)).collect()
+    }
+
+    /// Finishes the heap table
+    pub fn finish(self) -> Vec<SnapshotValue> {
+        self.cells
+    }
+}
+
+/// Recreates the heap cells of a snapshot.
+#[derive(Debug)]
+pub struct HeapReader {
+    cells: Vec<Rc<RefCell<Value>>>,
+}
+
+impl HeapReader {
+    /// Creates every heap cell first, then fills them in, so references between cells resolve
+    /// regardless of their order
+    pub fn new(heap: Vec<SnapshotValue>) -> Result<Self, VMError> {
+        let output = Self {
+            cells: (0..heap.len())
+                .map(|_| Rc::new(RefCell::new(Value::Empty)))
+                .collect(),
+        };
+        for (id, image) in heap.iter().enumerate() {
+            let value = output.value(image)?;
+            *output.cells[id].borrow_mut() = value;
+        }
+        Ok(output)
+    }
+
+    /// Gets the heap cell with the given id
+    pub fn cell(&self, id: usize) -> Result<Rc<RefCell<Value>>, VMError> {
+        self.cells
+            .get(id)
+            .cloned()
+            .ok_or_else(|| VMError::InvalidSnapshot(format!("no heap cell with id {id}")))
+    }
+
+    /// Converts a snapshot value back into a value
+    pub fn value(&self, image: &SnapshotValue) -> Result<Value, VMError> {
+        Ok(match image {
+            SnapshotValue::Empty => Value::Empty,
+            &SnapshotValue::Byte(b) => Value::Byte(b),
+            &SnapshotValue::Float(f) => Value::Float(f),
+            &SnapshotValue::Integer(i) => Value::Integer(i),
+            &SnapshotValue::UInteger(u) => Value::UInteger(u),
+            SnapshotValue::Str(s) => Value::Str(s.clone()),
+            SnapshotValue::Dictionary(entries) => Value::Dictionary(
+                entries
+                    .iter()
+                    .map(|(key, value)| Ok((key.clone(), self.value(value)?)))
+                    .collect::<Result<_, VMError>>()?,
+            ),
+            SnapshotValue::Array(array) => Value::Array(self.values(array)?),
+            &SnapshotValue::Reference(id) => Value::Reference(JRef::from(self.cell(id)?)),
+            SnapshotValue::Bytecode(bytecode) => Value::Bytecode(bytecode.clone()),
+            SnapshotValue::Function(location) => Value::Function(location.clone()),
+            SnapshotValue::Native => Value::Native,
+            SnapshotValue::NativeHandle {
+                owner,
+                type_name,
+                id,
+            } => Value::NativeHandle(NativeHandle::find(owner, *id).ok_or_else(|| {
+                VMError::InvalidSnapshot(format!("{type_name} handle {id} no longer exists"))
+            })?),
+        })
+    }
+
+    /// Converts a slice of snapshot values back into values
+    pub fn values(&self, images: &[SnapshotValue]) -> Result<Vec<Value>, VMError> {
+        images.iter().map(|image| self.value(image)).collect()
+    }
+}
+
+/// Memory implementations that can be captured within a snapshot.
+pub trait SnapshotMemory: MemoryTrait + Sized {
+    /// The serializable form of the memory
+    type Image: Serialize + DeserializeOwned;
+
+    /// Captures the memory, storing referenced values within the heap
+    fn to_image(&self, heap: &mut HeapWriter) -> Self::Image;
+
+    /// Recreates the memory from an image
+    fn from_image(image: Self::Image, heap: &HeapReader) -> Result<Self, VMError>;
+}
+
+/// The state of a fault handler within a snapshot
+#[derive(Debug, Serialize, Deserialize)]
+pub struct FaultHandleImage {
+    pub stored_pc: Vec<usize>,
+    pub stored_stack: Vec<SnapshotValue>,
+    pub fault: Fault,
+    pub target_function: SnapshotValue,
+}
+
+/// The full state of a virtual machine
+#[derive(Debug, Serialize, Deserialize)]
+pub struct VMSnapshot<I> {
+    pub instructions: Assembly,
+    pub label_to_instruction: Vec<(String, usize)>,
+    pub counter_stack: Vec<usize>,
+    pub next_anonymous_function: u64,
+    pub kernel_mode: bool,
+    pub paused: bool,
+    pub instruction_budget: Option<u64>,
+    pub instructions_run: u64,
+    pub handler: Option<FaultHandleImage>,
+    pub heap: Vec<SnapshotValue>,
+    pub memory: I,
+    pub debug_info: Vec<LoadedDebugInfo>,
+    pub image_entry_point: Option<String>,
+}
+
+impl<I: Serialize + DeserializeOwned> VMSnapshot<I> {
+    /// Encodes the snapshot as bytes
+    pub fn to_bytes(&self) -> Result<Vec<u8>, VMError> {
+        let mut output = Vec::from(&SNAPSHOT_MAGIC[..]);
+        output.extend(SNAPSHOT_VERSION.to_be_bytes());
+        let encoded =
+            bincode::serialize(self).map_err(|e| VMError::InvalidSnapshot(e.to_string()))?;
+        output.extend(encoded);
+        Ok(output)
+    }
+
+    /// Decodes a snapshot from bytes
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, VMError> {
+        let header_len = SNAPSHOT_MAGIC.len() + std::mem::size_of::<u32>();
+        if bytes.len() < header_len || &bytes[..SNAPSHOT_MAGIC.len()] != SNAPSHOT_MAGIC {
+            return Err(VMError::InvalidSnapshot(
+                "missing snapshot header".to_string(),
+            ));
+        }
+        let mut version = [0u8; 4];
+        version.copy_from_slice(&bytes[SNAPSHOT_MAGIC.len()..header_len]);
+        let version = u32::from_be_bytes(version);
+        if version != SNAPSHOT_VERSION {
+            return Err(VMError::InvalidSnapshot(format!(
+                "unsupported snapshot version {version} (expected {SNAPSHOT_VERSION})"
+            )));
+        }
+        bincode::deserialize(&bytes[header_len..])
+            .map_err(|e| VMError::InvalidSnapshot(e.to_string()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn shared_references_stay_shared() {
+        let shared = JRef::new(1u64);
+        let cyclic = Rc::new(RefCell::new(Value::Empty));
+        *cyclic.borrow_mut() = Value::Array(vec![Value::Reference(JRef::from(cyclic.clone()))]);
+
+        let mut writer = HeapWriter::default();
+        let images = writer.values(&[
+            Value::Reference(shared.clone()),
+            Value::Reference(shared.clone()),
+            Value::Reference(JRef::from(cyclic.clone())),
+        ]);
+        let reader = HeapReader::new(writer.finish()).expect("heap should be valid");
+        let values = reader.values(&images).expect("values should be valid");
+
+        match (&values[0], &values[1]) {
+            (Value::Reference(left), Value::Reference(right)) => {
+                *left.borrow_mut() = Value::UInteger(2);
+                assert_eq!(*right.borrow(), Value::UInteger(2));
+            }
+            _ => panic!("expected references"),
+        }
+        match &values[2] {
+            Value::Reference(cell) => match &*cell.borrow() {
+                Value::Array(inner) => match &inner[0] {
+                    Value::Reference(inner) => {
+                        assert!(std::ptr::eq::<RefCell<Value>>(&**inner, &**cell))
+                    }
+                    _ => panic!("expected a reference"),
+                },
+                _ => panic!("expected an array"),
+            },
+            _ => panic!("expected a reference"),
+        }
+    }
+}
@@ -0,0 +1,133 @@
+//! Standalone executables, which run a program without a separate install of the virtual machine.
+//!
+//! A standalone executable is a copy of the `jodin` runner, with a linked
+//! [image](ExecutableImage) appended to it. The image is followed by a trailer, which is the
+//! length of the image as a big endian `u64` and then [`STANDALONE_MAGIC`]. When the runner
+//! starts, it checks whether its own executable has an image, and runs the image with its own
+//! arguments if it does.
+
+use crate::error::VMError;
+use jodin_common::image::ExecutableImage;
+use std::fs::File;
+use std::io::{Read, Seek, SeekFrom};
+use std::path::Path;
+
+/// The magic bytes at the end of every standalone executable
+pub const STANDALONE_MAGIC: &[u8; 8] = b"JODINEXE";
+/// The size of the trailer after the image, in bytes
+const TRAILER_SIZE: u64 = 16;
+
+/// Writes a standalone executable, which is the runtime followed by the image. If the runtime is
+/// itself a standalone executable, its image is replaced.
+pub fn write_standalone<P1: AsRef<Path>, P2: AsRef<Path>>(
+    runtime: P1,
+    image: &ExecutableImage,
+    output: P2,
+) -> Result<(), VMError> {
+    let mut executable = std::fs::read(&runtime)?;
+    let before_trailer = executable.len().saturating_sub(TRAILER_SIZE as usize);
+    if let Some(image_length) = trailer(&executable[before_trailer..], before_trailer as u64) {
+        executable.truncate(before_trailer - image_length as usize);
+    }
+    let image = image.to_bytes()?;
+    executable.extend_from_slice(&image);
+    executable.extend_from_slice(&(image.len() as u64).to_be_bytes());
+    executable.extend_from_slice(STANDALONE_MAGIC);
+
+    let output = output.as_ref();
+    if let Some(parent) = output.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    std::fs::write(output, executable)?;
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        std::fs::set_permissions(output, std::fs::Permissions::from_mode(0o755))?;
+    }
+    Ok(())
+}
+
+/// Gets the image of a standalone executable, or `None` if the executable doesn't have one. Only
+/// the trailer and the image are read.
+///
+/// # Error
+///
+/// Returns [`CorruptImage`](VMError::CorruptImage) if the executable has a trailer, but the image
+/// before it can't be read.
+pub fn embedded_image<P: AsRef<Path>>(executable: P) -> Result<Option<ExecutableImage>, VMError> {
+    let mut file = File::open(executable)?;
+    let length = file.seek(SeekFrom::End(0))?;
+    if length < TRAILER_SIZE {
+        return Ok(None);
+    }
+    let mut trailer_bytes = [0; TRAILER_SIZE as usize];
+    file.seek(SeekFrom::End(-(TRAILER_SIZE as i64)))?;
+    file.read_exact(&mut trailer_bytes)?;
+    let image_length = match trailer(&trailer_bytes, length - TRAILER_SIZE) {
+        Some(image_length) => image_length,
+        None => return Ok(None),
+    };
+
+    let mut image = vec![0; image_length as usize];
+    file.seek(SeekFrom::Start(length - TRAILER_SIZE - image_length))
+        .and_then(|_| file.read_exact(&mut image))
+        .map_err(|e| VMError::CorruptImage(e.to_string()))?;
+    ExecutableImage::try_from(&*image)
+        .map(Some)
+        .map_err(|e| VMError::CorruptImage(e.to_string()))
+}
+
+/// Gets the length of the image from what might be a trailer, if it is one. The image must fit
+/// within the bytes before the trailer.
+fn trailer(bytes: &[u8], before_trailer: u64) -> Option<u64> {
+    if bytes.len() != TRAILER_SIZE as usize || &bytes[8..] != STANDALONE_MAGIC {
+        return None;
+    }
+    let length = u64::from_be_bytes(bytes[..8].try_into().unwrap());
+    (length <= before_trailer).then_some(length)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use jodin_common::assembly::instructions::Asm;
+
+    #[test]
+    fn images_are_replaced() {
+        let directory =
+            std::env::temp_dir().join(format!("jodin-standalone-{}", std::process::id()));
+        std::fs::create_dir_all(&directory).unwrap();
+        let runtime = directory.join("runtime");
+        std::fs::write(&runtime, b"not really an executable").unwrap();
+        assert!(embedded_image(&runtime).unwrap().is_none());
+
+        let first = ExecutableImage::new("main", vec![Asm::pub_label("main"), Asm::Return]);
+        let second = ExecutableImage::new("start", vec![Asm::pub_label("start"), Asm::Return]);
+        let output = directory.join("program");
+        write_standalone(&runtime, &first, &output).unwrap();
+        assert_eq!(embedded_image(&output).unwrap(), Some(first));
+
+        let replaced = directory.join("replaced");
+        write_standalone(&output, &second, &replaced).unwrap();
+        assert_eq!(embedded_image(&replaced).unwrap(), Some(second));
+        let runtime_length = std::fs::metadata(&runtime).unwrap().len();
+        let image_length = std::fs::metadata(&replaced).unwrap().len() - runtime_length;
+        assert_eq!(
+            &std::fs::read(&replaced).unwrap()[..runtime_length as usize],
+            b"not really an executable"
+        );
+        assert!(image_length > TRAILER_SIZE);
+
+        // the trailer is kept, but the image before it is overwritten
+        let mut corrupt = std::fs::read(&replaced).unwrap();
+        let image_start = runtime_length as usize;
+        corrupt[image_start..image_start + 8].fill(0);
+        let corrupted = directory.join("corrupt");
+        std::fs::write(&corrupted, corrupt).unwrap();
+        assert!(matches!(
+            embedded_image(&corrupted),
+            Err(VMError::CorruptImage(_))
+        ));
+        std::fs::remove_dir_all(directory).unwrap();
+    }
+}
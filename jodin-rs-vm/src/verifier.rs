@@ -0,0 +1,643 @@
+//! The verifier checks assembly before it's loaded, so that broken modules are rejected before any
+//! of their code runs.
+//!
+//! Every location that's jumped to or called must be within the module, or be a label that's
+//! already known to the virtual machine. Labels can't be defined twice, and `set_symbol` can only
+//! be loaded in kernel mode.
+//!
+//! The verifier also follows every path through the module, starting at its first instruction,
+//! its public labels, its static code, and any labels that can only be reached from outside of it.
+//! Along these paths it tracks the fewest values the stack could have, and reports instructions
+//! that could pop more values than were pushed. The arguments of a function are on the stack
+//! before it's entered, so only `set_var` and `pop`, which take the arguments, can pop values the
+//! function didn't push.
+//!
+//! Along the same paths it tracks which variables of the current scope are set, and reports
+//! `get_var` and `clear_var` instructions whose variable isn't set on every path to them. Once
+//! the code goes to the global scope, a saved scope, or back a scope, it's using variables that
+//! were set elsewhere, so they're no longer checked until the next `@push_scope`.
+
+use jodin_common::assembly::instructions::Asm;
+use jodin_common::assembly::location::AsmLocation;
+use jodin_common::assembly::text::instruction_to_jasm;
+use jodin_common::assembly::value::Value;
+use std::collections::{BTreeSet, HashMap, VecDeque};
+use std::fmt::{Display, Formatter};
+use thiserror::Error;
+
+/// Where a problem was found within a module
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Position {
+    /// The index of the instruction within the module
+    pub index: usize,
+    /// The closest label before the instruction, and how many instructions after it the
+    /// instruction is
+    pub label: Option<(String, usize)>,
+}
+
+impl Display for Position {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "instruction {}", self.index)?;
+        match &self.label {
+            Some((label, 0)) => write!(f, " ({})", label),
+            Some((label, offset)) => write!(f, " ({}+{})", label, offset),
+            None => Ok(()),
+        }
+    }
+}
+
+/// A problem found by the verifier
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum VerifyError {
+    /// A label is used, but isn't defined by the module or the virtual machine
+    #[error("{at}: label {label:?} is not defined")]
+    UndefinedLabel {
+        /// Where the label is used
+        at: Position,
+        /// The label
+        label: String,
+    },
+    /// A location is outside of the code of the virtual machine
+    #[error("{at}: {instruction} goes outside of the loaded code")]
+    InvalidLocation {
+        /// Where the location is used
+        at: Position,
+        /// The instruction with the location
+        instruction: String,
+    },
+    /// A label is defined by the module more than once, or is already defined
+    #[error("{at}: label {label:?} is already defined")]
+    DuplicateLabel {
+        /// Where the label is defined again
+        at: Position,
+        /// The label
+        label: String,
+    },
+    /// An instruction could pop more values than there are on the stack
+    #[error("{at}: {instruction} needs {needed} values, but the stack may only have {available}")]
+    StackUnderflow {
+        /// The instruction's position
+        at: Position,
+        /// The instruction
+        instruction: String,
+        /// How many values the instruction pops
+        needed: usize,
+        /// The fewest values that the stack could have
+        available: usize,
+    },
+    /// A variable is used on a path where it isn't set
+    #[error("{at}: variable {variable} may not be set")]
+    UnsetVariable {
+        /// Where the variable is used
+        at: Position,
+        /// The variable number
+        variable: u64,
+    },
+    /// An instruction that is only allowed in kernel mode is loaded outside of it
+    #[error("{at}: {instruction} can only be loaded in kernel mode")]
+    KernelOnly {
+        /// The instruction's position
+        at: Position,
+        /// The instruction
+        instruction: String,
+    },
+}
+
+/// Checks modules before they're loaded into a virtual machine
+pub struct Verifier<'a> {
+    start: usize,
+    kernel_mode: bool,
+    defined: Box<dyn Fn(&str) -> bool + 'a>,
+}
+
+impl<'a> Verifier<'a> {
+    /// Creates a verifier for a module that will be loaded at an instruction index
+    pub fn new(start: usize) -> Self {
+        Self {
+            start,
+            kernel_mode: false,
+            defined: Box::new(|_| false),
+        }
+    }
+
+    /// Sets whether the module is loaded in kernel mode
+    pub fn kernel_mode(mut self, kernel_mode: bool) -> Self {
+        self.kernel_mode = kernel_mode;
+        self
+    }
+
+    /// Sets which labels are already defined outside of the module
+    pub fn defined_labels<F: Fn(&str) -> bool + 'a>(mut self, defined: F) -> Self {
+        self.defined = Box::new(defined);
+        self
+    }
+
+    /// Verifies a module, returning every problem that was found
+    pub fn verify(&self, jasm: &[Asm]) -> Result<(), Vec<VerifyError>> {
+        let module = Module::new(jasm);
+        let mut errors = vec![];
+        for (index, asm) in jasm.iter().enumerate() {
+            if let Err(error) = self.check_instruction(&module, index, asm) {
+                errors.push(error);
+            }
+        }
+
+        let states = module.states(self);
+        for (index, state) in states.iter().enumerate() {
+            if let Some(state) = state {
+                errors.extend(module.check_state(index, state));
+            }
+        }
+
+        errors.sort_by_key(|error| position(error).index);
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors)
+        }
+    }
+
+    /// Checks the parts of an instruction that don't depend on the paths to it
+    fn check_instruction(
+        &self,
+        module: &Module,
+        index: usize,
+        asm: &Asm,
+    ) -> Result<(), VerifyError> {
+        let at = || module.position(index);
+        match asm {
+            Asm::Label(label) | Asm::PublicLabel(label) => {
+                let first = module.labels[label.as_str()];
+                if (first != index || (self.defined)(label)) && !label.starts_with("@@") {
+                    return Err(VerifyError::DuplicateLabel {
+                        at: at(),
+                        label: label.clone(),
+                    });
+                }
+            }
+            Asm::Goto(location) | Asm::CondGoto(location) | Asm::Call(location) => {
+                self.target(module, index, location)?;
+            }
+            Asm::SetSymbol(_) if !self.kernel_mode => {
+                return Err(VerifyError::KernelOnly {
+                    at: at(),
+                    instruction: describe(asm),
+                });
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    /// Gets the instruction within the module that a location goes to, or `None` if it goes to
+    /// code that's already loaded
+    fn target(
+        &self,
+        module: &Module,
+        index: usize,
+        location: &AsmLocation,
+    ) -> Result<Option<usize>, VerifyError> {
+        let target = match location {
+            AsmLocation::Label(label) => {
+                return match module.labels.get(label.as_str()) {
+                    Some(&target) => Ok(Some(target)),
+                    None if (self.defined)(label) => Ok(None),
+                    None => Err(VerifyError::UndefinedLabel {
+                        at: module.position(index),
+                        label: label.clone(),
+                    }),
+                };
+            }
+            &AsmLocation::InstructionDiff(diff) => index.checked_add_signed(diff),
+            &AsmLocation::ByteIndex(byte_index) if byte_index < self.start => return Ok(None),
+            &AsmLocation::ByteIndex(byte_index) => Some(byte_index - self.start),
+        };
+        match target {
+            Some(target) if target < module.jasm.len() => Ok(Some(target)),
+            _ => Err(VerifyError::InvalidLocation {
+                at: module.position(index),
+                instruction: describe(&module.jasm[index]),
+            }),
+        }
+    }
+}
+
+/// What is known about the stack and the variables before an instruction runs, along every path
+/// to it
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct State {
+    /// The fewest values that the stack could have, not counting arguments
+    depth: usize,
+    /// The variables of the current scope that are set, or `None` if the code went to a scope
+    /// whose variables were set elsewhere
+    set: Option<BTreeSet<u64>>,
+}
+
+impl State {
+    const ENTRY: State = State {
+        depth: 0,
+        set: Some(BTreeSet::new()),
+    };
+
+    /// Merges the state of another path into this state, returning whether this state changed
+    fn merge(&mut self, other: State) -> bool {
+        let set = match (self.set.take(), other.set) {
+            (Some(set), Some(other)) => Some(&set & &other),
+            (set, None) | (None, set) => set,
+        };
+        let merged = State {
+            depth: self.depth.min(other.depth),
+            set,
+        };
+        let changed = merged != *self;
+        *self = merged;
+        changed
+    }
+}
+
+/// A module being verified
+struct Module<'j> {
+    jasm: &'j [Asm],
+    labels: HashMap<&'j str, usize>,
+}
+
+impl<'j> Module<'j> {
+    fn new(jasm: &'j [Asm]) -> Self {
+        let mut labels = HashMap::new();
+        for (index, asm) in jasm.iter().enumerate() {
+            if let Some(label) = asm.defined_label() {
+                labels.entry(label).or_insert(index);
+            }
+        }
+        Self { jasm, labels }
+    }
+
+    fn position(&self, index: usize) -> Position {
+        let label = self.jasm[..=index]
+            .iter()
+            .enumerate()
+            .rev()
+            .find_map(|(label_index, asm)| {
+                asm.defined_label()
+                    .map(|label| (label.to_string(), index - label_index))
+            });
+        Position { index, label }
+    }
+
+    /// The instructions that can be entered from outside of the module, without following any
+    /// paths within it
+    fn entry_points(&self, verifier: &Verifier) -> Vec<usize> {
+        let mut entry_points = vec![];
+        if !self.jasm.is_empty() {
+            entry_points.push(0);
+        }
+        for (index, asm) in self.jasm.iter().enumerate() {
+            match asm {
+                Asm::PublicLabel(_) | Asm::Static => entry_points.push(index),
+                Asm::Call(location) => {
+                    if let Ok(Some(target)) = verifier.target(self, index, location) {
+                        entry_points.push(target);
+                    }
+                }
+                Asm::GetSymbol(label) | Asm::Push(Value::Function(AsmLocation::Label(label))) => {
+                    entry_points.extend(self.labels.get(label.as_str()));
+                }
+                _ => {}
+            }
+        }
+        entry_points
+    }
+
+    /// Follows every path through the module, getting the state before every instruction that
+    /// can be reached
+    fn states(&self, verifier: &Verifier) -> Vec<Option<State>> {
+        let mut states: Vec<Option<State>> = vec![None; self.jasm.len()];
+        let mut queue = VecDeque::new();
+        let mut entry_points = self.entry_points(verifier);
+        loop {
+            for entry_point in entry_points {
+                if enter(&mut states, entry_point, State::ENTRY) {
+                    queue.push_back(entry_point);
+                }
+            }
+            while let Some(index) = queue.pop_front() {
+                let after = transfer(&self.jasm[index], states[index].clone().unwrap());
+                for next in self.successors(verifier, index) {
+                    if enter(&mut states, next, after.clone()) {
+                        queue.push_back(next);
+                    }
+                }
+            }
+
+            // labels that no path reaches must be jumped to or called from outside of the module
+            entry_points = self
+                .jasm
+                .iter()
+                .enumerate()
+                .filter(|(index, asm)| states[*index].is_none() && asm.defined_label().is_some())
+                .map(|(index, _)| index)
+                .take(1)
+                .collect();
+            if entry_points.is_empty() {
+                return states;
+            }
+        }
+    }
+
+    /// The instructions within the module that can run after an instruction
+    fn successors(&self, verifier: &Verifier, index: usize) -> Vec<usize> {
+        let next = Some(index + 1).filter(|&next| next < self.jasm.len());
+        let target = |location| verifier.target(self, index, location).ok().flatten();
+        match &self.jasm[index] {
+            Asm::Return | Asm::Halt => vec![],
+            Asm::Goto(location) => target(location).into_iter().collect(),
+            Asm::CondGoto(location) => target(location).into_iter().chain(next).collect(),
+            _ => next.into_iter().collect(),
+        }
+    }
+
+    /// Checks an instruction against the state before it
+    fn check_state(&self, index: usize, state: &State) -> Vec<VerifyError> {
+        let asm = &self.jasm[index];
+        let mut errors = vec![];
+        let (pops, _) = stack_effect(asm);
+        if pops > state.depth && !takes_arguments(asm) {
+            errors.push(VerifyError::StackUnderflow {
+                at: self.position(index),
+                instruction: describe(asm),
+                needed: pops,
+                available: state.depth,
+            });
+        }
+        if let (&Asm::GetVar(variable) | &Asm::ClearVar(variable), Some(set)) = (asm, &state.set) {
+            if !set.contains(&variable) {
+                errors.push(VerifyError::UnsetVariable {
+                    at: self.position(index),
+                    variable,
+                });
+            }
+        }
+        errors
+    }
+}
+
+/// Merges a state into the state before an instruction, returning whether it changed
+fn enter(states: &mut [Option<State>], index: usize, state: State) -> bool {
+    match &mut states[index] {
+        Some(existing) => existing.merge(state),
+        empty => {
+            *empty = Some(state);
+            true
+        }
+    }
+}
+
+/// The state after an instruction runs
+fn transfer(asm: &Asm, state: State) -> State {
+    let (pops, pushes) = stack_effect(asm);
+    let depth = match asm {
+        Asm::Clear => 0,
+        _ => state.depth.saturating_sub(pops) + pushes,
+    };
+    let mut set = state.set;
+    match asm {
+        &Asm::SetVar(variable) => {
+            if let Some(set) = &mut set {
+                set.insert(variable);
+            }
+        }
+        Asm::ClearVar(variable) => {
+            if let Some(set) = &mut set {
+                set.remove(variable);
+            }
+        }
+        Asm::NativeMethod(method, _) => match method.as_str() {
+            "@push_scope" => set = Some(BTreeSet::new()),
+            "@global_scope" | "@load_scope" | "@back_scope" | "@pop_scope" => set = None,
+            _ => {}
+        },
+        _ => {}
+    }
+    State { depth, set }
+}
+
+/// Whether an instruction can always take the arguments of a function from the stack
+fn takes_arguments(asm: &Asm) -> bool {
+    matches!(asm, Asm::SetVar(_) | Asm::Pop)
+}
+
+/// How many values an instruction pops from the stack, and the fewest values it pushes
+fn stack_effect(asm: &Asm) -> (usize, usize) {
+    match asm {
+        Asm::Push(_) | Asm::GetVar(_) | Asm::GetSymbol(_) => (0, 1),
+        Asm::Pop | Asm::SetVar(_) | Asm::SetSymbol(_) | Asm::CondGoto(_) => (1, 0),
+        Asm::GetAttribute(_)
+        | Asm::Index(_)
+        | Asm::Deref
+        | Asm::GetRef
+        | Asm::Call(_)
+        | Asm::GT0
+        | Asm::Not
+        | Asm::Boolify
+        | Asm::BooleanNot
+        | Asm::IntoReference => (1, 1),
+        Asm::Add
+        | Asm::Subtract
+        | Asm::Multiply
+        | Asm::Divide
+        | Asm::Remainder
+        | Asm::Gt
+        | Asm::And
+        | Asm::Or
+        | Asm::BooleanAnd
+        | Asm::BooleanOr
+        | Asm::BooleanXor => (2, 1),
+        Asm::SetRef => (2, 0),
+        &Asm::Pack(count) => (count, 1),
+        // messages always leave their result on the stack
+        Asm::SendMessage => (3, 1),
+        // native methods that change the scope don't have a result
+        Asm::NativeMethod(method, count) if method.starts_with('@') && method != "@call" => {
+            (*count, 0)
+        }
+        Asm::NativeMethod(_, count) => (*count, 1),
+        _ => (0, 0),
+    }
+}
+
+fn describe(asm: &Asm) -> String {
+    instruction_to_jasm(asm).unwrap_or_else(|_| format!("{:?}", asm))
+}
+
+fn position(error: &VerifyError) -> &Position {
+    match error {
+        VerifyError::UndefinedLabel { at, .. }
+        | VerifyError::InvalidLocation { at, .. }
+        | VerifyError::DuplicateLabel { at, .. }
+        | VerifyError::StackUnderflow { at, .. }
+        | VerifyError::UnsetVariable { at, .. }
+        | VerifyError::KernelOnly { at, .. } => at,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use jodin_common::assembly::text::parse_jasm;
+
+    fn verify(jasm: &str) -> Result<(), Vec<String>> {
+        Verifier::new(1)
+            .defined_labels(|label| label == "loaded")
+            .verify(&parse_jasm(jasm).unwrap())
+            .map_err(|errors| errors.iter().map(ToString::to_string).collect())
+    }
+
+    #[test]
+    fn compiled_functions_are_accepted() {
+        let jasm = r#"
+            pub add:
+                push "add"
+                native_method @load_scope 1
+                native_method @push_scope 0
+                set_var 0
+                set_var 1
+                get_var 1
+                deref
+                get_var 0
+                deref
+                add
+                native_method @back_scope 0
+                return
+            pub main:
+                push 2u64
+                cond_goto then
+                push 1u64
+                goto end
+            then:
+                push 2u64
+            end:
+                pack 1
+                push "@call"
+                get_symbol add
+                send_message
+                return
+            greater:
+                set_var 0
+                set_var 1
+                get_var 1
+                deref
+                get_var 0
+                deref
+                gt
+                return
+        "#;
+        assert_eq!(verify(jasm), Ok(()));
+    }
+
+    #[test]
+    fn stack_underflows_are_found() {
+        let jasm = r#"
+            pub main:
+                push 1u64
+                add
+                push 2u64
+                cond_goto skip
+                push 3u64
+            skip:
+                pack 2
+                return
+        "#;
+        assert_eq!(
+            verify(jasm),
+            Err(vec![
+                "instruction 2 (main+2): add needs 2 values, but the stack may only have 1"
+                    .to_string(),
+                "instruction 7 (skip+1): pack 2 needs 2 values, but the stack may only have 1"
+                    .to_string(),
+            ])
+        );
+
+        let jasm = "pub main:\n push \"get\"\n push 0u64\n send_message\n return\n";
+        assert_eq!(
+            verify(jasm),
+            Err(vec![
+                "instruction 3 (main+3): send_message needs 3 values, but the stack may only have 2"
+                    .to_string()
+            ])
+        );
+
+        // only set_var and pop can take the arguments of a function
+        let jasm = "pub main:\n set_var 0\n pop\n pack 2\n return\n";
+        assert_eq!(
+            verify(jasm),
+            Err(vec![
+                "instruction 3 (main+3): pack 2 needs 2 values, but the stack may only have 0"
+                    .to_string()
+            ])
+        );
+    }
+
+    #[test]
+    fn variables_are_set_before_they_are_used() {
+        let jasm = r#"
+            pub main:
+                push 1u64
+                set_var 0
+                push 1u64
+                cond_goto skip
+                push 2u64
+                set_var 1
+            skip:
+                get_var 0
+                get_var 1
+                clear_var 0
+                clear_var 0
+                native_method @push_scope 0
+                get_var 0
+                native_method @global_scope 0
+                get_var 2
+                return
+        "#;
+        assert_eq!(
+            verify(jasm),
+            Err(vec![
+                "instruction 9 (skip+2): variable 1 may not be set".to_string(),
+                "instruction 11 (skip+4): variable 0 may not be set".to_string(),
+                "instruction 13 (skip+6): variable 0 may not be set".to_string(),
+            ])
+        );
+    }
+
+    #[test]
+    fn locations_and_labels_are_checked() {
+        let jasm = r#"
+            pub main:
+                goto loaded
+                cond_goto missing
+                goto +10
+                goto 0
+            main:
+                return
+        "#;
+        assert_eq!(
+            verify(jasm),
+            Err(vec![
+                "instruction 2 (main+2): label \"missing\" is not defined".to_string(),
+                "instruction 3 (main+3): goto +10 goes outside of the loaded code".to_string(),
+                "instruction 5 (main): label \"main\" is already defined".to_string(),
+            ])
+        );
+        assert!(verify("pub loaded:\n return\n").is_err());
+    }
+
+    #[test]
+    fn set_symbol_needs_kernel_mode() {
+        let jasm = parse_jasm("pub main:\n push 1u64\n set_symbol answer\n return\n").unwrap();
+        let errors = Verifier::new(0).verify(&jasm).unwrap_err();
+        assert_eq!(
+            errors[0].to_string(),
+            "instruction 2 (main+2): set_symbol answer can only be loaded in kernel mode"
+        );
+        assert!(Verifier::new(0).kernel_mode(true).verify(&jasm).is_ok());
+    }
+}
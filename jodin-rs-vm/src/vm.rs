@@ -1,23 +1,37 @@
+use crate::coverage::{CoverageReport, LoadedDebugInfo};
 use crate::error::VMError;
 use crate::fault::{Fault, FaultHandle, FaultJumpTable};
+use crate::limits::ResourceLimits;
+use crate::loadables::{FileSystemNode, LazyObjectPath};
+use crate::profiler::{Profile, Profiler};
+use crate::replay::{contains_native_handle, Trace, TraceEvent, TraceMode};
+use crate::snapshot::{FaultHandleImage, HeapReader, HeapWriter, SnapshotMemory, VMSnapshot};
+use crate::verifier::Verifier;
 use crate::{ArithmeticsTrait, MemoryTrait, VMTryLoadable, VirtualMachine, CALL, RECEIVE_MESSAGE};
 
+use jodin_common::asm_version::Version;
 use jodin_common::assembly::instructions::{Asm, Assembly, Decode, GetAsm};
 use jodin_common::assembly::location::AsmLocation;
+use jodin_common::assembly::native_handle::NativeHandle;
 use jodin_common::assembly::value::{JRef, Value};
 use jodin_common::identifier::Identifier;
-
-use jodin_vm_plugins::plugins::{LoadablePlugin, PluginManager, Stack, VMHandle};
-use jodin_vm_plugins::Plugin;
+use jodin_common::image::ExecutableImage;
+use jodin_common::upgrade::Upgrader;
+
+use jodin_vm_plugins::discovery::{DiscoveredPlugin, PluginManifest, PluginSearchPath};
+use jodin_vm_plugins::plugins::{
+    LabelConflict, LoadablePlugin, PendingFinalizations, PluginId, PluginManager, Stack, VMHandle,
+};
+use jodin_vm_plugins::{Error as PluginError, Plugin};
 use more_collection_macros::{map, set};
 use std::collections::hash_map::{DefaultHasher, Entry};
 use std::collections::{HashMap, VecDeque};
 use std::ffi::OsStr;
 use std::fmt::{Debug, Formatter};
 use std::hash::Hasher;
-use std::io::{stderr, stdout, Read, Write};
-use std::ops::{Add, Deref};
-use std::path::PathBuf;
+use std::io::{stderr, stdin, stdout, Read, Write};
+use std::ops::{Add, Deref, RangeInclusive};
+use std::path::{Path, PathBuf};
 use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::{Arc, RwLock};
 use std::time::{Duration, Instant};
@@ -47,6 +61,31 @@ where
     kernel_mode: bool,
 
     plugin_manager: Arc<RwLock<PluginManager>>,
+    /// Native handles dropped while the plugin manager was locked for writing
+    pending_finalizations: PendingFinalizations,
+
+    /// The amount of instructions that can still be run before the VM pauses
+    instruction_budget: Option<u64>,
+    paused: bool,
+
+    limits: ResourceLimits,
+    /// The amount of instructions run so far, counted against the limits
+    instructions_run: u64,
+    /// Modules that are loaded when one of their symbols is missing
+    lazy_object_path: LazyObjectPath,
+
+    trace_mode: Option<TraceMode>,
+    profiler: Option<Profiler>,
+
+    /// A fault raised by a plugin, which occurs once the current instruction completes
+    pending_fault: Option<Fault>,
+
+    /// The line tables of the loaded objects
+    debug_info: Vec<LoadedDebugInfo>,
+    /// The entry point of the last image that was loaded
+    image_entry_point: Option<String>,
+    /// How many times every instruction was executed, while coverage is enabled
+    coverage: Option<Vec<u64>>,
 }
 
 impl<'l, M, A> Debug for VM<'l, M, A>
@@ -91,6 +130,80 @@ where
         (result, start.elapsed())
     }
 
+    /// Runs the virtual machine with the profiler enabled, returning what was measured
+    pub fn run_with_profile(&mut self, start_label: &str) -> (Result<u32, VMError>, Profile) {
+        self.enable_profiler();
+        let result = self.run(start_label);
+        let profile = self.take_profile().unwrap_or_default();
+        (result, profile)
+    }
+
+    /// Starts counting the instructions executed and the time spent within each function
+    pub fn enable_profiler(&mut self) {
+        self.profiler = Some(Profiler::new());
+    }
+
+    /// Stops profiling, returning what was measured
+    pub fn take_profile(&mut self) -> Option<Profile> {
+        self.profiler.take().map(Profiler::finish)
+    }
+
+    /// Pauses the virtual machine after the given amount of instructions have been run. A paused
+    /// run returns [VMError::Paused], and can be continued with [resume](Self::resume).
+    pub fn pause_after(&mut self, instructions: u64) {
+        self.instruction_budget = Some(instructions);
+    }
+
+    /// Whether the virtual machine has been paused in the middle of running
+    pub fn is_paused(&self) -> bool {
+        self.paused
+    }
+
+    /// Continues running a paused virtual machine
+    pub fn resume(&mut self) -> Result<u32, VMError> {
+        if !self.is_paused() {
+            return Err(anyhow!("The virtual machine is not paused").into());
+        }
+        self.run_loop()
+    }
+
+    /// Starts recording every native method, plugin result and read from the standard input
+    /// into a [Trace].
+    pub fn start_recording(&mut self) {
+        self.trace_mode = Some(TraceMode::Recording(Trace::default()));
+    }
+
+    /// Starts replaying a trace. Plugins and the standard input are not used while replaying,
+    /// instead their recorded results are used. If the program diverges from the trace, the
+    /// run fails with [VMError::ReplayDivergence].
+    pub fn start_replay(&mut self, trace: Trace) {
+        self.trace_mode = Some(TraceMode::Replaying { trace, next: 0 });
+    }
+
+    /// Stops recording or replaying, returning the trace
+    pub fn stop_trace(&mut self) -> Option<Trace> {
+        self.trace_mode.take().map(TraceMode::into_trace)
+    }
+
+    /// Starts counting how many times every instruction is executed
+    pub fn enable_coverage(&mut self) {
+        self.coverage = Some(vec![0; self.instructions.len()]);
+    }
+
+    /// Stops counting executed instructions, returning the line coverage of the loaded objects
+    /// that have line tables
+    pub fn take_coverage(&mut self) -> Option<CoverageReport> {
+        self.coverage
+            .take()
+            .map(|counts| CoverageReport::new(&counts, &self.debug_info))
+    }
+
+    /// The versions of assembly that can be loaded. Older versions are upgraded into the version
+    /// run by the virtual machine when they're loaded.
+    pub fn supported_versions(&self) -> RangeInclusive<Version> {
+        Upgrader::default().supported()
+    }
+
     pub fn instructions(&self) -> &Vec<Asm> {
         &self.instructions
     }
@@ -131,7 +244,18 @@ where
         Identifier::new_alt_delimiter(string, "_")
     }
 
-    fn native_method(&mut self, message: &str, mut args: Vec<Value>) {
+    fn native_method(&mut self, message: &str, args: Vec<Value>) -> Result<(), VMError> {
+        if let Some(profiler) = &mut self.profiler {
+            profiler.enter_external(format!("[native] {message}"));
+        }
+        let result = self.run_native_method(message, args);
+        if let Some(profiler) = &mut self.profiler {
+            profiler.exit_external();
+        }
+        result
+    }
+
+    fn run_native_method(&mut self, message: &str, mut args: Vec<Value>) -> Result<(), VMError> {
         info!(
             "Running native method {:?} with args ({})",
             message,
@@ -141,6 +265,12 @@ where
                 .collect::<Vec<_>>()
                 .join(", ")
         );
+        let event = TraceEvent::Native {
+            pc: self.program_counter(),
+            method: message.to_string(),
+        };
+        self.replayed(&event)?;
+        self.record(event);
         match message {
             "print" => {
                 let s = format!("{:#}", args.remove(0));
@@ -185,7 +315,7 @@ where
                     .into_string()
                     .expect("String expected for message");
                 if let Value::Array(args) = args.pop().unwrap() {
-                    self.send_message(&mut target, &msg, args);
+                    self.send_message(&mut target, &msg, args)?;
                 } else {
                     panic!("Expected a value of type array")
                 }
@@ -204,12 +334,7 @@ where
             }
             "dynamic_call" => {
                 if let Value::Str(function) = args.remove(0) {
-                    let plugin_manager = self.plugin_manager.read().unwrap();
-                    let mut stack = self.stack();
-                    let mut handle = DefaultVmHandle::new(self);
-                    let result = plugin_manager
-                        .call_function(&*function, &mut stack, &mut handle)
-                        .expect("failed!");
+                    let result = self.call_plugin(&function)?;
                     self.memory.push(result);
                 } else {
                     panic!("Expected a value of type String")
@@ -244,19 +369,500 @@ where
             "@print_stack" => {
                 println!("memory: {:#?}", self.memory);
             }
+            "read_line" => {
+                let pc = self.program_counter();
+                let line = match self.replayed(&TraceEvent::Input { pc, line: None })? {
+                    Some(TraceEvent::Input { line, .. }) => line,
+                    _ => {
+                        let line = self.read_line()?;
+                        self.record(TraceEvent::Input {
+                            pc,
+                            line: line.clone(),
+                        });
+                        line
+                    }
+                };
+                self.memory
+                    .push(line.map(Value::Str).unwrap_or(Value::Empty));
+            }
+            "load_object" => {
+                let result = match args.remove(0) {
+                    Value::Str(path) => FileSystemNode::try_from(PathBuf::from(path))
+                        .and_then(|node| node.try_load_into_vm(self)),
+                    _ => Err(VMError::WrongFileType),
+                };
+                // loading failures are given back to the caller, which decides whether they matter
+                self.memory.push(match result {
+                    Ok(()) => Value::Empty,
+                    Err(e) => Value::Str(e.to_string()),
+                });
+            }
+            "entry_point" => {
+                let entry_point = self.image_entry_point.clone();
+                self.memory
+                    .push(entry_point.map(Value::Str).unwrap_or(Value::Empty));
+            }
+            "find_labels" => {
+                let name = args
+                    .remove(0)
+                    .into_string()
+                    .expect("first value should be a string");
+                let namespaced = format!("::{name}");
+                let mut labels = self
+                    .instructions
+                    .iter()
+                    .filter_map(|asm| match asm {
+                        Asm::PublicLabel(label)
+                            if label == &name || label.ends_with(&namespaced) =>
+                        {
+                            Some(label.clone())
+                        }
+                        _ => None,
+                    })
+                    .collect::<Vec<_>>();
+                labels.sort();
+                self.memory
+                    .push(Value::Array(labels.into_iter().map(Value::Str).collect()));
+            }
             "@call" => {
                 if let Value::Str(method) = args.remove(0) {
-                    self.native_method(&method, args)
+                    return self.native_method(&method, args);
                 } else {
                     panic!("Must have a string as the first argument if message is {CALL}")
                 }
             }
             _ => panic!("{:?} is not a native method", message),
         }
+        Ok(())
+    }
+
+    /// Reads a line from the standard input, without the trailing new line. Returns `None` if the
+    /// input has been exhausted.
+    fn read_line(&mut self) -> Result<Option<String>, VMError> {
+        let mut stdin: Box<dyn Read> = Box::new(stdin());
+        let input = self.stdin.as_mut().unwrap_or(&mut stdin);
+        let mut line = vec![];
+        let mut byte = [0u8; 1];
+        loop {
+            if input.read(&mut byte)? == 0 {
+                if line.is_empty() {
+                    return Ok(None);
+                }
+                break;
+            }
+            if byte[0] == b'\n' {
+                break;
+            }
+            line.push(byte[0]);
+        }
+        Ok(Some(String::from_utf8(line).map_err(|e| anyhow!(e))?))
+    }
+
+    /// Calls a plugin label. Arguments are passed to the plugin through the stack.
+    fn call_plugin(&mut self, label: &str) -> Result<Value, VMError> {
+        let pc = self.program_counter();
+        let expected = TraceEvent::Plugin {
+            pc,
+            label: label.to_string(),
+            popped: 0,
+            pushed: vec![],
+            result: Value::Empty,
+            nested: 0,
+        };
+        match self.replayed(&expected)? {
+            Some(TraceEvent::Plugin {
+                popped,
+                pushed,
+                result,
+                nested,
+                ..
+            }) => {
+                // the plugin isn't called while replaying, so its effect on the stack is
+                // reproduced and anything it did through the vm handle is skipped
+                for _ in 0..popped {
+                    self.memory.pop();
+                }
+                for value in pushed {
+                    self.memory.push(value);
+                }
+                if let Some(mode) = &mut self.trace_mode {
+                    mode.skip(nested);
+                }
+                return Ok(result);
+            }
+            Some(TraceEvent::PluginFailed { error, nested, .. }) => {
+                if let Some(mode) = &mut self.trace_mode {
+                    mode.skip(nested);
+                }
+                return Err(anyhow!(error).into());
+            }
+            _ => {}
+        }
+
+        let reserved = self.trace_mode.as_mut().and_then(|mode| mode.reserve());
+        let start_len = self.memory.stack().len();
+        if let Some(profiler) = &mut self.profiler {
+            profiler.enter_external(format!("[plugin] {label}"));
+        }
+        // the plugin manager isn't kept locked while the plugin runs, so guest code the plugin
+        // calls back into can load and unload plugins
+        let plugin = {
+            let plugin_manager = self.plugin_manager.read().unwrap();
+            plugin_manager.finalize_pending();
+            plugin_manager.plugin_for_label(label)
+        };
+        let called = plugin.and_then(|plugin| {
+            let mut handle = DefaultVmHandle::new(self).owned_by(plugin.id().to_string());
+            plugin
+                .call_label(label, &mut handle)
+                .map(|result| (result, handle.low_water()))
+        });
+        if let Some(profiler) = &mut self.profiler {
+            profiler.exit_external();
+        }
+        let (result, low_water) = match called {
+            Ok(called) => called,
+            Err(error) => {
+                if let (Some(mode), Some(index)) = (&mut self.trace_mode, reserved) {
+                    mode.complete(
+                        index,
+                        TraceEvent::PluginFailed {
+                            pc,
+                            label: label.to_string(),
+                            error: error.to_string(),
+                            nested: 0,
+                        },
+                    );
+                }
+                return Err(error.into());
+            }
+        };
+        if let (Some(mode), Some(index)) = (&mut self.trace_mode, reserved) {
+            let low_water = low_water.min(self.memory.stack().len());
+            let pushed = self.memory.stack()[low_water..].to_vec();
+            if pushed.iter().chain([&result]).any(contains_native_handle) {
+                let error =
+                    VMError::Unrecordable(format!("{expected}, which produced a native handle"));
+                mode.complete(
+                    index,
+                    TraceEvent::PluginFailed {
+                        pc,
+                        label: label.to_string(),
+                        error: error.to_string(),
+                        nested: 0,
+                    },
+                );
+                return Err(error);
+            }
+            mode.complete(
+                index,
+                TraceEvent::Plugin {
+                    pc,
+                    label: label.to_string(),
+                    popped: start_len - low_water,
+                    pushed,
+                    result: result.clone(),
+                    nested: 0,
+                },
+            );
+        }
+        Ok(result)
+    }
+
+    /// Sends a message to a native handle, which is handled by the plugin that owns it
+    fn native_handle_message(
+        &mut self,
+        native_handle: &NativeHandle,
+        message: &str,
+        args: Vec<Value>,
+    ) -> Result<Value, VMError> {
+        if let Some(profiler) = &mut self.profiler {
+            profiler.enter_external(format!(
+                "[plugin] {}.{}",
+                native_handle.type_name(),
+                message
+            ));
+        }
+        let plugin = self
+            .plugin_manager
+            .read()
+            .unwrap()
+            .plugin_for_handle(native_handle);
+        let result = plugin.and_then(|plugin| {
+            let mut handle = DefaultVmHandle::new(self).owned_by(native_handle.owner());
+            plugin.handle_message(native_handle, message, &args, &mut handle)
+        });
+        if let Some(profiler) = &mut self.profiler {
+            profiler.exit_external();
+        }
+        Ok(result?)
+    }
+
+    /// While replaying, gets the recorded event for an interaction. Fails if the interaction
+    /// isn't the next one in the trace.
+    fn replayed(&mut self, expected: &TraceEvent) -> Result<Option<TraceEvent>, VMError> {
+        match &mut self.trace_mode {
+            None => Ok(None),
+            Some(mode) => mode.replayed(expected).map_err(|mismatch| {
+                let pc = expected.pc();
+                VMError::ReplayDivergence {
+                    pc,
+                    instruction: format!("{:?}", self.instructions[pc]),
+                    expected: mismatch.expected,
+                    found: mismatch.found,
+                }
+            }),
+        }
+    }
+
+    /// While recording, adds an event to the trace
+    fn record(&mut self, event: TraceEvent) {
+        if let Some(mode) = &mut self.trace_mode {
+            mode.record(event);
+        }
+    }
+
+    /// Runs instructions from the current program counter until the VM stops or is paused
+    fn run_loop(&mut self) -> Result<u32, VMError> {
+        self.cont = true;
+        self.paused = false;
+        loop {
+            while self.cont && self.program_counter_in_range() {
+                if let Some(budget) = self.instruction_budget {
+                    if budget == 0 {
+                        self.instruction_budget = None;
+                        self.paused = true;
+                        return Err(VMError::Paused);
+                    }
+                    self.instruction_budget = Some(budget - 1);
+                }
+                self.step()?;
+            }
+
+            match std::mem::replace(&mut self.handler, None) {
+                None => break,
+                Some(handle) => {
+                    self.kernel_mode = false;
+                    self.end_fault(handle);
+                }
+            }
+        }
+        let output = match self.memory.pop() {
+            None => Err(VMError::NoExitCode),
+            Some(Value::UInteger(u)) => Ok(u as u32),
+            Some(v) => Err(VMError::ExitCodeInvalidType(v)),
+        };
+        output
+    }
+
+    fn program_counter_in_range(&self) -> bool {
+        (1..=self.instructions.len() - 1).contains(&self.program_counter())
+    }
+
+    /// Runs the instruction at the program counter
+    fn step(&mut self) -> Result<(), VMError> {
+        if let Some(max_instructions) = self.limits.max_instructions {
+            if self.instructions_run >= max_instructions {
+                return Err(VMError::InstructionLimitExceeded(max_instructions));
+            }
+        }
+        self.instructions_run += 1;
+        let pc = self.program_counter();
+        let ref instruction = self.instructions[pc].clone();
+        info!(
+            target: "virtual_machine",
+            "[{function:^18}] 0x{pc:016X}: {asm: <24}  {top}",
+            function=Identifier::abbreviate_identifier(self.pc_to_recent_id(pc), 18),
+            asm=format!("{:?}", instruction),
+            top=self.memory.stack().last().map(|s| format!("(top = {})", s)).unwrap_or(String::new())
+        );
+        if let Some(counts) = &mut self.coverage {
+            if counts.len() <= pc {
+                counts.resize(self.instructions.len(), 0);
+            }
+            counts[pc] += 1;
+        }
+        let started = self.profile_frames();
+        let next = self.interpret_instruction(instruction, pc)?;
+        if let (Some(profiler), Some(started)) = (&mut self.profiler, started) {
+            profiler.instruction(started.elapsed());
+        }
+        self.set_program_counter(next);
+        if let Some(fault) = self.pending_fault.take() {
+            self.fault(fault)?;
+        }
+        trace!(target: "virtual_machine", "vm: {:#?}", self);
+        Ok(())
+    }
+
+    /// Sends a message on behalf of a plugin. If the message calls guest code, the guest code is
+    /// run until it returns. The instruction budget doesn't apply to guest code run this way.
+    fn send_guest_message(
+        &mut self,
+        mut target: Value,
+        message: &str,
+        args: Vec<Value>,
+    ) -> Result<Value, VMError> {
+        let outer_fault = self.in_fault();
+        let stack_len = self.memory.stack().len();
+        // returning from the guest code lands on this counter, which stops the nested loop
+        self.counter_stack.push(0);
+        let depth = self.counter_stack.len();
+        let result = match self.send_message(&mut target, message, args) {
+            Ok(Some(next)) => {
+                self.set_program_counter(next);
+                self.run_nested(depth, outer_fault)
+            }
+            Ok(None) => Ok(()),
+            Err(e) => Err(e),
+        };
+        self.counter_stack.truncate(depth - 1);
+        result?;
+        if self.memory.stack().len() > stack_len {
+            Ok(self.memory.pop().unwrap_or(Value::Empty))
+        } else {
+            Ok(Value::Empty)
+        }
+    }
+
+    /// Runs instructions until the counter stack is back at `depth`. Faults that happen during
+    /// the nested run are handled within it.
+    fn run_nested(&mut self, depth: usize, outer_fault: bool) -> Result<(), VMError> {
+        loop {
+            while self.cont
+                && self.program_counter_in_range()
+                && (self.counter_stack.len() > depth || (!outer_fault && self.in_fault()))
+            {
+                self.step()?;
+            }
+            if self.cont && !outer_fault {
+                if let Some(handle) = self.handler.take() {
+                    self.kernel_mode = false;
+                    self.end_fault(handle);
+                    continue;
+                }
+            }
+            break;
+        }
+        if self.counter_stack.len() > depth {
+            Err(VMError::GuestCallIncomplete)
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Verifies a module, then loads it. Code within "static" blocks is run once the module is
+    /// loaded.
+    fn load_verified<Assembly: GetAsm>(
+        &mut self,
+        asm: Assembly,
+        kernel_mode: bool,
+    ) -> Result<(), VMError> {
+        let start_index = self.instructions.len();
+        let as_asm = asm.get_asm();
+        // labels are checked before anything is loaded, so a failed load changes nothing
+        if let Some(label) = as_asm.iter().filter_map(Asm::defined_label).find(|label| {
+            self.label_to_instruction.contains_key(*label) && !label.starts_with("@@")
+        }) {
+            return Err(VMError::LabelAlreadyRegistered(label.to_string()));
+        }
+        Verifier::new(start_index)
+            .kernel_mode(kernel_mode)
+            .defined_labels(|label| self.can_resolve(label))
+            .verify(&as_asm)
+            .map_err(VMError::VerificationFailed)?;
+        if let Some(debug_info) = asm.debug_info() {
+            self.debug_info.push(LoadedDebugInfo {
+                instructions: start_index..start_index + as_asm.len(),
+                debug_info,
+            });
+        }
+        let mut new_labels = map![];
+        let mut static_instructions = set![];
+        for (index, asm) in as_asm.into_iter().enumerate() {
+            let mut label: Option<&String> = None;
+            let mut is_static = false;
+            match &asm {
+                Asm::Label(lbl) => {
+                    label = Some(lbl);
+                }
+                Asm::PublicLabel(lbl) => {
+                    label = Some(lbl);
+                }
+                Asm::Static => {
+                    is_static = true;
+                }
+                _ => {}
+            };
+
+            if let Some(asm_label) = label {
+                let label_index = start_index + index;
+                // only labels starting with @@ can be registered again
+                self.label_to_instruction
+                    .insert(asm_label.clone(), label_index);
+                new_labels.insert(asm_label.clone(), label_index);
+            }
+            if is_static {
+                static_instructions.insert(start_index + index);
+            }
+
+            self.instructions.push(asm);
+        }
+        info!("Created new labels = {:?}", new_labels);
+
+        for static_instruction_index in static_instructions {
+            info!("Running static code at {static_instruction_index}");
+            self.run_static_code(static_instruction_index)?;
+        }
+        Ok(())
+    }
+
+    /// Runs static code. Returning from the static code stops the run, even if it was loaded while
+    /// other code is running.
+    fn run_static(&mut self, index: usize) -> Result<u32, VMError> {
+        let depth = self.counter_stack.len();
+        self.counter_stack.push(0);
+        let result = self.run_from_index(index);
+        self.counter_stack.truncate(depth);
+        result
+    }
+
+    /// Runs static code that's being loaded, which fails unless it exits with 0
+    fn run_static_code(&mut self, index: usize) -> Result<(), VMError> {
+        match self.run_static(index)? {
+            0 => Ok(()),
+            code => Err(VMError::StaticCodeFailed(code)),
+        }
+    }
+
+    /// Gets the value of a global variable
+    fn global(&mut self, var: usize) -> Option<Value> {
+        self.memory.global_scope();
+        let value = self
+            .memory
+            .get_var(var)
+            .ok()
+            .map(|value| value.borrow().clone());
+        self.memory.back_scope();
+        value
+    }
+
+    /// Sets the value of a global variable
+    fn set_global(&mut self, var: usize, value: Value) {
+        self.memory.global_scope();
+        self.memory.set_var(var, value);
+        self.memory.back_scope();
     }
 
-    fn stack(&self) -> VMStack<M> {
-        unsafe { VMStack::new(&mut *(&self.memory as *const M as *mut M)) }
+    /// Updates the frames of the profiler before an instruction is run, returning when the
+    /// instruction started
+    fn profile_frames(&mut self) -> Option<Instant> {
+        let mut profiler = self.profiler.take()?;
+        profiler.sync(&self.counter_stack, |pc| {
+            self.most_recent_public_label(pc).cloned()
+        });
+        self.profiler = Some(profiler);
+        Some(Instant::now())
     }
 
     fn send_message(
@@ -264,7 +870,7 @@ where
         target: &mut Value,
         message: &str,
         mut args: Vec<Value>,
-    ) -> Option<usize> {
+    ) -> Result<Option<usize>, VMError> {
         info!(
             "Sending {:?} to {:?} with args ({})",
             message,
@@ -335,7 +941,7 @@ where
                 let name = self.anonymous_function_label();
                 let label = Asm::Label(name.clone());
                 decoded.insert(0, label);
-                self.load(decoded);
+                self.try_load(decoded)?;
 
                 let mut value = Value::Function(AsmLocation::Label(name.clone()));
                 self.memory.save_current_scope(&name);
@@ -349,17 +955,37 @@ where
                 return self.call(f, args);
             }
             Value::Native => {
-                self.native_method(message, args);
+                self.native_method(message, args)?;
+            }
+            Value::NativeHandle(native_handle) => {
+                let native_handle = native_handle.clone();
+                let result = self.native_handle_message(&native_handle, message, args)?;
+                self.memory.push(result);
             }
         }
-        return None;
+        return Ok(None);
+    }
+
+    /// Whether a label is within the loaded code or belongs to a plugin
+    fn has_symbol(&self, label: &str) -> bool {
+        self.label_to_instruction.contains_key(label)
+            || self.plugin_manager.read().unwrap().loaded_label(label)
+    }
+
+    /// Whether a label is a symbol, or belongs to a module that the lazy object path can load
+    fn can_resolve(&self, label: &str) -> bool {
+        self.has_symbol(label) || self.lazy_object_path.provides(label)
     }
 
     fn program_counter(&self) -> usize {
         self.counter_stack.last().copied().unwrap_or(0)
     }
 
-    fn call(&mut self, asm_location: &AsmLocation, mut args: Vec<Value>) -> Option<usize> {
+    fn call(
+        &mut self,
+        asm_location: &AsmLocation,
+        mut args: Vec<Value>,
+    ) -> Result<Option<usize>, VMError> {
         info!(
             "Attempting to call {:?} with args ({})",
             asm_location,
@@ -379,20 +1005,34 @@ where
                 panic!("Illegal for calling functions")
             }
             AsmLocation::Label(l) => {
-                let read = self.plugin_manager.read().unwrap();
-                if read.loaded_label(l) {
-                    let ref mut stack = self.stack();
-                    let ref mut handle = DefaultVmHandle::new(self);
-                    let output = read.call_function(l.as_ref(), stack, handle).unwrap();
+                let is_plugin_label = self.plugin_manager.read().unwrap().loaded_label(l);
+                if is_plugin_label {
+                    let output = self.call_plugin(l)?;
                     self.memory.push(output);
-                    return None;
+                    return Ok(None);
+                }
+                match self.label_to_instruction.get(l) {
+                    Some(&pc) => pc,
+                    None => {
+                        // such as the label of a plugin that was unloaded, or of a module that
+                        // isn't loaded yet
+                        self.fault(Fault::MissingSymbol(l.clone()))?;
+                        *self
+                            .label_to_instruction
+                            .get(l)
+                            .ok_or_else(|| VMError::MissingSymbol(l.clone()))?
+                    }
                 }
-                self.label_to_instruction[l]
             }
         };
+        if let Some(max_call_depth) = self.limits.max_call_depth {
+            if self.counter_stack.len() >= max_call_depth {
+                return Err(VMError::CallDepthExceeded(max_call_depth));
+            }
+        }
         debug!("Returning next PC to function at index 0x{:016X}", next_pc);
         self.counter_stack.push(0);
-        Some(next_pc)
+        Ok(Some(next_pc))
     }
 
     fn anonymous_function_label(&self) -> String {
@@ -421,24 +1061,166 @@ where
         self.memory.replace_stack(stored_stack);
     }
 
-    fn handle_native_fault(&mut self, _handle: &FaultHandle) {}
+    /// Handles a fault within the virtual machine. A missing symbol is handled by loading the
+    /// module that owns it from the lazy object path, if there is one.
+    fn handle_native_fault(&mut self, fault: &Fault) -> Result<(), VMError> {
+        match fault {
+            Fault::MissingSymbol(symbol) => {
+                if let Some(module) = self.lazy_object_path.find_module(symbol)? {
+                    info!("Loading {:?}, which owns {}", module, symbol);
+                    module.try_load_into_vm(self)?;
+                }
+                Ok(())
+            }
+            Fault::DoubleFault => panic!("Double Fault Encountered"),
+        }
+    }
 
-    pub fn load_plugin<P: LoadablePlugin>(&mut self) {
+    pub fn load_plugin<P: LoadablePlugin>(&mut self) -> Result<PluginId, VMError> {
         self.with_plugin(P::new())
     }
 
-    pub fn with_plugin<P: Plugin>(&mut self, plugin: P) {
-        self.plugin_manager.write().unwrap().with_plugin(plugin);
+    pub fn with_plugin<P: Plugin>(&mut self, plugin: P) -> Result<PluginId, VMError> {
+        Ok(self.plugin_manager.write().unwrap().with_plugin(plugin)?)
     }
 
-    pub fn load_dynamic_plugin<S: AsRef<OsStr>>(&mut self, path: S) -> Result<(), VMError> {
+    pub fn load_dynamic_plugin<S: AsRef<OsStr>>(&mut self, path: S) -> Result<PluginId, VMError> {
         unsafe {
             let path = path.as_ref();
-            self.plugin_manager.write().unwrap().load_plugin(path)?;
-            println!("Loaded {:?}", path);
-            Ok(())
+            let id = self.plugin_manager.write().unwrap().load_plugin(path)?;
+            info!("Loaded plugin {:?}", path);
+            Ok(id)
         }
     }
+
+    /// Loads discovered plugins, checking that each registers its required labels
+    pub fn load_discovered_plugins(
+        &mut self,
+        plugins: &[DiscoveredPlugin],
+    ) -> Result<Vec<PluginId>, VMError> {
+        let mut ids = vec![];
+        for plugin in plugins {
+            let id = self.load_dynamic_plugin(&plugin.path)?;
+            let missing = {
+                let plugin_manager = self.plugin_manager.read().unwrap();
+                let registered = plugin_manager.labels_of(id);
+                plugin
+                    .labels
+                    .iter()
+                    .filter(|label| !registered.contains(&label.as_str()))
+                    .cloned()
+                    .collect::<Vec<_>>()
+            };
+            if !missing.is_empty() {
+                self.unload_plugin(id)?;
+                return Err(PluginError::MissingLabels {
+                    path: plugin.path.clone(),
+                    labels: missing,
+                }
+                .into());
+            }
+            ids.push(id);
+        }
+        Ok(ids)
+    }
+
+    /// Unloads a plugin. Calling one of its labels afterwards raises a `MissingSymbol` fault.
+    pub fn unload_plugin(&mut self, id: PluginId) -> Result<(), VMError> {
+        Ok(self.plugin_manager.write().unwrap().unload_plugin(id)?)
+    }
+
+    /// Loads the library of a dynamic plugin again. Loaded guest code and native handles owned by
+    /// the plugin keep working.
+    pub fn reload_plugin(&mut self, id: PluginId) -> Result<(), VMError> {
+        unsafe { Ok(self.plugin_manager.write().unwrap().reload_plugin(id)?) }
+    }
+
+    /// Sets what happens when a plugin registers a label that's already registered
+    pub fn set_label_conflict(&mut self, label_conflict: LabelConflict) {
+        self.plugin_manager
+            .write()
+            .unwrap()
+            .set_label_conflict(label_conflict);
+    }
+
+    /// The plugins of the virtual machine. Plugins can be loaded, unloaded and reloaded through it
+    /// from other threads while the virtual machine runs.
+    pub fn plugin_manager(&self) -> Arc<RwLock<PluginManager>> {
+        self.plugin_manager.clone()
+    }
+}
+
+impl<'l, M, A> VM<'l, M, A>
+where
+    M: SnapshotMemory,
+    A: ArithmeticsTrait,
+{
+    /// Captures the full state of the virtual machine as bytes. Plugins and the standard
+    /// input and outputs are not part of the snapshot.
+    pub fn snapshot(&self) -> Result<Vec<u8>, VMError> {
+        let mut heap = HeapWriter::default();
+        let memory = self.memory.to_image(&mut heap);
+        let handler = self.handler.as_ref().map(|handle| FaultHandleImage {
+            stored_pc: handle.stored_pc.clone(),
+            stored_stack: heap.values(&handle.stored_stack),
+            fault: handle.fault.clone(),
+            target_function: heap.value(&handle.target_function),
+        });
+        let mut label_to_instruction = self
+            .label_to_instruction
+            .iter()
+            .map(|(label, &index)| (label.clone(), index))
+            .collect::<Vec<_>>();
+        label_to_instruction.sort_by(|(left, left_index), (right, right_index)| {
+            (left_index, left).cmp(&(right_index, right))
+        });
+
+        let snapshot = VMSnapshot {
+            instructions: self.instructions.clone(),
+            label_to_instruction,
+            counter_stack: self.counter_stack.clone(),
+            next_anonymous_function: self.next_anonymous_function.load(Ordering::Relaxed),
+            kernel_mode: self.kernel_mode,
+            paused: self.paused,
+            instruction_budget: self.instruction_budget,
+            instructions_run: self.instructions_run,
+            handler,
+            heap: heap.finish(),
+            memory,
+            debug_info: self.debug_info.clone(),
+            image_entry_point: self.image_entry_point.clone(),
+        };
+        snapshot.to_bytes()
+    }
+
+    /// Replaces the state of this virtual machine with a snapshot. A snapshot taken while the
+    /// virtual machine was paused can be continued with [resume](Self::resume).
+    pub fn restore(&mut self, bytes: &[u8]) -> Result<(), VMError> {
+        let snapshot: VMSnapshot<M::Image> = VMSnapshot::from_bytes(bytes)?;
+        let heap = HeapReader::new(snapshot.heap)?;
+        let handler = match snapshot.handler {
+            None => None,
+            Some(image) => Some(FaultHandle::new(
+                image.stored_pc,
+                heap.values(&image.stored_stack)?,
+                image.fault,
+                heap.value(&image.target_function)?,
+            )),
+        };
+        self.memory = M::from_image(snapshot.memory, &heap)?;
+        self.instructions = snapshot.instructions;
+        self.label_to_instruction = snapshot.label_to_instruction.into_iter().collect();
+        self.counter_stack = snapshot.counter_stack;
+        self.next_anonymous_function = AtomicU64::new(snapshot.next_anonymous_function);
+        self.kernel_mode = snapshot.kernel_mode;
+        self.handler = handler;
+        self.paused = snapshot.paused;
+        self.instruction_budget = snapshot.instruction_budget;
+        self.instructions_run = snapshot.instructions_run;
+        self.debug_info = snapshot.debug_info;
+        self.image_entry_point = snapshot.image_entry_point;
+        Ok(())
+    }
 }
 
 impl<M, A> VirtualMachine for VM<'_, M, A>
@@ -453,7 +1235,7 @@ where
     ) -> Result<usize, VMError> {
         let mut next_instruction = instruction_pointer + 1;
         match bytecode {
-            Asm::Label(_) | Asm::PublicLabel(_) | Asm::Nop => {}
+            Asm::Label(_) | Asm::PublicLabel(_) | Asm::Static | Asm::Nop => {}
             Asm::Pop => {
                 self.memory.pop().unwrap();
             }
@@ -558,15 +1340,16 @@ where
                 self.memory.push(value);
             }
             &Asm::ClearVar(_v) => {}
-            Asm::GetSymbol(string) => match self.label_to_instruction.get(string) {
-                None => {
-                    self.fault(Fault::MissingSymbol(string.clone()));
-                }
-                Some(_) => {
-                    let value = Value::Function(AsmLocation::Label(string.clone()));
-                    self.memory.push(value);
+            Asm::GetSymbol(string) => {
+                if !self.has_symbol(string) {
+                    self.fault(Fault::MissingSymbol(string.clone()))?;
+                    if !self.has_symbol(string) {
+                        return Err(VMError::MissingSymbol(string.clone()));
+                    }
                 }
-            },
+                let value = Value::Function(AsmLocation::Label(string.clone()));
+                self.memory.push(value);
+            }
             Asm::SendMessage => {
                 let mut target = self
                     .memory
@@ -582,7 +1365,16 @@ where
                 } else {
                     panic!("Arguments must be an array of values")
                 };
-                if let Some(next) = self.send_message(&mut target, &*message, args) {
+                if let Some(next) = self.send_message(&mut target, &*message, args)? {
+                    next_instruction = next;
+                }
+            }
+            Asm::Call(location) => {
+                let argument = self
+                    .memory
+                    .pop()
+                    .expect("There should be an argument on the stack");
+                if let Some(next) = self.call(location, vec![argument])? {
                     next_instruction = next;
                 }
             }
@@ -593,7 +1385,7 @@ where
                     .memory
                     .pop()
                     .expect("There should be a target value on the stack")];
-                if let Some(next) = self.send_message(&mut target, message, args) {
+                if let Some(next) = self.send_message(&mut target, message, args)? {
                     next_instruction = next;
                 }
             }
@@ -608,7 +1400,7 @@ where
                             .expect("Expected a value on the stack for native method call"),
                     )
                 }
-                if let Some(next) = self.send_message(&mut target, message, args) {
+                if let Some(next) = self.send_message(&mut target, message, args)? {
                     next_instruction = next;
                 }
             }
@@ -718,114 +1510,84 @@ where
         todo!()
     }
 
-    fn load<Assembly: GetAsm>(&mut self, asm: Assembly) {
+    fn try_load<Assembly: GetAsm>(&mut self, asm: Assembly) -> Result<(), VMError> {
+        self.load_verified(asm, self.kernel_mode)
+    }
+
+    fn try_load_static<Assembly: GetAsm>(&mut self, asm: Assembly) -> Result<(), VMError> {
         let start_index = self.instructions.len();
-        let as_asm = asm.get_asm();
-        let mut new_labels = map![];
-        let mut static_instructions = set![];
-        for (index, asm) in as_asm.into_iter().enumerate() {
-            let mut label: Option<&String> = None;
-            let mut is_static = false;
-            match &asm {
-                Asm::Label(lbl) => {
-                    label = Some(lbl);
-                }
-                Asm::PublicLabel(lbl) => {
-                    label = Some(lbl);
-                }
-                Asm::Static => {
-                    is_static = true;
-                }
-                _ => {}
-            };
+        self.load_verified(asm, true)?;
+        self.memory.global_scope();
+        let result = self.run_static_code(start_index);
+        self.memory.back_scope();
+        result
+    }
 
-            if let Some(asm_label) = label {
-                let label_index = start_index + index;
-                match self.label_to_instruction.entry(asm_label.clone()) {
-                    Entry::Occupied(mut occupant) => {
-                        if occupant.key().starts_with("@@") {
-                            occupant.insert(label_index);
-                            new_labels.insert(asm_label.clone(), label_index);
-                        } else {
-                            panic!("label {:?} already registered", asm_label);
-                        }
-                    }
-                    Entry::Vacant(v) => {
-                        v.insert(label_index);
-                        new_labels.insert(asm_label.clone(), label_index);
-                    }
-                }
-            }
-            if is_static {
-                static_instructions.insert(start_index + index);
+    fn load_image(&mut self, image: ExecutableImage) -> Result<(), VMError> {
+        let start_index = self.instructions.len();
+        for label in image.labels.keys() {
+            if self.label_to_instruction.contains_key(label) && !label.starts_with("@@") {
+                return Err(VMError::LabelAlreadyRegistered(label.clone()));
             }
-
-            self.instructions.push(asm);
         }
-        info!("Created new labels = {:?}", new_labels);
+        Verifier::new(start_index)
+            .kernel_mode(self.kernel_mode)
+            .defined_labels(|label| self.can_resolve(label))
+            .verify(&image.jasm)
+            .map_err(VMError::VerificationFailed)?;
+        for (label, index) in image.labels {
+            self.label_to_instruction.insert(label, start_index + index);
+        }
+        for module in image.debug_info {
+            self.debug_info.push(LoadedDebugInfo {
+                instructions: start_index + module.instructions.start
+                    ..start_index + module.instructions.end,
+                debug_info: module.debug_info,
+            });
+        }
+        let static_instructions = image
+            .jasm
+            .iter()
+            .enumerate()
+            .filter(|(_, asm)| matches!(asm, Asm::Static))
+            .map(|(index, _)| start_index + index)
+            .collect::<Vec<_>>();
+        self.instructions.extend(image.jasm);
+        self.image_entry_point = Some(image.entry_point);
 
         for static_instruction_index in static_instructions {
             info!("Running static code at {static_instruction_index}");
-            self.run_from_index(static_instruction_index);
+            self.run_static_code(static_instruction_index)?;
         }
-    }
-
-    fn load_static<Assembly: GetAsm>(&mut self, asm: Assembly) {
-        let start_index = self.instructions.len();
-        self.load(asm);
-        self.memory.global_scope();
-        if self
-            .run_from_index(start_index)
-            .expect("VM Error encountered")
-            != 0
-        {
-            panic!("VM Failed")
+        for initializer in image.initializers {
+            self.memory.global_scope();
+            let result = self.run_static_code(start_index + initializer);
+            self.memory.back_scope();
+            result?;
         }
-        self.memory.back_scope();
+        Ok(())
     }
 
     fn run(&mut self, start_label: &str) -> Result<u32, VMError> {
-        let start_counter = self.label_to_instruction[start_label];
+        let start_counter = *self
+            .label_to_instruction
+            .get(start_label)
+            .ok_or_else(|| VMError::MissingSymbol(start_label.to_string()))?;
         self.run_from_index(start_counter)
     }
 
     fn run_from_index(&mut self, index: usize) -> Result<u32, VMError> {
-        self.cont = true;
         self.counter_stack.push(index);
-        loop {
-            while self.cont && (1..=self.instructions.len() - 1).contains(&self.program_counter()) {
-                let pc = self.program_counter();
-                let ref instruction = self.instructions[pc].clone();
-                info!(
-                    target: "virtual_machine",
-                    "[{function:^18}] 0x{pc:016X}: {asm: <24}  {top}",
-                    function=Identifier::abbreviate_identifier(self.pc_to_recent_id(pc), 18),
-                    asm=format!("{:?}", instruction),
-                    top=self.memory.stack().last().map(|s| format!("(top = {})", s)).unwrap_or(String::new())
-                );
-                let next = self.interpret_instruction(instruction, pc)?;
-                self.set_program_counter(next);
-                trace!(target: "virtual_machine", "vm: {:#?}", self);
-            }
-
-            match std::mem::replace(&mut self.handler, None) {
-                None => break,
-                Some(handle) => {
-                    self.kernel_mode = false;
-                    self.end_fault(handle);
-                }
-            }
-        }
-        let output = match self.memory.pop() {
-            None => Err(VMError::NoExitCode),
-            Some(Value::UInteger(u)) => Ok(u as u32),
-            Some(v) => Err(VMError::ExitCodeInvalidType(v)),
-        };
-        output
+        self.run_loop()
     }
 
-    fn fault(&mut self, fault: Fault) {
+    /// Faults handled natively are handled immediately, so the instruction that raised the fault
+    /// can be retried.
+    fn fault(&mut self, fault: Fault) -> Result<(), VMError> {
         let target = self.fault_table.get_fault_jump(&fault);
+        if let Value::Native = target {
+            return self.handle_native_fault(&fault);
+        }
 
         let saved_counter = std::mem::replace(&mut self.counter_stack, vec![0]);
         let saved_stack = self.memory.take_stack();
@@ -836,21 +1598,16 @@ where
                 match self.label_to_instruction.entry(s.clone()) {
                     Entry::Occupied(v) => *v.get(),
                     Entry::Vacant(_) => {
-                        self.fault(Fault::DoubleFault);
-                        return;
+                        return self.fault(Fault::DoubleFault);
                     }
                 }
             }
-            Value::Native => {
-                self.kernel_mode = true;
-                self.handle_native_fault(&handle);
-                0
-            }
             v => panic!("Invalid value for fault jump target (value = {:?})", v),
         };
         self.handler = Some(handle);
         self.counter_stack.push(next_pc);
         self.kernel_mode = true;
+        Ok(())
     }
 
     fn is_kernel_mode(&self) -> bool {
@@ -865,6 +1622,10 @@ pub struct VMBuilder<'l, A, M> {
     stdout: Option<Box<dyn Write + 'l>>,
     stderr: Option<Box<dyn Write + 'l>>,
     object_path: Vec<PathBuf>,
+    plugin_path: PluginSearchPath,
+    plugin_manifests: Vec<PathBuf>,
+    limits: ResourceLimits,
+    lazy_object_path: LazyObjectPath,
 }
 
 impl<'l, A: ArithmeticsTrait, M: MemoryTrait> VMBuilder<'l, A, M> {
@@ -876,7 +1637,13 @@ impl<'l, A: ArithmeticsTrait, M: MemoryTrait> VMBuilder<'l, A, M> {
             stdout,
             stderr,
             object_path,
+            plugin_path,
+            plugin_manifests,
+            limits,
+            lazy_object_path,
         } = self;
+        let plugin_manager = PluginManager::new();
+        let pending_finalizations = plugin_manager.pending_finalizations();
         let mut vm = VM {
             memory: memory.expect("Memory module must be set"),
             alu: arithmetic.expect("Arithmetic module must be set"),
@@ -892,8 +1659,29 @@ impl<'l, A: ArithmeticsTrait, M: MemoryTrait> VMBuilder<'l, A, M> {
             handler: None,
             fault_table: Default::default(),
             kernel_mode: false,
-            plugin_manager: Arc::new(RwLock::new(PluginManager::new())),
+            plugin_manager: Arc::new(RwLock::new(plugin_manager)),
+            pending_finalizations,
+            instruction_budget: None,
+            paused: false,
+            limits,
+            instructions_run: 0,
+            lazy_object_path,
+            trace_mode: None,
+            profiler: None,
+            pending_fault: None,
+            debug_info: vec![],
+            image_entry_point: None,
+            coverage: None,
         };
+        let mut plugins = plugin_path
+            .libraries()?
+            .into_iter()
+            .map(DiscoveredPlugin::new)
+            .collect::<Vec<_>>();
+        for manifest in plugin_manifests {
+            plugins.extend(PluginManifest::load(manifest)?.resolve(&plugin_path)?);
+        }
+        vm.load_discovered_plugins(&plugins)?;
         for obj_path in object_path {
             obj_path.try_load_into_vm(&mut vm)?;
         }
@@ -910,6 +1698,10 @@ impl<'l, A, M> VMBuilder<'l, A, M> {
             stdout: None,
             stderr: None,
             object_path: vec![],
+            plugin_path: PluginSearchPath::new(),
+            plugin_manifests: vec![],
+            limits: ResourceLimits::default(),
+            lazy_object_path: LazyObjectPath::new(),
         }
     }
 
@@ -933,6 +1725,32 @@ impl<'l, A, M> VMBuilder<'l, A, M> {
         self.object_path.push(as_path);
         self
     }
+
+    /// Loads every plugin within the directories of the search path when the virtual machine
+    /// is built. Files of plugin manifests are also searched for within it.
+    pub fn plugin_path(mut self, search_path: PluginSearchPath) -> Self {
+        self.plugin_path = search_path;
+        self
+    }
+
+    /// Loads the plugins listed by a [manifest](PluginManifest) when the virtual machine is built
+    pub fn plugin_manifest<P: AsRef<Path>>(mut self, manifest: P) -> Self {
+        self.plugin_manifests.push(manifest.as_ref().to_path_buf());
+        self
+    }
+
+    /// Limits the resources programs can use
+    pub fn limits(mut self, limits: ResourceLimits) -> Self {
+        self.limits = limits;
+        self
+    }
+
+    /// Modules within the directory are loaded once one of their symbols is used, instead of when
+    /// the virtual machine is built
+    pub fn lazy_object_path<P: AsRef<Path>>(mut self, directory: P) -> Self {
+        self.lazy_object_path = self.lazy_object_path.with_directory(directory);
+        self
+    }
 }
 
 impl<A: ArithmeticsTrait, M> VMBuilder<'_, A, M> {
@@ -957,47 +1775,134 @@ impl<'l, A: ArithmeticsTrait, M: MemoryTrait> TryFrom<VMBuilder<'l, A, M>> for V
     }
 }
 
-pub struct VMStack<'vm, M: MemoryTrait> {
-    memory: &'vm mut M,
-}
-
-impl<'vm, M: MemoryTrait> VMStack<'vm, M> {
-    pub fn new(memory: &'vm mut M) -> Self {
-        Self { memory }
-    }
+/// The handle to the virtual machine given to plugins. The handle is also the stack of the
+/// virtual machine, so a plugin only ever borrows the virtual machine once.
+pub struct DefaultVmHandle<'a, 'vm, A: ArithmeticsTrait, M: MemoryTrait> {
+    vm: &'a mut VM<'vm, M, A>,
+    /// The plugin that is being called, which owns the native handles it creates
+    plugin: Option<String>,
+    /// The smallest size the stack has had since the handle was created
+    low_water: usize,
 }
 
-impl<'vm, M: MemoryTrait> Stack for VMStack<'vm, M> {
+impl<'a, 'vm, A: ArithmeticsTrait, M: MemoryTrait> Stack for DefaultVmHandle<'a, 'vm, A, M> {
     fn empty(&self) -> bool {
-        self.memory.stack().is_empty()
+        self.vm.memory.stack().is_empty()
     }
 
     fn push(&mut self, value: Value) {
-        self.memory.push(value);
+        self.vm.memory.push(value);
     }
 
     fn pop(&mut self, output: &mut Option<Value>) {
-        *output = self.memory.pop();
+        *output = self.vm.memory.pop();
+        self.low_water = self.low_water.min(self.vm.memory.stack().len());
     }
 }
 
-pub struct DefaultVmHandle<'a, 'vm, A: ArithmeticsTrait, M: MemoryTrait> {
-    vm: &'a mut VM<'vm, M, A>,
-}
-
 impl<'a, 'vm, A: ArithmeticsTrait, M: MemoryTrait> VMHandle for DefaultVmHandle<'a, 'vm, A, M> {
+    fn stack(&mut self) -> &mut dyn Stack {
+        self
+    }
+
     fn native(&mut self, method: &str, values: &[Value], output: &mut Option<Value>) {
-        self.vm.native_method(method, Vec::from(values));
-        if !method.starts_with("@") {
-            *output = self.vm.memory.pop();
+        *output = match self.vm.native_method(method, Vec::from(values)) {
+            Ok(()) if method.starts_with('@') => Some(Value::Empty),
+            Ok(()) => self.vm.memory.pop(),
+            Err(e) => {
+                warn!("Native method {} called by a plugin failed: {}", method, e);
+                None
+            }
+        };
+    }
+
+    fn send_message(
+        &mut self,
+        target: &Value,
+        message: &str,
+        args: &[Value],
+        output: &mut Option<Result<Value, String>>,
+    ) {
+        *output = Some(
+            self.vm
+                .send_guest_message(target.clone(), message, Vec::from(args))
+                .map_err(|e| e.to_string()),
+        );
+    }
+
+    fn get_global(&mut self, var: usize, output: &mut Option<Value>) {
+        *output = self.vm.global(var);
+    }
+
+    fn set_global(&mut self, var: usize, value: Value) {
+        self.vm.set_global(var, value);
+    }
+
+    fn allocate(&mut self, value: Value, output: &mut Option<Value>) {
+        *output = match value {
+            Value::Reference(_) => None,
+            value => Some(value.into_reference()),
+        };
+    }
+
+    fn raise_fault(&mut self, fault: &str, detail: &str) -> bool {
+        match Fault::from_name(fault, detail) {
+            Some(fault) => {
+                self.vm.pending_fault = Some(fault);
+                true
+            }
+            None => false,
         }
     }
+
+    fn new_native_handle(&mut self, type_name: &str, id: u64, output: &mut Option<Value>) {
+        let owner = match &self.plugin {
+            Some(owner) => owner.clone(),
+            None => {
+                *output = None;
+                return;
+            }
+        };
+        let plugin_manager = Arc::downgrade(&self.vm.plugin_manager);
+        let pending_finalizations = self.vm.pending_finalizations.clone();
+        let finalized_owner = owner.clone();
+        let finalizer = move |type_name: &str, id: u64| {
+            // while plugins are loaded, unloaded or reloaded the plugin manager is locked for
+            // writing, so the handle is finalized once the plugin manager is used again
+            if let Some(plugin_manager) = plugin_manager.upgrade() {
+                match plugin_manager.try_read() {
+                    Ok(plugin_manager) => {
+                        plugin_manager.finalize_pending();
+                        plugin_manager.finalize_handle(&finalized_owner, type_name, id);
+                    }
+                    Err(_) => pending_finalizations.push(&finalized_owner, type_name, id),
+                }
+            }
+        };
+        *output = Some(Value::NativeHandle(NativeHandle::new(
+            owner, type_name, id, finalizer,
+        )));
+    }
 }
 
 impl<'a, 'vm, A: ArithmeticsTrait, M: MemoryTrait> DefaultVmHandle<'a, 'vm, A, M> {
-    pub fn new(vm: &VM<'vm, M, A>) -> Self {
+    pub fn new(vm: &'a mut VM<'vm, M, A>) -> Self {
+        let low_water = vm.memory.stack().len();
         Self {
-            vm: unsafe { &mut *(vm as *const _ as *mut _) },
+            vm,
+            plugin: None,
+            low_water,
         }
     }
+
+    /// The smallest size the stack has had since the handle was created
+    pub fn low_water(&self) -> usize {
+        self.low_water
+    }
+
+    /// Sets the plugin the handle is given to
+    pub fn owned_by(mut self, plugin: impl Into<String>) -> Self {
+        self.plugin = Some(plugin.into());
+        self
+    }
 }
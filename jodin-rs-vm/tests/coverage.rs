@@ -0,0 +1,67 @@
+use jodin_common::assembly::debug_info::{DebugInfo, LineEntry};
+use jodin_common::assembly::instructions::Asm;
+use jodin_common::identifier::Identifier;
+use jodin_common::unit::CompilationObject;
+use jodin_rs_vm::core_traits::VirtualMachine;
+use jodin_rs_vm::mvp::{MinimumALU, MinimumMemory};
+use jodin_rs_vm::vm::VMBuilder;
+use std::path::PathBuf;
+
+/// The object compiled from
+///
+/// ```text
+/// 1 fn main() -> unsigned int {
+/// 2     if (1) {
+/// 3         return 0u;
+/// 4     } else {
+/// 5         return 1u;
+/// 6     }
+/// 7 }
+/// ```
+fn branching_object() -> CompilationObject {
+    let jasm = vec![
+        Asm::pub_label("main"),
+        Asm::push(1u8),
+        Asm::cond_goto("then"),
+        Asm::goto("else"),
+        Asm::label("then"),
+        Asm::push(0u64),
+        Asm::Return,
+        Asm::label("else"),
+        Asm::push(1u64),
+        Asm::Return,
+    ];
+    let lines = vec![
+        LineEntry { pc: 1, line: 2 },
+        LineEntry { pc: 5, line: 3 },
+        LineEntry { pc: 8, line: 5 },
+    ];
+    CompilationObject::new(
+        PathBuf::from("branching.jobj"),
+        Identifier::from("branching"),
+        vec![],
+        jasm,
+    )
+    .with_debug_info(DebugInfo::new(Some(PathBuf::from("branching.jdn")), lines))
+}
+
+#[test]
+fn untaken_branch_is_uncovered() {
+    let mut vm = VMBuilder::new()
+        .memory(MinimumMemory::default())
+        .alu(MinimumALU)
+        .build()
+        .unwrap();
+    vm.load(branching_object());
+    vm.enable_coverage();
+    assert_eq!(vm.run("main").expect("VM should not fail"), 0);
+    let coverage = vm.take_coverage().expect("coverage was enabled");
+
+    assert_eq!(coverage.uncovered_lines("branching.jdn"), vec![5]);
+    let mut lcov = vec![];
+    coverage.write_lcov(&mut lcov).unwrap();
+    assert_eq!(
+        String::from_utf8(lcov).unwrap(),
+        "TN:\nSF:branching.jdn\nDA:2,1\nDA:3,1\nDA:5,0\nLF:3\nLH:2\nend_of_record\n"
+    );
+}
@@ -0,0 +1,32 @@
+//! Booting programs through the kernel
+
+use jodin_common::assembly::instructions::Asm;
+use jodin_common::assembly::value::Value;
+use jodin_rs_vm::core_traits::VirtualMachine;
+use jodin_rs_vm::kernel::boot;
+use jodin_rs_vm::mvp::MinimumALU;
+use jodin_rs_vm::scoped_memory::VMMemory;
+use jodin_rs_vm::vm::VMBuilder;
+use jodin_vm_kernel::KernelPlugin;
+
+#[test]
+fn programs_can_be_booted_twice() {
+    let mut vm = VMBuilder::new()
+        .memory(VMMemory::default())
+        .alu(MinimumALU)
+        .build()
+        .unwrap();
+    vm.load_plugin::<KernelPlugin>().unwrap();
+    // the label the kernel is called from can't clash with the labels of the program
+    vm.load(vec![Asm::pub_label("start"), Asm::push(5u64), Asm::Return]);
+
+    for _ in 0..2 {
+        let exit_code = boot(
+            &mut vm,
+            Value::Empty,
+            Value::Array(vec![]),
+            Value::from("start"),
+        );
+        assert_eq!(exit_code.expect("VM should not fail"), 5);
+    }
+}
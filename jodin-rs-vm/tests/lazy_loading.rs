@@ -0,0 +1,181 @@
+//! Loads modules when one of their symbols is first used
+
+use jodin_common::assembly::instructions::{Asm, Assembly};
+use jodin_common::assembly::location::AsmLocation;
+use jodin_common::assembly::value::Value;
+use jodin_common::compilation::{Compilable, Context, PaddedWriter, Target};
+use jodin_common::core::function_names::CALL;
+use jodin_common::identifier::Identifier;
+use jodin_common::unit::CompilationObject;
+use jodin_rs_vm::core_traits::VirtualMachine;
+use jodin_rs_vm::error::VMError;
+use jodin_rs_vm::mvp::MinimumALU;
+use jodin_rs_vm::scoped_memory::VMMemory;
+use jodin_rs_vm::vm::{VMBuilder, VM};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+struct Lazy;
+impl Target for Lazy {}
+
+fn object_directory(name: &str) -> PathBuf {
+    let directory =
+        std::env::temp_dir().join(format!("jodin-lazy-{}-{}", name, std::process::id()));
+    let _ = fs::remove_dir_all(&directory);
+    fs::create_dir_all(&directory).unwrap();
+    directory
+}
+
+fn write_object(directory: &Path, module: &str, name: &str, jasm: Assembly) {
+    let directory = directory.join(PathBuf::from(module.parse::<Identifier>().unwrap()));
+    fs::create_dir_all(&directory).unwrap();
+    let path = directory.join(name);
+    let object = CompilationObject::new(path.clone(), module.parse().unwrap(), vec![], jasm);
+    let mut buffer = vec![];
+    let mut writer = PaddedWriter::new(&mut buffer);
+    Compilable::<Lazy>::compile(object, &Context::new(), &mut writer).unwrap();
+    drop(writer);
+    fs::write(path, buffer).unwrap();
+}
+
+/// Writes the modules `math` and `util`, each with a function returning a constant
+fn write_modules(directory: &Path) {
+    write_object(
+        directory,
+        "math",
+        "constants.jobj",
+        vec![
+            Asm::PublicLabel("math::two".to_string()),
+            Asm::push(2u64),
+            Asm::Return,
+        ],
+    );
+    write_object(
+        directory,
+        "util",
+        "constants.jobj",
+        vec![
+            Asm::PublicLabel("util::three".to_string()),
+            Asm::push(3u64),
+            Asm::Return,
+        ],
+    );
+}
+
+fn build(directory: &Path) -> VM<'static, VMMemory, MinimumALU> {
+    VMBuilder::new()
+        .memory(VMMemory::default())
+        .alu(MinimumALU)
+        .lazy_object_path(directory)
+        .build()
+        .unwrap()
+}
+
+fn call(target: Asm) -> Vec<Asm> {
+    vec![
+        Asm::push(Value::from(Vec::<Value>::new())),
+        Asm::push(Value::from(CALL)),
+        target,
+        Asm::SendMessage,
+    ]
+}
+
+fn is_loaded(vm: &VM<VMMemory, MinimumALU>, label: &str) -> bool {
+    vm.instructions()
+        .iter()
+        .any(|asm| matches!(asm, Asm::PublicLabel(l) if l == label))
+}
+
+#[test]
+fn call_loads_module() {
+    let directory = object_directory("call");
+    write_modules(&directory);
+    let mut vm = build(&directory);
+
+    let mut program = vec![Asm::label("main")];
+    program.extend(call(Asm::push(Value::Function(AsmLocation::Label(
+        "math::two".to_string(),
+    )))));
+    program.push(Asm::Pop);
+    // the module is only loaded once
+    program.extend(call(Asm::GetSymbol("math::two".to_string())));
+    program.push(Asm::Return);
+    vm.load(program);
+
+    assert!(!is_loaded(&vm, "math::two"));
+    assert_eq!(vm.run("main").expect("VM should not fail"), 2);
+    assert!(is_loaded(&vm, "math::two"));
+    assert!(!is_loaded(&vm, "util::three"));
+    assert_eq!(
+        vm.instructions()
+            .iter()
+            .filter(|asm| matches!(asm, Asm::PublicLabel(l) if l == "math::two"))
+            .count(),
+        1
+    );
+    fs::remove_dir_all(directory).unwrap();
+}
+
+#[test]
+fn plain_call_loads_module() {
+    let directory = object_directory("plain-call");
+    write_modules(&directory);
+    let mut vm = build(&directory);
+
+    // the label isn't loaded yet, but the module that owns it can be
+    vm.try_load(vec![
+        Asm::label("main"),
+        Asm::push(0u64),
+        Asm::Call(AsmLocation::Label("math::two".to_string())),
+        Asm::Return,
+    ])
+    .expect("calls to lazily loaded modules should verify");
+    assert_eq!(vm.run("main").expect("VM should not fail"), 2);
+    assert!(is_loaded(&vm, "math::two"));
+
+    // labels that no module owns are still rejected
+    match vm.try_load(vec![
+        Asm::label("other"),
+        Asm::push(0u64),
+        Asm::Call(AsmLocation::Label("math::three".to_string())),
+        Asm::Return,
+    ]) {
+        Err(VMError::VerificationFailed(_)) => {}
+        other => panic!("expected a verification failure, got {:?}", other),
+    }
+    fs::remove_dir_all(directory).unwrap();
+}
+
+#[test]
+fn get_symbol_loads_module() {
+    let directory = object_directory("get-symbol");
+    write_modules(&directory);
+    let mut vm = build(&directory);
+
+    let mut program = vec![Asm::label("main")];
+    program.extend(call(Asm::GetSymbol("util::three".to_string())));
+    program.push(Asm::Return);
+    vm.load(program);
+
+    assert_eq!(vm.run("main").expect("VM should not fail"), 3);
+    assert!(!is_loaded(&vm, "math::two"));
+    fs::remove_dir_all(directory).unwrap();
+}
+
+#[test]
+fn unknown_symbol_is_an_error() {
+    let directory = object_directory("unknown");
+    write_modules(&directory);
+    let mut vm = build(&directory);
+
+    let mut program = vec![Asm::label("main")];
+    program.extend(call(Asm::GetSymbol("math::three".to_string())));
+    program.push(Asm::Return);
+    vm.load(program);
+
+    match vm.run("main") {
+        Err(VMError::MissingSymbol(symbol)) => assert_eq!(symbol, "math::three"),
+        other => panic!("expected a missing symbol, got {:?}", other),
+    }
+    fs::remove_dir_all(directory).unwrap();
+}
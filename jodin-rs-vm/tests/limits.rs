@@ -0,0 +1,79 @@
+#[macro_use]
+extern crate jasm_macros;
+
+use jodin_common::assembly::instructions::Assembly;
+use jodin_rs_vm::core_traits::VirtualMachine;
+use jodin_rs_vm::error::VMError;
+use jodin_rs_vm::limits::ResourceLimits;
+use jodin_rs_vm::mvp::MinimumALU;
+use jodin_rs_vm::scoped_memory::VMMemory;
+use jodin_rs_vm::vm::VMBuilder;
+
+fn create_fib_sequence_asm(n: u32) -> Assembly {
+    let block = block![
+        main:
+        label!(pub main);
+        return_!(call!(~ fibonacci, n));
+
+        label!(pub fibonacci);
+        scope!(global);
+        scope!(push);
+        var!(=> 0);
+        if_!(
+            (expr!(<, dvar!(0), 2u32)) {
+                block![
+                    dvar!(0);
+                    scope!(back);
+                    return_!();
+                ]
+            } else {
+                block![
+                        expr!(+,
+                                call!(~ fibonacci, expr!(-, dvar!(0), 1u32)),
+                                call!(~ fibonacci, expr!(-, dvar!(0), 2u32))
+                            );
+                        scope!(back);
+                        return_! ();
+                    ]
+            }
+        );
+    ];
+    block.normalize()
+}
+
+fn run_fibonacci(n: u32, limits: ResourceLimits) -> Result<u32, VMError> {
+    let mut vm = VMBuilder::new()
+        .memory(VMMemory::default())
+        .alu(MinimumALU)
+        .limits(limits)
+        .build()
+        .unwrap();
+    vm.load(create_fib_sequence_asm(n));
+    vm.run("main")
+}
+
+#[test]
+fn within_limits() {
+    let limits = ResourceLimits::new()
+        .max_instructions(1_000_000)
+        .max_call_depth(64);
+    assert_eq!(run_fibonacci(8, limits).unwrap(), 21);
+}
+
+#[test]
+fn instruction_limit() {
+    let limits = ResourceLimits::new().max_instructions(100);
+    match run_fibonacci(8, limits) {
+        Err(VMError::InstructionLimitExceeded(100)) => {}
+        other => panic!("expected the instruction limit to be exceeded, got {:?}", other),
+    }
+}
+
+#[test]
+fn call_depth_limit() {
+    let limits = ResourceLimits::new().max_call_depth(4);
+    match run_fibonacci(8, limits) {
+        Err(VMError::CallDepthExceeded(4)) => {}
+        other => panic!("expected the call depth to be exceeded, got {:?}", other),
+    }
+}
@@ -0,0 +1,57 @@
+//! Loading code and images into the virtual machine
+
+use jodin_common::asm_version::{Version, SUPPORTED_VERSIONS};
+use jodin_common::assembly::instructions::Asm;
+use jodin_common::image::ExecutableImage;
+use jodin_rs_vm::core_traits::VirtualMachine;
+use jodin_rs_vm::error::VMError;
+use jodin_rs_vm::mvp::MinimumALU;
+use jodin_rs_vm::scoped_memory::VMMemory;
+use jodin_rs_vm::vm::{VMBuilder, VM};
+
+fn vm() -> VM<'static, VMMemory, MinimumALU> {
+    VMBuilder::new()
+        .memory(VMMemory::default())
+        .alu(MinimumALU)
+        .build()
+        .unwrap()
+}
+
+#[test]
+fn labels_can_not_be_registered_twice() {
+    let mut vm = vm();
+    vm.load(vec![Asm::pub_label("main"), Asm::push(1u64), Asm::Return]);
+
+    let again = vec![Asm::pub_label("main"), Asm::push(2u64), Asm::Return];
+    assert!(matches!(
+        vm.try_load(again.clone()),
+        Err(VMError::LabelAlreadyRegistered(label)) if label == "main"
+    ));
+    assert!(matches!(
+        vm.load_image(ExecutableImage::new("main", again)),
+        Err(VMError::LabelAlreadyRegistered(label)) if label == "main"
+    ));
+    assert_eq!(vm.run("main").unwrap(), 1);
+}
+
+#[test]
+fn failing_static_code_is_an_error() {
+    let failing = vec![Asm::Static, Asm::push(3u64), Asm::Return];
+
+    assert!(matches!(
+        vm().try_load(failing.clone()),
+        Err(VMError::StaticCodeFailed(3))
+    ));
+    assert!(matches!(
+        vm().load_image(ExecutableImage::new("main", failing)),
+        Err(VMError::StaticCodeFailed(3))
+    ));
+}
+
+#[test]
+fn supported_versions() {
+    let supported = vm().supported_versions();
+    assert_eq!(supported, SUPPORTED_VERSIONS);
+    assert!(supported.contains(&Version::new(1, 0)));
+    assert_eq!(*supported.end(), Version::CURRENT);
+}
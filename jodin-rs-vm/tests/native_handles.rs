@@ -0,0 +1,177 @@
+use jodin_common::assembly::instructions::Asm;
+use jodin_common::assembly::location::AsmLocation;
+use jodin_common::assembly::value::Value;
+use jodin_common::core::function_names::CALL;
+use jodin_rs_vm::core_traits::VirtualMachine;
+use jodin_rs_vm::mvp::MinimumALU;
+use jodin_rs_vm::scoped_memory::VMMemory;
+use jodin_rs_vm::vm::VMBuilder;
+use jodin_vm_plugins::plugins::{LabelConflict, VMHandle};
+use jodin_vm_plugins::Plugin;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+/// Hands out counters as native handles. The counters themselves never leave the plugin.
+#[derive(Default, Clone)]
+struct CounterPlugin {
+    counters: Arc<Mutex<HashMap<u64, u64>>>,
+    finalized: Arc<Mutex<Vec<u64>>>,
+    /// Whether amounts are added ten times, like a newer version of the plugin would
+    scaled: bool,
+}
+
+impl Plugin for CounterPlugin {
+    fn labels<'s>(&'s self, buffer: &mut [&'s str]) {
+        buffer[0] = "open_counter";
+    }
+
+    fn labels_count(&self) -> i32 {
+        1
+    }
+
+    fn call_label(
+        &self,
+        _label: &str,
+        handle: &mut dyn VMHandle,
+        output: &mut Option<Result<Value, String>>,
+    ) {
+        let mut counters = self.counters.lock().unwrap();
+        let id = counters.len() as u64;
+        counters.insert(id, 0);
+        let mut value = None;
+        handle.new_native_handle("counter", id, &mut value);
+        *output = Some(value.ok_or_else(|| "handle could not be created".to_string()));
+    }
+
+    fn handle_message(
+        &self,
+        type_name: &str,
+        id: u64,
+        message: &str,
+        args: &[Value],
+        _handle: &mut dyn VMHandle,
+        output: &mut Option<Result<Value, String>>,
+    ) {
+        let mut counters = self.counters.lock().unwrap();
+        *output = Some(match (type_name, message, counters.get_mut(&id)) {
+            ("counter", "add", Some(counter)) => match args {
+                [Value::UInteger(amount)] => {
+                    *counter += if self.scaled { amount * 10 } else { *amount };
+                    Ok(Value::from(*counter))
+                }
+                _ => Err(format!("expected an amount, found {:?}", args)),
+            },
+            _ => Err(format!("{} #{} can't handle {}", type_name, id, message)),
+        });
+    }
+
+    fn finalize_handle(&self, type_name: &str, id: u64) {
+        assert_eq!(type_name, "counter");
+        self.counters.lock().unwrap().remove(&id);
+        self.finalized.lock().unwrap().push(id);
+    }
+}
+
+/// Opens a counter, then adds 5 and 2 to it
+fn counter_program() -> Vec<Asm> {
+    vec![
+        Asm::label("main"),
+        Asm::push(Value::from(Vec::<Value>::new())),
+        Asm::push(Value::from(CALL)),
+        Asm::push(Value::Function(AsmLocation::Label(
+            "open_counter".to_string(),
+        ))),
+        Asm::SendMessage,
+        Asm::SetVar(0),
+        Asm::push(Value::from(vec![Value::from(5u64)])),
+        Asm::push(Value::from("add")),
+        Asm::GetVar(0),
+        Asm::SendMessage,
+        Asm::Pop,
+        Asm::push(Value::from(vec![Value::from(2u64)])),
+        Asm::push(Value::from("add")),
+        Asm::GetVar(0),
+        Asm::SendMessage,
+        Asm::Return,
+    ]
+}
+
+#[test]
+fn messages_are_routed_to_the_owning_plugin() {
+    let plugin = CounterPlugin::default();
+    let counters = plugin.counters.clone();
+    let finalized = plugin.finalized.clone();
+    let mut vm = VMBuilder::new()
+        .memory(VMMemory::default())
+        .alu(MinimumALU)
+        .build()
+        .unwrap();
+    vm.with_plugin(plugin).unwrap();
+
+    vm.load(counter_program());
+    assert_eq!(vm.run("main").expect("VM should not fail"), 7);
+    assert!(
+        finalized.lock().unwrap().is_empty(),
+        "the handle is still in a variable"
+    );
+    drop(vm);
+    assert_eq!(*finalized.lock().unwrap(), vec![0]);
+    assert!(counters.lock().unwrap().is_empty());
+}
+
+#[test]
+fn replaced_plugin_keeps_its_handles() {
+    let plugin = CounterPlugin::default();
+    let mut vm = VMBuilder::new()
+        .memory(VMMemory::default())
+        .alu(MinimumALU)
+        .build()
+        .unwrap();
+    let id = vm.with_plugin(plugin.clone()).unwrap();
+    vm.load(counter_program());
+
+    // stop after the counter was opened and 5 was added to it
+    vm.pause_after(10);
+    assert!(vm.run("main").is_err());
+    assert!(vm.is_paused());
+    let newer = CounterPlugin {
+        scaled: true,
+        ..plugin.clone()
+    };
+    vm.plugin_manager()
+        .write()
+        .unwrap()
+        .replace_plugin(id, newer)
+        .unwrap();
+    assert_eq!(vm.resume().expect("VM should not fail"), 25);
+    drop(vm);
+    assert_eq!(*plugin.finalized.lock().unwrap(), vec![0]);
+}
+
+#[test]
+fn handles_dropped_while_plugins_are_loaded_are_finalized() {
+    let plugin = CounterPlugin::default();
+    let finalized = plugin.finalized.clone();
+    let mut vm = VMBuilder::new()
+        .memory(VMMemory::default())
+        .alu(MinimumALU)
+        .build()
+        .unwrap();
+    vm.with_plugin(plugin.clone()).unwrap();
+    vm.load(counter_program());
+    assert_eq!(vm.run("main").expect("VM should not fail"), 7);
+
+    let plugin_manager = vm.plugin_manager();
+    let mut locked = plugin_manager.write().unwrap();
+    drop(vm);
+    assert!(
+        finalized.lock().unwrap().is_empty(),
+        "the plugin manager is locked"
+    );
+    // the handle is finalized once the plugin manager is used again
+    locked.set_label_conflict(LabelConflict::Replace);
+    locked.with_plugin(CounterPlugin::default()).unwrap();
+    assert_eq!(*finalized.lock().unwrap(), vec![0]);
+    drop(locked);
+    assert!(plugin.counters.lock().unwrap().is_empty());
+}
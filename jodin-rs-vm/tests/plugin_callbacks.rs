@@ -0,0 +1,288 @@
+use jodin_common::assembly::instructions::Asm;
+use jodin_common::assembly::location::AsmLocation;
+use jodin_common::assembly::value::Value;
+use jodin_common::core::function_names::CALL;
+use jodin_rs_vm::core_traits::VirtualMachine;
+use jodin_rs_vm::mvp::MinimumALU;
+use jodin_rs_vm::scoped_memory::VMMemory;
+use jodin_rs_vm::vm::VMBuilder;
+use jodin_vm_plugins::plugins::{PluginManager, VMHandle};
+use jodin_vm_plugins::Plugin;
+use std::sync::{Arc, Mutex, OnceLock, RwLock};
+
+/// Sorts arrays with a comparator written in guest code, and keeps values in globals
+#[derive(Default)]
+struct CallbackPlugin {
+    sorted: Arc<Mutex<Vec<u64>>>,
+}
+
+impl CallbackPlugin {
+    fn sort_by(handle: &mut dyn VMHandle) -> Result<Vec<Value>, String> {
+        let mut array = None;
+        handle.stack().pop(&mut array);
+        let mut comparator = None;
+        handle.stack().pop(&mut comparator);
+        let (mut array, comparator) = match (array, comparator) {
+            (Some(Value::Array(array)), Some(comparator)) => (array, comparator),
+            other => {
+                return Err(format!(
+                    "expected an array and a comparator, found {:?}",
+                    other
+                ))
+            }
+        };
+        for end in (1..array.len()).rev() {
+            for index in 0..end {
+                let mut greater = None;
+                handle.call(
+                    &comparator,
+                    &[array[index].clone(), array[index + 1].clone()],
+                    &mut greater,
+                );
+                if greater.ok_or("comparator returned nothing")?? == Value::from(true) {
+                    array.swap(index, index + 1);
+                }
+            }
+        }
+        Ok(array)
+    }
+}
+
+impl Plugin for CallbackPlugin {
+    fn labels<'s>(&'s self, buffer: &mut [&'s str]) {
+        buffer[0] = "sort_by";
+        buffer[1] = "remember";
+        buffer[2] = "recall";
+        buffer[3] = "fault";
+        buffer[4] = "invoke_missing";
+    }
+
+    fn labels_count(&self) -> i32 {
+        5
+    }
+
+    fn call_label(
+        &self,
+        label: &str,
+        handle: &mut dyn VMHandle,
+        output: &mut Option<Result<Value, String>>,
+    ) {
+        *output = Some(match label {
+            "sort_by" => Self::sort_by(handle).map(|sorted| {
+                *self.sorted.lock().unwrap() = sorted
+                    .iter()
+                    .filter_map(|value| match value {
+                        &Value::UInteger(u) => Some(u),
+                        _ => None,
+                    })
+                    .collect();
+                Value::Array(sorted)
+            }),
+            "remember" => {
+                let mut value = None;
+                handle.stack().pop(&mut value);
+                let mut allocated = None;
+                handle.allocate(value.unwrap_or(Value::Empty), &mut allocated);
+                match allocated {
+                    Some(Value::Reference(reference)) => {
+                        handle.set_global(3, reference.borrow().clone());
+                        Ok(Value::Empty)
+                    }
+                    other => Err(format!("expected a reference, found {:?}", other)),
+                }
+            }
+            "invoke_missing" => {
+                let mut output = None;
+                handle.native(
+                    "invoke",
+                    &[
+                        Value::Array(vec![]),
+                        Value::from(CALL),
+                        Value::Function(AsmLocation::Label("missing".to_string())),
+                    ],
+                    &mut output,
+                );
+                // the failed method is reported to the plugin instead of panicking
+                Ok(Value::from(output.is_none() as u64))
+            }
+            "recall" => {
+                let mut value = None;
+                handle.get_global(3, &mut value);
+                value.ok_or_else(|| "nothing was remembered".to_string())
+            }
+            _ => {
+                if handle.raise_fault("NotAFault", "") {
+                    Err("unknown faults should be rejected".to_string())
+                } else if handle.raise_fault("MissingSymbol", "missing") {
+                    Ok(Value::Empty)
+                } else {
+                    Err("fault could not be raised".to_string())
+                }
+            }
+        });
+    }
+}
+
+/// Loads the callback plugin into the plugin manager while it is being called
+#[derive(Default)]
+struct InstallerPlugin {
+    manager: Arc<OnceLock<Arc<RwLock<PluginManager>>>>,
+}
+
+impl Plugin for InstallerPlugin {
+    fn labels<'s>(&'s self, buffer: &mut [&'s str]) {
+        buffer[0] = "install";
+    }
+
+    fn labels_count(&self) -> i32 {
+        1
+    }
+
+    fn call_label(
+        &self,
+        _label: &str,
+        _handle: &mut dyn VMHandle,
+        output: &mut Option<Result<Value, String>>,
+    ) {
+        let manager = self.manager.get().expect("manager should be set");
+        *output = Some(
+            manager
+                .write()
+                .unwrap()
+                .with_plugin(CallbackPlugin::default())
+                .map(|_| Value::Empty)
+                .map_err(|error| error.to_string()),
+        );
+    }
+}
+
+fn call_plugin(label: &str, args: Vec<Value>) -> Vec<Asm> {
+    vec![
+        Asm::push(Value::from(args)),
+        Asm::push(Value::from(CALL)),
+        Asm::push(Value::Function(AsmLocation::Label(label.to_string()))),
+        Asm::SendMessage,
+    ]
+}
+
+#[test]
+fn plugin_calls_guest_comparator() {
+    let plugin = CallbackPlugin::default();
+    let sorted = plugin.sorted.clone();
+    let mut vm = VMBuilder::new()
+        .memory(VMMemory::default())
+        .alu(MinimumALU)
+        .build()
+        .unwrap();
+    vm.with_plugin(plugin).unwrap();
+
+    let numbers = [5u64, 3, 9, 1, 7]
+        .into_iter()
+        .map(Value::from)
+        .collect::<Vec<_>>();
+    let mut program = vec![Asm::label("main")];
+    program.extend(call_plugin(
+        "sort_by",
+        vec![
+            Value::Array(numbers),
+            Value::Function(AsmLocation::Label("greater".to_string())),
+        ],
+    ));
+    program.extend([
+        Asm::Pop,
+        Asm::push(0u64),
+        Asm::Return,
+        Asm::label("greater"),
+        Asm::SetVar(0),
+        Asm::SetVar(1),
+        Asm::GetVar(1),
+        Asm::Deref,
+        Asm::GetVar(0),
+        Asm::Deref,
+        Asm::Gt,
+        Asm::Return,
+    ]);
+    vm.load(program);
+    assert_eq!(vm.run("main").expect("VM should not fail"), 0);
+    assert_eq!(*sorted.lock().unwrap(), vec![1, 3, 5, 7, 9]);
+}
+
+#[test]
+fn plugin_uses_globals_and_faults() {
+    let mut vm = VMBuilder::new()
+        .memory(VMMemory::default())
+        .alu(MinimumALU)
+        .build()
+        .unwrap();
+    vm.with_plugin(CallbackPlugin::default()).unwrap();
+
+    let mut program = vec![Asm::label("main")];
+    program.extend(call_plugin("remember", vec![Value::from(42u64)]));
+    program.push(Asm::Pop);
+    program.extend(call_plugin("fault", vec![]));
+    program.push(Asm::Pop);
+    program.extend(call_plugin("recall", vec![]));
+    program.push(Asm::Return);
+    vm.load(program);
+    assert_eq!(vm.run("main").expect("VM should not fail"), 42);
+}
+
+#[test]
+fn failed_natives_are_reported_to_plugins() {
+    let mut vm = VMBuilder::new()
+        .memory(VMMemory::default())
+        .alu(MinimumALU)
+        .build()
+        .unwrap();
+    vm.with_plugin(CallbackPlugin::default()).unwrap();
+
+    let mut program = vec![Asm::label("main")];
+    program.extend(call_plugin("invoke_missing", vec![]));
+    program.push(Asm::Return);
+    vm.load(program);
+    assert_eq!(vm.run("main").expect("VM should not fail"), 1);
+}
+
+#[test]
+fn plain_calls_reach_plugins() {
+    let mut vm = VMBuilder::new()
+        .memory(VMMemory::default())
+        .alu(MinimumALU)
+        .build()
+        .unwrap();
+    vm.with_plugin(CallbackPlugin::default()).unwrap();
+
+    // plugin labels aren't loaded as code, but can still be called
+    vm.try_load(vec![
+        Asm::label("main"),
+        Asm::push(42u64),
+        Asm::Call(AsmLocation::Label("remember".to_string())),
+        Asm::Call(AsmLocation::Label("recall".to_string())),
+        Asm::Return,
+    ])
+    .expect("calls to plugin labels should verify");
+    assert_eq!(vm.run("main").expect("VM should not fail"), 42);
+}
+
+#[test]
+fn plugins_can_be_loaded_while_a_plugin_runs() {
+    let installer = InstallerPlugin::default();
+    let manager = installer.manager.clone();
+    let mut vm = VMBuilder::new()
+        .memory(VMMemory::default())
+        .alu(MinimumALU)
+        .build()
+        .unwrap();
+    assert!(manager.set(vm.plugin_manager()).is_ok());
+    vm.with_plugin(installer).unwrap();
+
+    let mut program = vec![Asm::label("main")];
+    program.extend(call_plugin("install", vec![]));
+    program.push(Asm::Pop);
+    program.extend(call_plugin("remember", vec![Value::from(7u64)]));
+    program.push(Asm::Pop);
+    program.extend(call_plugin("recall", vec![]));
+    program.push(Asm::Return);
+    vm.load(program);
+    assert_eq!(vm.run("main").expect("VM should not fail"), 7);
+}
@@ -0,0 +1,167 @@
+#[macro_use]
+extern crate jasm_macros;
+
+use jodin_common::assembly::instructions::{Asm, Assembly};
+use jodin_common::assembly::location::AsmLocation;
+use jodin_common::assembly::value::Value;
+use jodin_common::core::function_names::CALL;
+use jodin_rs_vm::core_traits::VirtualMachine;
+use jodin_rs_vm::mvp::{MinimumALU, MinimumMemory};
+use jodin_rs_vm::scoped_memory::VMMemory;
+use jodin_rs_vm::vm::VMBuilder;
+use jodin_vm_plugins::plugins::VMHandle;
+use jodin_vm_plugins::Plugin;
+
+fn create_fib_sequence_asm(n: u32) -> Assembly {
+    let block = block![
+        main:
+        label!(pub main);
+        return_!(call!(~ fibonacci, n));
+
+        label!(pub fibonacci);
+        scope!(global);
+        scope!(push);
+        var!(=> 0);
+        if_!(
+            (expr!(<, dvar!(0), 2u32)) {
+                block![
+                    dvar!(0);
+                    scope!(back);
+                    return_!();
+                ]
+            } else {
+                block![
+                        expr!(+,
+                                call!(~ fibonacci, expr!(-, dvar!(0), 1u32)),
+                                call!(~ fibonacci, expr!(-, dvar!(0), 2u32))
+                            );
+                        scope!(back);
+                        return_! ();
+                    ]
+            }
+        );
+    ];
+    block.normalize()
+}
+
+#[test]
+fn profile_recursive_function() {
+    let mut vm = VMBuilder::new()
+        .memory(VMMemory::default())
+        .alu(MinimumALU)
+        .build()
+        .unwrap();
+    vm.load(create_fib_sequence_asm(8));
+    let (result, profile) = vm.run_with_profile("main");
+    assert_eq!(result.expect("VM should not fail"), 21);
+
+    let fibonacci = profile
+        .function("fibonacci")
+        .expect("fibonacci should have been profiled");
+    assert_eq!(fibonacci.calls, 67, "fibonacci(8) makes 67 calls");
+    let counted: u64 = profile.functions().values().map(|f| f.instructions).sum();
+    assert_eq!(counted, profile.total_instructions());
+    assert!(fibonacci.instructions > profile.function("main").unwrap().instructions);
+
+    let mut folded = vec![];
+    profile.write_folded(&mut folded).unwrap();
+    let folded = String::from_utf8(folded).unwrap();
+    assert!(folded
+        .lines()
+        .any(|line| line.starts_with("main;fibonacci;fibonacci ")));
+    assert!(folded
+        .lines()
+        .all(|line| line.rsplit_once(' ').unwrap().1.parse::<u128>().is_ok()));
+}
+
+#[test]
+fn profile_attributes_natives() {
+    let mut vm = VMBuilder::new()
+        .memory(MinimumMemory::default())
+        .alu(MinimumALU)
+        .with_stdout(Vec::new())
+        .build()
+        .unwrap();
+    vm.load(vec![
+        Asm::PublicLabel("main".to_string()),
+        Asm::push("Hello, world!"),
+        Asm::NativeMethod("print".to_string(), 1),
+        Asm::push(0u64),
+        Asm::Return,
+    ]);
+    let (result, profile) = vm.run_with_profile("main");
+    assert_eq!(result.expect("VM should not fail"), 0);
+    let print = profile
+        .function("[native] print")
+        .expect("print should have been profiled");
+    assert_eq!(print.calls, 1);
+
+    let mut report = vec![];
+    profile.write_report(&mut report).unwrap();
+    let report = String::from_utf8(report).unwrap();
+    assert!(report.contains("[native] print"));
+    assert!(report.contains("main"));
+}
+
+/// Calls a guest function with no arguments
+struct ApplyPlugin;
+
+impl Plugin for ApplyPlugin {
+    fn labels<'s>(&'s self, buffer: &mut [&'s str]) {
+        buffer[0] = "apply";
+    }
+
+    fn labels_count(&self) -> i32 {
+        1
+    }
+
+    fn call_label(
+        &self,
+        _label: &str,
+        handle: &mut dyn VMHandle,
+        output: &mut Option<Result<Value, String>>,
+    ) {
+        let mut function = None;
+        handle.stack().pop(&mut function);
+        let function = function.expect("a function should be given");
+        handle.call(&function, &[], output);
+    }
+}
+
+#[test]
+fn profile_guest_code_called_by_plugins() {
+    let mut vm = VMBuilder::new()
+        .memory(MinimumMemory::default())
+        .alu(MinimumALU)
+        .build()
+        .unwrap();
+    vm.with_plugin(ApplyPlugin).unwrap();
+    vm.load(vec![
+        Asm::PublicLabel("main".to_string()),
+        Asm::push(Value::from(vec![Value::Function(AsmLocation::Label(
+            "callee".to_string(),
+        ))])),
+        Asm::push(Value::from(CALL)),
+        Asm::push(Value::Function(AsmLocation::Label("apply".to_string()))),
+        Asm::SendMessage,
+        Asm::Return,
+        Asm::PublicLabel("callee".to_string()),
+        Asm::push(3u64),
+        Asm::Return,
+    ]);
+    let (result, profile) = vm.run_with_profile("main");
+    assert_eq!(result.expect("VM should not fail"), 3);
+    assert_eq!(profile.function("[plugin] apply").unwrap().calls, 1);
+    assert_eq!(profile.function("callee").unwrap().calls, 1);
+    assert_eq!(profile.function("callee").unwrap().instructions, 3);
+
+    let mut folded = vec![];
+    profile.write_folded(&mut folded).unwrap();
+    let folded = String::from_utf8(folded).unwrap();
+    assert!(folded
+        .lines()
+        .any(|line| line.starts_with("main;[plugin] apply;callee ")));
+    assert!(folded
+        .lines()
+        .any(|line| line.starts_with("main;[plugin] apply ")));
+}
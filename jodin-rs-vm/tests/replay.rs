@@ -0,0 +1,251 @@
+use jodin_common::assembly::instructions::Asm;
+use jodin_common::assembly::location::AsmLocation;
+use jodin_common::assembly::value::Value;
+use jodin_common::core::function_names::CALL;
+use jodin_common::init_logging;
+use jodin_rs_vm::core_traits::VirtualMachine;
+use jodin_rs_vm::error::VMError;
+use jodin_rs_vm::mvp::{MinimumALU, MinimumMemory};
+use jodin_rs_vm::replay::{Trace, TraceEvent};
+use jodin_rs_vm::vm::VMBuilder;
+use jodin_vm_plugins::plugins::VMHandle;
+use jodin_vm_plugins::Plugin;
+use log::LevelFilter;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// Hands out increasing tickets, so every run of a program gets a different result
+struct TicketPlugin {
+    issued: AtomicU64,
+    available: bool,
+}
+
+impl TicketPlugin {
+    fn new(available: bool) -> Self {
+        Self {
+            issued: AtomicU64::new(0),
+            available,
+        }
+    }
+}
+
+impl Plugin for TicketPlugin {
+    fn labels<'s>(&'s self, buffer: &mut [&'s str]) {
+        buffer[0] = "ticket";
+    }
+
+    fn labels_count(&self) -> i32 {
+        1
+    }
+
+    fn call_label(
+        &self,
+        _label: &str,
+        handle: &mut dyn VMHandle,
+        output: &mut Option<Result<Value, String>>,
+    ) {
+        assert!(
+            self.available,
+            "plugin should not be called during a replay"
+        );
+        let mut base = None;
+        handle.stack().pop(&mut base);
+        *output = match base {
+            Some(Value::UInteger(base)) => Some(Ok(Value::UInteger(
+                base + self.issued.fetch_add(7, Ordering::SeqCst),
+            ))),
+            other => Some(Err(format!("expected a base, found {:?}", other))),
+        };
+    }
+}
+
+fn ticket_program() -> Vec<Asm> {
+    vec![
+        Asm::label("main"),
+        Asm::NativeMethod("read_line".to_string(), 0),
+        Asm::NativeMethod("print".to_string(), 1),
+        Asm::push(Value::from(vec![Value::from(10u64)])),
+        Asm::push(Value::from(CALL)),
+        Asm::push(Value::Function(AsmLocation::Label("ticket".to_string()))),
+        Asm::SendMessage,
+        Asm::push(Value::from(vec![Value::from(10u64)])),
+        Asm::push(Value::from(CALL)),
+        Asm::push(Value::Function(AsmLocation::Label("ticket".to_string()))),
+        Asm::SendMessage,
+        Asm::Add,
+        Asm::Return,
+    ]
+}
+
+fn run_ticket_program(
+    input: &[u8],
+    plugin: TicketPlugin,
+    replay: Option<Trace>,
+) -> (u32, String, Trace) {
+    let mut output: Vec<u8> = Vec::new();
+    let result;
+    let trace;
+    {
+        let mut vm = VMBuilder::new()
+            .memory(MinimumMemory::default())
+            .alu(MinimumALU)
+            .with_stdin(input)
+            .with_stdout(&mut output)
+            .build()
+            .unwrap();
+        vm.with_plugin(plugin).unwrap();
+        vm.load(ticket_program());
+        match replay {
+            None => vm.start_recording(),
+            Some(trace) => vm.start_replay(trace),
+        }
+        result = vm.run("main").expect("VM should not fail");
+        trace = vm.stop_trace().expect("trace should still be active");
+    }
+    (
+        result,
+        String::from_utf8(output).expect("Output should be in utf-8"),
+        trace,
+    )
+}
+
+#[test]
+fn replay_reproduces_plugin_and_input() {
+    init_logging(LevelFilter::Info);
+    let (recorded_result, recorded_output, trace) =
+        run_ticket_program(b"jodin\n", TicketPlugin::new(true), None);
+    assert_eq!(recorded_result, 27);
+    assert_eq!(recorded_output, "jodin");
+    assert!(trace.events().iter().any(
+        |event| matches!(event, TraceEvent::Input { line: Some(line), .. } if line == "jodin")
+    ));
+
+    let mut saved = vec![];
+    trace.write_to(&mut saved).unwrap();
+    let loaded = Trace::read_from(&saved[..]).unwrap();
+    assert_eq!(loaded, trace);
+
+    let (replayed_result, replayed_output, _) =
+        run_ticket_program(b"", TicketPlugin::new(false), Some(loaded));
+    assert_eq!(replayed_result, recorded_result);
+    assert_eq!(replayed_output, recorded_output);
+}
+
+#[test]
+fn replay_detects_divergence() {
+    let (_, _, trace) = run_ticket_program(b"jodin\n", TicketPlugin::new(true), None);
+
+    let mut vm = VMBuilder::new()
+        .memory(MinimumMemory::default())
+        .alu(MinimumALU)
+        .with_stdout(Vec::new())
+        .build()
+        .unwrap();
+    vm.with_plugin(TicketPlugin::new(false)).unwrap();
+    vm.load(vec![
+        Asm::label("main"),
+        Asm::push("diverged"),
+        Asm::NativeMethod("print".to_string(), 1),
+        Asm::push(0u64),
+        Asm::Return,
+    ]);
+    vm.start_replay(trace);
+    assert!(matches!(
+        vm.run("main"),
+        Err(VMError::ReplayDivergence { .. })
+    ));
+}
+
+#[test]
+fn replay_reproduces_plugin_failures() {
+    let program = vec![
+        Asm::label("main"),
+        Asm::push(Value::from(vec![Value::from("no base")])),
+        Asm::push(Value::from(CALL)),
+        Asm::push(Value::Function(AsmLocation::Label("ticket".to_string()))),
+        Asm::SendMessage,
+        Asm::Return,
+    ];
+    let run = |plugin: TicketPlugin, replay: Option<Trace>| {
+        let mut vm = VMBuilder::new()
+            .memory(MinimumMemory::default())
+            .alu(MinimumALU)
+            .build()
+            .unwrap();
+        vm.with_plugin(plugin).unwrap();
+        vm.load(program.clone());
+        match replay {
+            None => vm.start_recording(),
+            Some(trace) => vm.start_replay(trace),
+        }
+        let error = vm.run("main").expect_err("plugin should fail");
+        (
+            error,
+            vm.stop_trace().expect("trace should still be active"),
+        )
+    };
+
+    let (recorded_error, trace) = run(TicketPlugin::new(true), None);
+    assert!(matches!(
+        trace.events(),
+        [TraceEvent::PluginFailed { label, .. }] if label == "ticket"
+    ));
+    let (replayed_error, _) = run(TicketPlugin::new(false), Some(trace));
+    assert!(!matches!(replayed_error, VMError::ReplayDivergence { .. }));
+    assert_eq!(replayed_error.to_string(), recorded_error.to_string());
+}
+
+/// Hands out native handles, which only mean something to the plugin
+#[derive(Default)]
+struct HandlePlugin;
+
+impl Plugin for HandlePlugin {
+    fn labels<'s>(&'s self, buffer: &mut [&'s str]) {
+        buffer[0] = "open";
+    }
+
+    fn labels_count(&self) -> i32 {
+        1
+    }
+
+    fn call_label(
+        &self,
+        _label: &str,
+        handle: &mut dyn VMHandle,
+        output: &mut Option<Result<Value, String>>,
+    ) {
+        let mut opened = None;
+        handle.new_native_handle("file", 1, &mut opened);
+        *output = Some(Ok(Value::Array(opened.into_iter().collect())));
+    }
+}
+
+#[test]
+fn native_handles_are_not_recorded() {
+    let mut vm = VMBuilder::new()
+        .memory(MinimumMemory::default())
+        .alu(MinimumALU)
+        .build()
+        .unwrap();
+    vm.with_plugin(HandlePlugin).unwrap();
+    vm.load(vec![
+        Asm::label("main"),
+        Asm::push(Value::from(Vec::<Value>::new())),
+        Asm::push(Value::from(CALL)),
+        Asm::push(Value::Function(AsmLocation::Label("open".to_string()))),
+        Asm::SendMessage,
+        Asm::Return,
+    ]);
+    vm.start_recording();
+
+    let error = vm.run("main").expect_err("handles can't be recorded");
+    assert!(matches!(error, VMError::Unrecordable(_)), "{:?}", error);
+    let trace = vm.stop_trace().expect("trace should still be active");
+    assert!(matches!(
+        trace.events(),
+        [TraceEvent::PluginFailed { label, .. }] if label == "open"
+    ));
+    // the trace can still be saved
+    let mut saved = vec![];
+    trace.write_to(&mut saved).unwrap();
+    assert_eq!(Trace::read_from(&*saved).unwrap(), trace);
+}
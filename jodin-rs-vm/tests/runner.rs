@@ -0,0 +1,346 @@
+//! Runs compiled programs with the `jodin` runner
+
+#[macro_use]
+extern crate jasm_macros;
+
+use jodin_common::assembly::debug_info::{DebugInfo, LineEntry};
+use jodin_common::assembly::instructions::{Asm, Assembly};
+use jodin_common::assembly::text::parse_jasm;
+use jodin_common::compilation::{Compilable, Context, PaddedWriter, Target};
+use jodin_common::identifier::Identifier;
+use jodin_common::linker::Linker;
+use jodin_common::unit::CompilationObject;
+use jodin_rs_vm::standalone::write_standalone;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output};
+
+struct Runner;
+impl Target for Runner {}
+
+fn object_directory(name: &str) -> PathBuf {
+    let directory =
+        std::env::temp_dir().join(format!("jodin-runner-{}-{}", name, std::process::id()));
+    let _ = fs::remove_dir_all(&directory);
+    fs::create_dir_all(&directory).unwrap();
+    directory
+}
+
+fn write_object(path: &Path, jasm: Assembly) {
+    let object = CompilationObject::new(path.to_path_buf(), Identifier::empty(), vec![], jasm);
+    write_compiled(path, object);
+}
+
+fn write_compiled(path: &Path, object: CompilationObject) {
+    let mut buffer = vec![];
+    let mut writer = PaddedWriter::new(&mut buffer);
+    Compilable::<Runner>::compile(object, &Context::new(), &mut writer).unwrap();
+    drop(writer);
+    fs::write(path, buffer).unwrap();
+}
+
+/// Writes a program whose `main` returns argc, and a `helper` function that returns 7
+fn write_program(directory: &Path) -> PathBuf {
+    let path = directory.join("program.jobj");
+    write_object(
+        &path,
+        vec![
+            // argc is on top of argv
+            Asm::PublicLabel("main".to_string()),
+            Asm::Return,
+            Asm::PublicLabel("helper".to_string()),
+            Asm::push(7u64),
+            Asm::Return,
+        ],
+    );
+    path
+}
+
+fn jodin(directory: &Path, arguments: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_jodin"))
+        .current_dir(directory)
+        .args(["-d", "0"])
+        .args(arguments)
+        .output()
+        .expect("could not run jodin")
+}
+
+#[test]
+fn program_arguments() {
+    let directory = object_directory("arguments");
+    let program = write_program(&directory);
+    let program = program.to_str().unwrap();
+
+    let output = jodin(&directory, &[program]);
+    assert_eq!(output.status.code(), Some(1));
+    let output = jodin(&directory, &[program, "--", "first", "second"]);
+    assert_eq!(output.status.code(), Some(3));
+    let output = jodin(&directory, &["-m", "minimum", program, "--", "first"]);
+    assert_eq!(output.status.code(), Some(2));
+    fs::remove_dir_all(directory).unwrap();
+}
+
+#[test]
+fn log_level() {
+    let directory = object_directory("log_level");
+    let program = write_program(&directory);
+    let runner = || {
+        let mut command = Command::new(env!("CARGO_BIN_EXE_jodin"));
+        command.current_dir(&directory).arg(&program);
+        command
+    };
+
+    // nothing but warnings are logged by default, so the output is the output of the program
+    let output = runner().output().unwrap();
+    assert_eq!(output.status.code(), Some(1));
+    assert!(output.stdout.is_empty(), "{:?}", output);
+    let output = runner().args(["-d", "9"]).output().unwrap();
+    assert_eq!(output.status.code(), Some(1));
+    assert!(
+        String::from_utf8_lossy(&output.stderr).contains("no debug level 9"),
+        "{:?}",
+        output
+    );
+    fs::remove_dir_all(directory).unwrap();
+}
+
+#[test]
+fn coverage() {
+    let directory = object_directory("coverage");
+    let program = directory.join("branching.jobj");
+    let jasm = vec![
+        Asm::pub_label("main"),
+        Asm::push(1u8),
+        Asm::cond_goto("then"),
+        Asm::goto("else"),
+        Asm::label("then"),
+        Asm::push(0u64),
+        Asm::Return,
+        Asm::label("else"),
+        Asm::push(1u64),
+        Asm::Return,
+    ];
+    let lines = vec![
+        LineEntry { pc: 1, line: 2 },
+        LineEntry { pc: 5, line: 3 },
+        LineEntry { pc: 8, line: 5 },
+    ];
+    write_compiled(
+        &program,
+        CompilationObject::new(program.clone(), Identifier::empty(), vec![], jasm)
+            .with_debug_info(DebugInfo::new(Some(PathBuf::from("branching.jdn")), lines)),
+    );
+
+    let lcov = directory.join("coverage.lcov");
+    let output = jodin(
+        &directory,
+        &[
+            "--coverage",
+            lcov.to_str().unwrap(),
+            program.to_str().unwrap(),
+        ],
+    );
+    assert_eq!(output.status.code(), Some(0), "{:?}", output);
+    let lcov = fs::read_to_string(lcov).unwrap();
+    assert!(
+        lcov.contains("SF:branching.jdn\nDA:2,1\nDA:3,1\nDA:5,0\n"),
+        "{}",
+        lcov
+    );
+    fs::remove_dir_all(directory).unwrap();
+}
+
+#[test]
+fn entry_label() {
+    let directory = object_directory("entry");
+    write_program(&directory);
+
+    let output = jodin(&directory, &["--entry", "helper", "."]);
+    assert_eq!(output.status.code(), Some(7));
+    let output = jodin(&directory, &["--entry", "missing", "."]);
+    assert_eq!(output.status.code(), Some(1));
+    fs::remove_dir_all(directory).unwrap();
+}
+
+#[test]
+fn resource_limits() {
+    let directory = object_directory("limits");
+    let program = directory.join("recursive.jobj");
+    write_object(
+        &program,
+        block![
+            label!(pub main);
+            call!(~ main);
+            return_!();
+        ]
+        .normalize(),
+    );
+    let program = program.to_str().unwrap();
+
+    let output = jodin(&directory, &["--max_call_depth", "16", program]);
+    assert_eq!(output.status.code(), Some(1));
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("call depth limit of 16"), "{}", stderr);
+
+    let output = jodin(&directory, &["--max_instructions", "1000", program]);
+    assert_eq!(output.status.code(), Some(1));
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("instruction limit of 1000"), "{}", stderr);
+    fs::remove_dir_all(directory).unwrap();
+}
+
+#[test]
+fn textual_assembly() {
+    let directory = object_directory("jasm");
+    let program = directory.join("program.jasm");
+    fs::write(
+        &program,
+        r#"
+        ; returns the answer stored in a dictionary
+        pub main:
+            push { "answer": 42u64 }
+            get_attribute answer
+            return
+        "#,
+    )
+    .unwrap();
+
+    let output = jodin(&directory, &[program.to_str().unwrap()]);
+    assert_eq!(output.status.code(), Some(42), "{:?}", output);
+
+    fs::write(&program, "pub main:\n    jump main\n").unwrap();
+    let output = jodin(&directory, &[program.to_str().unwrap()]);
+    assert_ne!(output.status.code(), Some(0));
+    fs::remove_dir_all(directory).unwrap();
+}
+
+#[test]
+fn linked_image() {
+    let directory = object_directory("image");
+    let mut linker = Linker::new().with_entry_point("begin");
+    linker.add_object(CompilationObject::new(
+        PathBuf::from("static.jobj"),
+        Identifier::empty(),
+        vec![],
+        vec![
+            Asm::push(40u64),
+            Asm::SetVar(0),
+            Asm::push(0u64),
+            Asm::Return,
+        ],
+    ));
+    linker.add_object(CompilationObject::new(
+        PathBuf::from("program.jobj"),
+        Identifier::empty(),
+        vec![],
+        parse_jasm(
+            r#"
+            pub begin:
+                native_method @global_scope 0
+                get_var 0
+                deref
+                native_method @back_scope 0
+                push 2u64
+                add
+                return
+            "#,
+        )
+        .unwrap(),
+    ));
+    let image_path = directory.join("program.jexe");
+    linker.link().unwrap().write(&image_path).unwrap();
+
+    // the image runs its entry point rather than main, after its static code
+    let output = jodin(&directory, &[image_path.to_str().unwrap()]);
+    assert_eq!(output.status.code(), Some(42), "{:?}", output);
+
+    // images can't be loaded alongside objects that define the same labels
+    let program = directory.join("program.jasm");
+    fs::write(&program, "pub begin:\n    push 0u64\n    return\n").unwrap();
+    let output = jodin(
+        &directory,
+        &[image_path.to_str().unwrap(), program.to_str().unwrap()],
+    );
+    assert_ne!(output.status.code(), Some(42), "{:?}", output);
+    fs::remove_dir_all(directory).unwrap();
+}
+
+#[test]
+fn standalone_executable() {
+    let directory = object_directory("standalone");
+    let program = write_program(&directory);
+    let mut linker = Linker::new();
+    linker.add_object(CompilationObject::try_from(program).unwrap());
+    let executable = directory.join("program");
+    write_standalone(
+        env!("CARGO_BIN_EXE_jodin"),
+        &linker.link().unwrap(),
+        &executable,
+    )
+    .unwrap();
+
+    // the executable is given its own arguments, rather than those of the runner
+    let output = Command::new(&executable)
+        .current_dir(&directory)
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(1), "{:?}", output);
+    let output = Command::new(&executable)
+        .current_dir(&directory)
+        .args(["-d", "first"])
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(3), "{:?}", output);
+    // standalone executables don't leave a log file behind
+    assert!(!directory.join("compiler.log").exists());
+
+    // a corrupt image is reported instead of falling back to the runner's command line
+    let mut corrupt = fs::read(&executable).unwrap();
+    let runtime_length = fs::metadata(env!("CARGO_BIN_EXE_jodin")).unwrap().len() as usize;
+    corrupt[runtime_length..runtime_length + 8].fill(0);
+    fs::write(&executable, corrupt).unwrap();
+    let output = Command::new(&executable)
+        .current_dir(&directory)
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(1), "{:?}", output);
+    assert!(String::from_utf8(output.stderr)
+        .unwrap()
+        .contains("The embedded image is corrupt"));
+    fs::remove_dir_all(directory).unwrap();
+}
+
+#[test]
+fn broken_modules_are_rejected() {
+    let directory = object_directory("verify");
+    let program = directory.join("program.jasm");
+    fs::write(
+        &program,
+        r#"
+        pub main:
+            push "never printed"
+            native_method print 1
+            add
+            goto missing
+        "#,
+    )
+    .unwrap();
+
+    let output = jodin(&directory, &[program.to_str().unwrap()]);
+    assert_ne!(output.status.code(), Some(0), "{:?}", output);
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(!stdout.contains("never printed"), "{}", stdout);
+    assert!(
+        stderr
+            .contains("instruction 3 (main+3): add needs 2 values, but the stack may only have 1"),
+        "{}",
+        stderr
+    );
+    assert!(
+        stderr.contains("instruction 4 (main+4): label \"missing\" is not defined"),
+        "{}",
+        stderr
+    );
+    fs::remove_dir_all(directory).unwrap();
+}
@@ -0,0 +1,96 @@
+#[macro_use]
+extern crate jasm_macros;
+
+use jodin_common::assembly::instructions::Assembly;
+use jodin_common::init_logging;
+use jodin_rs_vm::core_traits::VirtualMachine;
+use jodin_rs_vm::error::VMError;
+use jodin_rs_vm::mvp::MinimumALU;
+use jodin_rs_vm::scoped_memory::VMMemory;
+use jodin_rs_vm::vm::VMBuilder;
+use log::LevelFilter;
+
+fn create_fib_sequence_asm(n: u32) -> Assembly {
+    let block = block![
+        main:
+        label!(pub main);
+        return_!(call!(~ fibonacci, n));
+
+        label!(pub fibonacci);
+        scope!(global);
+        scope!(push);
+        var!(=> 0);
+        if_!(
+            (expr!(<, dvar!(0), 2u32)) {
+                block![
+                    dvar!(0);
+                    scope!(back);
+                    return_!();
+                ]
+            } else {
+                block![
+                        expr!(+,
+                                call!(~ fibonacci, expr!(-, dvar!(0), 1u32)),
+                                call!(~ fibonacci, expr!(-, dvar!(0), 2u32))
+                            );
+                        scope!(back);
+                        return_! ();
+                    ]
+            }
+        );
+    ];
+    block.normalize()
+}
+
+#[test]
+fn restore_paused_vm() {
+    init_logging(LevelFilter::Info);
+    let mut vm = VMBuilder::new()
+        .memory(VMMemory::default())
+        .alu(MinimumALU)
+        .build()
+        .unwrap();
+    vm.load(create_fib_sequence_asm(8));
+    vm.pause_after(500);
+    match vm.run("main") {
+        Err(VMError::Paused) => {}
+        other => panic!("VM should have paused (result = {:?})", other),
+    }
+    assert!(vm.is_paused());
+    let snapshot = vm
+        .snapshot()
+        .expect("Should be able to snapshot a paused VM");
+    drop(vm);
+
+    let mut restored = VMBuilder::new()
+        .memory(VMMemory::default())
+        .alu(MinimumALU)
+        .build()
+        .unwrap();
+    restored
+        .restore(&snapshot)
+        .expect("Should be able to restore a snapshot");
+    assert!(restored.is_paused());
+    assert_eq!(
+        restored.snapshot().unwrap(),
+        snapshot,
+        "A restored VM should have the same state"
+    );
+    let result = restored.resume().expect("Restored VM should finish");
+    assert_eq!(result, 21, "Incorrectly calculated fibonacci(8)");
+}
+
+#[test]
+fn reject_corrupt_snapshot() {
+    let mut vm = VMBuilder::new()
+        .memory(VMMemory::default())
+        .alu(MinimumALU)
+        .build()
+        .unwrap();
+    let mut snapshot = vm.snapshot().unwrap();
+    snapshot[0] = b'X';
+    assert!(matches!(
+        vm.restore(&snapshot),
+        Err(VMError::InvalidSnapshot(_))
+    ));
+}
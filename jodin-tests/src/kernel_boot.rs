@@ -0,0 +1,172 @@
+//! Boots programs through the kernel, like the `jodin` runner does
+
+use jodin_common::assembly::instructions::{Asm, Assembly};
+use jodin_common::assembly::location::AsmLocation;
+use jodin_common::assembly::value::Value;
+use jodin_common::compilation::{Compilable, Context, PaddedWriter};
+use jodin_common::compilation_settings::CompilationSettings;
+use jodin_common::core::function_names::CALL;
+use jodin_common::identifier::Identifier;
+use jodin_common::package::Package;
+use jodin_common::unit::CompilationObject;
+use jodin_rs_vm::core_traits::VirtualMachine;
+use jodin_rs_vm::mvp::MinimumALU;
+use jodin_rs_vm::scoped_memory::VMMemory;
+use jodin_rs_vm::vm::VMBuilder;
+use jodin_vm_kernel::KernelPlugin;
+use jodinc::compilation::incremental::IncrementalCompiler;
+use jodinc::compilation::jodin_vm_compiler::JodinVM;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+fn object_directory(name: &str) -> PathBuf {
+    let directory =
+        std::env::temp_dir().join(format!("jodin-kernel-boot-{}-{}", name, std::process::id()));
+    let _ = fs::remove_dir_all(&directory);
+    fs::create_dir_all(&directory).unwrap();
+    directory
+}
+
+fn write_object(path: &Path, jasm: Assembly) {
+    let object = CompilationObject::new(path.to_path_buf(), Identifier::empty(), vec![], jasm);
+    let mut buffer = vec![];
+    let mut writer = PaddedWriter::new(&mut buffer);
+    Compilable::<JodinVM>::compile(object, &Context::new(), &mut writer).unwrap();
+    drop(writer);
+    fs::write(path, buffer).unwrap();
+}
+
+/// Calls the kernel with an object path and program arguments
+fn start(object_path: &Path, arguments: Vec<Value>) -> Assembly {
+    let object_path = Value::Str(object_path.to_string_lossy().to_string());
+    vec![
+        Asm::label("boot"),
+        Asm::push(Value::from(vec![object_path, Value::Array(arguments)])),
+        Asm::push(Value::from(CALL)),
+        Asm::push(Value::Function(AsmLocation::Label("__start".to_string()))),
+        Asm::SendMessage,
+        Asm::Return,
+    ]
+}
+
+/// Boots the objects in a directory with some program arguments, returning the exit code and
+/// what the program printed
+fn boot(directory: &Path, arguments: &[&str]) -> (u32, String) {
+    let mut stdout = vec![];
+    let exit_code = {
+        let mut vm = VMBuilder::new()
+            .memory(VMMemory::default())
+            .alu(MinimumALU)
+            .with_stdout(&mut stdout)
+            .build()
+            .unwrap();
+        vm.load_plugin::<KernelPlugin>().unwrap();
+
+        let arguments = arguments.iter().map(|&arg| Value::from(arg)).collect();
+        vm.load(start(directory, arguments));
+        vm.run("boot").expect("VM should not fail")
+    };
+    (exit_code, String::from_utf8(stdout).unwrap())
+}
+
+#[test]
+fn static_modules_load_first() {
+    let directory = object_directory("static");
+    write_object(
+        &directory.join("program.jobj"),
+        vec![
+            Asm::PublicLabel("main".to_string()),
+            Asm::push("main"),
+            Asm::NativeMethod("print".to_string(), 1),
+            Asm::Pop,
+            // argc is on top of argv
+            Asm::Return,
+        ],
+    );
+    fs::create_dir(directory.join("lib")).unwrap();
+    write_object(
+        &directory.join("lib").join("static_boot.jobj"),
+        vec![
+            Asm::push("booted "),
+            Asm::NativeMethod("print".to_string(), 1),
+            Asm::Pop,
+            Asm::push(0u64),
+            Asm::Return,
+        ],
+    );
+
+    let (exit_code, stdout) = boot(&directory, &["program", "first", "second"]);
+    assert_eq!(exit_code, 3);
+    assert_eq!(stdout, "booted main");
+}
+
+#[test]
+fn boots_compiled_program() {
+    let directory = object_directory("compiled");
+    let source = directory.join("hello_world.jdn");
+    fs::write(
+        &source,
+        r#"
+        in std;
+
+        fn main(argc: int) -> int {
+            return argc;
+        }
+        "#,
+    )
+    .unwrap();
+    let output = directory.join("out");
+    let mut compiler = IncrementalCompiler::new(&output, CompilationSettings::default());
+    compiler
+        .compile_file(&source)
+        .expect("program should compile");
+
+    let (exit_code, _) = boot(&output, &["hello_world", "jodin"]);
+    assert_eq!(exit_code, 2);
+}
+
+#[test]
+fn boots_packaged_program() {
+    let directory = object_directory("package");
+    let objects = directory.join("objects");
+    fs::create_dir(&objects).unwrap();
+    write_object(
+        &objects.join("program.jobj"),
+        vec![
+            Asm::PublicLabel("main".to_string()),
+            Asm::Pop,
+            Asm::push(7u64),
+            Asm::Return,
+        ],
+    );
+    let packages = directory.join("packages");
+    Package::from_directory(&objects, packages.join("program.jdp"))
+        .unwrap()
+        .write()
+        .unwrap();
+
+    let (exit_code, _) = boot(&packages, &["program"]);
+    assert_eq!(exit_code, 7);
+}
+
+#[test]
+fn missing_main_is_an_error() {
+    let directory = object_directory("no-main");
+    write_object(
+        &directory.join("library.jobj"),
+        vec![
+            Asm::PublicLabel("helper".to_string()),
+            Asm::push(0u64),
+            Asm::Return,
+        ],
+    );
+
+    let mut vm = VMBuilder::new()
+        .memory(VMMemory::default())
+        .alu(MinimumALU)
+        .build()
+        .unwrap();
+    vm.load_plugin::<KernelPlugin>().unwrap();
+    vm.load(start(&directory, vec![]));
+    assert!(vm.run("boot").is_err());
+}
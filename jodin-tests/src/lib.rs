@@ -14,6 +14,9 @@ use jodinc::test_runner::ProjectBuilder;
 use lazy_static::lazy_static;
 use log::{debug, info, Level, LevelFilter};
 
+mod kernel_boot;
+mod plugin_discovery;
+
 #[test]
 fn fibonacci() {
     init_logging(LevelFilter::Off);
@@ -0,0 +1,144 @@
+//! Loads the kernel plugin from its dynamic library, like plugins are loaded at startup
+
+use jodin_common::assembly::instructions::Asm;
+use jodin_common::assembly::location::AsmLocation;
+use jodin_common::assembly::value::Value;
+use jodin_common::core::function_names::CALL;
+use jodin_rs_vm::core_traits::VirtualMachine;
+use jodin_rs_vm::error::VMError;
+use jodin_rs_vm::mvp::MinimumALU;
+use jodin_rs_vm::scoped_memory::VMMemory;
+use jodin_rs_vm::vm::{VMBuilder, VM};
+use jodin_vm_plugins::discovery::{library_file_name, PluginSearchPath};
+use jodin_vm_plugins::Error as PluginError;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// The kernel library built alongside the tests
+fn kernel_library() -> PathBuf {
+    let deps = std::env::current_exe()
+        .expect("test executable should have a path")
+        .parent()
+        .expect("test executable should be in a directory")
+        .to_path_buf();
+    let library = deps.join(library_file_name("jodin_vm_kernel"));
+    assert!(
+        library.is_file(),
+        "the kernel library should have been built at {:?}",
+        library
+    );
+    library
+}
+
+/// Creates an empty directory that only contains the kernel plugin
+fn plugin_directory(name: &str) -> PathBuf {
+    let directory = std::env::temp_dir().join(format!(
+        "jodin-plugin-discovery-{}-{}",
+        name,
+        std::process::id()
+    ));
+    let _ = fs::remove_dir_all(&directory);
+    fs::create_dir_all(&directory).unwrap();
+    fs::copy(
+        kernel_library(),
+        directory.join(library_file_name("jodin_vm_kernel")),
+    )
+    .unwrap();
+    directory
+}
+
+fn write_manifest(directory: &Path, manifest: &str) -> PathBuf {
+    let path = directory.join("plugins.json");
+    fs::write(&path, manifest).unwrap();
+    path
+}
+
+fn build(builder: VMBuilder<MinimumALU, VMMemory>) -> Result<VM<VMMemory, MinimumALU>, VMError> {
+    builder.memory(VMMemory::default()).alu(MinimumALU).build()
+}
+
+/// Boots a program whose `main` exits with 0 through the kernel
+fn run_start(mut vm: VM<VMMemory, MinimumALU>) -> u32 {
+    vm.load(vec![
+        Asm::label("boot"),
+        Asm::push(Value::from(Vec::<Value>::new())),
+        Asm::push(Value::from(CALL)),
+        Asm::push(Value::Function(AsmLocation::Label("__start".to_string()))),
+        Asm::SendMessage,
+        Asm::Return,
+        Asm::PublicLabel("main".to_string()),
+        Asm::push(0u64),
+        Asm::Return,
+    ]);
+    vm.run("boot").expect("VM should not fail")
+}
+
+#[test]
+fn plugins_are_loaded_from_search_path() {
+    let directory = plugin_directory("search-path");
+    let vm =
+        build(VMBuilder::new().plugin_path(PluginSearchPath::new().with_directory(&directory)))
+            .expect("kernel plugin should load");
+    assert_eq!(run_start(vm), 0);
+    fs::remove_dir_all(directory).unwrap();
+}
+
+#[test]
+fn plugins_are_loaded_from_manifest() {
+    let directory = plugin_directory("manifest");
+    let manifest = write_manifest(
+        &directory,
+        r#"{ "plugins": [{ "file": "jodin_vm_kernel", "labels": ["__start"] }] }"#,
+    );
+    let vm = build(VMBuilder::new().plugin_manifest(manifest)).expect("kernel plugin should load");
+    assert_eq!(run_start(vm), 0);
+    fs::remove_dir_all(directory).unwrap();
+}
+
+#[test]
+fn manifest_errors() {
+    let directory = plugin_directory("manifest-errors");
+
+    let manifest = write_manifest(
+        &directory,
+        r#"{ "plugins": [{ "file": "jodin_vm_kernel", "labels": ["__start", "__stop"] }] }"#,
+    );
+    match build(VMBuilder::new().plugin_manifest(&manifest)) {
+        Err(VMError::PluginError(PluginError::MissingLabels { labels, .. })) => {
+            assert_eq!(labels, vec!["__stop".to_string()])
+        }
+        other => panic!("expected missing labels, found {:?}", other.err()),
+    }
+
+    let manifest = write_manifest(&directory, r#"{ "plugins": [{ "file": "not_a_plugin" }] }"#);
+    match build(VMBuilder::new().plugin_manifest(&manifest)) {
+        Err(VMError::PluginError(PluginError::PluginNotFound(path))) => {
+            assert_eq!(path, directory.join("not_a_plugin"))
+        }
+        other => panic!("expected a missing plugin, found {:?}", other.err()),
+    }
+
+    let manifest = write_manifest(&directory, r#"{ "plugin": [] }"#);
+    assert!(matches!(
+        build(VMBuilder::new().plugin_manifest(&manifest)),
+        Err(VMError::PluginError(PluginError::InvalidManifest { .. }))
+    ));
+    fs::remove_dir_all(directory).unwrap();
+}
+
+#[test]
+fn broken_libraries_are_errors() {
+    let directory = plugin_directory("broken");
+    fs::write(
+        directory.join(library_file_name("broken")),
+        b"not a library",
+    )
+    .unwrap();
+    let result =
+        build(VMBuilder::new().plugin_path(PluginSearchPath::new().with_directory(&directory)));
+    assert!(matches!(
+        result,
+        Err(VMError::PluginError(PluginError::LibraryError(_)))
+    ));
+    fs::remove_dir_all(directory).unwrap();
+}
@@ -1,15 +1,175 @@
+//! The kernel of the jodin virtual machine, which boots programs.
+//!
+//! The `__start` label is called with the object path of a program, the arguments of the program,
+//! and optionally the label of the function to run instead of `main`. The kernel loads every
+//! `.jobj` module, `.jdp` package, `.jexe` image and `.jasm` assembly file found on the object path,
+//! loading the static modules (`static*.jobj`) first so their static blocks run before anything
+//! else. Then it calls the program's `main(argc, argv)`, or the entry point of the loaded image,
+//! and turns the value it returns into the exit code.
+
+use jodin_common::assembly::location::AsmLocation;
+use jodin_common::assembly::text::JASM_EXTENSION;
 use jodin_common::assembly::value::Value;
+use jodin_common::image::IMAGE_EXTENSION;
+use jodin_common::package::PACKAGE_EXTENSION;
 use jodin_vm_plugins::declare_plugin;
-use jodin_vm_plugins::plugins::{LoadablePlugin, Stack, VMHandle};
+use jodin_vm_plugins::plugins::{LoadablePlugin, VMHandle};
 use jodin_vm_plugins::Plugin;
-use std::ffi::CStr;
+use std::path::{Path, PathBuf};
+
+/// The name of the function that the kernel runs
+pub const ENTRY_POINT: &str = "main";
 
 pub struct KernelPlugin;
 impl KernelPlugin {
+    /// Boots a program. Expects the object path, either a path or an array of paths, the
+    /// arguments of the program, and the entry label on the stack. Empty values are ignored.
     pub fn start(&self, handle: &mut dyn VMHandle) -> Result<Value, String> {
-        println!("Hello, World!");
-        handle.native("@print_stack", &[], &mut None);
-        Ok(Value::UInteger(0))
+        let mut objects = None;
+        handle.stack().pop(&mut objects);
+        let mut arguments = None;
+        handle.stack().pop(&mut arguments);
+        let mut entry = None;
+        handle.stack().pop(&mut entry);
+
+        let object_path = match objects {
+            None | Some(Value::Empty) => vec![],
+            Some(Value::Str(path)) => vec![PathBuf::from(path)],
+            Some(Value::Array(paths)) => paths
+                .into_iter()
+                .map(|path| match path {
+                    Value::Str(path) => Ok(PathBuf::from(path)),
+                    other => Err(format!("{other:?} is not an object path")),
+                })
+                .collect::<Result<_, _>>()?,
+            Some(other) => return Err(format!("{other:?} is not an object path")),
+        };
+        let arguments = match arguments {
+            None | Some(Value::Empty) => vec![],
+            Some(Value::Array(arguments)) => arguments,
+            Some(other) => return Err(format!("{other:?} are not program arguments")),
+        };
+        let entry = match entry {
+            None | Some(Value::Empty) => None,
+            Some(Value::Str(entry)) => Some(entry),
+            Some(other) => return Err(format!("{other:?} is not an entry label")),
+        };
+
+        for module in boot_order(&object_path)? {
+            load_module(handle, &module)?;
+        }
+        let entry = entry
+            .or_else(|| image_entry_point(handle))
+            .unwrap_or_else(|| ENTRY_POINT.to_string());
+
+        let main = find_entry_point(handle, &entry)?;
+        let mut output = None;
+        handle.call(
+            &Value::Function(AsmLocation::Label(main.clone())),
+            &[
+                Value::Integer(arguments.len() as i64),
+                Value::Array(arguments),
+            ],
+            &mut output,
+        );
+        let returned = output.ok_or_else(|| format!("{main} did not return"))??;
+        exit_code(returned)
+    }
+}
+
+/// The modules on the object path in the order they're loaded in. Static modules come first, and
+/// modules are otherwise ordered by their path.
+fn boot_order(object_path: &[PathBuf]) -> Result<Vec<PathBuf>, String> {
+    let mut modules = vec![];
+    for path in object_path {
+        collect_modules(path, &mut modules)?;
+    }
+    modules.sort_by_key(|module| (!is_static(module), module.clone()));
+    Ok(modules)
+}
+
+fn collect_modules(path: &Path, modules: &mut Vec<PathBuf>) -> Result<(), String> {
+    if path.is_dir() {
+        let entries = std::fs::read_dir(path).map_err(|e| format!("{path:?}: {e}"))?;
+        for entry in entries {
+            let entry = entry.map_err(|e| format!("{path:?}: {e}"))?;
+            collect_modules(&entry.path(), modules)?;
+        }
+    } else if path.is_file() {
+        if path.extension().is_some_and(|ext| {
+            ext == "jobj"
+                || ext == PACKAGE_EXTENSION
+                || ext == IMAGE_EXTENSION
+                || ext == JASM_EXTENSION
+        }) {
+            modules.push(path.to_path_buf());
+        }
+    } else {
+        return Err(format!("{path:?} does not exist"));
+    }
+    Ok(())
+}
+
+/// Static modules are run entirely when they're loaded
+fn is_static(module: &Path) -> bool {
+    module
+        .file_name()
+        .and_then(|name| name.to_str())
+        .is_some_and(|name| name.starts_with("static"))
+}
+
+fn load_module(handle: &mut dyn VMHandle, module: &Path) -> Result<(), String> {
+    let mut output = None;
+    handle.native(
+        "load_object",
+        &[Value::Str(module.to_string_lossy().to_string())],
+        &mut output,
+    );
+    match output {
+        Some(Value::Empty) => Ok(()),
+        Some(Value::Str(error)) => Err(format!("could not load {module:?}: {error}")),
+        other => Err(format!("could not load {module:?}: {other:?}")),
+    }
+}
+
+/// The entry point recorded by the linker, if an image was loaded
+fn image_entry_point(handle: &mut dyn VMHandle) -> Option<String> {
+    let mut output = None;
+    handle.native("entry_point", &[], &mut output);
+    output.and_then(Value::into_string)
+}
+
+/// Finds the label of the entry point, which can be within a namespace
+fn find_entry_point(handle: &mut dyn VMHandle, entry: &str) -> Result<String, String> {
+    let mut output = None;
+    handle.native("find_labels", &[Value::from(entry)], &mut output);
+    let mut labels = match output {
+        Some(Value::Array(labels)) => labels
+            .into_iter()
+            .filter_map(Value::into_string)
+            .collect::<Vec<_>>(),
+        _ => vec![],
+    };
+    if labels.iter().any(|label| label == entry) {
+        return Ok(entry.to_string());
+    }
+    match labels.len() {
+        0 => Err(format!("no {entry} function was loaded")),
+        1 => Ok(labels.remove(0)),
+        _ => Err(format!("{entry} is ambiguous, found {}", labels.join(", "))),
+    }
+}
+
+/// Converts the value returned by the entry point into an exit code
+fn exit_code(returned: Value) -> Result<Value, String> {
+    match returned {
+        Value::Empty => Ok(Value::UInteger(0)),
+        Value::Byte(code) => Ok(Value::UInteger(code as u64)),
+        Value::Integer(code) => Ok(Value::UInteger(code as u32 as u64)),
+        Value::UInteger(code) => Ok(Value::UInteger(code as u32 as u64)),
+        other => Err(format!(
+            "the entry point returned {other:?}, which is not an exit code"
+        )),
     }
 }
 
@@ -20,7 +180,7 @@ impl LoadablePlugin for KernelPlugin {
 }
 
 impl Plugin for KernelPlugin {
-    fn labels(&self, buffer: &mut [&'static str]) {
+    fn labels<'s>(&'s self, buffer: &mut [&'s str]) {
         buffer[0] = "__start";
     }
 
@@ -31,7 +191,6 @@ impl Plugin for KernelPlugin {
     fn call_label(
         &self,
         label: &str,
-        _stack: &mut dyn Stack,
         handle: &mut dyn VMHandle,
         output: &mut Option<Result<Value, String>>,
     ) {
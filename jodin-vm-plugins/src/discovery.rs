@@ -0,0 +1,184 @@
+//! Finding plugin libraries before they're loaded.
+//!
+//! Plugins are found in two ways. Every library within the directories of a [PluginSearchPath]
+//! is a plugin, and the search path is usually read from the `JODIN_PLUGIN_PATH` environment
+//! variable. A [PluginManifest] instead lists the plugin files that should be loaded, along with
+//! labels each plugin must register.
+//!
+//! A manifest is a json file:
+//! ```json
+//! {
+//!     "plugins": [
+//!         { "file": "jodin_vm_kernel", "labels": ["__start"] },
+//!         { "file": "plugins/libextra.so" }
+//!     ]
+//! }
+//! ```
+//! Files are relative to the manifest. A file without an extension is the name of a library,
+//! such as `jodin_vm_kernel` for `libjodin_vm_kernel.so`, and is also searched for within the
+//! search path.
+
+use crate::error::PluginError;
+use std::env;
+use std::env::consts::{DLL_EXTENSION, DLL_PREFIX, DLL_SUFFIX};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// The environment variable that contains the plugin search path
+pub const PLUGIN_PATH_VAR: &str = "JODIN_PLUGIN_PATH";
+
+/// Gets the file name of a library on this platform, such as `libkernel.so` for `kernel`
+pub fn library_file_name(name: &str) -> String {
+    format!("{DLL_PREFIX}{name}{DLL_SUFFIX}")
+}
+
+/// Directories that contain plugin libraries
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct PluginSearchPath {
+    directories: Vec<PathBuf>,
+}
+
+impl PluginSearchPath {
+    /// Creates an empty search path
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Reads the search path from the `JODIN_PLUGIN_PATH` environment variable, which is
+    /// separated like the `PATH` variable of the platform
+    pub fn from_env() -> Self {
+        env::var_os(PLUGIN_PATH_VAR)
+            .map(|paths| Self {
+                directories: env::split_paths(&paths)
+                    .filter(|path| !path.as_os_str().is_empty())
+                    .collect(),
+            })
+            .unwrap_or_default()
+    }
+
+    /// Adds a directory to the end of the search path
+    pub fn with_directory<P: AsRef<Path>>(mut self, directory: P) -> Self {
+        self.directories.push(directory.as_ref().to_path_buf());
+        self
+    }
+
+    pub fn directories(&self) -> &[PathBuf] {
+        &self.directories
+    }
+
+    /// Finds a library by its file name or library name in the first directory that has it
+    pub fn find(&self, name: &str) -> Option<PathBuf> {
+        let file_name = library_file_name(name);
+        self.directories.iter().find_map(|directory| {
+            [directory.join(name), directory.join(&file_name)]
+                .into_iter()
+                .find(|path| path.is_file())
+        })
+    }
+
+    /// Every library within the directories of the search path, in order. Directories that
+    /// don't exist are skipped.
+    pub fn libraries(&self) -> Result<Vec<PathBuf>, PluginError> {
+        let mut libraries = vec![];
+        for directory in &self.directories {
+            if !directory.is_dir() {
+                continue;
+            }
+            let mut found = fs::read_dir(directory)
+                .map_err(|e| PluginError::DiscoveryFailed {
+                    path: directory.clone(),
+                    reason: e.to_string(),
+                })?
+                .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+                .filter(|path| {
+                    path.is_file() && path.extension().is_some_and(|ext| ext == DLL_EXTENSION)
+                })
+                .collect::<Vec<_>>();
+            found.sort();
+            libraries.extend(found);
+        }
+        Ok(libraries)
+    }
+}
+
+/// A plugin that was found, but not loaded yet
+#[derive(Debug, Clone, PartialEq)]
+pub struct DiscoveredPlugin {
+    pub path: PathBuf,
+    /// Labels the plugin must register
+    pub labels: Vec<String>,
+}
+
+impl DiscoveredPlugin {
+    /// A plugin that isn't required to register any label
+    pub fn new<P: AsRef<Path>>(path: P) -> Self {
+        Self {
+            path: path.as_ref().to_path_buf(),
+            labels: vec![],
+        }
+    }
+}
+
+/// A file listing the plugins to load
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PluginManifest {
+    pub plugins: Vec<ManifestEntry>,
+    /// The directory files are relative to
+    #[serde(skip)]
+    directory: PathBuf,
+}
+
+/// A plugin within a [PluginManifest]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ManifestEntry {
+    pub file: PathBuf,
+    #[serde(default)]
+    pub labels: Vec<String>,
+}
+
+impl PluginManifest {
+    /// Reads a manifest file
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, PluginError> {
+        let path = path.as_ref();
+        let manifest_error = |reason: String| PluginError::InvalidManifest {
+            path: path.to_path_buf(),
+            reason,
+        };
+        let text = fs::read_to_string(path).map_err(|e| manifest_error(e.to_string()))?;
+        let mut manifest: Self =
+            serde_json::from_str(&text).map_err(|e| manifest_error(e.to_string()))?;
+        manifest.directory = path.parent().map(Path::to_path_buf).unwrap_or_default();
+        Ok(manifest)
+    }
+
+    /// Finds the library of every plugin in the manifest. Files are found relative to the
+    /// manifest first, and then within the search path.
+    ///
+    /// # Error
+    ///
+    /// Errors if a plugin can't be found.
+    pub fn resolve(
+        &self,
+        search_path: &PluginSearchPath,
+    ) -> Result<Vec<DiscoveredPlugin>, PluginError> {
+        self.plugins
+            .iter()
+            .map(|entry| {
+                let name = entry.file.to_string_lossy();
+                let mut candidates = vec![self.directory.join(&entry.file)];
+                if entry.file.extension().is_none() {
+                    candidates.push(self.directory.join(library_file_name(&name)));
+                }
+                candidates
+                    .into_iter()
+                    .find(|path| path.is_file())
+                    .or_else(|| search_path.find(&name))
+                    .map(|path| DiscoveredPlugin {
+                        path,
+                        labels: entry.labels.clone(),
+                    })
+                    .ok_or_else(|| PluginError::PluginNotFound(self.directory.join(&entry.file)))
+            })
+            .collect()
+    }
+}
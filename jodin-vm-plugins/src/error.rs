@@ -1,3 +1,4 @@
+use std::path::PathBuf;
 use thiserror::Error;
 
 #[derive(Debug, Error)]
@@ -8,4 +9,40 @@ pub enum PluginError {
     LabelNotRegister(String),
     #[error(transparent)]
     LibraryError(#[from] libloading::Error),
+    #[error("No plugin library found at {0:?}")]
+    PluginNotFound(PathBuf),
+    #[error("{0:?} is not a jodin plugin")]
+    NotAPlugin(PathBuf),
+    #[error("Plugin {path:?} uses ABI version {found}, but version {expected} is required")]
+    AbiVersionMismatch {
+        path: PathBuf,
+        expected: u32,
+        found: u32,
+    },
+    #[error("Plugin {path:?} could not be created (status = {status})")]
+    CreationFailed { path: PathBuf, status: u32 },
+    #[error("The plugin that owns a native handle is not loaded (owner = {0})")]
+    HandleOwnerMissing(String),
+    #[error("Plugin reported a negative number of labels ({0})")]
+    InvalidLabelCount(i32),
+    #[error("Label {label:?} is already registered by plugin {owner}")]
+    LabelConflict { label: String, owner: String },
+    #[error("No plugin is loaded with id {0}")]
+    PluginNotLoaded(String),
+    #[error("Plugin {0} wasn't loaded from a library, so it can't be reloaded")]
+    NotDynamic(String),
+    #[error("Plugin {path:?} could not be reloaded: {reason}")]
+    ReloadFailed { path: PathBuf, reason: String },
+    #[error("Plugins could not be discovered in {path:?}: {reason}")]
+    DiscoveryFailed { path: PathBuf, reason: String },
+    #[error("Invalid plugin manifest {path:?}: {reason}")]
+    InvalidManifest { path: PathBuf, reason: String },
+    #[error("Plugin {path:?} does not register the required labels {labels:?}")]
+    MissingLabels { path: PathBuf, labels: Vec<String> },
+    #[error("Invalid label signature {0}")]
+    InvalidSignature(String),
+    #[error("Signature object {path:?} could not be written: {reason}")]
+    SignatureObjectFailed { path: PathBuf, reason: String },
+    #[error("Value could not be passed to or from a plugin: {0}")]
+    InvalidValue(String),
 }
@@ -0,0 +1,1614 @@
+//! The stable C ABI between the virtual machine and dynamically loaded plugins.
+//!
+//! Nothing but `repr(C)` structures and `extern "C"` functions cross the library boundary, so a
+//! plugin can be built with a different compiler or a different version of this crate than the
+//! virtual machine, as long as both agree on [PLUGIN_ABI_VERSION].
+//!
+//! A plugin library exports two symbols, which are generated by
+//! [declare_plugin!](crate::declare_plugin):
+//! - `_jodin_plugin_abi_version`, which returns the ABI version the plugin was built against
+//! - `_jodin_plugin_create`, which is given the ABI version of the host and fills in a
+//!   [PluginVTable]
+//!
+//! Values are marshalled as [FfiValue]s. Memory is always released by the side that allocated it,
+//! using the `free_value` function of that side's vtable. References are copied when they cross
+//! the boundary, while native handles keep their identity for as long as the host holds them.
+//! Since a copied reference is a different object, dynamic plugins can't
+//! [allocate](crate::plugins::VMHandle::allocate) objects, and should keep their own state behind
+//! native handles instead.
+
+use crate::error::PluginError;
+use crate::plugins::{Plugin, Stack, VMHandle};
+use crate::signatures::LabelSignature;
+use jodin_common::assembly::location::AsmLocation;
+use jodin_common::assembly::native_handle::NativeHandle;
+use jodin_common::assembly::value::{JRef, Value};
+use libloading::{Library, Symbol};
+use std::collections::HashMap;
+use std::ffi::c_void;
+use std::mem::{ManuallyDrop, MaybeUninit};
+use std::panic::{catch_unwind, AssertUnwindSafe};
+use std::path::Path;
+use std::ptr::null_mut;
+
+/// The version of the plugin ABI. Must be increased whenever any of the structures in this module
+/// change.
+pub const PLUGIN_ABI_VERSION: u32 = 4;
+
+/// The symbol of the function that returns the ABI version of a plugin
+pub const ABI_VERSION_SYMBOL: &[u8] = b"_jodin_plugin_abi_version\0";
+/// The symbol of the function that creates a plugin
+pub const CREATE_SYMBOL: &[u8] = b"_jodin_plugin_create\0";
+
+/// The signature of `_jodin_plugin_abi_version`
+pub type PluginAbiVersionFn = unsafe extern "C" fn() -> u32;
+/// The signature of `_jodin_plugin_create`
+pub type PluginCreateFn =
+    unsafe extern "C" fn(host_abi_version: u32, output: *mut PluginVTable) -> FfiStatus;
+
+/// The result of a call across the plugin boundary
+#[repr(transparent)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FfiStatus(pub u32);
+
+impl FfiStatus {
+    /// The call succeeded
+    pub const OK: Self = Self(0);
+    /// The call failed, and the output value contains an error message
+    pub const ERROR: Self = Self(1);
+    /// The two sides were built against different ABI versions
+    pub const ABI_VERSION_MISMATCH: Self = Self(2);
+    /// The plugin panicked
+    pub const PANICKED: Self = Self(3);
+}
+
+/// The kind of value stored within an [FfiValue]
+#[repr(transparent)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FfiValueKind(pub u32);
+
+impl FfiValueKind {
+    pub const EMPTY: Self = Self(0);
+    /// Stored in `scalar.byte`
+    pub const BYTE: Self = Self(1);
+    /// Stored in `scalar.float`
+    pub const FLOAT: Self = Self(2);
+    /// Stored in `scalar.integer`
+    pub const INTEGER: Self = Self(3);
+    /// Stored in `scalar.uinteger`
+    pub const UINTEGER: Self = Self(4);
+    /// Stored as utf-8 in `bytes`
+    pub const STR: Self = Self(5);
+    /// The keys are stored as utf-8 in `keys`, and the values are stored in the same order in
+    /// `children`
+    pub const DICTIONARY: Self = Self(6);
+    /// Stored in `children`
+    pub const ARRAY: Self = Self(7);
+    /// A copy of the referenced value is the only entry of `children`
+    pub const REFERENCE: Self = Self(8);
+    /// Stored in `bytes`
+    pub const BYTECODE: Self = Self(9);
+    /// A function at a label, stored as utf-8 in `bytes`
+    pub const FUNCTION_LABEL: Self = Self(10);
+    /// A function at an instruction, stored in `scalar.uinteger`
+    pub const FUNCTION_INDEX: Self = Self(11);
+    /// A function relative to the current instruction, stored in `scalar.integer`
+    pub const FUNCTION_OFFSET: Self = Self(12);
+    pub const NATIVE: Self = Self(13);
+    /// A native handle, with its id in `scalar.uinteger`, its type name as utf-8 in `bytes`, and
+    /// its owner as the only entry of `keys`
+    pub const NATIVE_HANDLE: Self = Self(14);
+}
+
+/// The scalar part of an [FfiValue]
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub union FfiScalar {
+    pub byte: u8,
+    pub float: f64,
+    pub integer: i64,
+    pub uinteger: u64,
+}
+
+/// An owned buffer. Can only be released by the side that allocated it.
+#[repr(C)]
+pub struct FfiBuffer<T> {
+    pub ptr: *mut T,
+    pub len: usize,
+    pub capacity: usize,
+}
+
+impl<T> FfiBuffer<T> {
+    /// A buffer without any allocation
+    pub const fn empty() -> Self {
+        Self {
+            ptr: null_mut(),
+            len: 0,
+            capacity: 0,
+        }
+    }
+
+    fn from_vec(vec: Vec<T>) -> Self {
+        let mut vec = ManuallyDrop::new(vec);
+        Self {
+            ptr: vec.as_mut_ptr(),
+            len: vec.len(),
+            capacity: vec.capacity(),
+        }
+    }
+
+    /// # Safety
+    /// The buffer must have been allocated by this side of the boundary
+    unsafe fn into_vec(self) -> Vec<T> {
+        if self.ptr.is_null() {
+            vec![]
+        } else {
+            Vec::from_raw_parts(self.ptr, self.len, self.capacity)
+        }
+    }
+
+    /// # Safety
+    /// The buffer must point to `len` valid entries
+    pub unsafe fn as_slice(&self) -> &[T] {
+        if self.ptr.is_null() {
+            &[]
+        } else {
+            std::slice::from_raw_parts(self.ptr, self.len)
+        }
+    }
+}
+
+/// A borrowed utf-8 string
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct FfiStr {
+    pub ptr: *const u8,
+    pub len: usize,
+}
+
+impl FfiStr {
+    /// Borrows a string
+    pub fn new(s: &str) -> Self {
+        Self {
+            ptr: s.as_ptr(),
+            len: s.len(),
+        }
+    }
+
+    /// # Safety
+    /// The string must still be alive
+    pub unsafe fn to_str<'a>(self) -> Result<&'a str, PluginError> {
+        if self.ptr.is_null() {
+            return Ok("");
+        }
+        std::str::from_utf8(std::slice::from_raw_parts(self.ptr, self.len))
+            .map_err(|e| PluginError::InvalidValue(e.to_string()))
+    }
+}
+
+/// A C compatible representation of a [Value]
+#[repr(C)]
+pub struct FfiValue {
+    pub kind: FfiValueKind,
+    pub scalar: FfiScalar,
+    pub bytes: FfiBuffer<u8>,
+    pub keys: FfiBuffer<FfiBuffer<u8>>,
+    pub children: FfiBuffer<FfiValue>,
+}
+
+impl FfiValue {
+    /// An empty value without any allocations
+    pub const fn empty() -> Self {
+        Self {
+            kind: FfiValueKind::EMPTY,
+            scalar: FfiScalar { uinteger: 0 },
+            bytes: FfiBuffer::empty(),
+            keys: FfiBuffer::empty(),
+            children: FfiBuffer::empty(),
+        }
+    }
+
+    fn scalar(kind: FfiValueKind, scalar: FfiScalar) -> Self {
+        Self {
+            kind,
+            scalar,
+            ..Self::empty()
+        }
+    }
+
+    fn bytes(kind: FfiValueKind, bytes: Vec<u8>) -> Self {
+        Self {
+            kind,
+            bytes: FfiBuffer::from_vec(bytes),
+            ..Self::empty()
+        }
+    }
+
+    fn children(kind: FfiValueKind, children: Vec<FfiValue>) -> Self {
+        Self {
+            kind,
+            children: FfiBuffer::from_vec(children),
+            ..Self::empty()
+        }
+    }
+
+    /// Marshals a value. The result must be released with [free](Self::free) on this side of the
+    /// boundary.
+    pub fn from_value(value: &Value) -> Self {
+        match value {
+            Value::Empty => Self::empty(),
+            &Value::Byte(byte) => Self::scalar(FfiValueKind::BYTE, FfiScalar { byte }),
+            &Value::Float(float) => Self::scalar(FfiValueKind::FLOAT, FfiScalar { float }),
+            &Value::Integer(integer) => Self::scalar(FfiValueKind::INTEGER, FfiScalar { integer }),
+            &Value::UInteger(uinteger) => {
+                Self::scalar(FfiValueKind::UINTEGER, FfiScalar { uinteger })
+            }
+            Value::Str(s) => Self::bytes(FfiValueKind::STR, s.as_bytes().to_vec()),
+            Value::Dictionary(dict) => {
+                let (keys, values): (Vec<_>, Vec<_>) = dict
+                    .iter()
+                    .map(|(key, value)| {
+                        (
+                            FfiBuffer::from_vec(key.as_bytes().to_vec()),
+                            Self::from_value(value),
+                        )
+                    })
+                    .unzip();
+                Self {
+                    keys: FfiBuffer::from_vec(keys),
+                    ..Self::children(FfiValueKind::DICTIONARY, values)
+                }
+            }
+            Value::Array(array) => Self::children(
+                FfiValueKind::ARRAY,
+                array.iter().map(Self::from_value).collect(),
+            ),
+            Value::Reference(reference) => Self::children(
+                FfiValueKind::REFERENCE,
+                vec![Self::from_value(&reference.borrow())],
+            ),
+            Value::Bytecode(bytecode) => Self::bytes(FfiValueKind::BYTECODE, bytecode.clone()),
+            Value::Function(AsmLocation::Label(label)) => {
+                Self::bytes(FfiValueKind::FUNCTION_LABEL, label.as_bytes().to_vec())
+            }
+            &Value::Function(AsmLocation::ByteIndex(index)) => Self::scalar(
+                FfiValueKind::FUNCTION_INDEX,
+                FfiScalar {
+                    uinteger: index as u64,
+                },
+            ),
+            &Value::Function(AsmLocation::InstructionDiff(diff)) => Self::scalar(
+                FfiValueKind::FUNCTION_OFFSET,
+                FfiScalar {
+                    integer: diff as i64,
+                },
+            ),
+            Value::Native => Self::empty_of(FfiValueKind::NATIVE),
+            Value::NativeHandle(handle) => Self {
+                keys: FfiBuffer::from_vec(vec![FfiBuffer::from_vec(
+                    handle.owner().as_bytes().to_vec(),
+                )]),
+                ..Self {
+                    scalar: FfiScalar {
+                        uinteger: handle.id(),
+                    },
+                    ..Self::bytes(
+                        FfiValueKind::NATIVE_HANDLE,
+                        handle.type_name().as_bytes().to_vec(),
+                    )
+                }
+            },
+        }
+    }
+
+    fn empty_of(kind: FfiValueKind) -> Self {
+        Self {
+            kind,
+            ..Self::empty()
+        }
+    }
+
+    /// Converts the marshalled value back into a value, without taking ownership of it
+    ///
+    /// # Safety
+    /// The buffers of the value must be valid
+    pub unsafe fn to_value(&self) -> Result<Value, PluginError> {
+        let string = |bytes: &FfiBuffer<u8>| {
+            String::from_utf8(bytes.as_slice().to_vec())
+                .map_err(|e| PluginError::InvalidValue(e.to_string()))
+        };
+        let children = || {
+            self.children
+                .as_slice()
+                .iter()
+                .map(|child| child.to_value())
+                .collect::<Result<Vec<_>, _>>()
+        };
+        Ok(match self.kind {
+            FfiValueKind::EMPTY => Value::Empty,
+            FfiValueKind::BYTE => Value::Byte(self.scalar.byte),
+            FfiValueKind::FLOAT => Value::Float(self.scalar.float),
+            FfiValueKind::INTEGER => Value::Integer(self.scalar.integer),
+            FfiValueKind::UINTEGER => Value::UInteger(self.scalar.uinteger),
+            FfiValueKind::STR => Value::Str(string(&self.bytes)?),
+            FfiValueKind::DICTIONARY => {
+                let keys = self.keys.as_slice();
+                let values = children()?;
+                if keys.len() != values.len() {
+                    return Err(PluginError::InvalidValue(format!(
+                        "dictionary has {} keys but {} values",
+                        keys.len(),
+                        values.len()
+                    )));
+                }
+                Value::Dictionary(
+                    keys.iter()
+                        .map(string)
+                        .zip(values)
+                        .map(|(key, value)| Ok((key?, value)))
+                        .collect::<Result<_, PluginError>>()?,
+                )
+            }
+            FfiValueKind::ARRAY => Value::Array(children()?),
+            FfiValueKind::REFERENCE => match children()?.pop() {
+                Some(inner) => Value::Reference(JRef::new(inner)),
+                None => {
+                    return Err(PluginError::InvalidValue(
+                        "reference without a value".to_string(),
+                    ))
+                }
+            },
+            FfiValueKind::BYTECODE => Value::Bytecode(self.bytes.as_slice().to_vec()),
+            FfiValueKind::FUNCTION_LABEL => {
+                Value::Function(AsmLocation::Label(string(&self.bytes)?))
+            }
+            FfiValueKind::FUNCTION_INDEX => {
+                Value::Function(AsmLocation::ByteIndex(self.scalar.uinteger as usize))
+            }
+            FfiValueKind::FUNCTION_OFFSET => {
+                Value::Function(AsmLocation::InstructionDiff(self.scalar.integer as isize))
+            }
+            FfiValueKind::NATIVE => Value::Native,
+            FfiValueKind::NATIVE_HANDLE => {
+                let owner = match self.keys.as_slice() {
+                    [owner] => string(owner)?,
+                    _ => {
+                        return Err(PluginError::InvalidValue(
+                            "native handle without an owner".to_string(),
+                        ))
+                    }
+                };
+                let id = self.scalar.uinteger;
+                // the original handle only exists on the side that created it
+                Value::NativeHandle(NativeHandle::find(&owner, id).unwrap_or_else(|| {
+                    NativeHandle::unmanaged(owner, string(&self.bytes).unwrap_or_default(), id)
+                }))
+            }
+            FfiValueKind(kind) => {
+                return Err(PluginError::InvalidValue(format!(
+                    "unknown value kind {kind}"
+                )))
+            }
+        })
+    }
+
+    /// Releases the buffers of the value
+    ///
+    /// # Safety
+    /// The value must have been created by [from_value](Self::from_value) on this side of the
+    /// boundary
+    pub unsafe fn free(self) {
+        self.bytes.into_vec();
+        for key in self.keys.into_vec() {
+            key.into_vec();
+        }
+        for child in self.children.into_vec() {
+            child.free();
+        }
+    }
+}
+
+/// Releases a value that was allocated on this side of the boundary, leaving an empty value in its
+/// place. This is the `free_value` function of both vtables.
+///
+/// # Safety
+/// The value must be null, or point to a live value that was created by
+/// [from_value](FfiValue::from_value) on this side of the boundary
+pub unsafe extern "C" fn free_ffi_value(value: *mut FfiValue) {
+    if value.is_null() {
+        return;
+    }
+    std::ptr::replace(value, FfiValue::empty()).free()
+}
+
+/// The functions a plugin provides to the virtual machine
+#[repr(C)]
+pub struct PluginVTable {
+    pub abi_version: u32,
+    /// The plugin itself, passed to every function of the vtable
+    pub instance: *mut c_void,
+    pub labels_count: extern "C" fn(instance: *const c_void) -> usize,
+    /// Gets a label. The string lives as long as the plugin.
+    pub label: extern "C" fn(instance: *const c_void, index: usize) -> FfiStr,
+    /// Calls a label. The output is allocated by the plugin, and must be released with
+    /// `free_value`.
+    pub call_label: extern "C" fn(
+        instance: *const c_void,
+        label: FfiStr,
+        host: *const HostVTable,
+        output: *mut FfiValue,
+    ) -> FfiStatus,
+    /// Sends a message to a native handle of the plugin. The output is allocated by the plugin,
+    /// and must be released with `free_value`.
+    pub handle_message: extern "C" fn(
+        instance: *const c_void,
+        type_name: FfiStr,
+        id: u64,
+        message: FfiStr,
+        args: *const FfiValue,
+        args_len: usize,
+        host: *const HostVTable,
+        output: *mut FfiValue,
+    ) -> FfiStatus,
+    /// Releases the resource behind a native handle of the plugin
+    pub finalize_handle: extern "C" fn(instance: *const c_void, type_name: FfiStr, id: u64),
+    /// Gets the signature of a label as a function type string, or an empty value if the label
+    /// has no signature. The output is allocated by the plugin, and must be released with
+    /// `free_value`.
+    pub signature:
+        extern "C" fn(instance: *const c_void, label: FfiStr, output: *mut FfiValue) -> FfiStatus,
+    pub free_value: unsafe extern "C" fn(value: *mut FfiValue),
+    pub destroy: extern "C" fn(instance: *mut c_void),
+}
+
+impl PluginVTable {
+    /// Creates the vtable of a plugin
+    pub fn for_plugin<P: Plugin>(plugin: P) -> Self {
+        Self {
+            abi_version: PLUGIN_ABI_VERSION,
+            instance: Box::into_raw(Box::new(plugin)) as *mut c_void,
+            labels_count: plugin_labels_count::<P>,
+            label: plugin_label::<P>,
+            call_label: plugin_call_label::<P>,
+            handle_message: plugin_handle_message::<P>,
+            finalize_handle: plugin_finalize_handle::<P>,
+            signature: plugin_signature::<P>,
+            free_value: free_ffi_value,
+            destroy: plugin_destroy::<P>,
+        }
+    }
+}
+
+fn plugin_labels<P: Plugin>(plugin: &P) -> Vec<&str> {
+    let mut buffer = vec![""; plugin.labels_count().max(0) as usize];
+    plugin.labels(&mut buffer);
+    buffer
+}
+
+extern "C" fn plugin_labels_count<P: Plugin>(instance: *const c_void) -> usize {
+    let plugin = unsafe { &*(instance as *const P) };
+    plugin.labels_count().max(0) as usize
+}
+
+extern "C" fn plugin_label<P: Plugin>(instance: *const c_void, index: usize) -> FfiStr {
+    let plugin = unsafe { &*(instance as *const P) };
+    FfiStr::new(plugin_labels(plugin).get(index).copied().unwrap_or(""))
+}
+
+/// Runs a call into the plugin, writing its result to the output
+fn plugin_call<F>(host: *const HostVTable, output: *mut FfiValue, call: F) -> FfiStatus
+where
+    F: FnOnce(&HostVTable) -> Result<Option<Result<Value, String>>, PluginError>,
+{
+    let result = catch_unwind(AssertUnwindSafe(|| unsafe {
+        let host = &*host;
+        if host.abi_version != PLUGIN_ABI_VERSION {
+            return (
+                FfiStatus::ABI_VERSION_MISMATCH,
+                Err(format!(
+                    "host uses plugin ABI version {} (expected {})",
+                    host.abi_version, PLUGIN_ABI_VERSION
+                )),
+            );
+        }
+        match call(host) {
+            Ok(Some(Ok(value))) => (FfiStatus::OK, Ok(value)),
+            Ok(Some(Err(e))) => (FfiStatus::ERROR, Err(e)),
+            Ok(None) => (
+                FfiStatus::ERROR,
+                Err("plugin produced no output".to_string()),
+            ),
+            Err(e) => (FfiStatus::ERROR, Err(e.to_string())),
+        }
+    }));
+    let (status, value) = match result {
+        Ok((status, Ok(value))) => (status, value),
+        Ok((status, Err(message))) => (status, Value::Str(message)),
+        Err(_) => (
+            FfiStatus::PANICKED,
+            Value::Str("plugin panicked".to_string()),
+        ),
+    };
+    if !output.is_null() {
+        unsafe { output.write(FfiValue::from_value(&value)) };
+    }
+    status
+}
+
+extern "C" fn plugin_call_label<P: Plugin>(
+    instance: *const c_void,
+    label: FfiStr,
+    host: *const HostVTable,
+    output: *mut FfiValue,
+) -> FfiStatus {
+    plugin_call(host, output, |host| unsafe {
+        let plugin = &*(instance as *const P);
+        let label = label.to_str()?;
+        let mut out = None;
+        plugin.call_label(label, &mut HostHandle::new(host), &mut out);
+        Ok(out)
+    })
+}
+
+extern "C" fn plugin_handle_message<P: Plugin>(
+    instance: *const c_void,
+    type_name: FfiStr,
+    id: u64,
+    message: FfiStr,
+    args: *const FfiValue,
+    args_len: usize,
+    host: *const HostVTable,
+    output: *mut FfiValue,
+) -> FfiStatus {
+    plugin_call(host, output, |host| unsafe {
+        let plugin = &*(instance as *const P);
+        let args = read_values(args, args_len)?;
+        let mut out = None;
+        plugin.handle_message(
+            type_name.to_str()?,
+            id,
+            message.to_str()?,
+            &args,
+            &mut HostHandle::new(host),
+            &mut out,
+        );
+        Ok(out)
+    })
+}
+
+extern "C" fn plugin_signature<P: Plugin>(
+    instance: *const c_void,
+    label: FfiStr,
+    output: *mut FfiValue,
+) -> FfiStatus {
+    let result = catch_unwind(AssertUnwindSafe(|| unsafe {
+        let plugin = &*(instance as *const P);
+        label.to_str().map(|label| plugin.signature(label))
+    }));
+    let (status, value) = match result {
+        Ok(Ok(Some(signature))) => (FfiStatus::OK, Value::Str(signature.to_string())),
+        Ok(Ok(None)) => (FfiStatus::OK, Value::Empty),
+        Ok(Err(e)) => (FfiStatus::ERROR, Value::Str(e.to_string())),
+        Err(_) => (
+            FfiStatus::PANICKED,
+            Value::Str("plugin panicked".to_string()),
+        ),
+    };
+    if !output.is_null() {
+        unsafe { output.write(FfiValue::from_value(&value)) };
+    }
+    status
+}
+
+extern "C" fn plugin_finalize_handle<P: Plugin>(
+    instance: *const c_void,
+    type_name: FfiStr,
+    id: u64,
+) {
+    let _ = catch_unwind(AssertUnwindSafe(|| unsafe {
+        let plugin = &*(instance as *const P);
+        if let Ok(type_name) = type_name.to_str() {
+            plugin.finalize_handle(type_name, id);
+        }
+    }));
+}
+
+extern "C" fn plugin_destroy<P: Plugin>(instance: *mut c_void) {
+    drop(unsafe { Box::from_raw(instance as *mut P) });
+}
+
+/// Creates a plugin on behalf of `_jodin_plugin_create`
+///
+/// # Safety
+/// `output` must be valid for writes
+pub unsafe fn export_plugin<P: Plugin>(
+    host_abi_version: u32,
+    output: *mut PluginVTable,
+    constructor: fn() -> P,
+) -> FfiStatus {
+    if host_abi_version != PLUGIN_ABI_VERSION {
+        return FfiStatus::ABI_VERSION_MISMATCH;
+    }
+    if output.is_null() {
+        return FfiStatus::ERROR;
+    }
+    match catch_unwind(constructor) {
+        Ok(plugin) => {
+            output.write(PluginVTable::for_plugin(plugin));
+            FfiStatus::OK
+        }
+        Err(_) => FfiStatus::PANICKED,
+    }
+}
+
+/// The functions the virtual machine provides to a plugin while one of its labels is called
+#[repr(C)]
+pub struct HostVTable {
+    pub abi_version: u32,
+    /// Passed to every function of the vtable
+    pub context: *mut c_void,
+    pub stack_empty: extern "C" fn(context: *mut c_void) -> bool,
+    /// Pushes a copy of the value. The plugin keeps ownership of the value.
+    pub stack_push: extern "C" fn(context: *mut c_void, value: *const FfiValue),
+    /// Pops a value into the output, returning false if the stack was empty. The output is
+    /// allocated by the host, and must be released with `free_value`.
+    pub stack_pop: extern "C" fn(context: *mut c_void, output: *mut FfiValue) -> bool,
+    /// Calls a native method, returning false if it failed. The output is allocated by the host,
+    /// and must be released with `free_value`.
+    pub native: extern "C" fn(
+        context: *mut c_void,
+        method: FfiStr,
+        args: *const FfiValue,
+        args_len: usize,
+        output: *mut FfiValue,
+    ) -> bool,
+    /// Sends a message to a value. The output is allocated by the host, and must be released with
+    /// `free_value`. If the message fails, the output contains an error message.
+    pub send_message: extern "C" fn(
+        context: *mut c_void,
+        target: *const FfiValue,
+        message: FfiStr,
+        args: *const FfiValue,
+        args_len: usize,
+        output: *mut FfiValue,
+    ) -> FfiStatus,
+    /// Gets a global variable, returning false if it isn't set. The output is allocated by the
+    /// host, and must be released with `free_value`.
+    pub get_global: extern "C" fn(context: *mut c_void, var: usize, output: *mut FfiValue) -> bool,
+    /// Sets a global variable to a copy of the value
+    pub set_global: extern "C" fn(context: *mut c_void, var: usize, value: *const FfiValue),
+    /// Allocates a copy of the value. The output is allocated by the host, and must be released
+    /// with `free_value`. References have no identity across the boundary, so this always fails.
+    pub allocate:
+        extern "C" fn(context: *mut c_void, value: *const FfiValue, output: *mut FfiValue) -> bool,
+    /// Raises a fault, returning false if the fault is unknown
+    pub raise_fault: extern "C" fn(context: *mut c_void, fault: FfiStr, detail: FfiStr) -> bool,
+    /// Creates a native handle owned by the plugin. The output is allocated by the host, and must
+    /// be released with `free_value`.
+    pub new_native_handle: extern "C" fn(
+        context: *mut c_void,
+        type_name: FfiStr,
+        id: u64,
+        output: *mut FfiValue,
+    ) -> bool,
+    pub free_value: unsafe extern "C" fn(value: *mut FfiValue),
+}
+
+impl HostVTable {
+    fn new(context: &mut HostContext) -> Self {
+        Self {
+            abi_version: PLUGIN_ABI_VERSION,
+            context: context as *mut HostContext as *mut c_void,
+            stack_empty: host_stack_empty,
+            stack_push: host_stack_push,
+            stack_pop: host_stack_pop,
+            native: host_native,
+            send_message: host_send_message,
+            get_global: host_get_global,
+            set_global: host_set_global,
+            allocate: host_allocate,
+            raise_fault: host_raise_fault,
+            new_native_handle: host_new_native_handle,
+            free_value: free_ffi_value,
+        }
+    }
+}
+
+/// What the host vtable refers to while the plugin is called. Messages to native handles are
+/// sent without a stack.
+struct HostContext<'a> {
+    handle: &'a mut dyn VMHandle,
+    /// Whether the plugin can use the stack of the handle
+    with_stack: bool,
+    /// Values with native handles that were given to the plugin
+    lent: Vec<Value>,
+}
+
+impl<'a> HostContext<'a> {
+    fn new(handle: &'a mut dyn VMHandle, with_stack: bool) -> Self {
+        Self {
+            handle,
+            with_stack,
+            lent: vec![],
+        }
+    }
+
+    /// The stack of the virtual machine, if the plugin can use it
+    fn stack(&mut self) -> Option<&mut dyn Stack> {
+        match self.with_stack {
+            true => Some(self.handle.stack()),
+            false => None,
+        }
+    }
+
+    /// Writes a value for the plugin, returning false if there's no output. Native handles within
+    /// the value are kept alive until the plugin returns, so they can be matched with the copies
+    /// the plugin gives back.
+    fn lend(&mut self, output: *mut FfiValue, value: Value) -> bool {
+        if output.is_null() {
+            return false;
+        }
+        unsafe { output.write(FfiValue::from_value(&value)) };
+        if contains_native_handle(&value) {
+            self.lent.push(value);
+        }
+        true
+    }
+}
+
+fn contains_native_handle(value: &Value) -> bool {
+    match value {
+        Value::NativeHandle(_) => true,
+        Value::Array(values) => values.iter().any(contains_native_handle),
+        Value::Dictionary(dict) => dict.values().any(contains_native_handle),
+        Value::Reference(reference) => contains_native_handle(&reference.borrow()),
+        _ => false,
+    }
+}
+
+fn host_context<'a>(context: *mut c_void) -> &'a mut HostContext<'a> {
+    unsafe { &mut *(context as *mut HostContext) }
+}
+
+extern "C" fn host_stack_empty(context: *mut c_void) -> bool {
+    catch_unwind(AssertUnwindSafe(|| {
+        host_context(context)
+            .stack()
+            .is_none_or(|stack| stack.empty())
+    }))
+    .unwrap_or(true)
+}
+
+extern "C" fn host_stack_push(context: *mut c_void, value: *const FfiValue) {
+    if value.is_null() {
+        return;
+    }
+    let _ = catch_unwind(AssertUnwindSafe(|| {
+        if let (Ok(value), Some(stack)) = (
+            unsafe { (*value).to_value() },
+            host_context(context).stack(),
+        ) {
+            stack.push(value);
+        }
+    }));
+}
+
+extern "C" fn host_stack_pop(context: *mut c_void, output: *mut FfiValue) -> bool {
+    catch_unwind(AssertUnwindSafe(|| {
+        let context = host_context(context);
+        let mut popped = None;
+        if let Some(stack) = context.stack() {
+            stack.pop(&mut popped);
+        }
+        popped.is_some_and(|value| context.lend(output, value))
+    }))
+    .unwrap_or(false)
+}
+
+extern "C" fn host_native(
+    context: *mut c_void,
+    method: FfiStr,
+    args: *const FfiValue,
+    args_len: usize,
+    output: *mut FfiValue,
+) -> bool {
+    catch_unwind(AssertUnwindSafe(|| unsafe {
+        let method = method.to_str().ok()?;
+        let args = read_values(args, args_len).ok()?;
+        let context = host_context(context);
+        let mut result = None;
+        context.handle.native(method, &args, &mut result);
+        context.lend(output, result?);
+        Some(())
+    }))
+    .ok()
+    .flatten()
+    .is_some()
+}
+
+extern "C" fn host_send_message(
+    context: *mut c_void,
+    target: *const FfiValue,
+    message: FfiStr,
+    args: *const FfiValue,
+    args_len: usize,
+    output: *mut FfiValue,
+) -> FfiStatus {
+    let result = catch_unwind(AssertUnwindSafe(|| unsafe {
+        if target.is_null() {
+            return Err("no target for message".to_string());
+        }
+        let target = (*target).to_value().map_err(|e| e.to_string())?;
+        let message = message.to_str().map_err(|e| e.to_string())?;
+        let args = read_values(args, args_len).map_err(|e| e.to_string())?;
+        let mut result = None;
+        host_context(context)
+            .handle
+            .send_message(&target, message, &args, &mut result);
+        result.unwrap_or_else(|| Err(format!("no result for message {message:?}")))
+    }));
+    let (status, value) = match result {
+        Ok(Ok(value)) => (FfiStatus::OK, value),
+        Ok(Err(message)) => (FfiStatus::ERROR, Value::Str(message)),
+        Err(_) => (
+            FfiStatus::PANICKED,
+            Value::Str("virtual machine panicked".to_string()),
+        ),
+    };
+    host_context(context).lend(output, value);
+    status
+}
+
+extern "C" fn host_get_global(context: *mut c_void, var: usize, output: *mut FfiValue) -> bool {
+    catch_unwind(AssertUnwindSafe(|| {
+        let context = host_context(context);
+        let mut value = None;
+        context.handle.get_global(var, &mut value);
+        value.is_some_and(|value| context.lend(output, value))
+    }))
+    .unwrap_or(false)
+}
+
+extern "C" fn host_set_global(context: *mut c_void, var: usize, value: *const FfiValue) {
+    if value.is_null() {
+        return;
+    }
+    let _ = catch_unwind(AssertUnwindSafe(|| {
+        if let Ok(value) = unsafe { (*value).to_value() } {
+            host_context(context).handle.set_global(var, value);
+        }
+    }));
+}
+
+/// Objects can't be allocated through the C ABI. References are copied when they cross the
+/// boundary, so the plugin would never see what the virtual machine writes to the object, or the
+/// other way around.
+extern "C" fn host_allocate(
+    _context: *mut c_void,
+    _value: *const FfiValue,
+    _output: *mut FfiValue,
+) -> bool {
+    false
+}
+
+extern "C" fn host_raise_fault(context: *mut c_void, fault: FfiStr, detail: FfiStr) -> bool {
+    catch_unwind(AssertUnwindSafe(|| {
+        match unsafe { (fault.to_str(), detail.to_str()) } {
+            (Ok(fault), Ok(detail)) => host_context(context).handle.raise_fault(fault, detail),
+            _ => false,
+        }
+    }))
+    .unwrap_or(false)
+}
+
+extern "C" fn host_new_native_handle(
+    context: *mut c_void,
+    type_name: FfiStr,
+    id: u64,
+    output: *mut FfiValue,
+) -> bool {
+    catch_unwind(AssertUnwindSafe(|| {
+        let type_name = match unsafe { type_name.to_str() } {
+            Ok(type_name) => type_name,
+            Err(_) => return false,
+        };
+        let context = host_context(context);
+        let mut handle = None;
+        context.handle.new_native_handle(type_name, id, &mut handle);
+        handle.is_some_and(|handle| context.lend(output, handle))
+    }))
+    .unwrap_or(false)
+}
+
+/// Reads the values of an argument list
+///
+/// # Safety
+/// `values` must point to `len` valid values, or be null
+unsafe fn read_values(values: *const FfiValue, len: usize) -> Result<Vec<Value>, PluginError> {
+    if values.is_null() {
+        return Ok(vec![]);
+    }
+    std::slice::from_raw_parts(values, len)
+        .iter()
+        .map(|value| value.to_value())
+        .collect()
+}
+
+/// The stack of the virtual machine, as seen from a plugin
+struct HostStack<'h>(&'h HostVTable);
+
+impl Stack for HostStack<'_> {
+    fn empty(&self) -> bool {
+        (self.0.stack_empty)(self.0.context)
+    }
+
+    fn push(&mut self, value: Value) {
+        let value = FfiValue::from_value(&value);
+        (self.0.stack_push)(self.0.context, &value);
+        unsafe { value.free() };
+    }
+
+    fn pop(&mut self, output: &mut Option<Value>) {
+        let mut popped = FfiValue::empty();
+        *output = if (self.0.stack_pop)(self.0.context, &mut popped) {
+            let value = unsafe { popped.to_value() }.ok();
+            unsafe { (self.0.free_value)(&mut popped) };
+            value
+        } else {
+            None
+        };
+    }
+}
+
+/// The virtual machine, as seen from a plugin
+struct HostHandle<'h>(&'h HostVTable, HostStack<'h>);
+
+impl<'h> HostHandle<'h> {
+    fn new(host: &'h HostVTable) -> Self {
+        Self(host, HostStack(host))
+    }
+}
+
+impl VMHandle for HostHandle<'_> {
+    fn stack(&mut self) -> &mut dyn Stack {
+        &mut self.1
+    }
+
+    fn native(&mut self, method: &str, values: &[Value], output: &mut Option<Value>) {
+        let args = values.iter().map(FfiValue::from_value).collect::<Vec<_>>();
+        let mut result = FfiValue::empty();
+        let called = (self.0.native)(
+            self.0.context,
+            FfiStr::new(method),
+            args.as_ptr(),
+            args.len(),
+            &mut result,
+        );
+        for arg in args {
+            unsafe { arg.free() };
+        }
+        if called {
+            *output = unsafe { result.to_value() }.ok();
+            unsafe { (self.0.free_value)(&mut result) };
+        }
+    }
+
+    fn send_message(
+        &mut self,
+        target: &Value,
+        message: &str,
+        args: &[Value],
+        output: &mut Option<Result<Value, String>>,
+    ) {
+        let target = FfiValue::from_value(target);
+        let args = args.iter().map(FfiValue::from_value).collect::<Vec<_>>();
+        let mut result = FfiValue::empty();
+        let status = (self.0.send_message)(
+            self.0.context,
+            &target,
+            FfiStr::new(message),
+            args.as_ptr(),
+            args.len(),
+            &mut result,
+        );
+        unsafe { target.free() };
+        for arg in args {
+            unsafe { arg.free() };
+        }
+        let value = unsafe { result.to_value() }.map_err(|e| e.to_string());
+        unsafe { (self.0.free_value)(&mut result) };
+        *output = Some(match status {
+            FfiStatus::OK => value,
+            FfiStatus(status) => Err(match value {
+                Ok(Value::Str(message)) => message,
+                _ => format!("message failed with status {status}"),
+            }),
+        });
+    }
+
+    fn get_global(&mut self, var: usize, output: &mut Option<Value>) {
+        let mut value = FfiValue::empty();
+        *output = if (self.0.get_global)(self.0.context, var, &mut value) {
+            let global = unsafe { value.to_value() }.ok();
+            unsafe { (self.0.free_value)(&mut value) };
+            global
+        } else {
+            None
+        };
+    }
+
+    fn set_global(&mut self, var: usize, value: Value) {
+        let value = FfiValue::from_value(&value);
+        (self.0.set_global)(self.0.context, var, &value);
+        unsafe { value.free() };
+    }
+
+    fn allocate(&mut self, value: Value, output: &mut Option<Value>) {
+        let value = FfiValue::from_value(&value);
+        let mut allocated = FfiValue::empty();
+        let success = (self.0.allocate)(self.0.context, &value, &mut allocated);
+        unsafe { value.free() };
+        *output = if success {
+            let reference = unsafe { allocated.to_value() }.ok();
+            unsafe { (self.0.free_value)(&mut allocated) };
+            reference
+        } else {
+            None
+        };
+    }
+
+    fn raise_fault(&mut self, fault: &str, detail: &str) -> bool {
+        (self.0.raise_fault)(self.0.context, FfiStr::new(fault), FfiStr::new(detail))
+    }
+
+    fn new_native_handle(&mut self, type_name: &str, id: u64, output: &mut Option<Value>) {
+        let mut handle = FfiValue::empty();
+        *output = if (self.0.new_native_handle)(
+            self.0.context,
+            FfiStr::new(type_name),
+            id,
+            &mut handle,
+        ) {
+            let value = unsafe { handle.to_value() }.ok();
+            unsafe { (self.0.free_value)(&mut handle) };
+            value
+        } else {
+            None
+        };
+    }
+}
+
+/// A plugin that was created through the C ABI, usually from a dynamically loaded library
+pub struct DynamicPlugin {
+    vtable: PluginVTable,
+    labels: Vec<String>,
+    signatures: HashMap<String, LabelSignature>,
+}
+
+// The ABI requires plugins to be thread safe, just like the Plugin trait
+unsafe impl Send for DynamicPlugin {}
+unsafe impl Sync for DynamicPlugin {}
+
+impl DynamicPlugin {
+    /// Creates the plugin of a library, after checking that the library was built against the
+    /// same ABI version
+    ///
+    /// # Safety
+    /// The library must stay loaded for as long as the plugin exists
+    pub unsafe fn load(library: &Library, path: &Path) -> Result<Self, PluginError> {
+        let abi_version: Symbol<PluginAbiVersionFn> = library
+            .get(ABI_VERSION_SYMBOL)
+            .map_err(|_| PluginError::NotAPlugin(path.to_path_buf()))?;
+        let found = abi_version();
+        if found != PLUGIN_ABI_VERSION {
+            return Err(PluginError::AbiVersionMismatch {
+                path: path.to_path_buf(),
+                expected: PLUGIN_ABI_VERSION,
+                found,
+            });
+        }
+        let create: Symbol<PluginCreateFn> = library
+            .get(CREATE_SYMBOL)
+            .map_err(|_| PluginError::NotAPlugin(path.to_path_buf()))?;
+        let mut vtable = MaybeUninit::<PluginVTable>::uninit();
+        match create(PLUGIN_ABI_VERSION, vtable.as_mut_ptr()) {
+            FfiStatus::OK => Self::from_vtable(vtable.assume_init(), path),
+            FfiStatus::ABI_VERSION_MISMATCH => Err(PluginError::AbiVersionMismatch {
+                path: path.to_path_buf(),
+                expected: PLUGIN_ABI_VERSION,
+                found,
+            }),
+            FfiStatus(status) => Err(PluginError::CreationFailed {
+                path: path.to_path_buf(),
+                status,
+            }),
+        }
+    }
+
+    /// Wraps a vtable, reading the labels of the plugin and their signatures
+    ///
+    /// # Safety
+    /// The functions of the vtable must be valid for as long as the plugin exists
+    pub unsafe fn from_vtable(vtable: PluginVTable, path: &Path) -> Result<Self, PluginError> {
+        if vtable.abi_version != PLUGIN_ABI_VERSION {
+            let found = vtable.abi_version;
+            (vtable.destroy)(vtable.instance);
+            return Err(PluginError::AbiVersionMismatch {
+                path: path.to_path_buf(),
+                expected: PLUGIN_ABI_VERSION,
+                found,
+            });
+        }
+        let mut output = Self {
+            vtable,
+            labels: vec![],
+            signatures: HashMap::new(),
+        };
+        let count = (output.vtable.labels_count)(output.vtable.instance);
+        for index in 0..count {
+            let label = (output.vtable.label)(output.vtable.instance, index).to_str()?;
+            // the library may be unloaded before the labels are done being used, so they're copied
+            output.labels.push(label.to_string());
+            let mut result = FfiValue::empty();
+            let status =
+                (output.vtable.signature)(output.vtable.instance, FfiStr::new(label), &mut result);
+            match output.take_result(status, result) {
+                Ok(Value::Empty) => {}
+                Ok(Value::Str(signature)) => {
+                    output
+                        .signatures
+                        .insert(label.to_string(), signature.parse()?);
+                }
+                Ok(other) => {
+                    return Err(PluginError::InvalidSignature(format!(
+                        "{label}: expected a string, found {other}"
+                    )))
+                }
+                Err(e) => return Err(PluginError::InvalidSignature(format!("{label}: {e}"))),
+            }
+        }
+        Ok(output)
+    }
+
+    /// Converts the output of a call into the plugin, releasing the output
+    fn take_result(&self, status: FfiStatus, mut result: FfiValue) -> Result<Value, String> {
+        let value = unsafe { result.to_value() }.map_err(|e| e.to_string());
+        unsafe { (self.vtable.free_value)(&mut result) };
+        match status {
+            FfiStatus::OK => value,
+            FfiStatus(status) => Err(match value {
+                Ok(Value::Str(message)) => message,
+                _ => format!("plugin call failed with status {status}"),
+            }),
+        }
+    }
+}
+
+impl Plugin for DynamicPlugin {
+    fn labels<'s>(&'s self, buffer: &mut [&'s str]) {
+        for (slot, label) in buffer.iter_mut().zip(&self.labels) {
+            *slot = label;
+        }
+    }
+
+    fn labels_count(&self) -> i32 {
+        self.labels.len() as i32
+    }
+
+    fn call_label(
+        &self,
+        label: &str,
+        handle: &mut dyn VMHandle,
+        output: &mut Option<Result<Value, String>>,
+    ) {
+        let mut context = HostContext::new(handle, true);
+        let host = HostVTable::new(&mut context);
+        let mut result = FfiValue::empty();
+        let status =
+            (self.vtable.call_label)(self.vtable.instance, FfiStr::new(label), &host, &mut result);
+        *output = Some(self.take_result(status, result));
+    }
+
+    fn handle_message(
+        &self,
+        type_name: &str,
+        id: u64,
+        message: &str,
+        args: &[Value],
+        handle: &mut dyn VMHandle,
+        output: &mut Option<Result<Value, String>>,
+    ) {
+        let mut context = HostContext::new(handle, false);
+        let host = HostVTable::new(&mut context);
+        let args = args.iter().map(FfiValue::from_value).collect::<Vec<_>>();
+        let mut result = FfiValue::empty();
+        let status = (self.vtable.handle_message)(
+            self.vtable.instance,
+            FfiStr::new(type_name),
+            id,
+            FfiStr::new(message),
+            args.as_ptr(),
+            args.len(),
+            &host,
+            &mut result,
+        );
+        for arg in args {
+            unsafe { arg.free() };
+        }
+        *output = Some(self.take_result(status, result));
+    }
+
+    fn signature(&self, label: &str) -> Option<LabelSignature> {
+        self.signatures.get(label).cloned()
+    }
+
+    fn finalize_handle(&self, type_name: &str, id: u64) {
+        (self.vtable.finalize_handle)(self.vtable.instance, FfiStr::new(type_name), id);
+    }
+}
+
+impl Drop for DynamicPlugin {
+    fn drop(&mut self) {
+        (self.vtable.destroy)(self.vtable.instance);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use jodin_common::core::function_names::CALL;
+    use std::collections::HashMap;
+    use std::path::PathBuf;
+    use std::sync::atomic::{AtomicU64, Ordering};
+
+    static FINALIZED: AtomicU64 = AtomicU64::new(0);
+
+    #[derive(Default)]
+    struct Echo;
+
+    impl Plugin for Echo {
+        fn labels<'s>(&'s self, buffer: &mut [&'s str]) {
+            buffer[0] = "echo";
+            buffer[1] = "fail";
+            buffer[2] = "open";
+        }
+
+        fn labels_count(&self) -> i32 {
+            3
+        }
+
+        fn signature(&self, label: &str) -> Option<LabelSignature> {
+            match label {
+                "echo" => Some("fn(*char) -> *char".parse().unwrap()),
+                _ => None,
+            }
+        }
+
+        fn call_label(
+            &self,
+            label: &str,
+            handle: &mut dyn VMHandle,
+            output: &mut Option<Result<Value, String>>,
+        ) {
+            match label {
+                "echo" => {
+                    let mut popped = None;
+                    handle.stack().pop(&mut popped);
+                    let mut native = None;
+                    handle.native("twice", &[Value::UInteger(21)], &mut native);
+                    handle.stack().push(native.unwrap_or(Value::Empty));
+                    let mut called = None;
+                    handle.call(
+                        &Value::Function(AsmLocation::Label("callback".to_string())),
+                        &[Value::UInteger(1)],
+                        &mut called,
+                    );
+                    if let Some(Ok(called)) = called {
+                        handle.set_global(0, called);
+                    }
+                    handle.raise_fault("MissingSymbol", "callback");
+                    *output = Some(Ok(popped.unwrap_or(Value::Empty)));
+                }
+                "open" => {
+                    let mut file = None;
+                    handle.new_native_handle("file", 9, &mut file);
+                    *output = file.map(Ok);
+                }
+                _ => *output = Some(Err("failed on purpose".to_string())),
+            }
+        }
+
+        fn handle_message(
+            &self,
+            type_name: &str,
+            id: u64,
+            message: &str,
+            _args: &[Value],
+            _handle: &mut dyn VMHandle,
+            output: &mut Option<Result<Value, String>>,
+        ) {
+            *output = Some(Ok(Value::Str(format!("{type_name}#{id} {message}"))));
+        }
+
+        fn finalize_handle(&self, _type_name: &str, id: u64) {
+            FINALIZED.store(id, Ordering::SeqCst);
+        }
+    }
+
+    #[derive(Default)]
+    struct VecStack(Vec<Value>);
+
+    impl Stack for VecStack {
+        fn empty(&self) -> bool {
+            self.0.is_empty()
+        }
+
+        fn push(&mut self, value: Value) {
+            self.0.push(value)
+        }
+
+        fn pop(&mut self, output: &mut Option<Value>) {
+            *output = self.0.pop();
+        }
+    }
+
+    #[derive(Default)]
+    struct Doubler {
+        stack: VecStack,
+        globals: HashMap<usize, Value>,
+        faults: Vec<String>,
+    }
+
+    impl VMHandle for Doubler {
+        fn stack(&mut self) -> &mut dyn Stack {
+            &mut self.stack
+        }
+
+        fn native(&mut self, _method: &str, values: &[Value], output: &mut Option<Value>) {
+            if let Some(Value::UInteger(u)) = values.first() {
+                *output = Some(Value::UInteger(u * 2));
+            }
+        }
+
+        fn send_message(
+            &mut self,
+            target: &Value,
+            message: &str,
+            args: &[Value],
+            output: &mut Option<Result<Value, String>>,
+        ) {
+            *output = Some(Ok(Value::Array(vec![
+                target.clone(),
+                Value::Str(message.to_string()),
+                Value::Array(args.to_vec()),
+            ])));
+        }
+
+        fn get_global(&mut self, var: usize, output: &mut Option<Value>) {
+            *output = self.globals.get(&var).cloned();
+        }
+
+        fn set_global(&mut self, var: usize, value: Value) {
+            self.globals.insert(var, value);
+        }
+
+        fn allocate(&mut self, value: Value, output: &mut Option<Value>) {
+            *output = Some(Value::Reference(JRef::new(value)));
+        }
+
+        fn raise_fault(&mut self, fault: &str, detail: &str) -> bool {
+            self.faults.push(format!("{fault}({detail})"));
+            true
+        }
+
+        fn new_native_handle(&mut self, type_name: &str, id: u64, output: &mut Option<Value>) {
+            *output = Some(Value::NativeHandle(NativeHandle::new(
+                "echo",
+                type_name,
+                id,
+                |_, _| {},
+            )));
+        }
+    }
+
+    fn create(host_abi_version: u32) -> Result<DynamicPlugin, PluginError> {
+        let mut vtable = MaybeUninit::uninit();
+        match unsafe { export_plugin(host_abi_version, vtable.as_mut_ptr(), Echo::default) } {
+            FfiStatus::OK => unsafe {
+                DynamicPlugin::from_vtable(vtable.assume_init(), Path::new("echo"))
+            },
+            FfiStatus::ABI_VERSION_MISMATCH => Err(PluginError::AbiVersionMismatch {
+                path: PathBuf::from("echo"),
+                expected: host_abi_version,
+                found: PLUGIN_ABI_VERSION,
+            }),
+            FfiStatus(status) => Err(PluginError::CreationFailed {
+                path: PathBuf::from("echo"),
+                status,
+            }),
+        }
+    }
+
+    #[test]
+    fn values_survive_marshalling() {
+        let value = Value::Array(vec![
+            Value::Empty,
+            Value::Byte(3),
+            Value::Float(1.5),
+            Value::Integer(-4),
+            Value::Str("hello".to_string()),
+            Value::Dictionary(HashMap::from([("key".to_string(), Value::UInteger(5))])),
+            Value::Reference(JRef::new(Value::UInteger(6))),
+            Value::Function(AsmLocation::Label("main".to_string())),
+            Value::Function(AsmLocation::InstructionDiff(-2)),
+            Value::Native,
+        ]);
+        let marshalled = FfiValue::from_value(&value);
+        assert_eq!(unsafe { marshalled.to_value() }.unwrap(), value);
+        unsafe { marshalled.free() };
+    }
+
+    #[test]
+    fn call_through_vtable() {
+        let plugin = create(PLUGIN_ABI_VERSION).expect("plugin should be created");
+        assert_eq!(plugin.labels, vec!["echo", "fail", "open"]);
+        assert_eq!(
+            plugin
+                .signature("echo")
+                .map(|signature| signature.to_string()),
+            Some("fn(*char) -> *char".to_string())
+        );
+        assert_eq!(plugin.signature("fail"), None);
+
+        let mut handle = Doubler {
+            stack: VecStack(vec![Value::Str("input".to_string())]),
+            ..Doubler::default()
+        };
+        let mut output = None;
+        plugin.call_label("echo", &mut handle, &mut output);
+        assert_eq!(output, Some(Ok(Value::Str("input".to_string()))));
+        assert_eq!(handle.stack.0, vec![Value::UInteger(42)]);
+        assert_eq!(
+            handle.globals[&0],
+            Value::Array(vec![
+                Value::Function(AsmLocation::Label("callback".to_string())),
+                Value::Str(CALL.to_string()),
+                Value::Array(vec![Value::UInteger(1)]),
+            ])
+        );
+        assert_eq!(handle.faults, vec!["MissingSymbol(callback)"]);
+
+        plugin.call_label("fail", &mut handle, &mut output);
+        assert_eq!(output, Some(Err("failed on purpose".to_string())));
+    }
+
+    #[test]
+    fn native_handles_keep_their_identity() {
+        let plugin = create(PLUGIN_ABI_VERSION).expect("plugin should be created");
+        let mut output = None;
+        plugin.call_label("open", &mut Doubler::default(), &mut output);
+        let file = match output.take() {
+            Some(Ok(Value::NativeHandle(file))) => file,
+            other => panic!("expected a native handle, found {:?}", other),
+        };
+        // the handle created by the host is kept alive by the copy that came back
+        assert_eq!(NativeHandle::find("echo", 9), Some(file));
+
+        plugin.handle_message("file", 9, "read", &[], &mut Doubler::default(), &mut output);
+        assert_eq!(output, Some(Ok(Value::Str("file#9 read".to_string()))));
+        plugin.finalize_handle("file", 9);
+        assert_eq!(FINALIZED.load(Ordering::SeqCst), 9);
+    }
+
+    #[test]
+    fn objects_are_not_allocated_across_the_boundary() {
+        let mut handle = Doubler::default();
+        let mut context = HostContext::new(&mut handle, true);
+        let host = HostVTable::new(&mut context);
+        let value = FfiValue::from_value(&Value::UInteger(1));
+        let mut output = FfiValue::empty();
+
+        assert!(!(host.allocate)(host.context, &value, &mut output));
+        assert_eq!(unsafe { output.to_value() }.unwrap(), Value::Empty);
+        unsafe { value.free() };
+    }
+
+    #[test]
+    fn reject_other_abi_version() {
+        assert!(matches!(
+            create(PLUGIN_ABI_VERSION + 1),
+            Err(PluginError::AbiVersionMismatch { .. })
+        ));
+    }
+
+    /// A virtual machine that panics whenever it's used
+    struct Panicking;
+
+    impl Stack for Panicking {
+        fn empty(&self) -> bool {
+            panic!("stack used")
+        }
+
+        fn push(&mut self, _value: Value) {
+            panic!("stack used")
+        }
+
+        fn pop(&mut self, _output: &mut Option<Value>) {
+            panic!("stack used")
+        }
+    }
+
+    impl VMHandle for Panicking {
+        fn stack(&mut self) -> &mut dyn Stack {
+            self
+        }
+
+        fn native(&mut self, _method: &str, _values: &[Value], _output: &mut Option<Value>) {
+            panic!("handle used")
+        }
+
+        fn send_message(
+            &mut self,
+            _target: &Value,
+            _message: &str,
+            _args: &[Value],
+            _output: &mut Option<Result<Value, String>>,
+        ) {
+            panic!("handle used")
+        }
+
+        fn get_global(&mut self, _var: usize, _output: &mut Option<Value>) {
+            panic!("handle used")
+        }
+
+        fn set_global(&mut self, _var: usize, _value: Value) {
+            panic!("handle used")
+        }
+
+        fn allocate(&mut self, _value: Value, _output: &mut Option<Value>) {
+            panic!("handle used")
+        }
+
+        fn raise_fault(&mut self, _fault: &str, _detail: &str) -> bool {
+            panic!("handle used")
+        }
+
+        fn new_native_handle(&mut self, _type_name: &str, _id: u64, _output: &mut Option<Value>) {
+            panic!("handle used")
+        }
+    }
+
+    #[test]
+    fn host_panics_do_not_unwind_into_plugins() {
+        let mut handle = Panicking;
+        let mut context = HostContext::new(&mut handle, true);
+        let host = HostVTable::new(&mut context);
+        let value = FfiValue::from_value(&Value::UInteger(1));
+        let mut output = FfiValue::empty();
+
+        assert!((host.stack_empty)(host.context));
+        (host.stack_push)(host.context, &value);
+        assert!(!(host.stack_pop)(host.context, &mut output));
+        assert!(!(host.native)(
+            host.context,
+            FfiStr::new("print"),
+            &value,
+            1,
+            &mut output
+        ));
+        assert!(!(host.get_global)(host.context, 0, &mut output));
+        (host.set_global)(host.context, 0, &value);
+        assert!(!(host.allocate)(host.context, &value, &mut output));
+        assert!(!(host.raise_fault)(
+            host.context,
+            FfiStr::new("MissingSymbol"),
+            FfiStr::new("")
+        ));
+        assert!(!(host.new_native_handle)(
+            host.context,
+            FfiStr::new("file"),
+            1,
+            &mut output
+        ));
+    }
+}
@@ -1,9 +1,15 @@
 //! Plugins for the VM!
 
+#[macro_use]
+extern crate serde_derive;
+
+pub mod discovery;
 pub mod error;
 pub use error::PluginError as Error;
+pub mod ffi;
 use jodin_common::assembly::value::Value;
 
 pub mod plugins;
+pub mod signatures;
 use crate::plugins::VMHandle;
 pub use plugins::Plugin;
@@ -1,11 +1,18 @@
 use crate::error::PluginError;
+use crate::ffi::DynamicPlugin;
+use crate::signatures::{write_signature_object, LabelSignature};
+use jodin_common::assembly::native_handle::NativeHandle;
 use jodin_common::assembly::value::Value;
-use libloading::{Library, Symbol};
+use jodin_common::core::function_names::CALL;
+use libloading::Library;
 use std::any::Any;
-use std::collections::hash_map::Entry;
 use std::collections::HashMap;
-use std::ffi::{CStr, OsStr};
-use std::path::Path;
+use std::ffi::OsStr;
+use std::fmt::{Display, Formatter};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
 use uuid::Uuid;
 
 /// A reference object for pushing, popping, and peeking the stack
@@ -22,23 +29,98 @@ pub trait Stack {
     fn pop(&mut self, output: &mut Option<Value>);
 }
 
+/// A handle to the virtual machine that called a plugin
 pub trait VMHandle {
+    /// The stack of the virtual machine, which the arguments of a label are popped from
+    fn stack(&mut self) -> &mut dyn Stack;
+
+    /// Calls a native method, with the value it returns as the output. Methods starting with `@`
+    /// return an empty value. The output is left as `None` if the method fails.
     fn native(&mut self, method: &str, values: &[Value], output: &mut Option<Value>);
+
+    /// Sends a message to a value. If the message runs guest code, the virtual machine runs until
+    /// that code returns.
+    fn send_message(
+        &mut self,
+        target: &Value,
+        message: &str,
+        args: &[Value],
+        output: &mut Option<Result<Value, String>>,
+    );
+
+    /// Calls a guest callable, such as a function, with some arguments
+    fn call(
+        &mut self,
+        callable: &Value,
+        args: &[Value],
+        output: &mut Option<Result<Value, String>>,
+    ) {
+        self.send_message(callable, CALL, args, output)
+    }
+
+    /// Gets the value of a global variable
+    fn get_global(&mut self, var: usize, output: &mut Option<Value>);
+
+    /// Sets the value of a global variable
+    fn set_global(&mut self, var: usize, value: Value);
+
+    /// Allocates an object, returning a reference to it. Plugins loaded through the
+    /// [C ABI](crate::ffi) can't allocate objects, since references are copied when they cross it.
+    fn allocate(&mut self, value: Value, output: &mut Option<Value>);
+
+    /// Raises a fault once the plugin returns. Faults are named like the faults of the virtual
+    /// machine, such as `MissingSymbol`.
+    ///
+    /// Returns false if the fault is unknown.
+    fn raise_fault(&mut self, fault: &str, detail: &str) -> bool;
+
+    /// Creates a [native handle](jodin_common::assembly::native_handle) owned by the calling
+    /// plugin. Messages sent to the handle are given to [Plugin::handle_message], and
+    /// [Plugin::finalize_handle] is called once the handle is dropped.
+    fn new_native_handle(&mut self, type_name: &str, id: u64, output: &mut Option<Value>);
 }
 
 /// A plugin which allows you to add functionality to the jodin VM
 pub trait Plugin: Any + Send + Sync {
-    fn labels(&self, buffer: &mut [&'static str]);
+    fn labels<'s>(&'s self, buffer: &mut [&'s str]);
 
     fn labels_count(&self) -> i32;
 
     fn call_label(
         &self,
         label: &str,
-        stack: &mut dyn Stack,
         handle: &mut dyn VMHandle,
         output: &mut Option<Result<Value, String>>,
     );
+
+    /// The parameter and return types of a label, which lets the compiler check calls to it.
+    /// Labels without a signature can't be declared as `extern`.
+    fn signature(&self, label: &str) -> Option<LabelSignature> {
+        let _ = label;
+        None
+    }
+
+    /// Receives a message sent to one of the native handles of this plugin
+    fn handle_message(
+        &self,
+        type_name: &str,
+        id: u64,
+        message: &str,
+        args: &[Value],
+        handle: &mut dyn VMHandle,
+        output: &mut Option<Result<Value, String>>,
+    ) {
+        let _ = (id, args, handle);
+        *output = Some(Err(format!(
+            "{type_name} handles don't accept messages (message = {message})"
+        )));
+    }
+
+    /// Called once the virtual machine drops the last copy of one of the native handles of this
+    /// plugin, so the resource behind it can be released
+    fn finalize_handle(&self, type_name: &str, id: u64) {
+        let _ = (type_name, id);
+    }
 }
 
 pub trait LoadablePlugin: Plugin {
@@ -51,108 +133,530 @@ impl<D: Default + Plugin> LoadablePlugin for D {
     }
 }
 
+/// The id of a plugin loaded by a [PluginManager]
+///
+/// A plugin keeps its id when it's reloaded, so native handles created by the plugin stay owned
+/// by it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct PluginId(Uuid);
+
+impl Display for PluginId {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl FromStr for PluginId {
+    type Err = uuid::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Uuid::parse_str(s).map(PluginId)
+    }
+}
+
+/// What happens when a plugin registers a label that was already registered by another plugin
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum LabelConflict {
+    /// The plugin isn't registered, and [PluginError::LabelConflict] is returned
+    #[default]
+    Reject,
+    /// The new plugin takes the label over. The label goes back to the old plugin if the new one
+    /// is unloaded.
+    Replace,
+    /// The label stays with the plugin that registered it first
+    Keep,
+}
+
+/// A dynamic library that a plugin was loaded from
+struct LoadedLibrary {
+    /// The path the plugin was loaded from, which is used when the plugin is reloaded
+    path: PathBuf,
+    /// The copy of the library that was actually opened, if any
+    shadow: Option<PathBuf>,
+    generation: usize,
+    library: Option<Library>,
+}
+
+impl Drop for LoadedLibrary {
+    fn drop(&mut self) {
+        // the library has to be closed before its copy can be removed
+        drop(self.library.take());
+        if let Some(shadow) = &self.shadow {
+            let _ = fs::remove_file(shadow);
+        }
+    }
+}
+
+/// A plugin, along with the library it was loaded from
+struct LoadedPlugin {
+    // the plugin is dropped before the library, so the library outlives the plugin created by it
+    plugin: Box<dyn Plugin>,
+    library: Option<LoadedLibrary>,
+}
+
+impl LoadedPlugin {
+    fn new(plugin: Box<dyn Plugin>) -> Self {
+        Self {
+            plugin,
+            library: None,
+        }
+    }
+}
+
+/// A loaded plugin, which can be called without keeping its [PluginManager] borrowed. The plugin
+/// and its library stay loaded until the last reference is dropped, even if the plugin is
+/// unloaded or reloaded in the meantime.
+#[derive(Clone)]
+pub struct PluginRef {
+    id: PluginId,
+    loaded: Arc<LoadedPlugin>,
+}
+
+impl PluginRef {
+    /// The id of the plugin. Native handles created by the plugin are owned by this id.
+    pub fn id(&self) -> PluginId {
+        self.id
+    }
+
+    /// Calls one of the labels of the plugin
+    pub fn call_label<V: VMHandle>(
+        &self,
+        label: &str,
+        handle: &mut V,
+    ) -> Result<Value, PluginError> {
+        let mut output = None;
+        self.loaded.plugin.call_label(label, handle, &mut output);
+        output
+            .unwrap_or_else(|| Err(format!("no result for label {label:?}")))
+            .map_err(PluginError::FunctionError)
+    }
+
+    /// Sends a message to one of the native handles of the plugin
+    pub fn handle_message<V: VMHandle>(
+        &self,
+        native_handle: &NativeHandle,
+        message: &str,
+        args: &[Value],
+        handle: &mut V,
+    ) -> Result<Value, PluginError> {
+        let mut output = None;
+        self.loaded.plugin.handle_message(
+            native_handle.type_name(),
+            native_handle.id(),
+            message,
+            args,
+            handle,
+            &mut output,
+        );
+        output
+            .unwrap_or_else(|| Err(format!("no result for message {message:?}")))
+            .map_err(PluginError::FunctionError)
+    }
+}
+
+/// Native handles that were dropped while their plugin manager was locked for writing. The plugin
+/// manager finalizes them once it can be used again.
+#[derive(Debug, Default, Clone)]
+pub struct PendingFinalizations(Arc<Mutex<Vec<(String, String, u64)>>>);
+
+impl PendingFinalizations {
+    /// Queues the finalization of a native handle
+    pub fn push(&self, owner: &str, type_name: &str, id: u64) {
+        self.lock()
+            .push((owner.to_string(), type_name.to_string(), id));
+    }
+
+    fn take(&self) -> Vec<(String, String, u64)> {
+        std::mem::take(&mut *self.lock())
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Vec<(String, String, u64)>> {
+        // the queue is only pushed to and taken from, so it's never left half changed
+        self.0
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
 /// The plugin manager for VMS
 pub struct PluginManager {
-    plugins: HashMap<Uuid, Box<dyn Plugin>>,
-    loaded_labels: HashMap<String, Uuid>,
-    loaded_libraries: Vec<Library>,
+    plugins: HashMap<PluginId, Arc<LoadedPlugin>>,
+    /// The ids of the loaded plugins, in the order they were loaded
+    load_order: Vec<PluginId>,
+    loaded_labels: HashMap<String, PluginId>,
+    label_conflict: LabelConflict,
+    pending_finalizations: PendingFinalizations,
 }
 
 impl PluginManager {
     pub fn new() -> Self {
         Self {
             plugins: HashMap::new(),
+            load_order: vec![],
             loaded_labels: HashMap::new(),
-            loaded_libraries: Vec::new(),
+            label_conflict: LabelConflict::default(),
+            pending_finalizations: PendingFinalizations::default(),
         }
     }
 
+    /// Sets what happens when a plugin registers a label that's already registered
+    pub fn set_label_conflict(&mut self, label_conflict: LabelConflict) {
+        self.label_conflict = label_conflict;
+    }
+
     /// Loads a plugin for the virtual machine
     ///
-    /// If successful, returns the count of created labels
+    /// If successful, returns the id of the plugin
     ///
     /// # Error
     ///
-    /// Errors if the library was invalid or a plugin couldn't be created.
+    /// Errors if the file doesn't exist, the library was invalid, the library was built against a
+    /// different [plugin ABI](crate::ffi), a plugin couldn't be created, or one of its labels
+    /// conflicts with another plugin.
     pub unsafe fn load_plugin<P: AsRef<OsStr>>(
         &mut self,
         filename: P,
-    ) -> Result<usize, PluginError> {
-        type PluginCreate = unsafe fn() -> *mut dyn Plugin;
-
-        let filename = filename.as_ref();
-        let path = Path::new(filename);
+    ) -> Result<PluginId, PluginError> {
+        let path = Path::new(filename.as_ref());
         if !path.exists() {
-            panic!("{:?} does not exist!", path)
+            return Err(PluginError::PluginNotFound(path.to_path_buf()));
         }
-        let lib = Library::new(filename)?;
-        self.loaded_libraries.push(lib);
-
-        let lib = self.loaded_libraries.last().unwrap();
-        let constructor: Symbol<PluginCreate> = lib.get(b"_plugin_create")?;
-        let boxed_raw = (*constructor)();
-        let plugin = Box::from_raw(boxed_raw);
-        Ok(self.register_plugin(plugin))
+        let lib = Library::new(path)?;
+        let plugin = DynamicPlugin::load(&lib, path)?;
+        self.register_plugin(LoadedPlugin {
+            plugin: Box::new(plugin),
+            library: Some(LoadedLibrary {
+                path: path.to_path_buf(),
+                shadow: None,
+                generation: 0,
+                library: Some(lib),
+            }),
+        })
     }
 
-    pub fn with_plugin<P: Plugin>(&mut self, plugin: P) -> usize {
-        let plugin: Box<dyn Plugin> = Box::new(plugin);
-        self.register_plugin(plugin)
+    pub fn with_plugin<P: Plugin>(&mut self, plugin: P) -> Result<PluginId, PluginError> {
+        self.register_plugin(LoadedPlugin::new(Box::new(plugin)))
     }
 
-    fn register_plugin(&mut self, plugin: Box<dyn Plugin>) -> usize {
-        let uuid = loop {
-            let uuid = Uuid::new_v4();
-            if !self.plugins.contains_key(&uuid) {
-                break uuid;
+    fn register_plugin(&mut self, plugin: LoadedPlugin) -> Result<PluginId, PluginError> {
+        let id = loop {
+            let id = PluginId(Uuid::new_v4());
+            if !self.plugins.contains_key(&id) {
+                break id;
             }
         };
-        self.plugins.insert(uuid, plugin);
-        let plugin = self.plugins.get(&uuid).unwrap();
-        let mut count = plugin.labels_count() as usize;
-        let mut buffer = vec![""; count];
-        plugin.labels(&mut buffer);
-        for label in buffer {
-            self.loaded_labels.insert(label.to_string(), uuid);
-            count += 1;
+        let labels = plugin_labels(&*plugin.plugin)?;
+        self.check_labels(id, &labels)?;
+        self.plugins.insert(id, Arc::new(plugin));
+        self.load_order.push(id);
+        self.claim_labels(id, labels);
+        self.finalize_pending();
+        Ok(id)
+    }
+
+    /// Replaces the implementation of a loaded plugin, keeping its id. Labels the new plugin no
+    /// longer has are removed.
+    pub fn replace_plugin<P: Plugin>(
+        &mut self,
+        id: PluginId,
+        plugin: P,
+    ) -> Result<(), PluginError> {
+        self.replace_loaded_plugin(id, LoadedPlugin::new(Box::new(plugin)))
+    }
+
+    fn replace_loaded_plugin(
+        &mut self,
+        id: PluginId,
+        plugin: LoadedPlugin,
+    ) -> Result<(), PluginError> {
+        if !self.plugins.contains_key(&id) {
+            return Err(PluginError::PluginNotLoaded(id.to_string()));
         }
-        count
+        let labels = plugin_labels(&*plugin.plugin)?;
+        self.check_labels(id, &labels)?;
+        drop(self.plugins.insert(id, Arc::new(plugin)));
+        self.loaded_labels.retain(|_, owner| *owner != id);
+        self.claim_labels(id, labels);
+        self.restore_labels();
+        self.finalize_pending();
+        Ok(())
+    }
+
+    /// Unloads a plugin, removing its labels. Labels the plugin took over from other plugins are
+    /// given back to them.
+    pub fn unload_plugin(&mut self, id: PluginId) -> Result<(), PluginError> {
+        // the plugin finalizes its handles before it's gone
+        self.finalize_pending();
+        let plugin = self
+            .plugins
+            .remove(&id)
+            .ok_or_else(|| PluginError::PluginNotLoaded(id.to_string()))?;
+        drop(plugin);
+        self.load_order.retain(|loaded| *loaded != id);
+        self.loaded_labels.retain(|_, owner| *owner != id);
+        self.restore_labels();
+        self.finalize_pending();
+        Ok(())
+    }
+
+    /// Loads the library of a plugin again, so changes to the library are picked up without
+    /// restarting the virtual machine. The plugin keeps its id.
+    ///
+    /// The library is copied before it's opened, because most platforms won't open a library
+    /// again while it's still open. If the new library can't be loaded, the old one stays loaded.
+    ///
+    /// # Error
+    ///
+    /// Errors if the plugin wasn't loaded from a library, or the library can't be loaded.
+    ///
+    /// # Safety
+    ///
+    /// The old library is unloaded once the calls into it that are still running return, so
+    /// nothing may refer to its code or data afterwards. The caller must make sure that:
+    /// - no guest frames or plugin calls are running code of the old library, other than through
+    ///   the plugin manager,
+    /// - no [native handles](VMHandle::new_native_handle) created by the old library are still
+    ///   alive, as they would be finalized by the new library, which never created them,
+    /// - the old library didn't hand out pointers to its code or data, such as callbacks or
+    ///   threads it spawned, that are still in use.
+    ///
+    /// The initialization routines of the new library are run when it's loaded, so they must be
+    /// sound to run, as with [`load_plugin`](Self::load_plugin).
+    pub unsafe fn reload_plugin(&mut self, id: PluginId) -> Result<(), PluginError> {
+        let loaded = self
+            .plugins
+            .get(&id)
+            .and_then(|plugin| plugin.library.as_ref())
+            .ok_or_else(|| PluginError::NotDynamic(id.to_string()))?;
+        let path = loaded.path.clone();
+        if !path.exists() {
+            return Err(PluginError::PluginNotFound(path));
+        }
+        let generation = loaded.generation + 1;
+        let file_name = path.file_name().unwrap_or_else(|| OsStr::new("plugin"));
+        let shadow = std::env::temp_dir().join(format!(
+            "jodin-plugin-{}-{}-{}",
+            id,
+            generation,
+            file_name.to_string_lossy()
+        ));
+        fs::copy(&path, &shadow).map_err(|e| PluginError::ReloadFailed {
+            path: path.clone(),
+            reason: e.to_string(),
+        })?;
+        // removes the copy if anything below fails
+        let mut loaded = LoadedLibrary {
+            path,
+            shadow: Some(shadow.clone()),
+            generation,
+            library: None,
+        };
+        let lib = Library::new(&shadow)?;
+        let plugin = DynamicPlugin::load(&lib, &loaded.path);
+        loaded.library = Some(lib);
+        self.replace_loaded_plugin(
+            id,
+            LoadedPlugin {
+                plugin: Box::new(plugin?),
+                library: Some(loaded),
+            },
+        )
+    }
+    /// Checks that a plugin can register labels
+    fn check_labels(&self, id: PluginId, labels: &[String]) -> Result<(), PluginError> {
+        if self.label_conflict != LabelConflict::Reject {
+            return Ok(());
+        }
+        for label in labels {
+            match self.loaded_labels.get(label) {
+                Some(owner) if *owner != id => {
+                    return Err(PluginError::LabelConflict {
+                        label: label.clone(),
+                        owner: owner.to_string(),
+                    })
+                }
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+
+    fn claim_labels(&mut self, id: PluginId, labels: Vec<String>) {
+        for label in labels {
+            match self.label_conflict {
+                LabelConflict::Keep => {
+                    self.loaded_labels.entry(label).or_insert(id);
+                }
+                LabelConflict::Reject | LabelConflict::Replace => {
+                    self.loaded_labels.insert(label, id);
+                }
+            }
+        }
+    }
+
+    /// Gives labels that no plugin has back to the plugins that registered them. When several
+    /// plugins registered a label, it goes to the plugin that would have it had they been loaded
+    /// in the same order again.
+    fn restore_labels(&mut self) {
+        let mut order = self.load_order.clone();
+        if self.label_conflict == LabelConflict::Replace {
+            order.reverse();
+        }
+        for id in order {
+            // the labels of a loaded plugin were already read once
+            for label in plugin_labels(&*self.plugins[&id].plugin).unwrap_or_default() {
+                self.loaded_labels.entry(label).or_insert(id);
+            }
+        }
+    }
+
+    /// The ids of every loaded plugin, in the order they were loaded
+    pub fn plugins(&self) -> impl Iterator<Item = PluginId> + '_ {
+        self.load_order.iter().copied()
+    }
+
+    /// The labels that are currently registered to a plugin
+    pub fn labels_of(&self, id: PluginId) -> Vec<&str> {
+        let mut labels = self
+            .loaded_labels
+            .iter()
+            .filter(|(_, owner)| **owner == id)
+            .map(|(label, _)| label.as_str())
+            .collect::<Vec<_>>();
+        labels.sort_unstable();
+        labels
     }
 
     pub fn loaded_label<S: AsRef<str>>(&self, label: S) -> bool {
         self.loaded_labels.contains_key(label.as_ref())
     }
 
-    pub fn call_function<S: Stack, V: VMHandle>(
-        &self,
-        label: &str,
-        stack: &mut S,
-        handle: &mut V,
-    ) -> Result<Value, PluginError> {
-        let uuid = self
+    /// The signatures of every registered label that has one, ordered by label
+    pub fn signatures(&self) -> Vec<(String, LabelSignature)> {
+        let mut signatures = self
             .loaded_labels
+            .iter()
+            .filter_map(|(label, id)| {
+                self.plugins[id]
+                    .plugin
+                    .signature(label)
+                    .map(|signature| (label.clone(), signature))
+            })
+            .collect::<Vec<_>>();
+        signatures.sort_by(|(left, _), (right, _)| left.cmp(right));
+        signatures
+    }
+
+    /// Writes the signatures of every registered label to a `.jobj` file, which can be given to
+    /// the compiler so `extern` declarations are checked against the labels
+    pub fn write_signature_object<P: AsRef<Path>>(&self, path: P) -> Result<(), PluginError> {
+        let signatures = self.signatures();
+        write_signature_object(
+            path,
+            signatures
+                .iter()
+                .map(|(label, signature)| (label.as_str(), signature)),
+        )
+    }
+
+    /// Gets the id of the plugin that registered a label. Native handles created by the plugin
+    /// are owned by this id.
+    pub fn plugin_of<S: AsRef<str>>(&self, label: S) -> Option<PluginId> {
+        self.loaded_labels.get(label.as_ref()).copied()
+    }
+
+    /// Gets the plugin that registered a label
+    pub fn plugin_for_label(&self, label: &str) -> Result<PluginRef, PluginError> {
+        self.loaded_labels
             .get(label)
-            .ok_or(PluginError::LabelNotRegister(label.to_string()))?;
+            .and_then(|id| self.plugin_ref(*id))
+            .ok_or_else(|| PluginError::LabelNotRegister(label.to_string()))
+    }
 
-        let plugin = self.plugins.get(uuid).unwrap();
-        let stack: &mut dyn Stack = stack;
-        let mut output: Option<Result<_, _>> = None;
-        plugin.call_label(label, stack, handle, &mut output);
-        output
-            .unwrap()
-            .map_err(|s| PluginError::FunctionError(unsafe { s }))
+    /// Gets the plugin that owns a native handle
+    pub fn plugin_for_handle(
+        &self,
+        native_handle: &NativeHandle,
+    ) -> Result<PluginRef, PluginError> {
+        native_handle
+            .owner()
+            .parse::<PluginId>()
+            .ok()
+            .and_then(|id| self.plugin_ref(id))
+            .ok_or_else(|| PluginError::HandleOwnerMissing(native_handle.owner().to_string()))
+    }
+
+    fn plugin_ref(&self, id: PluginId) -> Option<PluginRef> {
+        self.plugins.get(&id).map(|loaded| PluginRef {
+            id,
+            loaded: loaded.clone(),
+        })
+    }
+
+    /// Lets the owner of a native handle release the resource behind it. Does nothing if the
+    /// owner is no longer loaded.
+    pub fn finalize_handle(&self, owner: &str, type_name: &str, id: u64) {
+        if let Some(loaded) = owner
+            .parse::<PluginId>()
+            .ok()
+            .and_then(|id| self.plugins.get(&id))
+        {
+            loaded.plugin.finalize_handle(type_name, id);
+        }
+    }
+
+    /// The queue that native handles are put in when they're dropped while the plugin manager is
+    /// locked for writing
+    pub fn pending_finalizations(&self) -> PendingFinalizations {
+        self.pending_finalizations.clone()
+    }
+
+    /// Finalizes the native handles that were dropped while the plugin manager was locked
+    pub fn finalize_pending(&self) {
+        // finalizing a handle can drop other handles, which are queued again
+        loop {
+            let pending = self.pending_finalizations.take();
+            if pending.is_empty() {
+                break;
+            }
+            for (owner, type_name, id) in pending {
+                self.finalize_handle(&owner, &type_name, id);
+            }
+        }
     }
 }
 
+impl Drop for PluginManager {
+    fn drop(&mut self) {
+        self.finalize_pending();
+    }
+}
+
+/// Gets the labels a plugin wants to register
+fn plugin_labels(plugin: &dyn Plugin) -> Result<Vec<String>, PluginError> {
+    let count = plugin.labels_count();
+    let count = usize::try_from(count).map_err(|_| PluginError::InvalidLabelCount(count))?;
+    let mut buffer = vec![""; count];
+    plugin.labels(&mut buffer);
+    Ok(buffer.into_iter().map(str::to_string).collect())
+}
+
+/// Exports a plugin from a dynamic library through the [plugin ABI](crate::ffi)
 #[macro_export]
 macro_rules! declare_plugin {
     ($plugin_type:ty, $constructor:path) => {
         #[no_mangle]
-        pub extern "C" fn _plugin_create() -> *mut dyn $crate::Plugin {
+        pub extern "C" fn _jodin_plugin_abi_version() -> u32 {
+            $crate::ffi::PLUGIN_ABI_VERSION
+        }
+
+        #[no_mangle]
+        pub unsafe extern "C" fn _jodin_plugin_create(
+            host_abi_version: u32,
+            output: *mut $crate::ffi::PluginVTable,
+        ) -> $crate::ffi::FfiStatus {
             let constructor: fn() -> $plugin_type = $constructor;
-            let object = (constructor)();
-            let boxed: Box<dyn $crate::Plugin> = Box::new(object);
-            Box::into_raw(boxed)
+            $crate::ffi::export_plugin(host_abi_version, output, constructor)
         }
     };
 }
@@ -0,0 +1,149 @@
+//! The types of plugin labels, which let the compiler check calls into plugins.
+//!
+//! A plugin describes each of its labels with a [LabelSignature]. The signatures of every loaded
+//! plugin can be turned into a `.jobj` containing one
+//! [translation unit](jodin_common::unit::TranslationUnit) per label. Given that object, the
+//! compiler binds `extern` declarations to the labels and checks their types:
+//! ```jodin
+//! extern const print_line: fn(int) -> void;
+//! ```
+
+use crate::error::PluginError;
+use jodin_common::compilation::{Compilable, Context, PaddedWriter, Target};
+use jodin_common::core::privacy::Visibility;
+use jodin_common::identifier::Identifier;
+use jodin_common::types::intermediate_type::{IntermediateType, TypeTail};
+use jodin_common::unit::{CompilationObject, TranslationUnit};
+use std::fmt::{Display, Formatter};
+use std::fs;
+use std::path::Path;
+use std::str::FromStr;
+
+/// The parameter and return types of a plugin label
+#[derive(Debug, Clone, PartialEq)]
+pub struct LabelSignature {
+    pub parameters: Vec<IntermediateType>,
+    pub returns: IntermediateType,
+}
+
+impl LabelSignature {
+    pub fn new<I: IntoIterator<Item = IntermediateType>>(
+        parameters: I,
+        returns: IntermediateType,
+    ) -> Self {
+        Self {
+            parameters: parameters.into_iter().collect(),
+            returns,
+        }
+    }
+
+    /// The function type of the label, such as `fn(int) -> void`
+    pub fn function_type(&self) -> IntermediateType {
+        self.returns
+            .clone()
+            .with_function_params(self.parameters.iter().cloned())
+    }
+
+    /// Gets the signature of a function type. Returns `None` if the type isn't a function.
+    pub fn from_function_type(function_type: &IntermediateType) -> Option<Self> {
+        let mut returns = function_type.clone();
+        match returns.tails.pop() {
+            Some(TypeTail::Function(parameters)) => Some(Self {
+                parameters,
+                returns,
+            }),
+            _ => None,
+        }
+    }
+
+    /// The translation unit that declares a label with this signature
+    pub fn translation_unit(&self, label: &str) -> TranslationUnit {
+        TranslationUnit::new(Visibility::Public, self.function_type(), label)
+    }
+}
+
+impl Display for LabelSignature {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.function_type())
+    }
+}
+
+impl FromStr for LabelSignature {
+    type Err = PluginError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let function_type: IntermediateType = s
+            .parse()
+            .map_err(|e| PluginError::InvalidSignature(format!("{s:?}: {e}")))?;
+        Self::from_function_type(&function_type)
+            .ok_or_else(|| PluginError::InvalidSignature(format!("{s:?} is not a function type")))
+    }
+}
+
+/// The target of signature objects, which only contain translation units
+struct SignatureTable;
+impl Target for SignatureTable {}
+
+/// Creates the compilation object that declares labels. The object has no instructions.
+pub fn signature_object<'a, P, I>(path: P, signatures: I) -> CompilationObject
+where
+    P: AsRef<Path>,
+    I: IntoIterator<Item = (&'a str, &'a LabelSignature)>,
+{
+    let units = signatures
+        .into_iter()
+        .map(|(label, signature)| signature.translation_unit(label))
+        .collect();
+    CompilationObject::new(
+        path.as_ref().to_path_buf(),
+        Identifier::empty(),
+        units,
+        vec![],
+    )
+}
+
+/// Writes a compilation object that declares labels to a file
+pub fn write_signature_object<'a, P, I>(path: P, signatures: I) -> Result<(), PluginError>
+where
+    P: AsRef<Path>,
+    I: IntoIterator<Item = (&'a str, &'a LabelSignature)>,
+{
+    let path = path.as_ref();
+    let object = signature_object(path, signatures);
+    let mut buffer = vec![];
+    let mut writer = PaddedWriter::new(&mut buffer);
+    let error = |reason: String| PluginError::SignatureObjectFailed {
+        path: path.to_path_buf(),
+        reason,
+    };
+    Compilable::<SignatureTable>::compile(object, &Context::new(), &mut writer)
+        .map_err(|e| error(e.to_string()))?;
+    drop(writer);
+    fs::write(path, buffer).map_err(|e| error(e.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use jodin_common::parsing::parse_type;
+    use jodin_common::types::primitives::Primitive;
+    use jodin_common::types::Type;
+
+    #[test]
+    fn signature_matches_parsed_type() {
+        let signature = LabelSignature::new(
+            [
+                Primitive::Int.as_intermediate(),
+                Primitive::Char.as_intermediate().with_pointer(),
+            ],
+            Primitive::Void.as_intermediate(),
+        );
+        let parsed = parse_type("fn(int, *char) -> void").unwrap();
+        assert_eq!(signature.function_type(), parsed);
+        assert_eq!(
+            signature.to_string().parse::<LabelSignature>().unwrap(),
+            signature
+        );
+        assert!("int".parse::<LabelSignature>().is_err());
+    }
+}
@@ -0,0 +1,156 @@
+use jodin_common::assembly::value::Value;
+use jodin_vm_plugins::plugins::{LabelConflict, PluginManager, VMHandle};
+use jodin_vm_plugins::{Error, Plugin};
+use std::path::PathBuf;
+
+#[test]
+fn missing_library_is_an_error() {
+    let mut manager = PluginManager::new();
+    let path = PathBuf::from("this/plugin/does/not/exist.so");
+    match unsafe { manager.load_plugin(&path) } {
+        Err(Error::PluginNotFound(missing)) => assert_eq!(missing, path),
+        Err(e) => panic!("expected the plugin to be missing, found {}", e),
+        Ok(_) => panic!("loading a missing plugin should fail"),
+    }
+}
+
+/// A plugin that only registers labels
+struct Labels(&'static [&'static str]);
+
+impl Plugin for Labels {
+    fn labels<'s>(&'s self, buffer: &mut [&'s str]) {
+        buffer.copy_from_slice(self.0);
+    }
+
+    fn labels_count(&self) -> i32 {
+        self.0.len() as i32
+    }
+
+    fn call_label(
+        &self,
+        label: &str,
+        _handle: &mut dyn VMHandle,
+        output: &mut Option<Result<Value, String>>,
+    ) {
+        *output = Some(Ok(Value::from(label)));
+    }
+}
+
+/// A plugin that reports a negative number of labels
+struct NegativeLabels;
+
+impl Plugin for NegativeLabels {
+    fn labels<'s>(&'s self, _buffer: &mut [&'s str]) {}
+
+    fn labels_count(&self) -> i32 {
+        -1
+    }
+
+    fn call_label(
+        &self,
+        _label: &str,
+        _handle: &mut dyn VMHandle,
+        _output: &mut Option<Result<Value, String>>,
+    ) {
+    }
+}
+
+#[test]
+fn negative_label_counts_are_rejected() {
+    let mut manager = PluginManager::new();
+    assert!(matches!(
+        manager.with_plugin(NegativeLabels),
+        Err(Error::InvalidLabelCount(-1))
+    ));
+    assert_eq!(manager.plugins().count(), 0);
+}
+
+#[test]
+fn conflicting_labels_are_rejected() {
+    let mut manager = PluginManager::new();
+    let first = manager.with_plugin(Labels(&["open", "close"])).unwrap();
+    match manager.with_plugin(Labels(&["read", "close"])) {
+        Err(Error::LabelConflict { label, owner }) => {
+            assert_eq!(label, "close");
+            assert_eq!(owner, first.to_string());
+        }
+        other => panic!("expected a label conflict, found {:?}", other.map(|_| ())),
+    }
+    assert!(!manager.loaded_label("read"));
+    assert_eq!(manager.plugins().count(), 1);
+}
+
+#[test]
+fn label_conflict_policies() {
+    let mut manager = PluginManager::new();
+    manager.set_label_conflict(LabelConflict::Keep);
+    let first = manager.with_plugin(Labels(&["open", "close"])).unwrap();
+    let second = manager.with_plugin(Labels(&["read", "close"])).unwrap();
+    assert_eq!(manager.labels_of(first), vec!["close", "open"]);
+    assert_eq!(manager.labels_of(second), vec!["read"]);
+
+    manager.set_label_conflict(LabelConflict::Replace);
+    let third = manager.with_plugin(Labels(&["open"])).unwrap();
+    assert_eq!(manager.plugin_of("open"), Some(third));
+    assert_eq!(manager.labels_of(first), vec!["close"]);
+}
+
+#[test]
+fn unloading_gives_labels_back() {
+    let mut manager = PluginManager::new();
+    manager.set_label_conflict(LabelConflict::Replace);
+    let first = manager.with_plugin(Labels(&["open", "close"])).unwrap();
+    let second = manager.with_plugin(Labels(&["close"])).unwrap();
+    assert_eq!(manager.plugin_of("close"), Some(second));
+
+    manager.unload_plugin(second).unwrap();
+    assert_eq!(manager.plugin_of("close"), Some(first));
+    manager.unload_plugin(first).unwrap();
+    assert!(!manager.loaded_label("open"));
+    assert!(!manager.loaded_label("close"));
+    assert!(matches!(
+        manager.unload_plugin(first),
+        Err(Error::PluginNotLoaded(_))
+    ));
+}
+
+#[test]
+fn labels_are_given_back_in_load_order() {
+    // plugins are stored by id, so try enough managers for the ids to come out in any order
+    for _ in 0..16 {
+        let mut manager = PluginManager::new();
+        manager.set_label_conflict(LabelConflict::Replace);
+        let first = manager.with_plugin(Labels(&["close"])).unwrap();
+        let second = manager.with_plugin(Labels(&["close"])).unwrap();
+        let third = manager.with_plugin(Labels(&["close"])).unwrap();
+        assert_eq!(
+            manager.plugins().collect::<Vec<_>>(),
+            vec![first, second, third]
+        );
+        manager.unload_plugin(third).unwrap();
+        assert_eq!(manager.plugin_of("close"), Some(second));
+
+        let mut manager = PluginManager::new();
+        manager.set_label_conflict(LabelConflict::Keep);
+        let first = manager.with_plugin(Labels(&["close"])).unwrap();
+        let second = manager.with_plugin(Labels(&["close"])).unwrap();
+        manager.with_plugin(Labels(&["close"])).unwrap();
+        manager.unload_plugin(first).unwrap();
+        assert_eq!(manager.plugin_of("close"), Some(second));
+    }
+}
+
+#[test]
+fn replacing_keeps_the_id() {
+    let mut manager = PluginManager::new();
+    let id = manager.with_plugin(Labels(&["open", "close"])).unwrap();
+    manager
+        .replace_plugin(id, Labels(&["open", "flush"]))
+        .unwrap();
+    assert_eq!(manager.labels_of(id), vec!["flush", "open"]);
+    assert!(!manager.loaded_label("close"));
+    assert!(matches!(
+        unsafe { manager.reload_plugin(id) },
+        Err(Error::NotDynamic(_))
+    ));
+}
@@ -0,0 +1,68 @@
+//! Displays the contents of compiled objects
+
+use jodinc::cli::ObjdumpApp;
+use jodinc::objdump::{diff, ObjectDump, Style};
+use std::io::IsTerminal;
+use std::process::exit;
+
+fn main() {
+    let matches = ObjdumpApp::new().into_matches();
+    let style = match matches.value_of("color") {
+        Some("always") => Style::colored(),
+        Some("never") => Style::default(),
+        _ => Style {
+            color: std::io::stdout().is_terminal(),
+        },
+    };
+    let inputs = matches.values_of("INPUT").unwrap().collect::<Vec<_>>();
+
+    if matches.is_present("diff") {
+        if inputs.len() != 2 {
+            eprintln!("--diff needs exactly two objects");
+            exit(2);
+        }
+        match ObjectDump::open(inputs[0])
+            .and_then(|old| diff(&old, &ObjectDump::open(inputs[1])?, style))
+        {
+            Ok(None) => {}
+            Ok(Some(differences)) => {
+                print!("{}", differences);
+                exit(1);
+            }
+            Err(e) => {
+                eprintln!("{}", e);
+                exit(2);
+            }
+        }
+        return;
+    }
+
+    let sections = ["headers", "units", "disassemble", "xrefs"];
+    let all = !sections.iter().any(|section| matches.is_present(section));
+    let show = |section: &str| all || matches.is_present(section);
+    for input in inputs {
+        let result = ObjectDump::open(input).and_then(|dump| {
+            let mut out = format!("{}:\n", input);
+            if show("headers") {
+                dump.write_header(&mut out)?;
+            }
+            if show("units") {
+                dump.write_units(&mut out)?;
+            }
+            if show("disassemble") {
+                dump.write_disassembly(&mut out, style)?;
+            }
+            if show("xrefs") {
+                dump.write_cross_references(&mut out, style)?;
+            }
+            Ok(out)
+        });
+        match result {
+            Ok(out) => println!("{}", out),
+            Err(e) => {
+                eprintln!("{}", e);
+                exit(1);
+            }
+        }
+    }
+}
@@ -27,10 +27,85 @@ impl<'a, 'b: 'a> JodinRsApp<'a, 'b> {
             (version: "0.1.0")
             (author: "Joshua Radin <jradin16@gmail.com>")
             (about: "Compiler fo jodin")
-            (@arg debug: -d --debug_level +takes_value "set the debug level, from 0 to 5")
-            (@arg include: -I --include +takes_value ... "include the contents of a directory for indexing")
-            (@arg target_dir: -T --target_dir +takes_value "where generated files should be emitted")
-            (@arg INPUT: +required +takes_value ... "the file inputs")
+            (@setting SubcommandRequiredElseHelp)
+            (@arg debug: -d --debug_level +takes_value +global {is_debug_level} "set the debug level, from 0 to 5")
+            (@arg include: -I --include +takes_value +multiple +global number_of_values(1) "include the contents of a directory, package or object for indexing")
+            (@arg target_dir: -T --target_dir +takes_value +global "where generated files should be emitted")
+            (@subcommand build =>
+                (about: "compiles the inputs into .jobj files")
+                (@arg package: --package +takes_value "bundle the objects in the target directory into a .jdp package")
+                (@arg standalone: --standalone +takes_value "link the objects in the target directory and the included objects into a standalone executable")
+                (@arg runtime: --runtime +takes_value "the jodin runner that standalone executables are made from")
+                (@arg entry: -e --entry +takes_value "the function a standalone executable runs instead of main")
+                (@arg ast: --ast "also write the syntax tree of each input as .ast.json and .ast.dot files")
+                (@arg tast: --tast "also write the tagged syntax tree of each input as .tast.json and .tast.dot files")
+                (@arg INPUT: +required +takes_value ... "the file inputs")
+            )
+            (@subcommand check =>
+                (about: "analyzes the inputs without writing any files")
+                (@arg INPUT: +required +takes_value ... "the file inputs")
+            )
+            (@subcommand run =>
+                (about: "builds the inputs, then runs them")
+                (@arg entry: -e --entry +takes_value "the function to run instead of main")
+                (@arg coverage: --coverage +takes_value "write the line coverage of the program to this file, in the lcov format")
+                (@arg INPUT: +required +takes_value ... "the file inputs")
+                (@arg ARGS: +last +multiple "the arguments of the program, after --")
+            )
+            (@subcommand emit =>
+                (about: "prints an intermediate form of the inputs")
+                (@arg KIND: +required possible_value[tokens ast tast jasm] "what to print: the tokens, the syntax tree, the tagged syntax tree, or the assembly")
+                (@arg format: -f --format +takes_value possible_value[debug json dot] "how syntax trees are printed, defaulting to debug")
+                (@arg INPUT: +required +takes_value ... "the file inputs")
+            )
+        ))
+    }
+
+    /// Consumes the application, getting the command line arguments passed into the program
+    pub fn into_matches(self) -> ArgMatches<'a> {
+        self.0.get_matches()
+    }
+}
+
+/// Checks that a debug level is between 0 and 5
+fn is_debug_level(level: String) -> Result<(), String> {
+    match level.parse::<u8>() {
+        Ok(0..=5) => Ok(()),
+        _ => Err(format!("no debug level {level}, expected 0 to 5")),
+    }
+}
+
+/// Contains the clap app of the `jodin-objdump` tool, which inspects object files
+pub struct ObjdumpApp<'a, 'b: 'a>(App<'a, 'b>);
+
+impl<'a, 'b: 'a> Deref for ObjdumpApp<'a, 'b> {
+    type Target = App<'a, 'b>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl<'a, 'b: 'a> DerefMut for ObjdumpApp<'a, 'b> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.0
+    }
+}
+
+impl<'a, 'b: 'a> ObjdumpApp<'a, 'b> {
+    /// Creates the clap application to be used for the cli
+    pub fn new() -> Self {
+        Self(clap_app!(jodin_objdump =>
+            (version: "0.1.0")
+            (author: "Joshua Radin <jradin16@gmail.com>")
+            (about: "Displays the contents of .jobj files")
+            (@arg headers: -h --headers "show the header and sections of the object")
+            (@arg units: -u --units "show the translation units exported by the object")
+            (@arg disassemble: -d --disassemble "show the code of the object")
+            (@arg xrefs: -x --xrefs "show which labels are defined by the object and which are external")
+            (@arg diff: --diff conflicts_with_all(&["headers", "units", "disassemble", "xrefs"]) "compare two objects instead, exiting with 1 if they differ")
+            (@arg color: --color +takes_value possible_value[auto always never] default_value("auto") "when to highlight labels and changes")
+            (@arg INPUT: +required +takes_value ... "the .jobj files")
         ))
     }
 
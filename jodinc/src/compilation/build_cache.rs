@@ -0,0 +1,261 @@
+//! The build cache lets the incremental compiler skip files that haven't changed since they were
+//! last compiled.
+//!
+//! The cache is stored as JSON in the target directory. For every source file it records a hash of
+//! the contents of the file, and a hash of the interface the file was compiled against, which is
+//! the [translation units](TranslationUnit) of the modules it depends on. A file only needs to be
+//! compiled again when either hash changes, or when one of its objects is missing. As the interface
+//! only contains the signatures of the dependencies, changing the body of a function doesn't cause
+//! the modules that use it to be compiled again.
+//!
+//! The cache also records a hash of the configuration it was built with: the version of the
+//! compiler, the [version](Version) of the assembly it writes, the settings, and the included
+//! objects. When the configuration changes, every entry is dropped and every file is compiled again.
+
+use jodin_common::asm_version::Version;
+use jodin_common::compilation_settings::CompilationSettings;
+use jodin_common::error::JodinResult;
+use jodin_common::unit::TranslationUnit;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+/// The name of the build cache within the target directory
+pub const BUILD_CACHE_FILE_NAME: &str = "build-cache.json";
+
+/// What was produced the last time a source file was compiled
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CacheEntry {
+    /// The hash of the contents of the source file
+    pub source_hash: u64,
+    /// The hash of the translation units the source file was compiled against
+    pub interface_hash: u64,
+    /// The objects that were written
+    pub objects: Vec<PathBuf>,
+    /// The translation units of the objects that were written
+    pub units: Vec<String>,
+}
+
+impl CacheEntry {
+    /// Creates an entry for a source file that was just compiled
+    pub fn new(
+        source_hash: u64,
+        interface_hash: u64,
+        objects: Vec<PathBuf>,
+        units: &[TranslationUnit],
+    ) -> Self {
+        Self {
+            source_hash,
+            interface_hash,
+            objects,
+            units: units.iter().map(ToString::to_string).collect(),
+        }
+    }
+
+    /// The translation units of the objects that were written
+    pub fn translation_units(&self) -> JodinResult<Vec<TranslationUnit>> {
+        self.units
+            .iter()
+            .map(|unit| TranslationUnit::from_str(unit))
+            .collect()
+    }
+}
+
+/// The build cache of a target directory
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct BuildCache {
+    /// The hash of the configuration the entries were compiled with
+    #[serde(default)]
+    configuration: u64,
+    entries: HashMap<String, CacheEntry>,
+}
+
+impl BuildCache {
+    /// Creates an empty build cache
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Creates an empty build cache for a configuration
+    pub fn with_configuration(configuration: u64) -> Self {
+        Self {
+            configuration,
+            entries: HashMap::new(),
+        }
+    }
+
+    /// The path of the build cache within a target directory
+    pub fn path<P: AsRef<Path>>(target_directory: P) -> PathBuf {
+        target_directory.as_ref().join(BUILD_CACHE_FILE_NAME)
+    }
+
+    /// Reads the build cache of a target directory, for the [configuration](configuration_hash)
+    /// of the compiler. If there is no cache, it can't be read, or it was built with a different
+    /// configuration, the cache is empty and every file is compiled.
+    pub fn load<P: AsRef<Path>>(target_directory: P, configuration: u64) -> Self {
+        let path = Self::path(target_directory);
+        let contents = match std::fs::read_to_string(&path) {
+            Ok(contents) => contents,
+            Err(_) => return Self::with_configuration(configuration),
+        };
+        match serde_json::from_str::<Self>(&contents) {
+            Ok(cache) if cache.configuration == configuration => cache,
+            Ok(_) => {
+                info!("The configuration changed since {:?} was written", path);
+                Self::with_configuration(configuration)
+            }
+            Err(e) => {
+                warn!("Ignoring the build cache {:?}: {}", path, e);
+                Self::with_configuration(configuration)
+            }
+        }
+    }
+
+    /// Writes the build cache to a target directory
+    pub fn save<P: AsRef<Path>>(&self, target_directory: P) -> JodinResult<()> {
+        std::fs::create_dir_all(&target_directory)?;
+        let contents = serde_json::to_string_pretty(self).map_err(|e| anyhow::anyhow!(e))?;
+        std::fs::write(Self::path(target_directory), contents)?;
+        Ok(())
+    }
+
+    /// Gets the entry of a source file, if it's up to date. An entry is up to date when both hashes
+    /// are the same and all of its objects still exist.
+    pub fn up_to_date<P: AsRef<Path>>(
+        &self,
+        source: P,
+        source_hash: u64,
+        interface_hash: u64,
+    ) -> Option<&CacheEntry> {
+        self.entries
+            .get(&cache_key(source))
+            .filter(|entry| {
+                entry.source_hash == source_hash && entry.interface_hash == interface_hash
+            })
+            .filter(|entry| entry.objects.iter().all(|object| object.exists()))
+    }
+
+    /// Records that a source file was compiled
+    pub fn insert<P: AsRef<Path>>(&mut self, source: P, entry: CacheEntry) {
+        self.entries.insert(cache_key(source), entry);
+    }
+}
+
+/// Source files are keyed by their absolute path where possible
+fn cache_key<P: AsRef<Path>>(source: P) -> String {
+    let source = source.as_ref();
+    source
+        .canonicalize()
+        .unwrap_or_else(|_| source.to_path_buf())
+        .to_string_lossy()
+        .into_owned()
+}
+
+/// The 64-bit FNV-1a hash of some bytes, which is stable between runs of the compiler
+pub fn content_hash(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf2_9ce4_8422_2325, |hash, &byte| {
+        (hash ^ byte as u64).wrapping_mul(0x0100_0000_01b3)
+    })
+}
+
+/// The hash of everything besides the sources that changes what the compiler writes: the version of
+/// the compiler, the version of the assembly in its objects, the settings, and the paths of the
+/// included objects, which don't depend on their order
+pub fn configuration_hash<P: AsRef<Path>>(settings: &CompilationSettings, included: &[P]) -> u64 {
+    let mut included = included
+        .iter()
+        .map(|path| path.as_ref().to_string_lossy().into_owned())
+        .collect::<Vec<_>>();
+    included.sort();
+    let configuration = format!(
+        "jodinc {}\nasm {}\nast {}\ntast {}\ninclude {}",
+        env!("CARGO_PKG_VERSION"),
+        Version::CURRENT,
+        settings.output_ast,
+        settings.output_tast,
+        included.join("\n")
+    );
+    content_hash(configuration.as_bytes())
+}
+
+/// The hash of a set of translation units, which doesn't depend on their order
+pub fn interface_hash(units: &[TranslationUnit]) -> u64 {
+    let mut units = units.iter().map(ToString::to_string).collect::<Vec<_>>();
+    units.sort();
+    units.dedup();
+    content_hash(units.join("\n").as_bytes())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use jodin_common::core::privacy::Visibility;
+    use jodin_common::identifier::Identifier;
+    use jodin_common::types::primitives::Primitive;
+    use jodin_common::types::Type;
+
+    fn unit(name: &str, primitive: Primitive) -> TranslationUnit {
+        TranslationUnit::new(
+            Visibility::Public,
+            primitive.as_intermediate(),
+            Identifier::from(name),
+        )
+    }
+
+    #[test]
+    fn interface_hash_ignores_order() {
+        let first = unit("first", Primitive::Int);
+        let second = unit("second", Primitive::Float);
+        assert_eq!(
+            interface_hash(&[first.clone(), second.clone()]),
+            interface_hash(&[second, first.clone()])
+        );
+        assert_ne!(
+            interface_hash(&[first]),
+            interface_hash(&[unit("first", Primitive::Float)])
+        );
+    }
+
+    #[test]
+    fn cache_round_trips() {
+        let directory =
+            std::env::temp_dir().join(format!("jodin-build-cache-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&directory);
+        let object = directory.join("main.jobj");
+        let source = directory.join("main.jodin");
+
+        let mut cache = BuildCache::new();
+        let units = [unit("main", Primitive::Int)];
+        cache.insert(&source, CacheEntry::new(1, 2, vec![object.clone()], &units));
+        cache.save(&directory).unwrap();
+        std::fs::write(&object, []).unwrap();
+
+        let cache = BuildCache::load(&directory, 0);
+        let entry = cache
+            .up_to_date(&source, 1, 2)
+            .expect("entry should be up to date");
+        assert_eq!(entry.translation_units().unwrap(), units);
+        assert!(cache.up_to_date(&source, 1, 3).is_none());
+        assert!(cache.up_to_date(&source, 0, 2).is_none());
+
+        let other = BuildCache::load(&directory, 1);
+        assert!(other.up_to_date(&source, 1, 2).is_none());
+
+        std::fs::remove_file(&object).unwrap();
+        assert!(cache.up_to_date(&source, 1, 2).is_none());
+        std::fs::remove_dir_all(directory).unwrap();
+    }
+
+    #[test]
+    fn configuration_hash_covers_settings() {
+        let settings = CompilationSettings::default();
+        let base = configuration_hash(&settings, &["a.jobj", "b.jdp"]);
+        assert_eq!(base, configuration_hash(&settings, &["b.jdp", "a.jobj"]));
+        assert_ne!(base, configuration_hash(&settings, &["a.jobj"]));
+        let settings = CompilationSettings {
+            output_ast: true,
+            ..CompilationSettings::default()
+        };
+        assert_ne!(base, configuration_hash(&settings, &["a.jobj", "b.jdp"]));
+    }
+}
@@ -15,7 +15,7 @@ pub use statement_compiler::StatementCompiler;
 pub use top_level_declaration_compiler::TopLevelDeclarationCompiler;
 
 mod components;
-pub mod dependency_graph;
+pub use crate::compilation::dependency_graph;
 
 pub use components::*;
 
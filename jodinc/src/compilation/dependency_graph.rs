@@ -55,7 +55,7 @@ impl DependencyGraph {
         } else {
             *self.nodes.get(&id).unwrap()
         };
-        self.edges.entry(key).or_insert(HashSet::new()).extend(deps);
+        self.edges.entry(key).or_default().extend(deps);
         key
     }
 
@@ -102,10 +102,11 @@ impl DependencyGraph {
             .keys()
             .map(|key| (*key, self.edges[key].clone()))
             .collect();
-        identifier_keys.sort_by_cached_key(|(_, set)| Reverse(set.len()));
+        // ties are broken by the order the identifiers were added, so the order is stable
+        identifier_keys.sort_by_cached_key(|(key, set)| Reverse((set.len(), *key)));
 
         while let Some((key, set)) = identifier_keys.pop() {
-            if set.len() > 0 {
+            if !set.is_empty() {
                 return Err(JodinErrorType::CircularDependencyDetected.into());
             }
 
@@ -116,13 +117,19 @@ impl DependencyGraph {
                 set.remove(&key);
             }
 
-            identifier_keys.sort_by_cached_key(|(_, set)| Reverse(set.len()));
+            identifier_keys.sort_by_cached_key(|(key, set)| Reverse((set.len(), *key)));
         }
 
         Ok(dep_order)
     }
 }
 
+impl Default for DependencyGraph {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 /// Represent a type that has dependencies
 pub trait HasDependencies: Namespaced {
     /// The identifiers to which this identifier is dependent on.
@@ -19,19 +19,28 @@
 //!
 //! # Considerations
 //! When a file already exists that is being used for compilation, the compiler should rewrite the target file.
+//! Files that haven't changed since the last build, along with the interfaces of their
+//! dependencies, are skipped using the [build cache](crate::compilation::build_cache).
 //!
 //! [#74]: https://github.com/joshradin/jodin-rs/issues/74
 
+use crate::compilation::build_cache::{
+    configuration_hash, content_hash, interface_hash, BuildCache, CacheEntry,
+};
 use crate::compilation::jodin_vm_compiler::JodinVMCompiler;
 
-use crate::passes::analysis::analyze_with_preload;
-use crate::{optimize, JodinError};
+use crate::passes::analysis::{analyze_with_preload, ModuleGraph};
+use crate::passes::frontend::{FilesToJodinNodeTool, SourceFile};
+use crate::{optimize, JodinError, JodinNode};
+use jodin_common::ast::dump::write_dumps;
 use jodin_common::compilation::{Compilable, Compiler};
 use jodin_common::compilation_settings::CompilationSettings;
+use jodin_common::identifier::Identifier;
+use jodin_common::package::{is_package, Package};
 use jodin_common::parsing::parse_program;
 use jodin_common::unit::{CompilationObject, Incremental, TranslationUnit};
 use std::cell::RefCell;
-use std::collections::{HashSet, VecDeque};
+use std::collections::{HashMap, HashSet, VecDeque};
 use std::fs::File;
 use std::io::BufReader;
 
@@ -73,14 +82,196 @@ impl IncrementalCompiler {
     pub fn compile_file<P: AsRef<Path>>(&mut self, file: P) -> Result<(), JodinError> {
         let input = std::fs::read_to_string(&file)?;
         let parsed = parse_program(input)?;
-        let (analyzed, _env) = analyze_with_preload(parsed, &self.translation_units)?;
+        let compiler = self.compile_tree(file, parsed)?;
+        self.translation_units
+            .extend(compiler.translation_units().iter().cloned());
+        Ok(())
+    }
 
-        let optimized = optimize(analyzed)?;
+    /// Compiles many files, which can depend on each other. Each file is compiled after the files
+    /// that it depends on, and can use their translation units.
+    ///
+    /// Files that haven't changed since the last build, and whose dependencies have the same
+    /// translation units, are skipped using the [build cache](BuildCache) of the output directory,
+    /// unless the compiler, its settings or the included objects changed.
+    ///
+    /// Returns the files that were compiled.
+    ///
+    /// # Error
+    ///
+    /// Will return an error if the files depend on each other circularly, or if any file can not be
+    /// compiled.
+    pub fn compile_files<P: AsRef<Path>, I: IntoIterator<Item = P>>(
+        &mut self,
+        files: I,
+    ) -> Result<Vec<PathBuf>, JodinError> {
+        let (sources, graph) = self.parse_in_order(files)?;
+        let included = self.paths_added.iter().collect::<Vec<_>>();
+        let configuration = configuration_hash(&self.compilation_settings, &included);
+        let mut cache = BuildCache::load(&self.output_directory, configuration);
+        // the cache is saved even if a file fails to compile, so the files before it are kept
+        let compiled = self.compile_in_order(sources, &graph, &mut cache);
+        cache.save(&self.output_directory)?;
+        compiled
+    }
+
+    /// Analyzes many files in the same order as [`compile_files`](Self::compile_files), creating
+    /// their objects without writing them.
+    ///
+    /// # Error
+    ///
+    /// Will return an error if the files depend on each other circularly, or if any file can not be
+    /// compiled.
+    pub fn check_files<P: AsRef<Path>, I: IntoIterator<Item = P>>(
+        &mut self,
+        files: I,
+    ) -> Result<Vec<CheckedModule>, JodinError> {
+        let (sources, _graph) = self.parse_in_order(files)?;
+        let mut checked = vec![];
+        for source in sources {
+            let tree = self.analyze(&source.path, source.tree)?;
+            let mut compiler = JodinVMCompiler::default();
+            compiler.set_originating_file_path(&source.path);
+            let object = compiler.create_object(&tree, &self.compilation_settings)?;
+            self.translation_units.extend(object.units.iter().cloned());
+            checked.push(CheckedModule {
+                path: source.path,
+                tree,
+                object,
+            });
+        }
+        Ok(checked)
+    }
+
+    /// Parses files, getting them in the order they should be compiled in
+    fn parse_in_order<P: AsRef<Path>, I: IntoIterator<Item = P>>(
+        &self,
+        files: I,
+    ) -> Result<(Vec<SourceFile>, ModuleGraph), JodinError> {
+        let mut frontend = FilesToJodinNodeTool::new(&self.compilation_settings);
+        frontend.invoke(files)?;
+        let sources = frontend.finish()?;
+
+        let mut graph = ModuleGraph::new();
+        for source in &sources {
+            graph.add_module(source.module_id(), &source.tree);
+        }
+        let order = graph
+            .dependence_order()?
+            .into_iter()
+            .cloned()
+            .collect::<Vec<_>>();
+        debug!("Compiling modules in the order {:?}", order);
+
+        let mut sources = sources
+            .into_iter()
+            .map(|source| (source.module_id(), source))
+            .collect::<HashMap<_, _>>();
+        let ordered = order
+            .iter()
+            .map(|id| sources.remove(id).expect("every module is in the graph"))
+            .collect();
+        Ok((ordered, graph))
+    }
+
+    fn compile_in_order(
+        &mut self,
+        sources: Vec<SourceFile>,
+        graph: &ModuleGraph,
+        cache: &mut BuildCache,
+    ) -> Result<Vec<PathBuf>, JodinError> {
+        let preloaded = self.translation_units.clone();
+        let mut module_units = HashMap::<Identifier, Vec<TranslationUnit>>::new();
+        let mut compiled = vec![];
+
+        for source in sources {
+            let id = source.module_id();
+            let mut interface = preloaded.clone();
+            for dependency in graph.dependencies_of(&id) {
+                interface.extend(module_units[dependency].iter().cloned());
+            }
+            let source_hash = content_hash(source.source.as_bytes());
+            let interface_hash = interface_hash(&interface);
+
+            // the tagged tree is only dumped when a file is analyzed, so nothing can be skipped
+            let cached = if self.compilation_settings.output_tast {
+                None
+            } else {
+                cache.up_to_date(&source.path, source_hash, interface_hash)
+            };
+            let units = match cached {
+                Some(entry) => {
+                    info!("{:?} is up to date", source.path);
+                    entry.translation_units()?
+                }
+                None => {
+                    let compiler = self.compile_tree(&source.path, source.tree)?;
+                    let units = compiler.translation_units().to_vec();
+                    cache.insert(
+                        &source.path,
+                        CacheEntry::new(
+                            source_hash,
+                            interface_hash,
+                            compiler.objects().to_vec(),
+                            &units,
+                        ),
+                    );
+                    compiled.push(source.path);
+                    units
+                }
+            };
+            self.translation_units.extend(units.iter().cloned());
+            module_units.insert(id, units);
+        }
+        Ok(compiled)
+    }
+
+    /// Compiles a parsed file, getting the compiler that was used
+    fn compile_tree<P: AsRef<Path>>(
+        &mut self,
+        file: P,
+        parsed: JodinNode,
+    ) -> Result<JodinVMCompiler<'static>, JodinError> {
+        let optimized = self.analyze(&file, parsed)?;
 
         let mut compiler = JodinVMCompiler::default();
         compiler.set_originating_file_path(file);
 
-        compiler.compile(&optimized, &self.compilation_settings)
+        compiler.compile(&optimized, &self.compilation_settings)?;
+        Ok(compiler)
+    }
+
+    /// Analyzes and optimizes a parsed file, using every translation unit added so far. If the
+    /// settings ask for the tagged syntax tree, it's dumped to the target directory.
+    fn analyze<P: AsRef<Path>>(&self, file: P, parsed: JodinNode) -> Result<JodinNode, JodinError> {
+        let (analyzed, _env) = analyze_with_preload(parsed, &self.translation_units)?;
+        let optimized = optimize(analyzed)?;
+        let settings = &self.compilation_settings;
+        if settings.output_tast {
+            let file = file.as_ref();
+            write_dumps(
+                &optimized,
+                Some(file),
+                settings.tree_dump_path(file, "tast"),
+            )?;
+        }
+        Ok(optimized)
+    }
+
+    /// Includes the translation units of a directory of objects, a package, or a single object
+    pub fn include<P: AsRef<Path>>(&mut self, path: P) -> Result<(), JodinError> {
+        let path = path.as_ref();
+        if let Some(directory) = IncrementalDirectory::new(path) {
+            // reads the objects now, so that errors are returned instead of panicking
+            let modules = directory.to_modules()?;
+            directory.cache.borrow_mut().extend(modules);
+            self.add_incremental(directory);
+        } else if is_package(path) {
+            self.add_incremental(Package::open(path)?);
+        } else {
+            self.add_incremental(CompilationObject::try_from(path)?);
+        }
+        Ok(())
     }
 
     /// Add an incremental object to the compiler
@@ -95,6 +286,17 @@ impl IncrementalCompiler {
     }
 }
 
+/// A file that was analyzed by [`check_files`](IncrementalCompiler::check_files)
+#[derive(Debug)]
+pub struct CheckedModule {
+    /// The path of the file
+    pub path: PathBuf,
+    /// The analyzed tree of the file
+    pub tree: JodinNode,
+    /// The object that would be written for the file
+    pub object: CompilationObject,
+}
+
 #[derive(Debug)]
 pub struct IncrementalDirectory<'path> {
     dir_path: &'path Path,
@@ -147,6 +349,10 @@ impl<'path> IncrementalDirectory<'path> {
                     Ok(dir_child) => match dir_child.file_type()? {
                         fs if fs.is_dir() => dir_stack.push_back(dir_child.path()),
                         fs if fs.is_file() => {
+                            // skips other files, such as the build cache
+                            if dir_child.path().extension().is_none_or(|ext| ext != "jobj") {
+                                continue;
+                            }
                             let obj = CompilationObject::try_from(dir_child.path())?;
                             output.push(obj);
                         }
@@ -4,6 +4,7 @@ use crate::{JodinError, JodinNode, JodinResult};
 use anyhow::anyhow;
 use jodin_common::asm_version::Version;
 use jodin_common::assembly::asm_block::{AssemblyBlock, InsertAsm};
+use jodin_common::assembly::debug_info::DebugInfo;
 use jodin_common::assembly::instructions::{Asm, Bytecode, Encode};
 use jodin_common::ast::JodinNodeType;
 use jodin_common::compilation::{
@@ -44,6 +45,8 @@ impl Target for JodinVM {}
 pub struct JodinVMCompiler<'c> {
     originating_file_path: Option<PathBuf>,
     writer_override: Option<Box<dyn io::Write + 'c>>,
+    translation_units: Vec<TranslationUnit>,
+    objects: Vec<PathBuf>,
     lifetime: PhantomData<&'c ()>,
 }
 
@@ -61,6 +64,8 @@ impl<'c> JodinVMCompiler<'c> {
         JodinVMCompiler {
             originating_file_path: None,
             writer_override: as_box,
+            translation_units: vec![],
+            objects: vec![],
             lifetime: PhantomData::default(),
         }
     }
@@ -69,6 +74,16 @@ impl<'c> JodinVMCompiler<'c> {
     pub fn set_originating_file_path(&mut self, originating_file_path: impl AsRef<Path>) {
         self.originating_file_path = Some(originating_file_path.as_ref().to_path_buf());
     }
+
+    /// The translation units of every object compiled so far
+    pub fn translation_units(&self) -> &[TranslationUnit] {
+        &self.translation_units
+    }
+
+    /// The paths of every object written so far
+    pub fn objects(&self) -> &[PathBuf] {
+        &self.objects
+    }
 }
 
 impl Default for JodinVMCompiler<'static> {
@@ -76,41 +91,21 @@ impl Default for JodinVMCompiler<'static> {
         Self {
             originating_file_path: None,
             writer_override: None,
+            translation_units: vec![],
+            objects: vec![],
             lifetime: PhantomData::default(),
         }
     }
 }
 
-impl<'c> Compiler<JodinVM> for JodinVMCompiler<'c> {
-    fn compile(&mut self, tree: &JodinNode, settings: &CompilationSettings) -> JodinResult<()> {
-        // let modules = split_by_module(tree);
-        //
-        //
-        // let context = Context::new();
-        // for module in modules {
-        //     info!("Compiling module {:?}", module.identifier);
-        //     match &mut self.writer_override {
-        //         None => {
-        //             let builder = module.builder(&settings.target_directory);
-        //             for member in module.objects() {
-        //                 let resolved_id = member.resolved_id()?;
-        //                 info!("Compiling {:?}", resolved_id);
-        //                 let mut object_compiler =
-        //                     builder.translation_object_compiler(resolved_id.this());
-        //                 object_compiler.compile(member, settings)?;
-        //             }
-        //             let static_obj: CompilationObject = module.static_object(&builder)?;
-        //             let ref mut writer = static_obj.writer();
-        //             Compilable::<JodinVM>::compile(static_obj, &context, writer)?;
-        //         }
-        //         Some(s) => {
-        //             let _writer = PaddedWriter::new(s);
-        //             // module.compile(&context, &mut writer)?;
-        //             todo!()
-        //         }
-        //     };
-        // }
-
+impl<'c> JodinVMCompiler<'c> {
+    /// Creates the object of a tree without writing it. The location of the object is where it
+    /// would be written to.
+    pub fn create_object(
+        &mut self,
+        tree: &JodinNode,
+        settings: &CompilationSettings,
+    ) -> JodinResult<CompilationObject> {
         let to_compile: &JodinNode;
         let mut namespace: Option<Identifier> = None;
 
@@ -132,7 +127,7 @@ impl<'c> Compiler<JodinVM> for JodinVMCompiler<'c> {
 
         let file_name = match &self.originating_file_path {
             None => { OsString::from("a.out") }
-            Some(file) => { file.file_name().unwrap().to_os_string() }
+            Some(file) => { Path::new(file.file_name().unwrap()).with_extension("jobj").into_os_string() }
         };
 
         let id_to_path = match &namespace {
@@ -147,17 +142,61 @@ impl<'c> Compiler<JodinVM> for JodinVMCompiler<'c> {
             Path::new(&file_name)
         ]);
 
-        info!("Compiling to file {output_path:?}");
         let mut file_compiler = SingleUseCompiler::new(
             output_path.clone(), namespace.unwrap_or(Identifier::empty())
         );
+        if let Some(source) = &self.originating_file_path {
+            file_compiler.set_source_path(source);
+        }
 
         let compilable = file_compiler.create_compilable(to_compile)?;
+        self.translation_units.extend(compilable.units.iter().cloned());
+        Ok(compilable)
+    }
+}
+
+impl<'c> Compiler<JodinVM> for JodinVMCompiler<'c> {
+    fn compile(&mut self, tree: &JodinNode, settings: &CompilationSettings) -> JodinResult<()> {
+        // let modules = split_by_module(tree);
+        //
+        //
+        // let context = Context::new();
+        // for module in modules {
+        //     info!("Compiling module {:?}", module.identifier);
+        //     match &mut self.writer_override {
+        //         None => {
+        //             let builder = module.builder(&settings.target_directory);
+        //             for member in module.objects() {
+        //                 let resolved_id = member.resolved_id()?;
+        //                 info!("Compiling {:?}", resolved_id);
+        //                 let mut object_compiler =
+        //                     builder.translation_object_compiler(resolved_id.this());
+        //                 object_compiler.compile(member, settings)?;
+        //             }
+        //             let static_obj: CompilationObject = module.static_object(&builder)?;
+        //             let ref mut writer = static_obj.writer();
+        //             Compilable::<JodinVM>::compile(static_obj, &context, writer)?;
+        //         }
+        //         Some(s) => {
+        //             let _writer = PaddedWriter::new(s);
+        //             // module.compile(&context, &mut writer)?;
+        //             todo!()
+        //         }
+        //     };
+        // }
+
+        let compilable = self.create_object(tree, settings)?;
+        let output_path = compilable.file_location.clone();
+        info!("Compiling to file {output_path:?}");
+        if let Some(parent) = output_path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
         let mut file = OpenOptions::new()
             .write(true)
             .create(true)
             .truncate(true)
-            .open(output_path)?;
+            .open(&output_path)?;
+        self.objects.push(output_path);
 
         let mut writer = PaddedWriter::new(file);
 
@@ -168,12 +207,18 @@ impl<'c> Compiler<JodinVM> for JodinVMCompiler<'c> {
 
 pub struct SingleUseCompiler {
     file: PathBuf,
-    in_module: Identifier
+    in_module: Identifier,
+    source: Option<PathBuf>,
 }
 
 impl SingleUseCompiler {
     pub fn new(file: PathBuf, in_module: Identifier) -> Self {
-        SingleUseCompiler { file, in_module }
+        SingleUseCompiler { file, in_module, source: None }
+    }
+
+    /// Sets the source file recorded in the line table of the created object
+    pub fn set_source_path(&mut self, source: impl AsRef<Path>) {
+        self.source = Some(source.as_ref().to_path_buf());
     }
 }
 
@@ -206,12 +251,16 @@ impl MicroCompiler<JodinVM, CompilationObject> for SingleUseCompiler {
 
                 translation_units.push(TranslationUnit::new(vis.clone(), j_type.clone(), id));
             }
+            // externs are provided by other objects or plugins, so they don't create any code
+            JodinNodeType::ExternDeclaration { .. } => {}
             _ => {
                 panic!("invalid tree given to compiler: {:?}", tree);
             }
         }
 
-        let mut output = CompilationObject::new(self.file.clone(), self.in_module.clone(), translation_units, assembly.normalize());
+        let (assembly, lines) = assembly.normalize_with_lines();
+        let mut output = CompilationObject::new(self.file.clone(), self.in_module.clone(), translation_units, assembly)
+            .with_debug_info(DebugInfo::new(self.source.clone(), lines));
         for object in created {
             output += object;
         }
@@ -14,7 +14,7 @@ use jodin_common::error::JodinErrorType;
 use jasm_macros::{cond, if_, pop, return_, scope, value, var, while_};
 use jodin_common::block;
 use jodin_common::core::operator::Operator;
-use jodin_common::core::tags::TagTools;
+use jodin_common::core::tags::{SourceLineTag, TagTools};
 use jodin_common::types::StorageModifier;
 use std::cell::RefCell;
 use std::rc::Rc;
@@ -81,6 +81,9 @@ impl StatementCompiler {
 impl MicroCompiler<JodinVM, AssemblyBlock> for StatementCompiler {
     fn create_compilable(&mut self, tree: &JodinNode) -> JodinResult<AssemblyBlock> {
         let mut block = AssemblyBlock::new(None);
+        if let Ok(source_line) = tree.get_tag::<SourceLineTag>() {
+            block.mark_source_line(source_line.line);
+        }
         match tree.r#type() {
             JodinNodeType::StoreVariable {
                 storage_type: StorageModifier::Local,
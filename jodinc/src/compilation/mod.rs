@@ -5,6 +5,8 @@
 
 // pub use c_compiler::{C99Compiler, C99};
 
+pub mod dependency_graph;
+
 #[cfg(feature = "c_compiler")]
 pub mod c_compiler;
 #[cfg(feature = "c_compiler")]
@@ -15,6 +17,8 @@ pub mod jodin_vm_compiler;
 #[cfg(feature = "jodin_vm_compiler")]
 pub use jodin_vm_compiler::JodinVM;
 
+#[cfg(feature = "incremental")]
+pub mod build_cache;
 #[cfg(feature = "incremental")]
 pub mod incremental;
 
@@ -56,6 +56,9 @@ extern crate log;
 #[macro_use]
 extern crate static_assertions;
 
+#[macro_use]
+extern crate serde_derive;
+
 use crate::passes::analysis::analyze;
 use crate::passes::optimization::optimize;
 use jodin_common::ast::JodinNode;
@@ -65,6 +68,7 @@ use jodin_common::types::type_environment::TypeEnvironment;
 pub mod cli;
 pub mod compilation;
 pub mod error_reporting;
+pub mod objdump;
 pub mod passes;
 pub mod test_runner;
 
@@ -1,102 +1,288 @@
+use anyhow::anyhow;
+use clap::ArgMatches;
+use jodin_common::assembly::text::to_jasm;
+use jodin_common::assembly::value::Value;
+use jodin_common::ast::dump;
+use jodin_common::ast::JodinNode;
 use jodin_common::compilation_settings::CompilationSettings;
-use jodin_common::error::{JodinError, JodinErrorType, JodinResult};
+use jodin_common::error::{JodinError, JodinResult};
 use jodin_common::init_logging;
+use jodin_common::linker::{Linker, DEFAULT_ENTRY_POINT};
+use jodin_common::package::Package;
+use jodin_common::parsing::{parse_program, JodinLexer};
+use jodin_rs_vm::kernel::boot;
+use jodin_rs_vm::mvp::MinimumALU;
+use jodin_rs_vm::scoped_memory::VMMemory;
+use jodin_rs_vm::standalone::write_standalone;
+use jodin_rs_vm::vm::VMBuilder;
+use jodin_vm_kernel::KernelPlugin;
+use jodin_vm_plugins::discovery::PluginSearchPath;
 use jodinc::cli::JodinRsApp;
-
-use jodinc::passes::frontend::FilesToJodinNodeTool;
-
-use jodinc::process_jodin_node;
-use log::{error, LevelFilter};
-use std::fs::File;
-use std::io::Write;
-use std::path::PathBuf;
-use std::process::exit;
-use std::str::FromStr;
 use jodinc::compilation::incremental::IncrementalCompiler;
+use log::{debug, error, info, LevelFilter};
+use std::path::{Path, PathBuf};
+use std::process::exit;
 
 fn main() {
     let cli = JodinRsApp::new();
     let matches = cli.into_matches();
-    let mut settings = CompilationSettings::default();
+    let (command, matches) = match matches.subcommand() {
+        (command, Some(matches)) => (command, matches),
+        _ => unreachable!("a subcommand is required"),
+    };
 
-    if let Some(target) = matches.value_of("target") {
-        let path = PathBuf::from(target);
-        settings.target_directory = path;
-    }
-    if matches.is_present("pt") {
-        settings.output_parse_tree = true;
-    }
-    if matches.is_present("ast") {
-        settings.output_ast = true;
-    }
-    if matches.is_present("tast") {
-        settings.output_tast = true;
-    }
-    if matches.is_present("debug") {
-        let level = match u8::from_str(matches.value_of("debug").unwrap()) {
-            Ok(0) => LevelFilter::Off,
-            Ok(1) => LevelFilter::Error,
-            Ok(2) => LevelFilter::Warn,
-            Ok(3) => LevelFilter::Info,
-            Ok(4) => LevelFilter::Debug,
-            Ok(5) => LevelFilter::Trace,
-            Ok(o) => panic!("No debug level {}", o),
-            Err(_) => panic!("invalid value for debug"),
-        };
-        init_logging(level)
-    } else {
-        init_logging(LevelFilter::Info)
+    // the debug level is checked by the cli
+    let level = match matches.value_of("debug") {
+        Some("0") => LevelFilter::Off,
+        Some("1") => LevelFilter::Error,
+        Some("2") => LevelFilter::Warn,
+        Some("3") => LevelFilter::Info,
+        Some("4") => LevelFilter::Debug,
+        Some("5") => LevelFilter::Trace,
+        // keeps the output of emit and run free of progress messages
+        _ if command == "emit" || command == "run" => LevelFilter::Warn,
+        _ => LevelFilter::Info,
+    };
+    init_logging(level);
+
+    let mut settings = CompilationSettings::default();
+    if let Some(target) = matches.value_of("target_dir") {
+        settings.target_directory = PathBuf::from(target);
     }
+    settings.output_ast = matches.is_present("ast");
+    settings.output_tast = matches.is_present("tast");
+    let target_directory = settings.target_directory.clone();
+    let mut incremental = IncrementalCompiler::new(&target_directory, settings);
 
+    let result = include(&mut incremental, matches).and_then(|()| {
+        let inputs = input_paths(matches)?;
+        match command {
+            "build" => build(&mut incremental, &inputs, matches, &target_directory),
+            "check" => check(&mut incremental, &inputs),
+            "run" => run(&mut incremental, &inputs, matches, &target_directory),
+            "emit" => emit(&mut incremental, &inputs, matches),
+            _ => unreachable!("unknown subcommand {}", command),
+        }
+    });
 
-    let inputs = matches.values_of("INPUT").unwrap();
-    let mut full_paths = vec![];
+    match result {
+        Ok(code) => exit(code),
+        Err(error) => {
+            error!("{error}");
+            for line in format!("{:?}", error.backtrace()).lines() {
+                debug!("{}", line);
+            }
+            exit(1)
+        }
+    }
+}
 
-    for input in inputs {
+/// Expands the glob patterns of the inputs
+fn input_paths(matches: &ArgMatches) -> JodinResult<Vec<PathBuf>> {
+    let mut full_paths = vec![];
+    for input in matches.values_of("INPUT").unwrap() {
         let paths = glob::glob(input)
-            .expect(format!("Given input was an invalid path: {}", input).as_str())
+            .map_err(|e| anyhow!("{:?} is not a valid input: {}", input, e))?
             .collect::<Vec<_>>();
         if paths.is_empty() {
-            panic!("No file(s) can be found using the path {:?}", input);
+            return Err(anyhow!("no files can be found using the path {:?}", input).into());
         }
-        for result in paths {
-            match result {
-                Ok(path) => {
-                    if !path.exists() {
-                        panic!("{:?} does not exist!", path)
-                    }
-                    full_paths.push(path)
-                }
-                Err(e) => {
-                    panic!("{:?}", e)
-                }
+        for path in paths {
+            let path = path.map_err(|e| anyhow!("{}", e))?;
+            if !path.exists() {
+                return Err(anyhow!("{:?} does not exist", path).into());
             }
+            full_paths.push(path)
         }
     }
+    Ok(full_paths)
+}
 
-    let mut incremental = IncrementalCompiler::new(settings.target_directory.clone(), settings);
+/// Included objects, such as the signatures of plugins, can be referred to by extern declarations
+fn include(incremental: &mut IncrementalCompiler, matches: &ArgMatches) -> JodinResult<()> {
+    for include in matches.values_of("include").into_iter().flatten() {
+        incremental
+            .include(include)
+            .map_err(|e| anyhow!("{:?} can not be included: {}", include, e))?;
+    }
+    Ok(())
+}
 
-    let mut errors = vec![];
+fn build(
+    incremental: &mut IncrementalCompiler,
+    inputs: &[PathBuf],
+    matches: &ArgMatches,
+    target_directory: &Path,
+) -> JodinResult<i32> {
+    // files are compiled after the files they depend on
+    incremental.compile_files(inputs)?;
+    if let Some(package) = matches.value_of("package") {
+        Package::from_directory(target_directory, package)
+            .and_then(|package| package.write())
+            .map_err(|e| anyhow!("could not create package {:?}: {}", package, e))?;
+    }
+    if let Some(executable) = matches.value_of("standalone") {
+        standalone(matches, target_directory, Path::new(executable)).map_err(|e| {
+            anyhow!(
+                "could not create standalone executable {:?}: {}",
+                executable,
+                e
+            )
+        })?;
+    }
+    Ok(0)
+}
 
-    for path in full_paths {
-        match incremental.compile_file(path) {
-            Ok(()) => {}
-            Err(e) => {
-                errors.push(e);
-            }
-        }
+/// Links the objects in the target directory and the included objects into an image, which is
+/// appended to a copy of the runner
+fn standalone(matches: &ArgMatches, target_directory: &Path, executable: &Path) -> JodinResult<()> {
+    let mut linker =
+        Linker::new().with_entry_point(matches.value_of("entry").unwrap_or(DEFAULT_ENTRY_POINT));
+    linker.add_path(target_directory)?;
+    for include in matches.values_of("include").into_iter().flatten() {
+        linker.add_path(include)?;
+    }
+    let image = linker.link()?;
+    let runtime = find_runtime(matches)?;
+    write_standalone(&runtime, &image, executable).map_err(|e| anyhow!("{}", e))?;
+    info!("Created standalone executable {:?}", executable);
+    Ok(())
+}
+
+/// The runner that standalone executables are made from. Unless one is given, it's
+/// `$JODIN_RUNTIME`, or the `jodin` executable installed next to the compiler.
+fn find_runtime(matches: &ArgMatches) -> JodinResult<PathBuf> {
+    if let Some(runtime) = matches.value_of("runtime") {
+        return Ok(PathBuf::from(runtime));
+    }
+    if let Some(runtime) = std::env::var_os("JODIN_RUNTIME") {
+        return Ok(PathBuf::from(runtime));
+    }
+    let compiler = std::env::current_exe()?;
+    let runtime = compiler.with_file_name(format!("jodin{}", std::env::consts::EXE_SUFFIX));
+    if runtime.is_file() {
+        Ok(runtime)
+    } else {
+        Err(anyhow!(
+            "the jodin runner isn't next to {:?}, so it must be given with --runtime or JODIN_RUNTIME",
+            compiler
+        )
+        .into())
     }
+}
+
+fn check(incremental: &mut IncrementalCompiler, inputs: &[PathBuf]) -> JodinResult<i32> {
+    let checked = incremental.check_files(inputs)?;
+    info!("{} file(s) checked", checked.len());
+    Ok(0)
+}
+
+fn run(
+    incremental: &mut IncrementalCompiler,
+    inputs: &[PathBuf],
+    matches: &ArgMatches,
+    target_directory: &Path,
+) -> JodinResult<i32> {
+    incremental.compile_files(inputs)?;
 
-    match errors.as_slice() {
-        &[] => return,
-        errors => {
-            for error in errors {
-                error!("{error}");
-                for line in format!("{:?}", error.backtrace()).lines() {
-                    error!("{}", line);
+    let mut vm = VMBuilder::new()
+        .memory(VMMemory::default())
+        .alu(MinimumALU)
+        .plugin_path(PluginSearchPath::from_env())
+        .build()
+        .map_err(|e| anyhow!("{}", e))?;
+    vm.load_plugin::<KernelPlugin>()
+        .map_err(|e| anyhow!("{}", e))?;
+
+    // the program is booted by the kernel, the same way the jodin runner boots it
+    let mut object_path = vec![Value::from(target_directory.to_string_lossy().to_string())];
+    object_path.extend(
+        matches
+            .values_of("include")
+            .into_iter()
+            .flatten()
+            .map(Value::from),
+    );
+    // the first input is also the first argument of the program
+    let mut arguments = vec![Value::from(inputs[0].to_string_lossy().to_string())];
+    arguments.extend(
+        matches
+            .values_of("ARGS")
+            .into_iter()
+            .flatten()
+            .map(Value::from),
+    );
+    let entry = matches.value_of("entry").map_or(Value::Empty, Value::from);
+    let coverage_output = matches.value_of("coverage");
+    if coverage_output.is_some() {
+        vm.enable_coverage();
+    }
+    let code = boot(
+        &mut vm,
+        Value::Array(object_path),
+        Value::Array(arguments),
+        entry,
+    )
+    .map_err(|e| anyhow!("{}", e))?;
+    if let (Some(output), Some(coverage)) = (coverage_output, vm.take_coverage()) {
+        coverage.save_lcov(output)?;
+    }
+    Ok(code as i32)
+}
+
+fn emit(
+    incremental: &mut IncrementalCompiler,
+    inputs: &[PathBuf],
+    matches: &ArgMatches,
+) -> JodinResult<i32> {
+    match matches.value_of("KIND").unwrap() {
+        "tokens" => {
+            for input in inputs {
+                let source = std::fs::read_to_string(input)?;
+                println!("// {}", input.display());
+                for token in JodinLexer::new(&source) {
+                    let (start, token, end) = token?;
+                    println!("{}..{} {:?}", start, end, token);
                 }
             }
         }
+        "ast" => {
+            for input in inputs {
+                let tree = parse_program(std::fs::read_to_string(input)?)?;
+                print_tree(&tree, input, matches)?;
+            }
+        }
+        "tast" => {
+            for module in incremental.check_files(inputs)? {
+                print_tree(&module.tree, &module.path, matches)?;
+            }
+        }
+        "jasm" => {
+            for module in incremental.check_files(inputs)? {
+                println!("; {}", module.path.display());
+                print!("{}", to_jasm(&module.object.jasm)?);
+            }
+        }
+        kind => return Err(JodinError::from(anyhow!("can not emit {}", kind))),
     }
+    Ok(0)
+}
 
+fn print_tree(tree: &JodinNode, path: &Path, matches: &ArgMatches) -> JodinResult<()> {
+    match matches.value_of("format").unwrap_or("debug") {
+        "json" => {
+            let document = dump::to_json_document(tree, Some(path));
+            let json = serde_json::to_string_pretty(&document).map_err(|e| anyhow!(e))?;
+            println!("{}", json);
+        }
+        "dot" => {
+            println!("// {}", path.display());
+            print!("{}", dump::to_dot(tree));
+        }
+        _ => {
+            println!("// {}", path.display());
+            println!("{:#?}", tree);
+        }
+    }
+    Ok(())
 }
@@ -0,0 +1,525 @@
+//! Inspects compiled objects. This is what the `jodin-objdump` tool prints.
+//!
+//! An [ObjectDump] shows the container of an object, such as its magic number and sections, the
+//! translation units that the object exports, a disassembly of its code, and the
+//! [cross-references](CrossReferences) between the labels it defines and the labels it uses. Two
+//! objects can also be [compared](diff).
+
+use jodin_common::asm_version::Version;
+use jodin_common::assembly::instructions::Asm;
+use jodin_common::assembly::text::instruction_to_jasm;
+use jodin_common::error::JodinResult;
+use jodin_common::linker::CrossReferences;
+use jodin_common::object_file::{ObjectFile, FORMAT_VERSION};
+use jodin_common::unit::CompilationObject;
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::path::{Path, PathBuf};
+
+/// How text is highlighted
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Style {
+    /// Whether to use ANSI colors
+    pub color: bool,
+}
+
+impl Style {
+    /// A style with ANSI colors
+    pub fn colored() -> Self {
+        Self { color: true }
+    }
+
+    fn paint(&self, code: &str, text: &str) -> String {
+        if self.color {
+            format!("\x1b[{}m{}\x1b[0m", code, text)
+        } else {
+            text.to_string()
+        }
+    }
+
+    fn label(&self, text: &str) -> String {
+        self.paint("1;33", text)
+    }
+
+    fn public_label(&self, text: &str) -> String {
+        self.paint("1;32", text)
+    }
+
+    fn removed(&self, text: &str) -> String {
+        self.paint("31", text)
+    }
+
+    fn added(&self, text: &str) -> String {
+        self.paint("32", text)
+    }
+}
+
+/// A compiled object, along with the container it was read from
+#[derive(Debug)]
+pub struct ObjectDump {
+    /// The path the object was read from
+    pub path: PathBuf,
+    /// The container of the object
+    pub file: ObjectFile,
+    /// The object
+    pub object: CompilationObject,
+}
+
+impl ObjectDump {
+    /// Reads an object file
+    ///
+    /// # Error
+    ///
+    /// Will return an error if the file isn't an object, or the object is corrupt or was compiled
+    /// with another version of the assembly.
+    pub fn open<P: AsRef<Path>>(path: P) -> JodinResult<Self> {
+        let path = path.as_ref();
+        let bytes = std::fs::read(path)?;
+        let file = ObjectFile::from_bytes(&bytes)?;
+        let object = CompilationObject::try_from(&*bytes)?;
+        Ok(Self {
+            path: path.to_path_buf(),
+            file,
+            object,
+        })
+    }
+
+    /// Writes the header and section table of the object
+    pub fn write_header(&self, out: &mut String) -> JodinResult<()> {
+        writeln!(out, "header:")?;
+        let version = match Version::from_magic_number(self.file.magic_number) {
+            Some(version) if version < Version::CURRENT => {
+                format!("jodin asm {}, upgraded to {}", version, Version::CURRENT)
+            }
+            Some(version) => format!("jodin asm {}", version),
+            None => "unknown asm version".to_string(),
+        };
+        writeln!(
+            out,
+            "  magic:          0x{:016x} ({})",
+            self.file.magic_number, version
+        )?;
+        writeln!(out, "  format version: {}", FORMAT_VERSION)?;
+        writeln!(
+            out,
+            "  location:       {}",
+            self.object.file_location.display()
+        )?;
+        let module = match self.object.module.is_empty() {
+            true => "<none>".to_string(),
+            false => self.object.module.to_string(),
+        };
+        writeln!(out, "  module:         {}", module)?;
+        writeln!(out, "  sections:")?;
+        for section in self.file.sections() {
+            let kind = format!("{:?}", section.kind);
+            writeln!(out, "    {:<12} {:>8} bytes", kind, section.data.len())?;
+        }
+        Ok(())
+    }
+
+    /// Writes the translation units exported by the object
+    pub fn write_units(&self, out: &mut String) -> JodinResult<()> {
+        writeln!(out, "translation units:")?;
+        if self.object.units.is_empty() {
+            writeln!(out, "  <none>")?;
+        }
+        for unit in &self.object.units {
+            writeln!(
+                out,
+                "  {:<9} {}: {}",
+                unit.vis.to_string(),
+                unit.name,
+                unit.jtype
+            )?;
+        }
+        Ok(())
+    }
+
+    /// Writes the code of the object as textual assembly. Each instruction is prefixed with its
+    /// index, and instructions that start a source line are followed by the line.
+    pub fn write_disassembly(&self, out: &mut String, style: Style) -> JodinResult<()> {
+        writeln!(out, "disassembly:")?;
+        let jasm = &self.object.jasm;
+        let width = jasm.len().saturating_sub(1).to_string().len();
+        let lines = self
+            .object
+            .debug_info
+            .iter()
+            .flat_map(|debug_info| &debug_info.lines)
+            .map(|entry| (entry.pc, entry.line))
+            .collect::<BTreeMap<_, _>>();
+        for (index, asm) in jasm.iter().enumerate() {
+            let line = disassemble(asm, width + 8, style)?;
+            match lines.get(&index) {
+                Some(source_line) => {
+                    writeln!(out, "  {:>width$}  {}  ; line {}", index, line, source_line)?
+                }
+                None => writeln!(out, "  {:>width$}  {}", index, line)?,
+            }
+        }
+        Ok(())
+    }
+
+    /// Writes the labels defined by the object and the labels it uses, split into the labels that
+    /// are defined locally and those that must be defined by another object or plugin
+    pub fn write_cross_references(&self, out: &mut String, style: Style) -> JodinResult<()> {
+        let references = CrossReferences::new(&self.object.jasm);
+        writeln!(out, "cross-references:")?;
+        writeln!(out, "  local:")?;
+        for (label, symbol) in references.local() {
+            let name = match symbol.public {
+                true => style.public_label(&format!("pub {}", label)),
+                false => style.label(label),
+            };
+            writeln!(
+                out,
+                "    {} (defined at {}{})",
+                name,
+                symbol.definition.unwrap(),
+                uses(&symbol.uses)
+            )?;
+        }
+        writeln!(out, "  external:")?;
+        for (label, symbol) in references.external() {
+            writeln!(
+                out,
+                "    {} ({})",
+                label,
+                uses(&symbol.uses).trim_start_matches("; ")
+            )?;
+        }
+        Ok(())
+    }
+}
+
+fn uses(uses: &[usize]) -> String {
+    if uses.is_empty() {
+        String::new()
+    } else {
+        let uses = uses.iter().map(usize::to_string).collect::<Vec<_>>();
+        format!("; used at {}", uses.join(", "))
+    }
+}
+
+/// Disassembles a single instruction. Labels are highlighted, and instructions are indented under
+/// them. Instructions that span many lines have their following lines indented by `indent`.
+fn disassemble(asm: &Asm, indent: usize, style: Style) -> JodinResult<String> {
+    let text = instruction_to_jasm(asm)?;
+    Ok(match asm {
+        Asm::Label(_) => style.label(&text),
+        Asm::PublicLabel(_) => style.public_label(&text),
+        _ => format!("    {}", text.replace('\n', &format!("\n{:indent$}", ""))),
+    })
+}
+
+/// Compares two objects, getting a description of their differences, or `None` if they're the
+/// same. The code of the objects is compared instruction by instruction, with two instructions of
+/// context around each change.
+pub fn diff(old: &ObjectDump, new: &ObjectDump, style: Style) -> JodinResult<Option<String>> {
+    let mut out = String::new();
+    let mut changed = false;
+    writeln!(out, "--- {}", old.path.display())?;
+    writeln!(out, "+++ {}", new.path.display())?;
+
+    if old.file.magic_number != new.file.magic_number {
+        changed = true;
+        writeln!(
+            out,
+            "magic: 0x{:016x} -> 0x{:016x}",
+            old.file.magic_number, new.file.magic_number
+        )?;
+    }
+    if old.object.module != new.object.module {
+        changed = true;
+        writeln!(
+            out,
+            "module: {} -> {}",
+            old.object.module, new.object.module
+        )?;
+    }
+
+    let old_units = old.object.units.iter().map(ToString::to_string);
+    let new_units = new.object.units.iter().map(ToString::to_string);
+    let unit_changes = changes(
+        &old_units.collect::<Vec<_>>(),
+        &new_units.collect::<Vec<_>>(),
+    );
+    if unit_changes
+        .iter()
+        .any(|change| !matches!(change, Change::Same(..)))
+    {
+        changed = true;
+        writeln!(out, "translation units:")?;
+        for change in unit_changes {
+            match change {
+                Change::Same(..) => {}
+                Change::Removed(index) => {
+                    let unit = &old.object.units[index];
+                    let line = format!(
+                        "- {:<9} {}: {}",
+                        unit.vis.to_string(),
+                        unit.name,
+                        unit.jtype
+                    );
+                    writeln!(out, "{}", style.removed(&line))?;
+                }
+                Change::Added(index) => {
+                    let unit = &new.object.units[index];
+                    let line = format!(
+                        "+ {:<9} {}: {}",
+                        unit.vis.to_string(),
+                        unit.name,
+                        unit.jtype
+                    );
+                    writeln!(out, "{}", style.added(&line))?;
+                }
+            }
+        }
+    }
+
+    let old_code = disassemble_all(&old.object.jasm)?;
+    let new_code = disassemble_all(&new.object.jasm)?;
+    let code_changes = changes(&old_code, &new_code);
+    if code_changes
+        .iter()
+        .any(|change| !matches!(change, Change::Same(..)))
+    {
+        changed = true;
+        writeln!(out, "code:")?;
+        write_code_changes(&mut out, &code_changes, &old_code, &new_code, style)?;
+    }
+
+    Ok(if changed { Some(out) } else { None })
+}
+
+fn disassemble_all(jasm: &[Asm]) -> JodinResult<Vec<String>> {
+    jasm.iter()
+        .map(|asm| disassemble(asm, 0, Style::default()))
+        .collect()
+}
+
+/// The amount of unchanged instructions shown around each change
+const CONTEXT: usize = 2;
+
+fn write_code_changes(
+    out: &mut String,
+    code_changes: &[Change],
+    old_code: &[String],
+    new_code: &[String],
+    style: Style,
+) -> JodinResult<()> {
+    let changed = code_changes
+        .iter()
+        .enumerate()
+        .filter(|(_, change)| !matches!(change, Change::Same(..)))
+        .map(|(index, _)| index)
+        .collect::<Vec<_>>();
+    let shown = |index: usize| {
+        changed
+            .iter()
+            .any(|&changed| index + CONTEXT >= changed && index <= changed + CONTEXT)
+    };
+
+    let mut skipped = false;
+    for (index, change) in code_changes.iter().enumerate() {
+        if !shown(index) {
+            skipped = true;
+            continue;
+        }
+        if skipped {
+            writeln!(out, "  ...")?;
+            skipped = false;
+        }
+        match *change {
+            Change::Same(_, new_index) => {
+                writeln!(out, "  {:>5}  {}", new_index, new_code[new_index])?
+            }
+            Change::Removed(old_index) => {
+                let line = format!("- {:>5}  {}", old_index, old_code[old_index]);
+                writeln!(out, "{}", style.removed(&line))?
+            }
+            Change::Added(new_index) => {
+                let line = format!("+ {:>5}  {}", new_index, new_code[new_index]);
+                writeln!(out, "{}", style.added(&line))?
+            }
+        }
+    }
+    if skipped {
+        writeln!(out, "  ...")?;
+    }
+    Ok(())
+}
+
+/// A step in changing one list into another
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Change {
+    /// An item in both lists, at its index in the old and the new list
+    Same(usize, usize),
+    /// An item only in the old list
+    Removed(usize),
+    /// An item only in the new list
+    Added(usize),
+}
+
+/// The changes from one list to another, using their shortest edit script. The common prefix and
+/// suffix are trimmed before Myers' algorithm runs on the rest, which takes O((N + M) * D) time
+/// and memory for D differences.
+fn changes<T: PartialEq>(old: &[T], new: &[T]) -> Vec<Change> {
+    let prefix = old.iter().zip(new).take_while(|(o, n)| o == n).count();
+    let suffix = old[prefix..]
+        .iter()
+        .rev()
+        .zip(new[prefix..].iter().rev())
+        .take_while(|(o, n)| o == n)
+        .count();
+
+    let mut changes = (0..prefix).map(|i| Change::Same(i, i)).collect::<Vec<_>>();
+    let middle = shortest_edit(
+        &old[prefix..old.len() - suffix],
+        &new[prefix..new.len() - suffix],
+    );
+    changes.extend(middle.into_iter().map(|change| match change {
+        Change::Same(i, j) => Change::Same(prefix + i, prefix + j),
+        Change::Removed(i) => Change::Removed(prefix + i),
+        Change::Added(j) => Change::Added(prefix + j),
+    }));
+    changes
+        .extend((0..suffix).map(|s| Change::Same(old.len() - suffix + s, new.len() - suffix + s)));
+    changes
+}
+
+/// Myers' diff algorithm. Removals come before additions where either order would do.
+fn shortest_edit<T: PartialEq>(old: &[T], new: &[T]) -> Vec<Change> {
+    let (n, m) = (old.len() as isize, new.len() as isize);
+    let offset = n + m;
+    // furthest[k] is how far into old the furthest path on diagonal k - offset reaches
+    let mut furthest = vec![0isize; 2 * offset as usize + 2];
+    // the furthest paths before each step
+    let mut trace = vec![];
+    let index = |k: isize| (k + offset) as usize;
+    let goes_down = |furthest: &[isize], k: isize, d: isize| {
+        k == -d || (k != d && furthest[index(k - 1)] < furthest[index(k + 1)])
+    };
+
+    'search: for d in 0..=offset {
+        trace.push(furthest.clone());
+        for k in (-d..=d).step_by(2) {
+            let mut x = if goes_down(&furthest, k, d) {
+                furthest[index(k + 1)]
+            } else {
+                furthest[index(k - 1)] + 1
+            };
+            let mut y = x - k;
+            while x < n && y < m && old[x as usize] == new[y as usize] {
+                x += 1;
+                y += 1;
+            }
+            furthest[index(k)] = x;
+            if x >= n && y >= m {
+                break 'search;
+            }
+        }
+    }
+
+    let mut changes = vec![];
+    let (mut x, mut y) = (n, m);
+    for (d, furthest) in trace.iter().enumerate().rev() {
+        let d = d as isize;
+        let k = x - y;
+        let previous_k = if goes_down(furthest, k, d) {
+            k + 1
+        } else {
+            k - 1
+        };
+        let previous_x = furthest[index(previous_k)];
+        let previous_y = previous_x - previous_k;
+        while x > previous_x && y > previous_y {
+            x -= 1;
+            y -= 1;
+            changes.push(Change::Same(x as usize, y as usize));
+        }
+        if d > 0 {
+            if x == previous_x {
+                changes.push(Change::Added(previous_y as usize));
+            } else {
+                changes.push(Change::Removed(previous_x as usize));
+            }
+        }
+        x = previous_x;
+        y = previous_y;
+    }
+    changes.reverse();
+    changes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use jodin_common::identifier::Identifier;
+
+    #[test]
+    fn changes_keep_common_items() {
+        let old = ["a", "b", "c", "d"];
+        let new = ["a", "c", "d", "e"];
+        assert_eq!(
+            changes(&old, &new),
+            vec![
+                Change::Same(0, 0),
+                Change::Removed(1),
+                Change::Same(2, 1),
+                Change::Same(3, 2),
+                Change::Added(3),
+            ]
+        );
+        assert_eq!(
+            changes(&["a", "b"], &["c"]),
+            vec![Change::Removed(0), Change::Removed(1), Change::Added(0)]
+        );
+        assert_eq!(changes::<&str>(&[], &[]), vec![]);
+    }
+
+    #[test]
+    fn changes_of_long_lists() {
+        let old = (0..100_000).collect::<Vec<_>>();
+        let mut new = old.clone();
+        new[50_000] = -1;
+        new.push(-2);
+        let changes = changes(&old, &new);
+        assert_eq!(changes.len(), 100_002);
+        assert_eq!(changes[50_000], Change::Removed(50_000));
+        assert_eq!(changes[50_001], Change::Added(50_000));
+        assert_eq!(changes[100_001], Change::Added(100_000));
+        assert!(changes
+            .iter()
+            .all(|change| !matches!(change, Change::Same(i, j) if old[*i] != new[*j])));
+    }
+
+    fn write_object(directory: &Path, name: &str, jasm: Vec<Asm>) -> ObjectDump {
+        let path = directory.join(name);
+        let object = CompilationObject::new(path.clone(), Identifier::empty(), vec![], jasm);
+        std::fs::write(&path, object.to_bytes().unwrap()).unwrap();
+        ObjectDump::open(path).unwrap()
+    }
+
+    #[test]
+    fn diff_objects() {
+        let directory = std::env::temp_dir().join(format!("jodin-objdump-{}", std::process::id()));
+        std::fs::create_dir_all(&directory).unwrap();
+        let mut jasm = vec![Asm::pub_label("main")];
+        jasm.extend((0..10u64).map(Asm::push));
+        jasm.push(Asm::Return);
+        let old = write_object(&directory, "old.jobj", jasm.clone());
+        jasm[5] = Asm::push(100u64);
+        let new = write_object(&directory, "new.jobj", jasm);
+
+        assert!(diff(&old, &old, Style::default()).unwrap().is_none());
+        let text = diff(&old, &new, Style::default()).unwrap().unwrap();
+        assert!(text.contains("-     5      push 4u64"), "{}", text);
+        assert!(text.contains("+     5      push 100u64"), "{}", text);
+        // only the context around the change is shown
+        assert!(!text.contains("push 0u64"), "{}", text);
+        assert!(text.contains("push 2u64"), "{}", text);
+        std::fs::remove_dir_all(directory).unwrap();
+    }
+}
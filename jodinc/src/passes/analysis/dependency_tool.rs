@@ -1,17 +1,320 @@
+//! Finds the dependencies between the modules of a project, before they are analyzed, so that
+//! modules can be compiled after the modules they depend on.
+
+use crate::compilation::dependency_graph::DependencyGraph;
+use jodin_common::ast::{JodinNode, JodinNodeType};
+use jodin_common::error::JodinResult;
 use jodin_common::identifier::Identifier;
+use jodin_common::utility::Tree;
+use std::collections::HashSet;
 use std::iter::FromIterator;
 
-/// Creates dependency tags
+/// The identifiers that a module declares, and the identifiers that it refers to
+#[derive(Debug, Default)]
+pub struct ModuleDependencies {
+    /// The absolute identifiers of the top level declarations of the module
+    pub declared: HashSet<Identifier>,
+    /// Every absolute identifier that the identifiers referred to by the module could resolve to
+    pub referenced: HashSet<Identifier>,
+}
+
+impl ModuleDependencies {
+    /// Whether this module refers to something declared by another module. Identifiers that this
+    /// module declares itself never create a dependency.
+    pub fn depends_on(&self, other: &ModuleDependencies) -> bool {
+        self.referenced
+            .iter()
+            .filter(|id| !self.declared.contains(*id))
+            .any(|id| other.declared.contains(id))
+    }
+}
+
+/// Finds the identifiers that a module declares and refers to
+#[derive(Default)]
 pub struct DependencyTool {
     major_id: Vec<String>,
+    /// The parameters and variables of the function being visited, which shadow other identifiers
+    locals: HashSet<Identifier>,
+    dependencies: ModuleDependencies,
 }
 
 impl DependencyTool {
+    /// Creates a new dependency tool
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Finds the dependencies of a parsed module
+    pub fn visit(mut self, tree: &JodinNode) -> ModuleDependencies {
+        self.visit_top_level(tree);
+        self.dependencies
+    }
+
     fn major_namespace(&self) -> Identifier {
-        Identifier::from_iter(&self.major_id)
+        namespace(&self.major_id)
+    }
+
+    /// Every absolute identifier that an id used within the major namespace could refer to
+    fn ids_within_major_namespace(&self, id: &Identifier) -> Vec<Identifier> {
+        (0..=self.major_id.len())
+            .map(|length| Identifier::new_concat(namespace(&self.major_id[..length]), id))
+            .collect()
+    }
+
+    fn declare(&mut self, name: &JodinNode) {
+        if let JodinNodeType::Identifier(id) = name.inner() {
+            let declared = Identifier::new_concat(self.major_namespace(), id);
+            self.dependencies.declared.insert(declared);
+        }
+    }
+
+    fn refer(&mut self, id: &Identifier) {
+        if self.locals.contains(id) {
+            return;
+        }
+        let ids = self.ids_within_major_namespace(id);
+        self.dependencies.referenced.extend(ids);
+    }
+
+    fn visit_top_level(&mut self, tree: &JodinNode) {
+        match tree.inner() {
+            JodinNodeType::TopLevelDeclarations { decs } => {
+                for dec in decs {
+                    self.visit_top_level(dec);
+                }
+            }
+            JodinNodeType::InNamespace { namespace, inner } => {
+                let pushed = match namespace.inner() {
+                    JodinNodeType::Identifier(id) => {
+                        let components = id.iter().collect::<Vec<_>>();
+                        let count = components.len();
+                        self.major_id.extend(components);
+                        count
+                    }
+                    _ => 0,
+                };
+                self.visit_top_level(inner);
+                self.major_id.truncate(self.major_id.len() - pushed);
+            }
+            JodinNodeType::ExternDeclaration { declaration } => {
+                if let JodinNodeType::StoreVariable { name, .. } = declaration.inner() {
+                    if let JodinNodeType::Identifier(id) = name.inner() {
+                        self.refer(id);
+                    }
+                }
+            }
+            JodinNodeType::FunctionDefinition {
+                name,
+                arguments,
+                block,
+                ..
+            } => {
+                self.declare(name);
+                for node in arguments.iter().chain(block.children_prefix()) {
+                    self.locals.extend(local_names(node));
+                }
+                self.visit_references(block);
+                self.locals.clear();
+            }
+            JodinNodeType::StoreVariable {
+                name,
+                maybe_initial_value,
+                ..
+            } => {
+                self.declare(name);
+                if let Some(value) = maybe_initial_value {
+                    self.visit_references(value);
+                }
+            }
+            JodinNodeType::VarDeclarations { names, values, .. } => {
+                for name in names {
+                    self.declare(name);
+                }
+                for value in values.iter().flatten() {
+                    self.visit_references(value);
+                }
+            }
+            JodinNodeType::CompoundTypeDefinition { name, members, .. } => {
+                self.declare(name);
+                for member in members {
+                    self.visit_references(member);
+                }
+            }
+            _ => self.visit_references(tree),
+        }
+    }
+
+    /// Records the identifiers used within a tree. The names of declared variables and members
+    /// aren't references.
+    fn visit_references(&mut self, tree: &JodinNode) {
+        match tree.inner() {
+            JodinNodeType::Identifier(id) => self.refer(id),
+            JodinNodeType::NamedValue { .. } => {}
+            JodinNodeType::StoreVariable {
+                maybe_initial_value,
+                ..
+            } => {
+                if let Some(value) = maybe_initial_value {
+                    self.visit_references(value);
+                }
+            }
+            JodinNodeType::VarDeclarations { values, .. } => {
+                for value in values.iter().flatten() {
+                    self.visit_references(value);
+                }
+            }
+            JodinNodeType::GetMember { compound, .. } => self.visit_references(compound),
+            _ => {
+                for child in tree.direct_children() {
+                    self.visit_references(child);
+                }
+            }
+        }
+    }
+}
+
+/// The names of the variables declared by a node
+fn local_names(node: &JodinNode) -> Vec<Identifier> {
+    let names = match node.inner() {
+        JodinNodeType::NamedValue { name, .. } | JodinNodeType::StoreVariable { name, .. } => {
+            vec![name]
+        }
+        JodinNodeType::VarDeclarations { names, .. } => names.iter().collect(),
+        _ => vec![],
+    };
+    names
+        .into_iter()
+        .filter_map(|name| match name.inner() {
+            JodinNodeType::Identifier(id) => Some(id.clone()),
+            _ => None,
+        })
+        .collect()
+}
+
+fn namespace(components: &[String]) -> Identifier {
+    if components.is_empty() {
+        Identifier::empty()
+    } else {
+        Identifier::from_iter(components)
+    }
+}
+
+/// A graph of the dependencies between the modules of a project, where each module is a parsed
+/// file
+#[derive(Default)]
+pub struct ModuleGraph {
+    modules: Vec<(Identifier, ModuleDependencies)>,
+}
+
+impl ModuleGraph {
+    /// Creates an empty module graph
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a module to the graph
+    pub fn add_module(&mut self, id: Identifier, tree: &JodinNode) {
+        let dependencies = DependencyTool::new().visit(tree);
+        self.modules.push((id, dependencies));
+    }
+
+    /// The modules that a module depends on, in the order they were added
+    pub fn dependencies_of(&self, id: &Identifier) -> Vec<&Identifier> {
+        let module = match self.modules.iter().find(|(module, _)| module == id) {
+            Some((_, module)) => module,
+            None => return vec![],
+        };
+        self.modules
+            .iter()
+            .filter(|(other, dependencies)| other != id && module.depends_on(dependencies))
+            .map(|(other, _)| other)
+            .collect()
+    }
+
+    /// Gets the modules in an order such that no module depends on a following module.
+    ///
+    /// # Error
+    ///
+    /// Will return an error if there is a circular dependency
+    pub fn dependence_order(&self) -> JodinResult<Vec<&Identifier>> {
+        let mut graph = DependencyGraph::new();
+        for (id, _) in &self.modules {
+            let dependencies = self
+                .dependencies_of(id)
+                .into_iter()
+                .cloned()
+                .collect::<Vec<_>>();
+            graph.add_dependencies(id.clone(), dependencies);
+        }
+        let order = graph.dependence_order()?;
+        Ok(order
+            .into_iter()
+            .filter_map(|id| self.modules.iter().find(|(module, _)| module == id))
+            .map(|(module, _)| module)
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use jodin_common::error::JodinErrorType;
+    use jodin_common::parsing::parse_program;
+
+    fn graph(modules: &[(&str, &str)]) -> ModuleGraph {
+        let mut graph = ModuleGraph::new();
+        for (id, program) in modules {
+            let tree = parse_program(program).unwrap();
+            graph.add_module(Identifier::from(id), &tree);
+        }
+        graph
+    }
+
+    #[test]
+    fn namespaced_dependencies() {
+        let graph = graph(&[
+            (
+                "main",
+                "in app; extern const square: fn(int) -> int; fn main() -> int { return square(2); }",
+            ),
+            ("square", "in app { fn square(x: int) -> int { return x * x; } }"),
+            ("unused", "fn x() -> int { return 0; }"),
+        ]);
+        assert_eq!(
+            graph.dependencies_of(&Identifier::from("main")),
+            vec![&Identifier::from("square")]
+        );
+        // the parameter `x` shadows the function declared by `unused`
+        assert!(graph
+            .dependencies_of(&Identifier::from("square"))
+            .is_empty());
+        let order = graph
+            .dependence_order()
+            .unwrap()
+            .into_iter()
+            .map(|id| id.to_string())
+            .collect::<Vec<_>>();
+        assert_eq!(order, vec!["square", "main", "unused"]);
     }
 
-    fn id_within_major_namespace(&self, _id: &Identifier) -> bool {
-        unimplemented!()
+    #[test]
+    fn circular_dependencies() {
+        let graph = graph(&[
+            (
+                "even",
+                "extern const odd: fn(int) -> int; fn even(x: int) -> int { return odd(x); }",
+            ),
+            (
+                "odd",
+                "extern const even: fn(int) -> int; fn odd(x: int) -> int { return even(x); }",
+            ),
+        ]);
+        match graph.dependence_order() {
+            Err(e) => assert!(matches!(
+                e.error_type,
+                JodinErrorType::CircularDependencyDetected
+            )),
+            Ok(order) => panic!("expected a circular dependency, got {:?}", order),
+        }
     }
 }
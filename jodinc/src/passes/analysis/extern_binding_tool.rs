@@ -0,0 +1,171 @@
+use jodin_common::ast::{JodinNode, JodinNodeType};
+use jodin_common::core::literal::Literal;
+use jodin_common::core::tags::TagTools;
+use jodin_common::error::{JodinErrorType, JodinResult};
+use jodin_common::identifier::Identifier;
+use jodin_common::types::intermediate_type::{IntermediateType, TypeSpecifier, TypeTail};
+use jodin_common::types::primitives::Primitive;
+use jodin_common::unit::TranslationUnit;
+use jodin_common::utility::Tree;
+use std::collections::HashMap;
+
+/// Binds extern declarations to the translation units that were preloaded, such as the labels
+/// exported by plugins, and checks the calls made to them.
+pub struct ExternBindingTool {
+    /// The types of the preloaded units
+    available: HashMap<Identifier, IntermediateType>,
+    /// The externs that were bound, with their declared types
+    bound: HashMap<Identifier, IntermediateType>,
+}
+
+impl ExternBindingTool {
+    /// Create a new extern binding tool
+    pub fn with_translation_units(units: &[TranslationUnit]) -> Self {
+        Self {
+            available: units
+                .iter()
+                .map(|unit| (unit.name.clone(), unit.jtype.clone()))
+                .collect(),
+            bound: HashMap::new(),
+        }
+    }
+
+    /// Binds every extern declaration in the tree, then checks the calls to them
+    pub fn visit(&mut self, tree: &JodinNode) -> JodinResult<()> {
+        self.bind_externs(tree)?;
+        self.check_calls(tree)
+    }
+
+    fn bind_externs(&mut self, tree: &JodinNode) -> JodinResult<()> {
+        match tree.inner() {
+            JodinNodeType::ExternDeclaration { declaration } => {
+                if let JodinNodeType::StoreVariable { name, var_type, .. } = declaration.inner() {
+                    let id = name.resolved_id()?;
+                    let expected = self
+                        .available
+                        .get(id)
+                        .ok_or_else(|| JodinErrorType::UnresolvedExtern(id.clone()))?;
+                    if !same_type(var_type, expected) {
+                        return Err(JodinErrorType::ExternTypeMismatch {
+                            id: id.clone(),
+                            declared: var_type.to_string(),
+                            expected: expected.to_string(),
+                        }
+                        .into());
+                    }
+                    self.bound.insert(id.clone(), var_type.clone());
+                }
+            }
+            _ => {
+                for child in tree.direct_children() {
+                    self.bind_externs(child)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn check_calls(&self, tree: &JodinNode) -> JodinResult<()> {
+        if let JodinNodeType::Call {
+            called, arguments, ..
+        } = tree.inner()
+        {
+            if let JodinNodeType::Identifier(_) = called.inner() {
+                if let Some((id, jtype)) = called
+                    .resolved_id()
+                    .ok()
+                    .and_then(|id| self.bound.get_key_value(id))
+                {
+                    check_arguments(id, jtype, arguments)?;
+                }
+            }
+        }
+        for child in tree.direct_children() {
+            self.check_calls(child)?;
+        }
+        Ok(())
+    }
+}
+
+/// Whether two types are the same, ignoring whether they are constant
+fn same_type(left: &IntermediateType, right: &IntermediateType) -> bool {
+    left.type_specifier == right.type_specifier
+        && left.generics == right.generics
+        && left.tails == right.tails
+}
+
+/// Checks the arguments of a call against the parameters of a function type. Only literal
+/// arguments can be checked before types are inferred.
+fn check_arguments(
+    function: &Identifier,
+    jtype: &IntermediateType,
+    arguments: &[JodinNode],
+) -> JodinResult<()> {
+    let parameters = match jtype.tails.last() {
+        Some(TypeTail::Function(parameters)) => parameters,
+        _ => return Ok(()),
+    };
+    let variadic = matches!(
+        parameters.last().map(|param| &param.type_specifier),
+        Some(TypeSpecifier::Primitive(Primitive::VaList))
+    );
+    let required = if variadic {
+        parameters.len() - 1
+    } else {
+        parameters.len()
+    };
+    if arguments.len() < required || (!variadic && arguments.len() > required) {
+        return Err(JodinErrorType::IncorrectArgumentCount {
+            function: function.clone(),
+            expected: required,
+            found: arguments.len(),
+        }
+        .into());
+    }
+
+    for (index, (argument, parameter)) in arguments.iter().zip(parameters).enumerate() {
+        let expected = match parameter {
+            IntermediateType {
+                type_specifier: TypeSpecifier::Primitive(primitive),
+                generics,
+                tails,
+                ..
+            } if generics.is_empty() && tails.is_empty() => primitive,
+            _ => continue,
+        };
+        if let JodinNodeType::Literal(literal) = argument.inner() {
+            match literal_type(literal) {
+                Some(found) if &found != expected && expected != &Primitive::VaList => {
+                    return Err(JodinErrorType::ArgumentTypeMismatch {
+                        function: function.clone(),
+                        index,
+                        expected: expected.to_string(),
+                        found: found.to_string(),
+                    }
+                    .into());
+                }
+                _ => {}
+            }
+        }
+    }
+    Ok(())
+}
+
+/// The primitive type of a literal, if it has one
+fn literal_type(literal: &Literal) -> Option<Primitive> {
+    Some(match literal {
+        Literal::String(_) => return None,
+        Literal::Char(_) => Primitive::Char,
+        Literal::Boolean(_) => Primitive::Boolean,
+        Literal::Float(_) => Primitive::Float,
+        Literal::Double(_) => Primitive::Double,
+        Literal::Byte(_) => Primitive::Byte,
+        Literal::Short(_) => Primitive::Short,
+        Literal::Int(_) => Primitive::Int,
+        Literal::Long(_) => Primitive::Long,
+        Literal::UnsignedByte(_) => Primitive::UnsignedByte,
+        Literal::UnsignedShort(_) => Primitive::UnsignedShort,
+        Literal::UnsignedInt(_) => Primitive::UnsignedInt,
+        Literal::UnsignedLong(_) => Primitive::UnsignedLong,
+    })
+}
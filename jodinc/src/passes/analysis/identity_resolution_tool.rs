@@ -316,6 +316,20 @@ impl IdentifierCreator {
             JodinNodeType::ExternDeclaration {
                 declaration: delcaration,
             } => {
+                // An extern that names a preloaded unit refers to that unit instead of creating
+                // a new identity
+                if let JodinNodeType::StoreVariable { name, .. } = delcaration.inner_mut() {
+                    if let JodinNodeType::Identifier(id) = name.inner() {
+                        let path = Identifier::new_concat(id_resolver.current_namespace(), id);
+                        if visibility_registry.get(&path).is_ok()
+                            && !id_resolver.contains_absolute_identifier(&path)
+                        {
+                            let path = id_resolver.create_absolute_path(id);
+                            name.add_tag(ResolvedIdentityTag::new(path))?;
+                            return Ok(());
+                        }
+                    }
+                }
                 self.create_identities(delcaration, id_resolver, visibility_registry)?;
             }
             _other => {}
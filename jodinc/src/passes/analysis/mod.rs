@@ -2,6 +2,7 @@
 //!
 //! These passes do not modify the structure of the AST in any way
 
+pub use dependency_tool::{DependencyTool, ModuleDependencies, ModuleGraph};
 pub use identity_resolution_tool::IdentityResolutionTool;
 use jodin_common::ast::JodinNode;
 pub use jodin_common::core::tags::BlockIdentifierTag;
@@ -9,10 +10,12 @@ pub use jodin_common::core::tags::ResolvedIdentityTag;
 use jodin_common::error::JodinResult;
 use jodin_common::types::type_environment::TypeEnvironment;
 
+use crate::passes::analysis::extern_binding_tool::ExternBindingTool;
 use crate::passes::analysis::type_resolution_tool::TypeResolutionTool;
 use jodin_common::unit::TranslationUnit;
 
 mod dependency_tool;
+mod extern_binding_tool;
 mod identity_resolution_tool;
 mod type_resolution_tool;
 
@@ -46,5 +49,8 @@ where
     type_resolution.visit(&mut tree)?;
     let environment = type_resolution.finish();
 
+    let mut extern_binding = ExternBindingTool::with_translation_units(&units);
+    extern_binding.visit(&tree)?;
+
     Ok((tree, environment))
 }
@@ -7,7 +7,7 @@ use jodin_common::error::{JodinErrorType, JodinResult};
 use jodin_common::types::intermediate_type::IntermediateType;
 use jodin_common::types::structure::Structure;
 use jodin_common::types::type_environment::{TypeEnvironment, TypeEnvironmentManager};
-use jodin_common::types::Field;
+use jodin_common::types::{Field, TypeTag};
 use jodin_common::unit::TranslationUnit;
 use jodin_common::utility::Tree;
 
@@ -48,10 +48,41 @@ impl<'nodes> TypeResolutionTool {
 
     pub fn visit(&mut self, tree: &'nodes mut JodinNode) -> JodinResult<()> {
         self.visit_type_definitions(tree)?;
+        Self::tag_function_types(tree)?;
 
         Ok(())
     }
 
+    /// Adds a type tag with the function type to every function definition
+    fn tag_function_types(tree: &mut JodinNode) -> JodinResult<()> {
+        let function_type = match tree.inner() {
+            JodinNodeType::FunctionDefinition {
+                return_type,
+                arguments,
+                ..
+            } => {
+                let mut parameters = vec![];
+                for argument in arguments {
+                    match argument.inner() {
+                        JodinNodeType::NamedValue { var_type, .. } => {
+                            parameters.push(var_type.clone())
+                        }
+                        _ => return Err(JodinErrorType::IllegalTreeType.into()),
+                    }
+                }
+                Some(return_type.clone().with_function_params(parameters))
+            }
+            _ => None,
+        };
+        if let Some(function_type) = function_type {
+            tree.add_tag(TypeTag::new(function_type))?;
+        }
+        for child in tree.inner_mut().children_mut() {
+            Self::tag_function_types(child)?;
+        }
+        Ok(())
+    }
+
     fn visit_type_definitions(&mut self, tree: &'nodes JodinNode) -> JodinResult<()> {
         match tree.inner() {
             JodinNodeType::CompoundTypeDefinition { .. } => self.build_structure(tree)?,
@@ -1,51 +1,79 @@
 //! The frontend of the compiler.
 //!
 
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
+use jodin_common::ast::dump::write_dumps;
 use jodin_common::ast::JodinNode;
 use jodin_common::compilation_settings::CompilationSettings;
 use jodin_common::error::JodinResult;
+use jodin_common::identifier::Identifier;
+use jodin_common::parsing::parse_program;
+
+/// A file that has been parsed, but not analyzed
+#[derive(Debug)]
+pub struct SourceFile {
+    /// The path of the file
+    pub path: PathBuf,
+    /// The contents of the file
+    pub source: String,
+    /// The tree parsed from the file
+    pub tree: JodinNode,
+}
+
+impl SourceFile {
+    /// The identifier of the module made from this file within a
+    /// [module graph](crate::passes::analysis::ModuleGraph)
+    pub fn module_id(&self) -> Identifier {
+        module_id(&self.path)
+    }
+}
+
+/// The identifier of the module made from a file
+pub fn module_id<P: AsRef<Path>>(path: P) -> Identifier {
+    Identifier::from(path.as_ref().to_string_lossy())
+}
 
 /// A tool to turn files into a jodin node tree
 pub struct FilesToJodinNodeTool<'a> {
     settings: &'a CompilationSettings,
+    files: Vec<SourceFile>,
 }
 
 impl<'a> FilesToJodinNodeTool<'a> {
     /// Creates a new toolchain that uses the a reference to compilation settings
     pub fn new(settings: &'a CompilationSettings) -> Self {
-        Self { settings }
+        Self {
+            settings,
+            files: vec![],
+        }
     }
 
-    /// When finish is called, no more files can be added to this JodinNode.
-    pub fn finish(self) -> JodinResult<JodinNode> {
-        todo!()
+    /// When finish is called, no more files can be added. Gets the parsed files, in the order they
+    /// were given.
+    pub fn finish(self) -> JodinResult<Vec<SourceFile>> {
+        Ok(self.files)
     }
 
-    /// invoke the front end
-    pub fn invoke<I: IntoIterator<Item = PathBuf>>(&mut self, _input_iter: I) -> JodinResult<()> {
-        /*
+    /// invoke the front end. If the settings ask for the abstract syntax tree, the tree of each file
+    /// is dumped to the target directory.
+    pub fn invoke<P: AsRef<Path>, I: IntoIterator<Item = P>>(
+        &mut self,
+        input_iter: I,
+    ) -> JodinResult<()> {
         for path in input_iter {
-            let file = File::open(&path)?;
-            let mut buffer = String::new();
-            BufReader::new(file).read_to_string(&mut buffer)?;
-            let pairs = complete_parse(JodinRule::jodin_file, buffer.as_str())?
-                .nth(0)
-                .unwrap();
-            if self.settings.output_parse_tree {
-                let string = format!("{:#?}", pairs);
-                let mut new_path = PathBuf::from(&path);
-                new_path.set_extension("pt");
-                let newer_path = self.settings.output_file_path(new_path);
-                let mut file = File::create(newer_path)?;
-                writeln!(file, "{}", string)?;
+            let path = path.as_ref().to_path_buf();
+            let source = std::fs::read_to_string(&path)?;
+            let tree = parse_program(&source)?;
+            if self.settings.output_ast {
+                write_dumps(
+                    &tree,
+                    Some(&path),
+                    self.settings.tree_dump_path(&path, "ast"),
+                )?;
             }
-            self.builder
-                .add_source_string(format!("{}", path.to_str().unwrap()), pairs)?;
+            self.files.push(SourceFile { path, source, tree });
         }
-
-         */
         Ok(())
     }
 }
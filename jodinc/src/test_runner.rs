@@ -19,6 +19,7 @@ pub struct ProjectBuilder {
     object_paths: Vec<PathBuf>,
     input: Option<ProjectBuilderInput>,
     settings: Option<CompilationSettings>,
+    coverage_output: Option<PathBuf>,
 }
 
 #[derive(Debug)]
@@ -35,6 +36,7 @@ impl ProjectBuilder {
             object_paths: vec![],
             input: None,
             settings: None,
+            coverage_output: None,
         }
     }
 
@@ -79,6 +81,12 @@ impl ProjectBuilder {
         self
     }
 
+    /// Writes the line coverage of the executed program to a file in the lcov format
+    pub fn coverage_output<P: AsRef<Path>>(mut self, path: P) -> Self {
+        self.coverage_output = Some(path.as_ref().to_path_buf());
+        self
+    }
+
     pub fn objects_path<S: AsRef<str>>(mut self, path: S) -> Self {
         let path = path.as_ref();
         let paths = std::env::split_paths(path);
@@ -181,7 +189,11 @@ impl ProjectBuilder {
     }
 
     /// Compiles then executes
-    pub fn execute(self, function: Identifier) -> Result<(u32, String, String), Box<dyn Error>> {
+    pub fn execute(
+        mut self,
+        function: Identifier,
+    ) -> Result<(u32, String, String), Box<dyn Error>> {
+        let coverage_output = self.coverage_output.take();
         let path = self.compile()?;
         let mut stdout = Vec::<u8>::new();
         let mut stderr = Vec::<u8>::new();
@@ -197,7 +209,13 @@ impl ProjectBuilder {
         let start = function
             .os_compat_str()
             .ok_or("Function name incompatible")?;
+        if coverage_output.is_some() {
+            virtual_machine.enable_coverage();
+        }
         let result = virtual_machine.run(&start)?;
+        if let (Some(output), Some(coverage)) = (coverage_output, virtual_machine.take_coverage()) {
+            coverage.save_lcov(output)?;
+        }
         drop(virtual_machine);
         Ok((
             result,
@@ -0,0 +1,275 @@
+//! Runs the subcommands of the compiler
+
+use jodin_rs_vm::standalone::embedded_image;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output};
+
+const LIBRARY: &str = r#"
+fn answer() -> unsigned int {
+    return 42u;
+}
+"#;
+
+const PROGRAM: &str = r#"
+extern const answer: fn() -> unsigned int;
+
+fn main() -> unsigned int {
+    return answer();
+}
+"#;
+
+fn project_directory(name: &str) -> PathBuf {
+    let directory =
+        std::env::temp_dir().join(format!("jodinc-cli-{}-{}", name, std::process::id()));
+    let _ = std::fs::remove_dir_all(&directory);
+    std::fs::create_dir_all(&directory).unwrap();
+    std::fs::write(directory.join("library.jodin"), LIBRARY).unwrap();
+    std::fs::write(directory.join("program.jodin"), PROGRAM).unwrap();
+    directory
+}
+
+fn jodinc(directory: &Path, args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_jodinc"))
+        .current_dir(directory)
+        .args(["-d", "0"])
+        .args(args)
+        .output()
+        .expect("compiler should start")
+}
+
+#[test]
+fn build_then_run() {
+    let directory = project_directory("run");
+
+    let output = jodinc(
+        &directory,
+        &["build", "-T", "out", "program.jodin", "library.jodin"],
+    );
+    assert!(output.status.success(), "{:?}", output);
+    assert!(directory.join("out").join("program.jobj").exists());
+    assert!(directory.join("out").join("library.jobj").exists());
+
+    let output = jodinc(
+        &directory,
+        &["run", "-T", "out", "program.jodin", "library.jodin"],
+    );
+    assert_eq!(output.status.code(), Some(42), "{:?}", output);
+    std::fs::remove_dir_all(directory).unwrap();
+}
+
+#[test]
+fn run_with_arguments() {
+    let directory = project_directory("arguments");
+    std::fs::write(
+        directory.join("count.jodin"),
+        "fn main(argc: int) -> int {\n    return argc;\n}\n",
+    )
+    .unwrap();
+
+    // the first argument is the first input
+    let output = jodinc(&directory, &["run", "-T", "out", "count.jodin"]);
+    assert_eq!(output.status.code(), Some(1), "{:?}", output);
+    let output = jodinc(
+        &directory,
+        &["run", "-T", "out", "count.jodin", "--", "first", "second"],
+    );
+    assert_eq!(output.status.code(), Some(3), "{:?}", output);
+
+    // only warnings are logged by default, so the log doesn't mix with the output of the program
+    let output = Command::new(env!("CARGO_BIN_EXE_jodinc"))
+        .current_dir(&directory)
+        .args(["run", "-T", "out", "count.jodin"])
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(1), "{:?}", output);
+    assert!(output.stdout.is_empty(), "{:?}", output);
+    std::fs::remove_dir_all(directory).unwrap();
+}
+
+#[test]
+fn run_with_coverage() {
+    let directory = project_directory("coverage");
+
+    let output = jodinc(
+        &directory,
+        &[
+            "run",
+            "-T",
+            "out",
+            "--coverage",
+            "coverage.lcov",
+            "program.jodin",
+            "library.jodin",
+        ],
+    );
+    assert_eq!(output.status.code(), Some(42), "{:?}", output);
+    let lcov = std::fs::read_to_string(directory.join("coverage.lcov")).unwrap();
+    for source in ["program.jodin", "library.jodin"] {
+        assert!(lcov.contains(&format!("SF:{}", source)), "{}", lcov);
+    }
+    assert!(lcov.contains("DA:5,1"), "{}", lcov);
+    assert!(lcov.contains("DA:3,1"), "{}", lcov);
+    std::fs::remove_dir_all(directory).unwrap();
+}
+
+#[test]
+fn bad_inputs_are_errors() {
+    let directory = project_directory("errors");
+
+    // errors are reported, rather than panicking, which would exit with 101
+    for args in [
+        &["run", "-T", "out", "-e", "missing", "library.jodin"][..],
+        &["check", "missing.jodin"],
+        &["check", "["],
+    ] {
+        let output = jodinc(&directory, args);
+        assert_eq!(output.status.code(), Some(1), "{:?}", output);
+    }
+    let output = Command::new(env!("CARGO_BIN_EXE_jodinc"))
+        .current_dir(&directory)
+        .args(["-d", "9", "check", "program.jodin"])
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(1), "{:?}", output);
+    std::fs::remove_dir_all(directory).unwrap();
+}
+
+#[test]
+fn run_with_included_library() {
+    let directory = project_directory("include");
+
+    let output = jodinc(&directory, &["build", "-T", "library", "library.jodin"]);
+    assert!(output.status.success(), "{:?}", output);
+
+    let output = jodinc(&directory, &["check", "program.jodin"]);
+    assert!(
+        !output.status.success(),
+        "externs can't be bound without the library"
+    );
+
+    let output = jodinc(
+        &directory,
+        &["run", "-I", "library", "-T", "out", "program.jodin"],
+    );
+    assert_eq!(output.status.code(), Some(42), "{:?}", output);
+    std::fs::remove_dir_all(directory).unwrap();
+}
+
+#[test]
+fn check_writes_nothing() {
+    let directory = project_directory("check");
+
+    let output = jodinc(
+        &directory,
+        &["check", "-T", "out", "program.jodin", "library.jodin"],
+    );
+    assert!(output.status.success(), "{:?}", output);
+    assert!(!directory.join("out").exists());
+
+    std::fs::write(directory.join("broken.jodin"), "fn broken() -> int {").unwrap();
+    let output = jodinc(&directory, &["check", "-T", "out", "broken.jodin"]);
+    assert!(!output.status.success());
+    std::fs::remove_dir_all(directory).unwrap();
+}
+
+#[test]
+fn emit_intermediate_forms() {
+    let directory = project_directory("emit");
+    let emit = |kind: &str| {
+        let output = jodinc(&directory, &["emit", kind, "library.jodin"]);
+        assert!(output.status.success(), "{:?}", output);
+        String::from_utf8(output.stdout).unwrap()
+    };
+
+    assert!(emit("tokens").contains("Identifier(\"answer\")"));
+    let ast = emit("ast");
+    assert!(ast.contains("FunctionDefinition"));
+    assert!(!ast.contains("ResolvedId"));
+    assert!(emit("tast").contains("ResolvedId"));
+    let jasm = emit("jasm");
+    assert!(jasm.contains("pub answer:"));
+    assert!(jodin_common::assembly::text::parse_jasm(&jasm).is_ok());
+    assert!(!directory.join("library.jobj").exists());
+    std::fs::remove_dir_all(directory).unwrap();
+}
+
+#[test]
+fn build_writes_tree_dumps() {
+    let directory = project_directory("dumps");
+
+    let output = jodinc(
+        &directory,
+        &["build", "--ast", "--tast", "-T", "out", "library.jodin"],
+    );
+    assert!(output.status.success(), "{:?}", output);
+    let out = directory.join("out");
+    for kind in ["ast", "tast"] {
+        assert!(out.join(format!("library.{}.dot", kind)).exists());
+        let json = std::fs::read_to_string(out.join(format!("library.{}.json", kind))).unwrap();
+        let document: serde_json::Value = serde_json::from_str(&json).unwrap();
+        assert_eq!(document["source"], "library.jodin");
+        assert!(document["root"]["kind"].is_string());
+    }
+    let tast = std::fs::read_to_string(out.join("library.tast.json")).unwrap();
+    assert!(tast.contains("\"resolved_id\""));
+    let ast = std::fs::read_to_string(out.join("library.ast.json")).unwrap();
+    assert!(!ast.contains("\"resolved_id\""));
+
+    let output = jodinc(&directory, &["emit", "tast", "-f", "dot", "library.jodin"]);
+    assert!(output.status.success(), "{:?}", output);
+    assert!(String::from_utf8(output.stdout)
+        .unwrap()
+        .contains("digraph ast {"));
+    std::fs::remove_dir_all(directory).unwrap();
+}
+
+#[cfg(unix)]
+#[test]
+fn build_standalone_executable() {
+    let directory = project_directory("standalone");
+    // stands in for the runner, which stops before reaching the image appended to it
+    let runtime = directory.join("runtime.sh");
+    std::fs::write(&runtime, "#!/bin/sh\nexit 7\n").unwrap();
+
+    let output = jodinc(
+        &directory,
+        &[
+            "build",
+            "-T",
+            "out",
+            "--standalone",
+            "bin/program",
+            "--runtime",
+            runtime.to_str().unwrap(),
+            "program.jodin",
+            "library.jodin",
+        ],
+    );
+    assert!(output.status.success(), "{:?}", output);
+    let executable = directory.join("bin").join("program");
+    let output = Command::new(&executable).output().unwrap();
+    assert_eq!(output.status.code(), Some(7), "{:?}", output);
+    let image = embedded_image(&executable)
+        .unwrap()
+        .expect("image should be embedded");
+    assert_eq!(image.entry_point, "main");
+    assert!(image.labels.contains_key("answer"));
+
+    // the library can't be linked into an executable, since it has no main
+    let output = jodinc(
+        &directory,
+        &[
+            "build",
+            "-T",
+            "alone",
+            "--standalone",
+            "bin/broken",
+            "--runtime",
+            runtime.to_str().unwrap(),
+            "library.jodin",
+        ],
+    );
+    assert!(!output.status.success(), "{:?}", output);
+    assert!(!directory.join("bin").join("broken").exists());
+    std::fs::remove_dir_all(directory).unwrap();
+}
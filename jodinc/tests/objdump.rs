@@ -0,0 +1,105 @@
+//! Inspects compiled objects with the jodin-objdump tool
+
+use jodin_common::asm_version::Version;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output};
+
+const LIBRARY: &str = r#"
+fn answer() -> unsigned int {
+    return 42u;
+}
+"#;
+
+const PROGRAM: &str = r#"
+extern const answer: fn() -> unsigned int;
+
+fn main() -> unsigned int {
+    return answer();
+}
+"#;
+
+fn compile(name: &str, program: &str) -> PathBuf {
+    let directory =
+        std::env::temp_dir().join(format!("jodin-objdump-{}-{}", name, std::process::id()));
+    let _ = std::fs::remove_dir_all(&directory);
+    std::fs::create_dir_all(&directory).unwrap();
+    std::fs::write(directory.join("library.jodin"), LIBRARY).unwrap();
+    std::fs::write(directory.join("program.jodin"), program).unwrap();
+    let output = Command::new(env!("CARGO_BIN_EXE_jodinc"))
+        .current_dir(&directory)
+        .args([
+            "-d",
+            "0",
+            "build",
+            "-T",
+            "out",
+            "program.jodin",
+            "library.jodin",
+        ])
+        .output()
+        .expect("compiler should start");
+    assert!(output.status.success(), "{:?}", output);
+    directory
+}
+
+fn objdump(directory: &Path, args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_jodin-objdump"))
+        .current_dir(directory)
+        .args(args)
+        .output()
+        .expect("objdump should start")
+}
+
+#[test]
+fn dump_object() {
+    let directory = compile("dump", PROGRAM);
+
+    let output = objdump(&directory, &["out/program.jobj"]);
+    assert!(output.status.success(), "{:?}", output);
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(
+        stdout.contains(&format!("jodin asm {}", Version::CURRENT)),
+        "{}",
+        stdout
+    );
+    assert!(stdout.contains("main: fn() -> unsigned int"), "{}", stdout);
+    assert!(stdout.contains("pub main:"), "{}", stdout);
+    assert!(stdout.contains("external:\n    answer"), "{}", stdout);
+    // colors are only used on terminals
+    assert!(!stdout.contains('\x1b'), "{}", stdout);
+
+    let output = objdump(&directory, &["--xrefs", "out/program.jobj"]);
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(stdout.contains("cross-references:"), "{}", stdout);
+    assert!(!stdout.contains("disassembly:"), "{}", stdout);
+    std::fs::remove_dir_all(directory).unwrap();
+}
+
+#[test]
+fn diff_objects() {
+    let old = compile("diff-old", PROGRAM);
+    let new = compile("diff-new", &PROGRAM.replace("answer()", "answer() + 1u"));
+    let old_object = old.join("out").join("program.jobj");
+    let new_object = new.join("out").join("program.jobj");
+    let old_object = old_object.to_str().unwrap();
+    let new_object = new_object.to_str().unwrap();
+
+    let output = objdump(&old, &["--diff", old_object, old_object]);
+    assert_eq!(output.status.code(), Some(0), "{:?}", output);
+    assert!(output.stdout.is_empty());
+
+    let output = objdump(&old, &["--diff", old_object, new_object]);
+    assert_eq!(output.status.code(), Some(1), "{:?}", output);
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(stdout.contains("code:"), "{}", stdout);
+    assert!(
+        stdout.lines().any(|line| line.starts_with('+')),
+        "{}",
+        stdout
+    );
+
+    let output = objdump(&old, &["--diff", old_object]);
+    assert_eq!(output.status.code(), Some(2), "{:?}", output);
+    std::fs::remove_dir_all(old).unwrap();
+    std::fs::remove_dir_all(new).unwrap();
+}
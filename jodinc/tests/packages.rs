@@ -0,0 +1,74 @@
+use jodin_common::compilation_settings::CompilationSettings;
+use jodin_common::package::Package;
+use jodin_common::unit::Incremental;
+use jodin_rs_vm::core_traits::VirtualMachine;
+use jodin_rs_vm::mvp::MinimumALU;
+use jodin_rs_vm::scoped_memory::VMMemory;
+use jodin_rs_vm::vm::VMBuilder;
+use jodinc::compilation::incremental::IncrementalCompiler;
+use std::path::{Path, PathBuf};
+
+fn compile(compiler: &mut IncrementalCompiler, directory: &Path, name: &str, program: &str) {
+    let source = directory.join(format!("{}.jodin", name));
+    std::fs::write(&source, program).unwrap();
+    compiler
+        .compile_file(&source)
+        .expect("program should compile");
+}
+
+#[test]
+fn program_uses_packaged_library() {
+    let directory: PathBuf =
+        std::env::temp_dir().join(format!("jodin-packages-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&directory);
+    std::fs::create_dir_all(&directory).unwrap();
+
+    let library_output = directory.join("library");
+    let mut compiler = IncrementalCompiler::new(&library_output, CompilationSettings::default());
+    compile(
+        &mut compiler,
+        &directory,
+        "answers",
+        r#"
+        fn answer() -> unsigned int {
+            return 42u;
+        }
+        "#,
+    );
+    let package_path = directory.join("answers.jdp");
+    Package::from_directory(&library_output, &package_path)
+        .unwrap()
+        .write()
+        .unwrap();
+    std::fs::remove_dir_all(&library_output).unwrap();
+
+    let package = Package::open(&package_path).unwrap();
+    assert_eq!(package.entries().len(), 1);
+    assert!(!package.translation_units().is_empty());
+
+    let program_output = directory.join("program");
+    let mut compiler = IncrementalCompiler::new(&program_output, CompilationSettings::default());
+    compiler.add_incremental(package);
+    compile(
+        &mut compiler,
+        &directory,
+        "program",
+        r#"
+        extern const answer: fn() -> unsigned int;
+
+        fn main() -> unsigned int {
+            return answer();
+        }
+        "#,
+    );
+
+    let mut vm = VMBuilder::new()
+        .memory(VMMemory::default())
+        .alu(MinimumALU)
+        .object_path(&package_path)
+        .object_path(&program_output)
+        .build()
+        .unwrap();
+    assert_eq!(vm.run("main").expect("VM should not fail"), 42);
+    std::fs::remove_dir_all(directory).unwrap();
+}
@@ -0,0 +1,184 @@
+use jodin_common::assembly::value::Value;
+use jodin_common::compilation_settings::CompilationSettings;
+use jodin_common::error::JodinErrorType;
+use jodin_common::unit::CompilationObject;
+use jodin_rs_vm::core_traits::VirtualMachine;
+use jodin_rs_vm::mvp::MinimumALU;
+use jodin_rs_vm::scoped_memory::VMMemory;
+use jodin_rs_vm::vm::VMBuilder;
+use jodin_vm_plugins::plugins::{PluginManager, VMHandle};
+use jodin_vm_plugins::signatures::LabelSignature;
+use jodin_vm_plugins::Plugin;
+use jodinc::compilation::incremental::IncrementalCompiler;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+
+/// Adds numbers, and records the values it's given
+#[derive(Default, Clone)]
+struct MathPlugin {
+    recorded: Arc<Mutex<Vec<i64>>>,
+}
+
+impl Plugin for MathPlugin {
+    fn labels<'s>(&'s self, buffer: &mut [&'s str]) {
+        buffer[0] = "add_numbers";
+        buffer[1] = "record";
+    }
+
+    fn labels_count(&self) -> i32 {
+        2
+    }
+
+    fn call_label(
+        &self,
+        label: &str,
+        handle: &mut dyn VMHandle,
+        output: &mut Option<Result<Value, String>>,
+    ) {
+        let mut args = vec![];
+        while !handle.stack().empty() {
+            let mut value = None;
+            handle.stack().pop(&mut value);
+            args.extend(value);
+        }
+        *output = Some(match (label, args.as_slice()) {
+            ("add_numbers", [Value::Integer(left), Value::Integer(right)]) => {
+                Ok(Value::Integer(left + right))
+            }
+            ("record", &[Value::Integer(value)]) => {
+                self.recorded.lock().unwrap().push(value);
+                Ok(Value::Empty)
+            }
+            _ => Err(format!("{label} can't be called with {args:?}")),
+        });
+    }
+
+    fn signature(&self, label: &str) -> Option<LabelSignature> {
+        let signature = match label {
+            "add_numbers" => "fn(int, int) -> int",
+            "record" => "fn(int) -> void",
+            _ => return None,
+        };
+        Some(signature.parse().unwrap())
+    }
+}
+
+/// Compiles a program against the signatures of the math plugin
+fn compile(name: &str, program: &str) -> Result<PathBuf, JodinErrorType> {
+    let directory = std::env::temp_dir().join(format!(
+        "jodin-plugin-externs-{}-{}",
+        name,
+        std::process::id()
+    ));
+    let _ = std::fs::remove_dir_all(&directory);
+    let output = directory.join("out");
+    std::fs::create_dir_all(&output).unwrap();
+
+    let mut manager = PluginManager::new();
+    manager.with_plugin(MathPlugin::default()).unwrap();
+    let signatures = directory.join("math.jobj");
+    manager.write_signature_object(&signatures).unwrap();
+
+    let mut compiler = IncrementalCompiler::new(&output, CompilationSettings::default());
+    compiler.add_incremental(CompilationObject::try_from(signatures.as_path()).unwrap());
+    let source = directory.join(format!("{}.jodin", name));
+    std::fs::write(&source, program).unwrap();
+    compiler
+        .compile_file(&source)
+        .map(|_| output)
+        .map_err(|e| e.into_err_and_bt().0)
+}
+
+#[test]
+fn extern_binds_to_plugin_label() {
+    let output = compile(
+        "binds",
+        r#"
+        extern const add_numbers: fn(int, int) -> int;
+        extern const record: fn(int) -> void;
+
+        fn main() -> unsigned int {
+            record(add_numbers(2, 3));
+            return 0u;
+        }
+        "#,
+    )
+    .expect("program should compile");
+
+    let plugin = MathPlugin::default();
+    let recorded = plugin.recorded.clone();
+    let mut vm = VMBuilder::new()
+        .memory(VMMemory::default())
+        .alu(MinimumALU)
+        .object_path(output)
+        .build()
+        .unwrap();
+    vm.with_plugin(plugin).unwrap();
+    assert_eq!(vm.run("main").expect("VM should not fail"), 0);
+    assert_eq!(*recorded.lock().unwrap(), vec![5]);
+}
+
+#[test]
+fn extern_must_match_signature() {
+    match compile(
+        "mismatch",
+        r#"
+        extern const add_numbers: fn(int) -> int;
+        "#,
+    ) {
+        Err(JodinErrorType::ExternTypeMismatch {
+            declared, expected, ..
+        }) => {
+            assert_eq!(declared, "fn(int) -> int");
+            assert_eq!(expected, "fn(int,int) -> int");
+        }
+        other => panic!("expected a type mismatch, found {:?}", other),
+    }
+
+    assert!(matches!(
+        compile(
+            "unknown",
+            r#"
+            extern const subtract_numbers: fn(int, int) -> int;
+            "#,
+        ),
+        Err(JodinErrorType::UnresolvedExtern(_))
+    ));
+}
+
+#[test]
+fn extern_calls_are_checked() {
+    match compile(
+        "argument-count",
+        r#"
+        extern const add_numbers: fn(int, int) -> int;
+
+        fn main() -> unsigned int {
+            add_numbers(1);
+            return 0u;
+        }
+        "#,
+    ) {
+        Err(JodinErrorType::IncorrectArgumentCount {
+            expected, found, ..
+        }) => assert_eq!((expected, found), (2, 1)),
+        other => panic!("expected a wrong argument count, found {:?}", other),
+    }
+
+    match compile(
+        "argument-type",
+        r#"
+        extern const record: fn(int) -> void;
+
+        fn main() -> unsigned int {
+            record(2.5);
+            return 0u;
+        }
+        "#,
+    ) {
+        Err(JodinErrorType::ArgumentTypeMismatch {
+            index, expected, ..
+        }) => assert_eq!((index, expected.as_str()), (0, "int")),
+        other => panic!("expected a wrong argument type, found {:?}", other),
+    }
+}
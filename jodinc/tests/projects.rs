@@ -0,0 +1,265 @@
+use jodin_common::compilation_settings::CompilationSettings;
+use jodin_common::error::JodinErrorType;
+use jodin_common::linker::Linker;
+use jodin_common::unit::CompilationObject;
+use jodin_rs_vm::core_traits::VirtualMachine;
+use jodin_rs_vm::mvp::MinimumALU;
+use jodin_rs_vm::scoped_memory::VMMemory;
+use jodin_rs_vm::vm::VMBuilder;
+use jodinc::compilation::incremental::IncrementalCompiler;
+use std::path::{Path, PathBuf};
+
+fn project_directory(name: &str) -> PathBuf {
+    let directory =
+        std::env::temp_dir().join(format!("jodin-project-{}-{}", name, std::process::id()));
+    let _ = std::fs::remove_dir_all(&directory);
+    std::fs::create_dir_all(&directory).unwrap();
+    directory
+}
+
+/// Writes the sources of a project, getting their paths in the order given
+fn write_sources(directory: &Path, sources: &[(&str, &str)]) -> Vec<PathBuf> {
+    sources
+        .iter()
+        .map(|(name, program)| {
+            let path = directory.join(format!("{}.jodin", name));
+            std::fs::write(&path, program).unwrap();
+            path
+        })
+        .collect()
+}
+
+#[test]
+fn files_are_compiled_after_their_dependencies() {
+    let directory = project_directory("ordered");
+    // the program is given before the libraries it uses
+    let sources = write_sources(
+        &directory,
+        &[
+            (
+                "program",
+                r#"
+                extern const answer: fn() -> unsigned int;
+
+                fn main() -> unsigned int {
+                    return answer();
+                }
+                "#,
+            ),
+            (
+                "answers",
+                r#"
+                extern const half: fn() -> unsigned int;
+
+                fn answer() -> unsigned int {
+                    return half() + half();
+                }
+                "#,
+            ),
+            (
+                "halves",
+                r#"
+                fn half() -> unsigned int {
+                    return 21u;
+                }
+                "#,
+            ),
+        ],
+    );
+
+    let output = directory.join("out");
+    let mut compiler = IncrementalCompiler::new(&output, CompilationSettings::default());
+    compiler
+        .compile_files(&sources)
+        .expect("project should compile");
+
+    let mut vm = VMBuilder::new()
+        .memory(VMMemory::default())
+        .alu(MinimumALU)
+        .object_path(&output)
+        .build()
+        .unwrap();
+    assert_eq!(vm.run("main").expect("VM should not fail"), 42);
+    std::fs::remove_dir_all(directory).unwrap();
+}
+
+#[test]
+fn circular_dependencies_are_detected() {
+    let directory = project_directory("circular");
+    let sources = write_sources(
+        &directory,
+        &[
+            (
+                "ping",
+                r#"
+                extern const pong: fn() -> unsigned int;
+
+                fn ping() -> unsigned int {
+                    return pong();
+                }
+                "#,
+            ),
+            (
+                "pong",
+                r#"
+                extern const ping: fn() -> unsigned int;
+
+                fn pong() -> unsigned int {
+                    return ping();
+                }
+                "#,
+            ),
+        ],
+    );
+
+    let mut compiler =
+        IncrementalCompiler::new(directory.join("out"), CompilationSettings::default());
+    match compiler.compile_files(&sources) {
+        Err(e) => assert!(matches!(
+            e.error_type,
+            JodinErrorType::CircularDependencyDetected
+        )),
+        Ok(_) => panic!("expected a circular dependency"),
+    }
+    std::fs::remove_dir_all(directory).unwrap();
+}
+
+#[test]
+fn unchanged_files_are_not_recompiled() {
+    let directory = project_directory("cached");
+    let program = r#"
+        extern const answer: fn() -> unsigned int;
+
+        fn main() -> unsigned int {
+            return answer();
+        }
+        "#;
+    let answers = r#"
+        extern const half: fn() -> unsigned int;
+
+        fn answer() -> unsigned int {
+            return half() + half();
+        }
+        "#;
+    let sources = write_sources(
+        &directory,
+        &[
+            ("program", program),
+            ("answers", answers),
+            ("halves", "fn half() -> unsigned int { return 21u; }"),
+        ],
+    );
+    let output = directory.join("out");
+    let build_with = |settings: CompilationSettings| {
+        let mut compiler = IncrementalCompiler::new(&output, settings);
+        compiler
+            .compile_files(&sources)
+            .expect("project should compile")
+            .into_iter()
+            .map(|path| path.file_stem().unwrap().to_string_lossy().into_owned())
+            .collect::<Vec<_>>()
+    };
+    let build = || build_with(CompilationSettings::default());
+
+    assert_eq!(build(), vec!["halves", "answers", "program"]);
+    assert!(build().is_empty());
+
+    // changing the settings rebuilds every file
+    let dumping = CompilationSettings {
+        output_ast: true,
+        ..CompilationSettings::default()
+    };
+    assert_eq!(build_with(dumping), vec!["halves", "answers", "program"]);
+    assert_eq!(build(), vec!["halves", "answers", "program"]);
+
+    // changing a body doesn't change the interface used by dependents
+    write_sources(
+        &directory,
+        &[("halves", "fn half() -> unsigned int { return 20u + 1u; }")],
+    );
+    assert_eq!(build(), vec!["halves"]);
+
+    // adding to the interface rebuilds the files that depend on it
+    write_sources(
+        &directory,
+        &[(
+            "halves",
+            r#"
+            fn half() -> unsigned int { return 21u; }
+            fn quarter() -> unsigned int { return 10u; }
+            "#,
+        )],
+    );
+    assert_eq!(build(), vec!["halves", "answers"]);
+
+    let mut vm = VMBuilder::new()
+        .memory(VMMemory::default())
+        .alu(MinimumALU)
+        .object_path(&output)
+        .build()
+        .unwrap();
+    assert_eq!(vm.run("main").expect("VM should not fail"), 42);
+    std::fs::remove_dir_all(directory).unwrap();
+}
+
+#[test]
+fn linked_projects_run_as_one_image() {
+    let directory = project_directory("linked");
+    let sources = write_sources(
+        &directory,
+        &[
+            (
+                "program",
+                r#"
+                extern const answer: fn() -> unsigned int;
+
+                fn main() -> unsigned int {
+                    return answer();
+                }
+                "#,
+            ),
+            (
+                "answers",
+                r#"
+                private fn half() -> unsigned int { return 21u; }
+                private fn unused() -> unsigned int { return 0u; }
+
+                fn answer() -> unsigned int {
+                    return half() + half();
+                }
+                "#,
+            ),
+        ],
+    );
+    let output = directory.join("out");
+    let mut compiler = IncrementalCompiler::new(&output, CompilationSettings::default());
+    compiler
+        .compile_files(&sources)
+        .expect("project should compile");
+
+    let mut linker = Linker::new();
+    for name in ["program", "answers"] {
+        let object = CompilationObject::try_from(output.join(format!("{}.jobj", name)));
+        linker.add_object(object.unwrap());
+    }
+    let image = linker.link().expect("project should link");
+    assert!(image.labels.contains_key("half"));
+    assert!(!image.labels.contains_key("unused"));
+    let image_path = directory.join("program.jexe");
+    image.write(&image_path).unwrap();
+
+    let mut vm = VMBuilder::new()
+        .memory(VMMemory::default())
+        .alu(MinimumALU)
+        .object_path(&image_path)
+        .build()
+        .unwrap();
+    assert_eq!(vm.run("main").expect("VM should not fail"), 42);
+
+    // linking the program without its library leaves its symbols undefined
+    let mut linker = Linker::new();
+    linker.add_object(CompilationObject::try_from(output.join("program.jobj")).unwrap());
+    let error = linker.link().unwrap_err().to_string();
+    assert!(error.contains("answer is not defined"), "{}", error);
+    std::fs::remove_dir_all(directory).unwrap();
+}